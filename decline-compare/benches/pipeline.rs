@@ -0,0 +1,107 @@
+//! Indicator and screen timings over synthetic daily bars, for checking
+//! refactors of the hot paths (caching, parallelism) against a baseline:
+//!
+//!     cargo bench --bench pipeline -- --save-baseline before
+//!     cargo bench --bench pipeline -- --baseline before
+//!
+//! Bars are a seeded random walk, so every run measures the same data.
+
+use std::collections::HashMap;
+use std::hint::black_box;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use clap::Parser;
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use decline_compare::config::Config;
+use decline_compare::decline::{self, DeclineArgs};
+use decline_compare::indicators;
+use decline_compare::schedule;
+use decline_compare::sina::Candle;
+
+/// Trading days per year.
+const YEARS: usize = 250;
+
+/// Codes in the screen benchmark, a large watchlist.
+const SCREEN_CODES: usize = 200;
+
+#[derive(Parser)]
+struct Cli {
+    #[command(flatten)]
+    decline: DeclineArgs,
+}
+
+/// `len` weekday bars from 2015-01-05 on, a random walk from `seed`.
+fn bars(len: usize, seed: u64) -> Vec<Candle> {
+    let mut state = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+    let mut next = move || {
+        // PCG-style step; the top bits are uniform enough for a walk.
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (state >> 11) as f64 / (1u64 << 53) as f64
+    };
+    let mut date = NaiveDate::from_ymd_opt(2015, 1, 5).unwrap();
+    let mut close = 1.0;
+    let mut candles = Vec::with_capacity(len);
+    while candles.len() < len {
+        if !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            let open = close;
+            close = (open * (1.0 + (next() - 0.5) * 0.04)).max(0.01);
+            let spread = open.max(close) * next() * 0.01;
+            candles.push(Candle {
+                time: date.and_time(schedule::SESSION_CLOSE),
+                open,
+                high: open.max(close) + spread,
+                low: open.min(close) - spread,
+                close,
+                volume: 1e6 * (0.5 + next()),
+            });
+        }
+        date += Duration::days(1);
+    }
+    candles
+}
+
+fn indicator_suite(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indicators");
+    for years in [1, 5, 10] {
+        let candles = bars(years * YEARS, years as u64);
+        let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+        group.throughput(Throughput::Elements(candles.len() as u64));
+        group.bench_with_input(BenchmarkId::new("adx", years), &candles, |b, c| {
+            b.iter(|| indicators::adx(black_box(c), 14))
+        });
+        group.bench_with_input(BenchmarkId::new("parabolic_sar", years), &candles, |b, c| {
+            b.iter(|| indicators::parabolic_sar(black_box(c), 0.02, 0.2))
+        });
+        group.bench_with_input(BenchmarkId::new("rsi", years), &closes, |b, c| {
+            b.iter(|| indicators::rsi(black_box(c), 14))
+        });
+        group.bench_with_input(BenchmarkId::new("rolling_returns", years), &closes, |b, c| {
+            b.iter(|| indicators::rolling_returns(black_box(c), 5))
+        });
+        group.bench_with_input(BenchmarkId::new("regression", years), &closes, |b, c| {
+            b.iter(|| indicators::regression(black_box(c), 60))
+        });
+    }
+    group.finish();
+}
+
+fn screen(c: &mut Criterion) {
+    let args = Cli::parse_from(["bench"]).decline;
+    let cfg = Config::default();
+    let bonds = HashMap::new();
+    let mut group = c.benchmark_group("screen");
+    group.sample_size(10);
+    for years in [1, 5] {
+        let loaded: Vec<decline::Loaded> = (0..SCREEN_CODES)
+            .map(|i| (i, format!("{:06}", 510000 + i), (bars(years * YEARS, i as u64), None)))
+            .collect();
+        group.throughput(Throughput::Elements(SCREEN_CODES as u64));
+        group.bench_with_input(BenchmarkId::new("process", years), &loaded, |b, loaded| {
+            b.iter(|| decline::process_loaded(loaded.clone(), &args, &cfg, &bonds))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, indicator_suite, screen);
+criterion_main!(benches);
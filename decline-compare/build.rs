@@ -0,0 +1,14 @@
+fn main() {
+    // The gRPC stubs are only generated for builds that serve them.
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/biga.proto");
+        let fds = protox::compile(["proto/biga.proto"], ["proto"]).expect("failed to parse proto/biga.proto");
+        let builder = tonic_prost_build::configure().build_client(false);
+        // The JSON endpoints answer with the same messages, so they get
+        // serialized and described in the OpenAPI document too.
+        #[cfg(feature = "openapi")]
+        let builder = builder.type_attribute(".biga.v1", "#[derive(serde::Serialize, utoipa::ToSchema)]");
+        builder.compile_fds(fds).expect("failed to generate gRPC code");
+    }
+}
@@ -0,0 +1,106 @@
+// The dashboard `serve --http` hosts at /: the screen's table with a
+// sparkline of each code's recent closes, and the alerts logged lately.
+// Everything comes from the JSON endpoints next to it and refreshes every
+// minute.
+
+const REFRESH_MS = 60 * 1000;
+const SPARK_DAYS = 60;
+
+async function getJson(path) {
+  const response = await fetch(path);
+  const body = await response.json();
+  if (!response.ok) throw new Error(body.error || response.statusText);
+  return body;
+}
+
+function cell(text, className) {
+  const td = document.createElement("td");
+  td.textContent = text;
+  if (className) td.className = className;
+  return td;
+}
+
+function fixed(value, digits) {
+  return value === undefined || value === null || Number.isNaN(value) ? "" : value.toFixed(digits);
+}
+
+function sign(value) {
+  return value > 0 ? "up" : value < 0 ? "down" : "";
+}
+
+function sparkline(closes) {
+  const svg = document.createElementNS("http://www.w3.org/2000/svg", "svg");
+  const width = 120, height = 28;
+  svg.setAttribute("class", "spark");
+  svg.setAttribute("width", width);
+  svg.setAttribute("height", height);
+  if (closes.length > 1) {
+    const low = Math.min(...closes), high = Math.max(...closes);
+    const span = high - low || 1;
+    const points = closes.map((c, i) => {
+      const x = (i / (closes.length - 1)) * width;
+      const y = height - 2 - ((c - low) / span) * (height - 4);
+      return x.toFixed(1) + "," + y.toFixed(1);
+    });
+    const line = document.createElementNS("http://www.w3.org/2000/svg", "polyline");
+    line.setAttribute("points", points.join(" "));
+    svg.appendChild(line);
+  }
+  return svg;
+}
+
+async function renderScreen() {
+  const report = await getJson("v1/decline");
+  const codes = report.rows.map((r) => r.code);
+  const closes = {};
+  if (codes.length > 0) {
+    const series = await getJson("v1/closes?days=" + SPARK_DAYS + "&codes=" + codes.join(","));
+    for (const s of series) closes[s.code] = s.closes;
+  }
+  const body = document.querySelector("#screen tbody");
+  body.replaceChildren(
+    ...report.rows.map((row) => {
+      const tr = document.createElement("tr");
+      tr.append(
+        cell(row.code),
+        cell(row.date),
+        cell(fixed(row.close, 3), "num"),
+        cell(fixed(row.decline, 2), "num " + sign(row.decline)),
+        cell(fixed(row.metrics.rsi, 1), "num"),
+        cell(fixed(row.metrics.drawdown, 2), "num"),
+        cell(fixed(row.score, 2), "num"),
+      );
+      const spark = document.createElement("td");
+      spark.appendChild(sparkline(closes[row.code] || []));
+      tr.appendChild(spark);
+      return tr;
+    }),
+  );
+  const session = report.session ? " (" + report.session.replace("_", " ") + ")" : "";
+  return "As of " + (report.as_of || "—") + session;
+}
+
+async function renderAlerts() {
+  const events = await getJson("v1/events?days=7");
+  const body = document.querySelector("#alerts tbody");
+  body.replaceChildren(
+    ...events.reverse().map((event) => {
+      const tr = document.createElement("tr");
+      tr.append(cell(event.time), cell(event.date), cell(event.code), cell(event.message));
+      return tr;
+    }),
+  );
+}
+
+async function refresh() {
+  const status = document.getElementById("status");
+  try {
+    const [asOf] = await Promise.all([renderScreen(), renderAlerts()]);
+    status.textContent = asOf + " · updated " + new Date().toLocaleTimeString();
+  } catch (e) {
+    status.textContent = "Update failed: " + e.message;
+  }
+}
+
+refresh();
+setInterval(refresh, REFRESH_MS);
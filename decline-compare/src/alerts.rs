@@ -0,0 +1,200 @@
+use std::fmt;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::i18n::tr;
+use crate::indicators::{self, SarPoint};
+use crate::sina::Candle;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AlertKind {
+    /// The latest bar traded below the lowest low of the previous N bars.
+    DonchianLow { days: usize, level: f64, low: f64 },
+    /// The latest bar traded above the highest high of the previous N bars.
+    DonchianHigh { days: usize, level: f64, high: f64 },
+    /// The latest bar's low reached the lower Keltner band.
+    KeltnerLower { level: f64, low: f64 },
+    /// The latest bar's high reached the upper Keltner band.
+    KeltnerUpper { level: f64, high: f64 },
+    /// Parabolic SAR switched sides on the latest bar; `long` means it is now
+    /// below price.
+    SarFlip { long: bool, sar: f64, close: f64 },
+    /// A price ratio moved at least `threshold` standard deviations away from
+    /// its rolling mean.
+    SpreadStretch { zscore: f64, threshold: f64 },
+    /// The fund goes ex-dividend in `days` days, paying `amount` per share.
+    ExDividend { ex_date: NaiveDate, amount: f64, days: i64 },
+    /// The rolling correlation of daily returns to `benchmark` moved from
+    /// `before` to `now`, at least `threshold` either way.
+    CorrelationShift { benchmark: String, before: f64, now: f64, threshold: f64 },
+    /// The last trade reached a configured price level, at or below it if
+    /// `below`, else at or above.
+    PriceLevel { below: bool, level: f64, price: f64 },
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            AlertKind::DonchianLow { days, level, .. } => {
+                tr!("broke {}-day low ({:.3}) today", "今日跌破 {} 日低点 ({:.3})", days, level)
+            }
+            AlertKind::DonchianHigh { days, level, .. } => {
+                tr!("broke {}-day high ({:.3}) today", "今日突破 {} 日高点 ({:.3})", days, level)
+            }
+            AlertKind::KeltnerLower { level, .. } => {
+                tr!("touched lower Keltner band ({:.3})", "触及肯特纳通道下轨 ({:.3})", level)
+            }
+            AlertKind::KeltnerUpper { level, .. } => {
+                tr!("touched upper Keltner band ({:.3})", "触及肯特纳通道上轨 ({:.3})", level)
+            }
+            AlertKind::SarFlip { long: true, sar, .. } => {
+                tr!("SAR flipped below price ({:.3})", "SAR 翻转至价格下方 ({:.3})", sar)
+            }
+            AlertKind::SarFlip { long: false, sar, .. } => {
+                tr!("SAR flipped above price ({:.3})", "SAR 翻转至价格上方 ({:.3})", sar)
+            }
+            AlertKind::SpreadStretch { zscore, threshold } => {
+                tr!("spread z-score {:+.2} beyond ±{:.2}", "价差 Z 值 {:+.2} 超出 ±{:.2}", zscore, threshold)
+            }
+            AlertKind::ExDividend { ex_date, amount, days } => tr!(
+                "goes ex-dividend {} ({:.4}/share) in {} days",
+                "{} 除息 (每份 {:.4}), {} 日后",
+                ex_date,
+                amount,
+                days
+            ),
+            AlertKind::CorrelationShift { benchmark, before, now, .. } => tr!(
+                "correlation to {} moved from {:.2} to {:.2}",
+                "与 {} 的相关性从 {:.2} 变为 {:.2}",
+                benchmark,
+                before,
+                now
+            ),
+            AlertKind::PriceLevel { below: true, level, price } => {
+                tr!("at {:.3}, at or below {:.3}", "现价 {:.3}, 已跌至 {:.3} 或以下", price, level)
+            }
+            AlertKind::PriceLevel { below: false, level, price } => {
+                tr!("at {:.3}, at or above {:.3}", "现价 {:.3}, 已涨至 {:.3} 或以上", price, level)
+            }
+        };
+        f.write_str(&text)
+    }
+}
+
+impl AlertKind {
+    /// Short name of what was measured, for structured notifications. Also
+    /// keys cooldown state, so it is not localized.
+    pub fn metric(&self) -> String {
+        match self {
+            AlertKind::DonchianLow { days, .. } => format!("{}-day low breakout", days),
+            AlertKind::DonchianHigh { days, .. } => format!("{}-day high breakout", days),
+            AlertKind::KeltnerLower { .. } => "lower Keltner band".to_string(),
+            AlertKind::KeltnerUpper { .. } => "upper Keltner band".to_string(),
+            AlertKind::SarFlip { long: true, .. } => "SAR flip (bullish)".to_string(),
+            AlertKind::SarFlip { long: false, .. } => "SAR flip (bearish)".to_string(),
+            AlertKind::SpreadStretch { .. } => "spread z-score".to_string(),
+            AlertKind::ExDividend { .. } => "ex-dividend".to_string(),
+            AlertKind::CorrelationShift { .. } => "correlation shift".to_string(),
+            AlertKind::PriceLevel { below: true, level, .. } => format!("price <= {}", level),
+            AlertKind::PriceLevel { below: false, level, .. } => format!("price >= {}", level),
+        }
+    }
+
+    /// The observed value that triggered the alert.
+    pub fn value(&self) -> f64 {
+        match self {
+            AlertKind::DonchianLow { low, .. } | AlertKind::KeltnerLower { low, .. } => *low,
+            AlertKind::DonchianHigh { high, .. } | AlertKind::KeltnerUpper { high, .. } => *high,
+            AlertKind::SarFlip { close, .. } => *close,
+            AlertKind::SpreadStretch { zscore, .. } => *zscore,
+            AlertKind::ExDividend { amount, .. } => *amount,
+            AlertKind::CorrelationShift { now, .. } => *now,
+            AlertKind::PriceLevel { price, .. } => *price,
+        }
+    }
+
+    /// Which way the alert says price should go next: up for the dips the
+    /// screen buys and bullish SAR flips, down for breakouts higher and
+    /// bearish flips, `None` for alerts that don't call a direction.
+    pub fn expects_rise(&self) -> Option<bool> {
+        match self {
+            AlertKind::DonchianLow { .. } | AlertKind::KeltnerLower { .. } => Some(true),
+            AlertKind::DonchianHigh { .. } | AlertKind::KeltnerUpper { .. } => Some(false),
+            AlertKind::SarFlip { long, .. } => Some(*long),
+            AlertKind::SpreadStretch { .. }
+            | AlertKind::ExDividend { .. }
+            | AlertKind::CorrelationShift { .. }
+            | AlertKind::PriceLevel { .. } => None,
+        }
+    }
+
+    /// The level the value was compared against.
+    pub fn threshold(&self) -> f64 {
+        match self {
+            AlertKind::DonchianLow { level, .. }
+            | AlertKind::DonchianHigh { level, .. }
+            | AlertKind::KeltnerLower { level, .. }
+            | AlertKind::KeltnerUpper { level, .. } => *level,
+            AlertKind::SarFlip { sar, .. } => *sar,
+            AlertKind::SpreadStretch { threshold, .. } => *threshold,
+            AlertKind::ExDividend { days, .. } => *days as f64,
+            AlertKind::CorrelationShift { threshold, .. } => *threshold,
+            AlertKind::PriceLevel { level, .. } => *level,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub code: String,
+    pub kind: AlertKind,
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.kind)
+    }
+}
+
+/// Channel breakout and band touch events for the latest bar.
+pub fn channel_events(candles: &[Candle], donchian_days: usize) -> Vec<AlertKind> {
+    let mut events = Vec::new();
+    let Some(last) = candles.last() else {
+        return events;
+    };
+
+    if let Some((low, high)) = indicators::donchian(candles, donchian_days) {
+        if last.low < low {
+            events.push(AlertKind::DonchianLow { days: donchian_days, level: low, low: last.low });
+        }
+        if last.high > high {
+            events.push(AlertKind::DonchianHigh { days: donchian_days, level: high, high: last.high });
+        }
+    }
+
+    if let Some((lower, _, upper)) = indicators::keltner(
+        candles,
+        indicators::KELTNER_EMA_PERIOD,
+        indicators::KELTNER_ATR_PERIOD,
+        indicators::KELTNER_MULTIPLIER,
+    ) {
+        if last.low <= lower {
+            events.push(AlertKind::KeltnerLower { level: lower, low: last.low });
+        }
+        if last.high >= upper {
+            events.push(AlertKind::KeltnerUpper { level: upper, high: last.high });
+        }
+    }
+
+    events
+}
+
+/// A flip event when the last SAR point is on the other side of price from
+/// the one before it.
+pub fn sar_events(points: &[SarPoint], close: f64) -> Vec<AlertKind> {
+    match points {
+        [.., prev, last] if prev.long != last.long => vec![AlertKind::SarFlip { long: last.long, sar: last.sar, close }],
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,45 @@
+//! Single-day moves far outside a code's own return distribution, such as
+//! a circuit-breaker day or a premium collapse, flagged apart from the
+//! decline screen since they call for a look rather than a trade.
+
+use chrono::NaiveDate;
+use serde::Serialize;
+
+use crate::config::AnomalyConfig;
+use crate::sina::Candle;
+
+/// Fewest earlier daily returns a deviation is trusted from.
+const MIN_RETURNS: usize = 60;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Anomaly {
+    pub code: String,
+    pub date: NaiveDate,
+    /// The latest bar's close-to-close return, percent.
+    pub change: f64,
+    /// How many standard deviations `change` is from the mean.
+    pub sigma: f64,
+}
+
+/// The latest bar's move, if it lies at least `cfg.sigma` deviations from
+/// the mean of the `cfg.lookback_days` returns before it.
+pub fn detect(code: &str, candles: &[Candle], cfg: &AnomalyConfig) -> Option<Anomaly> {
+    if cfg.sigma <= 0.0 {
+        return None;
+    }
+    let returns: Vec<f64> = candles.windows(2).filter(|w| w[0].close > 0.0).map(|w| w[1].close / w[0].close - 1.0).collect();
+    let (&latest, earlier) = returns.split_last()?;
+    let earlier = &earlier[earlier.len().saturating_sub(cfg.lookback_days)..];
+    if earlier.len() < MIN_RETURNS {
+        return None;
+    }
+    let n = earlier.len() as f64;
+    let mean = earlier.iter().sum::<f64>() / n;
+    let sd = (earlier.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    if sd <= 0.0 {
+        return None;
+    }
+    let sigma = (latest - mean) / sd;
+    let date = candles.last()?.date();
+    (sigma.abs() >= cfg.sigma).then(|| Anomaly { code: code.to_string(), date, change: latest * 100.0, sigma })
+}
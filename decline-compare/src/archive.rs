@@ -0,0 +1,146 @@
+//! Each day's report kept on disk, so past screens can be looked up with
+//! `history` rather than by saving stdout.
+//!
+//! Reports go to `$STOCK_DATA_DIR/reports` (`reports.PROFILE` under a
+//! profile), a pair of files per bar date: `2025-03-14.json`, the report
+//! as `--output json` prints it, and `2025-03-14.txt`, as text. A later run
+//! for the same date replaces both, so the one kept is the day's last,
+//! normally after the close. Reports older than `[archive] keep_days` are
+//! removed as new ones are written.
+
+use std::fs;
+use std::path::PathBuf;
+
+use chrono::{Duration, NaiveDate};
+use clap::Args;
+use serde_json::Value;
+
+use crate::config::{self, Config, OutputFormat};
+use crate::decline::{self, DeclineArgs, Report};
+use crate::i18n::tr;
+use crate::schedule;
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Dates to show, e.g. 2025-03-14 or 2025-03-* (? matches one
+    /// character); with none, lists the archived dates
+    pattern: Option<String>,
+}
+
+fn default_dir(profile: Option<&str>) -> PathBuf {
+    let name = match profile {
+        Some(name) => format!("reports.{}", name),
+        None => "reports".to_string(),
+    };
+    config::data_dir().join(name)
+}
+
+/// Archived bar dates, oldest first.
+pub fn dates(profile: Option<&str>) -> Vec<NaiveDate> {
+    let Ok(entries) = fs::read_dir(default_dir(profile)) else {
+        return Vec::new();
+    };
+    let mut dates: Vec<NaiveDate> = entries
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.strip_suffix(".json")?.parse().ok())
+        .collect();
+    dates.sort();
+    dates
+}
+
+/// The archived JSON report for `date`.
+pub fn read(profile: Option<&str>, date: NaiveDate) -> std::io::Result<String> {
+    fs::read_to_string(default_dir(profile).join(format!("{}.json", date)))
+}
+
+/// Whether `text` matches `pattern`, where `*` stands for any run of
+/// characters and `?` for one.
+fn matches(pattern: &[u8], text: &[u8]) -> bool {
+    match (pattern.split_first(), text.split_first()) {
+        (None, _) => text.is_empty(),
+        (Some((b'*', rest)), _) => matches(rest, text) || (!text.is_empty() && matches(pattern, &text[1..])),
+        (Some((&p, rest)), Some((&t, text))) => (p == b'?' || p == t) && matches(rest, text),
+        (Some(_), None) => false,
+    }
+}
+
+/// Archive `report` under its bar date and drop reports past retention.
+/// Failures are reported but don't fail the run.
+pub fn save(args: &DeclineArgs, cfg: &Config, report: &Report) {
+    let (Some(date), true, false) = (report.as_of, cfg.archive.enabled, cfg.dry_run) else {
+        return;
+    };
+    let dir = default_dir(cfg.profile.as_deref());
+    let written = fs::create_dir_all(&dir)
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_string_pretty(report).map_err(|e| e.to_string()))
+        .and_then(|json| fs::write(dir.join(format!("{}.json", date)), json).map_err(|e| e.to_string()))
+        .and_then(|()| {
+            let text = decline::render(args, cfg, report);
+            fs::write(dir.join(format!("{}.txt", date)), text).map_err(|e| e.to_string())
+        });
+    if let Err(e) = written {
+        eprintln!("{}", tr!("Failed to archive the report in {}: {}", "归档报告到 {} 失败: {}", dir.display(), e));
+        return;
+    }
+
+    if cfg.archive.keep_days == 0 {
+        return;
+    }
+    let cutoff = schedule::now().date_naive() - Duration::days(cfg.archive.keep_days.into());
+    for old in dates(cfg.profile.as_deref()).into_iter().take_while(|&d| d < cutoff) {
+        for ext in ["json", "txt"] {
+            let path = dir.join(format!("{}.{}", old, ext));
+            if let Err(e) = fs::remove_file(&path)
+                && e.kind() != std::io::ErrorKind::NotFound
+            {
+                eprintln!("{}", tr!("Failed to remove {}: {}", "删除 {} 失败: {}", path.display(), e));
+            }
+        }
+    }
+}
+
+pub fn run(args: &HistoryArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let profile = cfg.profile.as_deref();
+    let dir = default_dir(profile);
+    let all = dates(profile);
+
+    let Some(pattern) = &args.pattern else {
+        if cfg.output == OutputFormat::Json {
+            println!("{}", serde_json::to_string_pretty(&all)?);
+            return Ok(());
+        }
+        println!("\n {}", tr!("Archived reports in {}:", "{} 中的归档报告:", dir.display()));
+        println!("-----------------------------------------");
+        match (all.first(), all.last()) {
+            (Some(first), Some(last)) => {
+                println!("{}", tr!("{} reports, {} to {}", "共 {} 份, {} 至 {}", all.len(), first, last))
+            }
+            _ => println!("{}", tr!("No reports archived yet", "尚无归档报告")),
+        }
+        return Ok(());
+    };
+
+    let picked: Vec<NaiveDate> =
+        all.into_iter().filter(|d| matches(pattern.as_bytes(), d.to_string().as_bytes())).collect();
+    if picked.is_empty() {
+        return Err(tr!("No archived report matches {}", "没有匹配 {} 的归档报告", pattern).into());
+    }
+    if cfg.output == OutputFormat::Json {
+        let mut reports = Vec::new();
+        for &date in &picked {
+            let text = read(profile, date)?;
+            reports.push(serde_json::from_str::<Value>(&text)?);
+        }
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    for date in &picked {
+        println!("\n {}", tr!("Report for {}:", "{} 的报告:", date));
+        println!("=========================================");
+        match fs::read_to_string(dir.join(format!("{}.txt", date))) {
+            Ok(text) => println!("{}", text),
+            Err(e) => println!("{}", tr!("No text report: {}", "无文本报告: {}", e)),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,304 @@
+//! `backtest optimize`: replay the dip the screen looks for over daily
+//! history and grid-search the rule's parameters.
+//!
+//! The rule buys at the close when the decline over `--day` trading days
+//! (as the screen's `decline` metric measures it) reaches the threshold, and
+//! sells at the close once the holding period is up or the close falls to
+//! the stop below the entry. One position per code at a time, no fees.
+//! Every combination of `--thresholds`, `--holds` and `--stops` runs over
+//! every code in parallel; the table ranks them by average compounded
+//! return per code. `--heatmap` writes the best return for each threshold
+//! and holding period as CSV.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Args, Subcommand};
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::sina;
+use crate::symbols;
+
+/// Trading days per year, for turning `--years` into bars.
+const TRADING_DAYS_PER_YEAR: usize = 250;
+
+#[derive(Args, Debug)]
+pub struct BacktestArgs {
+    #[command(subcommand)]
+    command: BacktestCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum BacktestCommand {
+    /// Grid-search the decline threshold, holding period and stop over daily history
+    Optimize(OptimizeArgs),
+}
+
+#[derive(Args, Debug)]
+struct OptimizeArgs {
+    /// Codes to trade, e.g. 510300; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Trading days the decline is measured over, as in the screen
+    #[arg(long, default_value_t = 5)]
+    day: usize,
+
+    /// Declines to buy at, in percent
+    #[arg(long, value_delimiter = ',', default_values_t = [2.0, 3.0, 4.0, 5.0, 6.0, 8.0, 10.0])]
+    thresholds: Vec<f64>,
+
+    /// Trading days to hold before selling
+    #[arg(long, value_delimiter = ',', default_values_t = [5, 10, 20, 40, 60])]
+    holds: Vec<usize>,
+
+    /// Stop losses in percent below the entry; 0 for none
+    #[arg(long, value_delimiter = ',', default_values_t = [0.0, 5.0, 8.0, 12.0])]
+    stops: Vec<f64>,
+
+    /// Years of history to test over, as far as it reaches
+    #[arg(long, default_value_t = 5)]
+    years: u32,
+
+    /// Rows of the ranked table to show
+    #[arg(long, default_value_t = 20)]
+    top: usize,
+
+    /// Write the best return per threshold and holding period to this CSV file
+    #[arg(long)]
+    heatmap: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+struct Params {
+    /// Decline to buy at, percent.
+    threshold: f64,
+    /// Trading days held at most.
+    hold: usize,
+    /// Percent below the entry to sell at; 0 for none.
+    stop: f64,
+}
+
+/// One combination's results over every code.
+#[derive(Debug, Serialize)]
+struct Trial {
+    #[serde(flatten)]
+    params: Params,
+    trades: usize,
+    /// Share of trades that made money, percent.
+    win_rate: f64,
+    /// Mean return per trade, percent.
+    mean_trade: f64,
+    /// Compounded return per code, averaged over the codes, percent.
+    total_return: f64,
+}
+
+/// The returns, percent, of the trades `params` makes on `closes` (oldest
+/// first) with the decline measured over `day` closes, at least two. A
+/// trade still open at the end of the history is left out.
+fn simulate(closes: &[f64], day: usize, params: Params) -> Vec<f64> {
+    let mut trades = Vec::new();
+    let mut i = day - 1;
+    while i < closes.len() {
+        let (pre, entry) = (closes[i + 1 - day], closes[i]);
+        if pre <= 0.0 || entry <= 0.0 || (pre - entry) / pre * 100.0 < params.threshold {
+            i += 1;
+            continue;
+        }
+        let floor = entry * (1.0 - params.stop / 100.0);
+        let last = (i + params.hold).min(closes.len() - 1);
+        let stopped = (params.stop > 0.0).then(|| (i + 1..=last).find(|&j| closes[j] <= floor)).flatten();
+        let exit = match stopped {
+            Some(j) => j,
+            None if i + params.hold < closes.len() => i + params.hold,
+            None => break,
+        };
+        trades.push((closes[exit] - entry) / entry * 100.0);
+        i = exit + 1;
+    }
+    trades
+}
+
+fn trial(series: &[Vec<f64>], day: usize, params: Params) -> Trial {
+    let per_code: Vec<Vec<f64>> = series.iter().map(|closes| simulate(closes, day, params)).collect();
+    let all: Vec<f64> = per_code.iter().flatten().copied().collect();
+    let compounded = |trades: &Vec<f64>| (trades.iter().map(|r| 1.0 + r / 100.0).product::<f64>() - 1.0) * 100.0;
+    let mean = |values: &[f64]| if values.is_empty() { 0.0 } else { values.iter().sum::<f64>() / values.len() as f64 };
+    Trial {
+        params,
+        trades: all.len(),
+        win_rate: mean(&all.iter().map(|&r| if r > 0.0 { 100.0 } else { 0.0 }).collect::<Vec<_>>()),
+        mean_trade: mean(&all),
+        total_return: mean(&per_code.iter().map(compounded).collect::<Vec<_>>()),
+    }
+}
+
+/// Thresholds down, holding periods across, each cell the best total return
+/// over the stops.
+fn heatmap(trials: &[Trial], thresholds: &[f64], holds: &[usize]) -> String {
+    let mut csv = String::from("threshold");
+    for hold in holds {
+        let _ = write!(csv, ",{}", hold);
+    }
+    csv.push('\n');
+    for &threshold in thresholds {
+        let _ = write!(csv, "{}", threshold);
+        for &hold in holds {
+            let best = trials
+                .iter()
+                .filter(|t| t.params.threshold == threshold && t.params.hold == hold)
+                .map(|t| t.total_return)
+                .max_by(f64::total_cmp);
+            let _ = write!(csv, ",{}", best.map_or_else(String::new, |r| format!("{:.2}", r)));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+async fn optimize(args: &OptimizeArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if args.day < 2 {
+        return Err("--day must be at least 2".into());
+    }
+    if args.years == 0 {
+        return Err("--years must be positive".into());
+    }
+    if args.thresholds.iter().chain(&args.stops).any(|v| !v.is_finite() || *v < 0.0) {
+        return Err("--thresholds and --stops must be non-negative".into());
+    }
+    if args.holds.contains(&0) {
+        return Err("--holds must be at least 1 day".into());
+    }
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let len = args.years as usize * TRADING_DAYS_PER_YEAR;
+    let deadline = http::deadline(&cfg.http);
+
+    let mut series = Vec::new();
+    for code in &codes {
+        match http::until(deadline, sina::fetch_etf_kline(code, len)).await {
+            Some(Ok((candles, None | Some(200)))) if candles.len() > args.day => {
+                series.push(candles.iter().map(|c| c.close).collect::<Vec<f64>>());
+            }
+            Some(Ok((candles, None | Some(200)))) => {
+                eprintln!("{}", tr!("Not enough data for {}: got {} days", "{} 数据不足: 仅 {} 日", code, candles.len()))
+            }
+            Some(Ok((_, Some(status)))) => {
+                eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, code))
+            }
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch {}: {}", "获取 {} 失败: {}", code, e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+        }
+    }
+    if series.is_empty() {
+        return Err(tr!("No history to test on", "没有可回测的历史数据").into());
+    }
+
+    let grid: Vec<Params> = args
+        .thresholds
+        .iter()
+        .flat_map(|&threshold| {
+            args.holds.iter().flat_map(move |&hold| args.stops.iter().map(move |&stop| Params { threshold, hold, stop }))
+        })
+        .collect();
+    let mut trials: Vec<Trial> = grid.into_par_iter().map(|params| trial(&series, args.day, params)).collect();
+    trials.sort_by(|a, b| b.total_return.total_cmp(&a.total_return));
+
+    if let Some(path) = &args.heatmap {
+        fs::write(path, heatmap(&trials, &args.thresholds, &args.holds))
+            .map_err(|e| tr!("Failed to write {}: {}", "写入 {} 失败: {}", path.display(), e))?;
+    }
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&trials)?);
+        return Ok(());
+    }
+    println!(
+        "\n {}",
+        tr!(
+            "Best of {} combinations over {} codes, {}-day decline:",
+            "{} 种组合, {} 个代码, {} 日跌幅, 最佳结果:",
+            trials.len(),
+            series.len(),
+            args.day
+        )
+    );
+    println!("-----------------------------------------");
+    for (rank, t) in trials.iter().take(args.top).enumerate() {
+        let stop = if t.params.stop > 0.0 { format!("{}%", t.params.stop) } else { tr!("none", "无") };
+        println!(
+            "{}",
+            tr!(
+                "{:>3}. dip {:>4}% | hold {:>3}d | stop {:>4} | {:>+8.2}% | {:>4} trades, {:>3.0}% won, {:>+6.2}% each",
+                "{:>3}. 跌幅 {:>4}% | 持有 {:>3} 日 | 止损 {:>4} | {:>+8.2}% | {:>4} 笔, 胜率 {:>3.0}%, 每笔 {:>+6.2}%",
+                rank + 1,
+                t.params.threshold,
+                t.params.hold,
+                stop,
+                t.total_return,
+                t.trades,
+                t.win_rate,
+                t.mean_trade
+            )
+        );
+    }
+    if let Some(path) = &args.heatmap {
+        println!("{}", tr!("Heatmap written to {}", "热力图已写入 {}", path.display()));
+    }
+    Ok(())
+}
+
+pub async fn run(args: &BacktestArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.command {
+        BacktestCommand::Optimize(args) => optimize(args, cfg).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(threshold: f64, hold: usize, stop: f64) -> Params {
+        Params { threshold, hold, stop }
+    }
+
+    #[test]
+    fn buys_the_dip_and_sells_after_the_holding_period() {
+        // A 5% fall over 3 closes, then a climb.
+        let closes = [100.0, 98.0, 95.0, 96.0, 97.0, 99.0, 99.0];
+        let trades = simulate(&closes, 3, params(5.0, 3, 0.0));
+        assert_eq!(trades.len(), 1);
+        assert!((trades[0] - (99.0 / 95.0 - 1.0) * 100.0).abs() < 1e-9);
+        // A deeper threshold never buys.
+        assert!(simulate(&closes, 3, params(6.0, 3, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn the_stop_sells_early() {
+        let closes = [100.0, 98.0, 95.0, 92.0, 89.0, 99.0, 99.0];
+        let trades = simulate(&closes, 3, params(5.0, 4, 5.0));
+        assert_eq!(trades.len(), 1);
+        assert!((trades[0] - (89.0 / 95.0 - 1.0) * 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn holds_one_position_and_drops_the_open_one() {
+        // Every close is a dip; each trade starts the day after the last sold.
+        let closes = [100.0, 90.0, 81.0, 72.9, 65.6, 59.0];
+        assert_eq!(simulate(&closes, 2, params(5.0, 1, 0.0)).len(), 2);
+        // Bought on the last dip with no close left to sell at.
+        assert!(simulate(&closes[..3], 2, params(5.0, 5, 0.0)).is_empty());
+    }
+
+    #[test]
+    fn heatmap_keeps_the_best_stop() {
+        let series = vec![vec![100.0, 98.0, 95.0, 92.0, 89.0, 99.0, 99.0]];
+        let trials: Vec<Trial> =
+            [0.0, 5.0].iter().map(|&stop| trial(&series, 3, params(5.0, 4, stop))).collect();
+        let csv = heatmap(&trials, &[5.0, 8.0], &[4]);
+        assert_eq!(csv, format!("threshold,4\n5,{:.2}\n8,\n", (99.0 / 95.0 - 1.0) * 100.0));
+    }
+}
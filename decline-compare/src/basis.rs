@@ -0,0 +1,212 @@
+//! Futures curves behind commodity ETFs: whether the curve is in contango or
+//! backwardation, and how close the front contract is to being rolled.
+//!
+//! Commodity ETFs that hold futures lose the contango between contracts on
+//! every roll (or earn the backwardation), so a decline in a steep contango
+//! market partly reflects carry rather than a cheaper commodity. Gold ETFs
+//! hold bullion; their curve still shows the carry priced into gold.
+//!
+//! Contracts come from hq.sinajs.cn's domestic futures feed. Which product
+//! backs which ETF is built in for common funds and set per code under
+//! `[instruments.futures]`.
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+use clap::Args;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina;
+use crate::symbols;
+
+/// Commodity ETFs and LOFs and the futures product each tracks: soybean
+/// meal (DCE), gold and silver (SHFE).
+const PRODUCTS: &[(&str, &str)] = &[
+    ("159985", "M"),
+    ("518880", "AU"),
+    ("518800", "AU"),
+    ("159934", "AU"),
+    ("159937", "AU"),
+    ("161226", "AG"),
+];
+
+/// Contract months ahead of today looked up for the curve.
+const CURVE_MONTHS: u32 = 12;
+
+/// Fund managers roll out of the front contract roughly this many days
+/// before its delivery month starts.
+const ROLL_LEAD_DAYS: i64 = 20;
+
+/// Contracts trading fewer lots than this a day are too thin to price the
+/// curve from.
+const MIN_VOLUME: f64 = 100.0;
+
+#[derive(Args, Debug)]
+pub struct BasisArgs {
+    /// Codes to show; defaults to the commodity ETFs in the watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Contract {
+    pub symbol: String,
+    /// First day of the delivery month.
+    pub delivery: NaiveDate,
+    pub price: f64,
+    pub volume: f64,
+    pub open_interest: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Curve {
+    pub code: String,
+    pub product: String,
+    /// Liquid contracts, nearest first.
+    pub contracts: Vec<Contract>,
+    /// Annualized change from the front to the most held later contract,
+    /// percent; positive is contango.
+    pub roll_yield: Option<f64>,
+    /// Suggested roll date off the front contract and the days until it.
+    pub roll_date: Option<NaiveDate>,
+    pub days_to_roll: Option<i64>,
+}
+
+/// The futures product behind `code`, from the config if set there.
+pub fn product(code: &str, cfg: &Config) -> Option<String> {
+    cfg.instruments
+        .futures
+        .get(code)
+        .cloned()
+        .or_else(|| PRODUCTS.iter().find(|(c, _)| *c == code).map(|(_, p)| p.to_string()))
+}
+
+/// Sina symbol for `product` delivering in `delivery`'s month, e.g. `nf_M2601`.
+fn symbol(product: &str, delivery: NaiveDate) -> String {
+    format!("nf_{}{:02}{:02}", product, delivery.year() % 100, delivery.month())
+}
+
+/// Fields are name, time, open, high, low, prev close, bid, ask, last,
+/// settle, prev settle, bid size, ask size, open interest, volume, ...
+fn contract(symbol: &str, delivery: NaiveDate, fields: &[String]) -> Option<Contract> {
+    let num = |i: usize| fields.get(i)?.parse::<f64>().ok().filter(|v| *v > 0.0);
+    Some(Contract {
+        symbol: symbol.trim_start_matches("nf_").to_string(),
+        delivery,
+        price: num(8).or_else(|| num(10))?,
+        volume: num(14).unwrap_or(0.0),
+        open_interest: num(13).unwrap_or(0.0),
+    })
+}
+
+/// Liquid contracts of `product` delivering over the next `CURVE_MONTHS`.
+pub async fn fetch_curve(product: &str, today: NaiveDate) -> Result<Vec<Contract>, Box<dyn std::error::Error>> {
+    let first = today.with_day(1).unwrap_or(today);
+    let months: Vec<NaiveDate> = (0..=CURVE_MONTHS).filter_map(|m| first.checked_add_months(Months::new(m))).collect();
+    let symbols: Vec<String> = months.iter().map(|&d| symbol(product, d)).collect();
+    let refs: Vec<&str> = symbols.iter().map(String::as_str).collect();
+    let hq = sina::fetch_hq(&refs).await?;
+    Ok(symbols
+        .iter()
+        .zip(&months)
+        .filter_map(|(s, &d)| contract(s, d, hq.get(s)?))
+        .filter(|c| c.volume >= MIN_VOLUME)
+        .collect())
+}
+
+/// Roll yield and roll timing for a curve, nearest contract first.
+pub fn analyze(code: &str, product: &str, contracts: Vec<Contract>, today: NaiveDate) -> Curve {
+    let front = contracts.first();
+    // Compare against the most held later contract, which is where the
+    // fund rolls to, rather than whatever month happens to be next.
+    let target = contracts.iter().skip(1).max_by(|a, b| a.open_interest.total_cmp(&b.open_interest));
+    let roll_yield = front.zip(target).and_then(|(f, t)| {
+        let months = (t.delivery.year() - f.delivery.year()) * 12 + t.delivery.month() as i32 - f.delivery.month() as i32;
+        (months > 0).then(|| (t.price / f.price - 1.0) * 12.0 / months as f64 * 100.0)
+    });
+    let roll_date = front.map(|f| f.delivery - Duration::days(ROLL_LEAD_DAYS));
+    Curve {
+        code: code.to_string(),
+        product: product.to_string(),
+        roll_yield,
+        roll_date,
+        days_to_roll: roll_date.map(|d| (d - today).num_days()),
+        contracts,
+    }
+}
+
+fn print_curve(curve: &Curve) {
+    println!("\n {}", tr!("Futures curve for {} ({}):", "{} 期货曲线 ({}):", curve.code, curve.product));
+    println!("-----------------------------------------");
+    if curve.contracts.is_empty() {
+        println!("{}", tr!("No liquid contracts quoted", "无活跃合约报价"));
+        return;
+    }
+    for c in &curve.contracts {
+        println!(
+            "{}",
+            tr!(
+                "{} | Price: {:.2} | Volume: {:.0} | Open interest: {:.0}",
+                "{} | 价格: {:.2} | 成交量: {:.0} | 持仓量: {:.0}",
+                c.symbol,
+                c.price,
+                c.volume,
+                c.open_interest
+            )
+        );
+    }
+    match curve.roll_yield {
+        Some(y) if y > 0.0 => {
+            println!("{}", tr!("Contango: {:+.2}% a year lost rolling", "升水结构: 移仓年化损耗 {:+.2}%", y))
+        }
+        Some(y) => println!("{}", tr!("Backwardation: {:+.2}% a year earned rolling", "贴水结构: 移仓年化收益 {:+.2}%", -y)),
+        None => println!("{}", tr!("Curve shape: n/a (one liquid contract)", "曲线结构: n/a (仅一个活跃合约)")),
+    }
+    if let (Some(date), Some(days)) = (curve.roll_date, curve.days_to_roll) {
+        let when = if days < 0 { tr!("due now", "已到期") } else { tr!("in {} days", "{} 日后", days) };
+        println!("{}", tr!("Front contract roll: around {} ({})", "主力移仓: 约 {} ({})", date, when));
+    }
+}
+
+pub async fn run(args: &BasisArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes: Vec<String> = if args.codes.is_empty() {
+        cfg.watchlist.iter().filter(|c| product(c, cfg).is_some()).cloned().collect()
+    } else {
+        args.codes.clone()
+    };
+    let today = schedule::now().date_naive();
+    let deadline = http::deadline(&cfg.http);
+    let mut curves = Vec::new();
+    for code in &codes {
+        let Some(product) = product(code, cfg) else {
+            eprintln!(
+                "{}",
+                tr!(
+                    "No futures product known for {}; set one under [instruments.futures]",
+                    "{} 无对应期货品种, 请在 [instruments.futures] 中配置",
+                    code
+                )
+            );
+            continue;
+        };
+        match http::until(deadline, fetch_curve(&product, today)).await {
+            Some(Ok(contracts)) => curves.push(analyze(code, &product, contracts, today)),
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch futures for {}: {}", "获取 {} 期货行情失败: {}", code, e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+        }
+    }
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&curves)?);
+        return Ok(());
+    }
+    if curves.is_empty() && codes.is_empty() {
+        println!("{}", tr!("No commodity ETFs in the watchlist", "自选列表中没有商品 ETF"));
+    }
+    for curve in &curves {
+        print_curve(curve);
+    }
+    Ok(())
+}
@@ -0,0 +1,167 @@
+//! Error rate and latency per data source, and a circuit breaker over each.
+//!
+//! After `TRIP_AFTER` failures in a row a source's breaker opens for
+//! `OPEN_SECS`: callers that have a fallback send their requests there
+//! instead, the rest fail fast. Once the time is up the next request probes
+//! the source again; a success closes the breaker, a failure reopens it.
+//!
+//! The counters live in `$STOCK_DATA_DIR/sources.json`, read on first use
+//! and written when a command finishes (and after every watch pass), so
+//! short cron runs share what earlier ones saw and `doctor` can show it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::cooldown;
+use crate::i18n::tr;
+use crate::replay;
+
+const STATE_FILE: &str = "sources.json";
+
+/// Failures in a row that open a breaker.
+const TRIP_AFTER: u32 = 5;
+
+/// How long an open breaker keeps traffic away.
+const OPEN_SECS: u64 = 5 * 60;
+
+/// Weight of the newest request in the latency average.
+const LATENCY_WEIGHT: f64 = 0.2;
+
+pub const SINA_KLINES: &str = "sina klines";
+pub const SINA_QUOTES: &str = "sina quotes";
+pub const EASTMONEY_KLINES: &str = "eastmoney klines";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SourceHealth {
+    pub requests: u64,
+    pub failures: u64,
+    /// Failures since the last success.
+    pub consecutive: u32,
+    /// Moving average of request time, milliseconds.
+    pub latency_ms: f64,
+    /// Unix seconds an open breaker stays open until.
+    pub open_until: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Closed,
+    /// Tripped; no requests until the given Unix time.
+    Open(u64),
+    /// Tripped, but the wait is over and the next request is a probe.
+    HalfOpen,
+}
+
+impl SourceHealth {
+    pub fn error_rate(&self) -> f64 {
+        if self.requests == 0 { 0.0 } else { self.failures as f64 / self.requests as f64 }
+    }
+
+    pub fn state(&self, now: u64) -> State {
+        match self.open_until {
+            Some(until) if now < until => State::Open(until),
+            Some(_) => State::HalfOpen,
+            None => State::Closed,
+        }
+    }
+}
+
+static SOURCES: OnceLock<Mutex<BTreeMap<String, SourceHealth>>> = OnceLock::new();
+
+fn default_path() -> PathBuf {
+    config::data_dir().join(STATE_FILE)
+}
+
+fn sources() -> &'static Mutex<BTreeMap<String, SourceHealth>> {
+    SOURCES.get_or_init(|| {
+        let saved = fs::read_to_string(default_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Mutex::new(saved)
+    })
+}
+
+/// Whether `source` may be sent a request now.
+pub fn available(source: &str) -> bool {
+    let now = cooldown::now_secs();
+    sources()
+        .lock()
+        .map(|s| s.get(source).is_none_or(|h| !matches!(h.state(now), State::Open(_))))
+        .unwrap_or(true)
+}
+
+/// Count a request to `source` begun at `started`, failed with `error` if
+/// it did.
+pub fn record(source: &str, started: Instant, error: Option<String>) {
+    // Replayed responses say nothing about how the source is doing now.
+    if replay::replaying() {
+        return;
+    }
+    let Ok(mut sources) = sources().lock() else {
+        return;
+    };
+    let health = sources.entry(source.to_string()).or_default();
+    let elapsed = started.elapsed().as_secs_f64() * 1000.0;
+    health.latency_ms = if health.requests == 0 {
+        elapsed
+    } else {
+        LATENCY_WEIGHT * elapsed + (1.0 - LATENCY_WEIGHT) * health.latency_ms
+    };
+    health.requests += 1;
+    match error {
+        None => {
+            health.consecutive = 0;
+            health.open_until = None;
+        }
+        Some(error) => {
+            health.failures += 1;
+            health.consecutive += 1;
+            health.last_error = Some(error);
+            if health.consecutive >= TRIP_AFTER && !matches!(health.state(cooldown::now_secs()), State::Open(_)) {
+                health.open_until = Some(cooldown::now_secs() + OPEN_SECS);
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "{} failed {} times in a row; avoiding it for {} minutes",
+                        "{} 连续失败 {} 次, 暂停使用 {} 分钟",
+                        source,
+                        health.consecutive,
+                        OPEN_SECS / 60
+                    )
+                );
+            }
+        }
+    }
+}
+
+/// Every source seen so far, by name.
+pub fn snapshot() -> BTreeMap<String, SourceHealth> {
+    sources().lock().map(|s| s.clone()).unwrap_or_default()
+}
+
+/// Write the counters out, if anything used them this run.
+pub fn save() {
+    let Some(sources) = SOURCES.get() else {
+        return;
+    };
+    let Ok(sources) = sources.lock() else {
+        return;
+    };
+    let path = default_path();
+    let saved = fs::create_dir_all(config::data_dir())
+        .map_err(|e| e.to_string())
+        .and_then(|_| serde_json::to_string_pretty(&*sources).map_err(|e| e.to_string()))
+        .and_then(|text| fs::write(&path, text).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        eprintln!("{}", tr!("Failed to save source health to {}: {}", "保存数据源状态到 {} 失败: {}", path.display(), e));
+    }
+}
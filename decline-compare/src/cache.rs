@@ -0,0 +1,538 @@
+//! Daily bars saved once a session has closed, so re-running the screen
+//! before the next open is served without touching the network.
+//!
+//! Each code's bars are stored as columns of small integers: times as
+//! offsets from the bar before, and prices and volume as changes from the
+//! bar before in the finest decimal unit the series uses. That keeps
+//! years of bars for a long watchlist to a fraction of plain JSON, without
+//! rounding anything.
+//!
+//! `cache prune` trims this file and the history store to the `[cache]`
+//! retention, so a long-running server's data directory stays bounded.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use clap::{Args, Subcommand};
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, OutputFormat};
+use crate::fixture;
+use crate::history;
+use crate::i18n::tr;
+use crate::replay;
+use crate::schedule;
+use crate::sina::Candle;
+
+const CACHE_FILE: &str = "kline_cache.json";
+
+/// Most decimals a column is scaled by; finer digits are rounded off.
+const MAX_DECIMALS: u32 = 8;
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct KlineCache {
+    /// Unix seconds when the bars were fetched.
+    written_at: u64,
+    /// Bars requested per code.
+    datalen: usize,
+    #[serde(with = "columns")]
+    bars: BTreeMap<String, Vec<Candle>>,
+}
+
+pub fn default_path() -> PathBuf {
+    config::cache_dir().join(CACHE_FILE)
+}
+
+/// Whether runs leave the cache alone: fixtures and recordings bring their
+/// own bars, and a recording has to see every request.
+pub fn bypassed() -> bool {
+    fixture::active() || replay::active()
+}
+
+impl KlineCache {
+    /// The saved bars if no session has opened since they were fetched and
+    /// they are at least `datalen` long; otherwise an empty cache.
+    pub fn load(path: &Path, datalen: usize, now: u64) -> KlineCache {
+        Some(KlineCache::read(path))
+            .filter(|cache| cache.datalen >= datalen && now < schedule::next_open(cache.written_at))
+            .unwrap_or_default()
+    }
+
+    /// Whatever is saved at `path`, however old.
+    pub fn read(path: &Path) -> KlineCache {
+        fs::read_to_string(path).ok().and_then(|text| serde_json::from_str(&text).ok()).unwrap_or_default()
+    }
+
+    /// Unix seconds the bars were fetched.
+    pub fn written_at(&self) -> u64 {
+        self.written_at
+    }
+
+    /// The last `datalen` cached bars for `code`.
+    pub fn bars(&self, code: &str, datalen: usize) -> Option<Vec<Candle>> {
+        let bars = self.bars.get(code)?;
+        Some(bars[bars.len().saturating_sub(datalen)..].to_vec())
+    }
+
+    pub fn save(
+        path: &Path,
+        datalen: usize,
+        bars: BTreeMap<String, Vec<Candle>>,
+        now: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let cache = KlineCache { written_at: now, datalen, bars };
+        config::write_atomic(path, &serde_json::to_string(&cache)?)?;
+        Ok(())
+    }
+}
+
+/// Whether `text` is a cache written before the bars were delta-encoded,
+/// as plain bars per code. It reads as empty and is replaced on the next
+/// save.
+pub fn plain_format(text: &str) -> bool {
+    #[derive(Deserialize)]
+    #[allow(dead_code)]
+    struct Plain {
+        bars: BTreeMap<String, Vec<Candle>>,
+    }
+    serde_json::from_str::<Plain>(text).is_ok()
+}
+
+/// Delta-encoded columns for `KlineCache::bars`.
+mod columns {
+    use super::*;
+
+    use serde::{Deserializer, Serializer, ser};
+
+    /// One series, `values[i] = values[i - 1] + delta[i]` in units of
+    /// `10^-decimals`.
+    #[derive(Serialize, Deserialize)]
+    struct Column {
+        decimals: u32,
+        delta: Vec<i64>,
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Columns {
+        /// First bar's time; the rest as seconds after the bar before.
+        start: NaiveDateTime,
+        time: Vec<i64>,
+        open: Column,
+        high: Column,
+        low: Column,
+        close: Column,
+        volume: Column,
+    }
+
+    /// The fewest decimals that keep every value exact.
+    fn decimals(values: &[f64]) -> u32 {
+        (0..MAX_DECIMALS)
+            .find(|&d| {
+                let scale = 10f64.powi(d as i32);
+                values.iter().all(|&v| (v * scale).round() / scale == v)
+            })
+            .unwrap_or(MAX_DECIMALS)
+    }
+
+    /// `values` as a column; an error for a value that isn't finite or
+    /// doesn't fit the integer units, which would otherwise be stored as 0
+    /// or clamped and read back as a price.
+    fn encode(values: Vec<f64>) -> Result<Column, String> {
+        let decimals = decimals(&values);
+        let scale = 10f64.powi(decimals as i32);
+        let mut last: i64 = 0;
+        let delta = values
+            .iter()
+            .map(|&v| {
+                let units = (v * scale).round();
+                // i64::MAX as f64 rounds up to 2^63, which no longer fits.
+                if !units.is_finite() || units.abs() >= i64::MAX as f64 {
+                    return Err(format!("{} doesn't fit the cache", v));
+                }
+                let units = units as i64;
+                let d = units.checked_sub(last).ok_or_else(|| format!("{} doesn't fit the cache", v))?;
+                last = units;
+                Ok(d)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Column { decimals, delta })
+    }
+
+    fn decode(column: &Column) -> Vec<f64> {
+        let scale = 10f64.powi(column.decimals as i32);
+        let mut units = 0;
+        column
+            .delta
+            .iter()
+            .map(|&d| {
+                units += d;
+                units as f64 / scale
+            })
+            .collect()
+    }
+
+    pub fn serialize<S: Serializer>(bars: &BTreeMap<String, Vec<Candle>>, s: S) -> Result<S::Ok, S::Error> {
+        let mut encoded: BTreeMap<&String, Columns> = BTreeMap::new();
+        for (code, candles) in bars {
+            let Some(start) = candles.first().map(|c| c.time) else {
+                continue;
+            };
+            let mut previous = start;
+            let time = candles
+                .iter()
+                .map(|c| {
+                    let d = (c.time - previous).num_seconds();
+                    previous = c.time;
+                    d
+                })
+                .collect();
+            let column = |f: fn(&Candle) -> f64| {
+                encode(candles.iter().map(f).collect()).map_err(|e| ser::Error::custom(format!("{}: {}", code, e)))
+            };
+            let columns = Columns {
+                start,
+                time,
+                open: column(|c| c.open)?,
+                high: column(|c| c.high)?,
+                low: column(|c| c.low)?,
+                close: column(|c| c.close)?,
+                volume: column(|c| c.volume)?,
+            };
+            encoded.insert(code, columns);
+        }
+        encoded.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<BTreeMap<String, Vec<Candle>>, D::Error> {
+        let encoded = BTreeMap::<String, Columns>::deserialize(d)?;
+        Ok(encoded
+            .into_iter()
+            .map(|(code, c)| {
+                let mut time = c.start;
+                let times = c.time.iter().map(|&d| {
+                    time += chrono::Duration::seconds(d);
+                    time
+                });
+                let (open, high, low, close, volume) =
+                    (decode(&c.open), decode(&c.high), decode(&c.low), decode(&c.close), decode(&c.volume));
+                let candles = times
+                    .enumerate()
+                    .filter_map(|(i, time)| {
+                        Some(Candle {
+                            time,
+                            open: *open.get(i)?,
+                            high: *high.get(i)?,
+                            low: *low.get(i)?,
+                            close: *close.get(i)?,
+                            volume: *volume.get(i)?,
+                        })
+                    })
+                    .collect();
+                (code, candles)
+            })
+            .collect())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct CacheArgs {
+    #[command(subcommand)]
+    command: CacheCommand,
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheCommand {
+    /// Sizes and contents of the kline cache, the history store and the rest
+    /// of the data directory
+    Stats,
+    /// Drop bars older than the `[cache]` retention, and the oldest history
+    /// beyond `max_mb`
+    Prune {
+        /// Count what would go without removing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct StoreStats {
+    name: String,
+    path: String,
+    bytes: u64,
+    codes: usize,
+    bars: usize,
+    /// Newest bar or write time, when known.
+    updated: Option<String>,
+    /// What the same bars take as plain JSON, where that applies.
+    #[serde(skip_serializing_if = "is_zero")]
+    plain_bytes: u64,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |m| m.len())
+}
+
+fn cache_stats() -> StoreStats {
+    let path = default_path();
+    let cache = KlineCache::read(&path);
+    StoreStats {
+        name: tr!("kline cache", "K线缓存"),
+        path: path.display().to_string(),
+        bytes: file_size(&path),
+        codes: cache.bars.len(),
+        bars: cache.bars.values().map(Vec::len).sum(),
+        updated: (cache.written_at > 0).then(|| schedule::at(cache.written_at).format("%Y-%m-%d %H:%M").to_string()),
+        plain_bytes: serde_json::to_string(&cache.bars).map_or(0, |text| text.len() as u64),
+    }
+}
+
+fn history_stats() -> Result<StoreStats, Box<dyn std::error::Error>> {
+    let path = history::default_db_path();
+    let mut stats = StoreStats {
+        name: tr!("history", "历史数据"),
+        path: path.display().to_string(),
+        // The write-ahead log holds pages not yet merged into the file.
+        bytes: history::disk_size(&path),
+        codes: 0,
+        bars: 0,
+        updated: None,
+        plain_bytes: 0,
+    };
+    if path.exists() {
+        let conn = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let (codes, bars, updated): (i64, i64, Option<String>) =
+            conn.query_row("SELECT COUNT(DISTINCT code), COUNT(*), MAX(date) FROM bars", [], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+            })?;
+        stats.codes = codes as usize;
+        stats.bars = bars as usize;
+        stats.updated = updated;
+    }
+    Ok(stats)
+}
+
+#[derive(Debug, Serialize)]
+struct CacheStats {
+    stores: Vec<StoreStats>,
+    /// Everything in the data directory, bytes.
+    total_bytes: u64,
+    files: usize,
+}
+
+fn human(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+pub fn run(args: &CacheArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match args.command {
+        CacheCommand::Stats => stats(cfg),
+        CacheCommand::Prune { dry_run } => prune(cfg, dry_run),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct PruneRow {
+    name: String,
+    removed: usize,
+    bytes_before: u64,
+    bytes_after: u64,
+}
+
+/// Drop cached bars older than `daily_before`, or `minute_before` for bars
+/// not stamped at the close; returns how many went.
+fn prune_cache(path: &Path, daily_before: NaiveDate, minute_before: NaiveDate, dry_run: bool) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut cache = KlineCache::read(path);
+    let mut removed = 0;
+    for candles in cache.bars.values_mut() {
+        let len = candles.len();
+        candles.retain(|c| {
+            let before = if c.time.time() == schedule::SESSION_CLOSE { daily_before } else { minute_before };
+            c.date() >= before
+        });
+        removed += len - candles.len();
+    }
+    cache.bars.retain(|_, candles| !candles.is_empty());
+    if removed > 0 && !dry_run {
+        KlineCache::save(path, cache.datalen, cache.bars, cache.written_at)?;
+    }
+    Ok(removed)
+}
+
+fn prune(cfg: &Config, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let retention = &cfg.cache;
+    let today = schedule::now().date_naive();
+    let daily_before = today.with_year(today.year() - retention.daily_years as i32).unwrap_or(today);
+    let minute_before = today - chrono::Duration::days(retention.minute_days as i64);
+
+    let cache_path = default_path();
+    let before = file_size(&cache_path);
+    let removed = prune_cache(&cache_path, daily_before, minute_before, dry_run)?;
+    let mut rows = vec![PruneRow { name: tr!("kline cache", "K线缓存"), removed, bytes_before: before, bytes_after: file_size(&cache_path) }];
+
+    let history_path = history::default_db_path();
+    if history_path.exists() {
+        let before = history::disk_size(&history_path);
+        let max_bytes = retention.max_mb.map(|mb| mb << 20);
+        let removed = history::History::open(&history_path)?.prune(&history_path, daily_before, max_bytes, dry_run)?;
+        rows.push(PruneRow { name: tr!("history", "历史数据"), removed, bytes_before: before, bytes_after: history::disk_size(&history_path) });
+    }
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    let heading = if dry_run { tr!("Would prune (dry run):", "将清理 (试运行):") } else { tr!("Pruned:", "已清理:") };
+    println!("\n {}", heading);
+    println!("-----------------------------------------");
+    println!(
+        "{}",
+        tr!(
+            "Keeping daily bars from {} and minute bars from {}",
+            "保留 {} 起的日K线和 {} 起的分钟K线",
+            daily_before,
+            minute_before
+        )
+    );
+    for row in &rows {
+        println!(
+            "{}",
+            tr!(
+                "{}: {} bars | {} -> {}",
+                "{}: {} 根K线 | {} -> {}",
+                row.name,
+                row.removed,
+                human(row.bytes_before),
+                human(row.bytes_after)
+            )
+        );
+    }
+    Ok(())
+}
+
+fn stats(cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = config::data_dir();
+    let sizes: Vec<u64> = fs::read_dir(&dir)
+        .map(|entries| entries.flatten().filter_map(|e| e.metadata().ok()).filter(|m| m.is_file()).map(|m| m.len()).collect())
+        .unwrap_or_default();
+    let stats = CacheStats { stores: vec![cache_stats(), history_stats()?], total_bytes: sizes.iter().sum(), files: sizes.len() };
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
+    println!("\n {}", tr!("Cache ({}):", "缓存 ({}):", dir.display()));
+    println!("-----------------------------------------");
+    for store in &stats.stores {
+        let per_bar = if store.bars > 0 { format!("{:.1}", store.bytes as f64 / store.bars as f64) } else { "n/a".to_string() };
+        println!(
+            "{}",
+            tr!(
+                "{}: {} | Codes: {} | Bars: {} | Bytes/bar: {} | Updated: {}",
+                "{}: {} | 代码: {} | K线: {} | 每根字节: {} | 更新: {}",
+                store.name,
+                human(store.bytes),
+                store.codes,
+                store.bars,
+                per_bar,
+                store.updated.as_deref().unwrap_or("n/a")
+            )
+        );
+        if store.plain_bytes > 0 {
+            println!(
+                "{}",
+                tr!(
+                    "  {:.0}% of the {} the same bars take as plain JSON",
+                    "  为纯JSON的 {:.0}% (同样K线存为纯JSON占 {})",
+                    store.bytes as f64 / store.plain_bytes as f64 * 100.0,
+                    human(store.plain_bytes)
+                )
+            );
+        }
+    }
+    println!("{}", tr!("Data directory: {} in {} files", "数据目录: {} 个文件共 {}", human(stats.total_bytes), stats.files));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candle(day: u32, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Candle {
+        let time = NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_time(schedule::SESSION_CLOSE);
+        Candle { time, open, high, low, close, volume }
+    }
+
+    fn cache(bars: Vec<(&str, Vec<Candle>)>) -> KlineCache {
+        let bars = bars.into_iter().map(|(code, candles)| (code.to_string(), candles)).collect();
+        KlineCache { written_at: 1_710_000_000, datalen: 3, bars }
+    }
+
+    #[test]
+    fn columns_round_trip_exactly() {
+        // As Sina serves them: ETFs to three decimals, indices to two,
+        // volumes in shares.
+        let etf = vec![
+            candle(4, 3.512, 3.538, 3.497, 3.531, 812_345_600.0),
+            candle(5, 3.530, 3.530, 3.468, 3.472, 1_034_229_900.0),
+            candle(6, 3.475, 3.501, 3.461, 3.499, 698_112_300.0),
+        ];
+        let index = vec![
+            candle(4, 3046.02, 3053.15, 3038.19, 3047.79, 35_305_567_900.0),
+            candle(5, 3040.92, 3049.96, 3023.33, 3047.79, 41_117_954_500.0),
+        ];
+        let original = cache(vec![("sh510300", etf), ("sh000001", index)]);
+        let text = serde_json::to_string(&original).unwrap();
+        let read: KlineCache = serde_json::from_str(&text).unwrap();
+        assert_eq!(read.written_at, original.written_at);
+        assert_eq!(read.datalen, original.datalen);
+        for (code, candles) in &original.bars {
+            let back = &read.bars[code];
+            assert_eq!(back.len(), candles.len());
+            for (a, b) in candles.iter().zip(back) {
+                assert_eq!(a.time, b.time);
+                assert_eq!(
+                    (a.open, a.high, a.low, a.close, a.volume),
+                    (b.open, b.high, b.low, b.close, b.volume),
+                    "{}",
+                    code
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn columns_reject_non_finite_values() {
+        let bars = vec![candle(4, 3.512, 3.538, 3.497, f64::NAN, 812_345_600.0)];
+        assert!(serde_json::to_string(&cache(vec![("sh510300", bars)])).is_err());
+    }
+
+    #[test]
+    fn columns_reject_values_out_of_range() {
+        // Volume in whole units that no i64 holds.
+        let bars = vec![candle(4, 3.512, 3.538, 3.497, 3.531, 1e19)];
+        assert!(serde_json::to_string(&cache(vec![("sh510300", bars)])).is_err());
+        // Each fits, but not the step from one to the next.
+        let bars = vec![candle(4, 3.5, 3.5, 3.5, 3.5, -9e18), candle(5, 3.5, 3.5, 3.5, 3.5, 9e18)];
+        assert!(serde_json::to_string(&cache(vec![("sh510300", bars)])).is_err());
+    }
+
+    #[test]
+    fn plain_format_is_recognised() {
+        let plain = r#"{"written_at":1,"datalen":1,"bars":{"sh510300":[{"time":"2024-03-04T15:00:00",
+            "open":3.512,"high":3.538,"low":3.497,"close":3.531,"volume":812345600.0}]}}"#;
+        assert!(plain_format(plain));
+        let text = serde_json::to_string(&cache(vec![("sh510300", vec![candle(4, 1.0, 1.0, 1.0, 1.0, 1.0)])])).unwrap();
+        assert!(!plain_format(&text));
+    }
+}
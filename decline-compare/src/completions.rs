@@ -0,0 +1,46 @@
+use clap::builder::PossibleValuesParser;
+use clap::{Args, Command, CommandFactory};
+use clap_complete::Shell;
+use decline_compare::config::Config;
+
+use crate::Cli;
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    shell: Shell,
+}
+
+/// Print a completion script. Arguments that take codes, and pairs of them,
+/// complete from the watchlist configured when the script is generated, so
+/// regenerate it after changing the watchlist.
+pub fn run(args: &CompletionsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes = &cfg.watchlist;
+    let pairs: Vec<String> = codes
+        .iter()
+        .flat_map(|a| codes.iter().filter(move |b| *b != a).map(move |b| format!("{}/{}", a, b)))
+        .collect();
+    let mut cmd = complete(Cli::command(), codes, &pairs);
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(args.shell, &mut cmd, name, &mut std::io::stdout());
+    Ok(())
+}
+
+/// Offer `codes` for every `codes` argument and `pairs` for every `pair`,
+/// in `cmd` and all its subcommands.
+fn complete(mut cmd: Command, codes: &[String], pairs: &[String]) -> Command {
+    let ids: Vec<String> = cmd.get_arguments().map(|arg| arg.get_id().to_string()).collect();
+    for id in ids {
+        let values = match id.as_str() {
+            "codes" => codes.to_vec(),
+            "pair" => pairs.to_vec(),
+            _ => continue,
+        };
+        cmd = cmd.mut_arg(id, |arg| arg.value_parser(PossibleValuesParser::new(values)));
+    }
+    let names: Vec<String> = cmd.get_subcommands().map(|sub| sub.get_name().to_string()).collect();
+    for name in names {
+        cmd = cmd.mut_subcommand(name, |sub| complete(sub, codes, pairs));
+    }
+    cmd
+}
@@ -0,0 +1,1028 @@
+//! Layered settings: built-in defaults < config file < `BIGA_*` environment
+//! variables < command-line flags.
+//!
+//! The file is TOML. Environment variables use `__` to reach nested keys, so
+//! `BIGA_HTTP__TIMEOUT_SECS=5` sets `[http] timeout_secs = 5`. The settings a
+//! container most often needs also have flat, comma-separated forms (see
+//! `FLAT_ENV`) so the tool can run from environment alone. Command-line flags
+//! are applied on top by each command.
+//!
+//! `[profiles.NAME]` tables hold named variants, e.g. a "bonds" profile
+//! with its own watchlist, alert rules and notification targets. With
+//! `--profile NAME` its keys are layered over the file's, below the
+//! environment.
+//!
+//! Without `--config` the file is `./biga.toml` if there is one, else
+//! `biga.toml` in `--config-dir` or the platform's config directory
+//! (`~/.config/biga` on Linux, `~/Library/Application Support/biga` on
+//! macOS, `%APPDATA%\biga\config` on Windows). State goes to
+//! `$STOCK_DATA_DIR` when it is set, as the grid backtester's does, else to
+//! the working directory if it already holds state files, else to the
+//! platform's data and cache directories. `biga paths` prints where each
+//! resolves to.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use directories::ProjectDirs;
+use figment::Figment;
+use figment::providers::{Env, Format, Serialized, Toml};
+use serde::{Deserialize, Serialize};
+
+use crate::ETF_CODES;
+use crate::context::{ContextItem, DEFAULT_CONTEXT};
+use crate::decline::MetricFilter;
+use crate::fundinfo::FundInfo;
+use crate::i18n::{Lang, tr};
+use crate::indicators::RSI_PERIOD;
+use crate::instruments::{FundKind, Settlement};
+use crate::levels::PriceAlert;
+use crate::schedule::{self, DeliveryWindow};
+use crate::smooth::Smoothing;
+use crate::score::Formula;
+use crate::symbols;
+
+pub const DEFAULT_CONFIG_FILE: &str = "biga.toml";
+
+static CONFIG_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// The platform's directories for the tool; `None` without a home directory.
+fn project_dirs() -> Option<ProjectDirs> {
+    ProjectDirs::from("", "", "biga")
+}
+
+/// `$STOCK_DATA_DIR`, when set and not empty.
+fn stock_data_dir() -> Option<PathBuf> {
+    std::env::var_os("STOCK_DATA_DIR").filter(|v| !v.is_empty()).map(PathBuf::from)
+}
+
+/// Look for the default config file in `dir` from now on (`--config-dir`).
+/// Call before `Config::load`.
+pub fn set_config_dir(dir: &Path) {
+    let _ = CONFIG_DIR.set(dir.to_path_buf());
+}
+
+/// Where the default config file lives: `--config-dir`, else the
+/// platform's config directory, else the working directory.
+pub fn config_dir() -> PathBuf {
+    match CONFIG_DIR.get() {
+        Some(dir) => dir.clone(),
+        None => project_dirs().map_or_else(|| PathBuf::from("."), |d| d.config_dir().to_path_buf()),
+    }
+}
+
+/// The config file read without `--config`: `./biga.toml` if present and
+/// no `--config-dir` is given, else `biga.toml` in `config_dir()`.
+pub fn default_config_file() -> PathBuf {
+    let local = PathBuf::from(DEFAULT_CONFIG_FILE);
+    if CONFIG_DIR.get().is_none() && local.exists() { local } else { config_dir().join(DEFAULT_CONFIG_FILE) }
+}
+
+/// Stems of the state files kept in the working directory before the
+/// platform directories, with or without a profile.
+const LOCAL_STATE: &[&str] = &[
+    "alert_state",
+    "dividends",
+    "events",
+    "export_state",
+    "fund_info",
+    "history",
+    "kline_cache",
+    "paper",
+    "portfolio_history",
+    "premium_history",
+    "snoozed",
+    "sources",
+    "symbols",
+];
+
+/// Whether the working directory already holds state from before the
+/// platform directories; checked once per process.
+fn local_state() -> bool {
+    static LOCAL: OnceLock<bool> = OnceLock::new();
+    *LOCAL.get_or_init(|| {
+        let Ok(entries) = std::fs::read_dir(".") else {
+            return false;
+        };
+        entries.flatten().any(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.split_once('.').is_some_and(|(stem, _)| LOCAL_STATE.contains(&stem))
+        })
+    })
+}
+
+/// Which directory state goes to, as `paths` reports it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataDirSource {
+    /// `$STOCK_DATA_DIR`.
+    Env,
+    /// The working directory, which already holds state.
+    WorkingDir,
+    /// The platform's data and cache directories.
+    Platform,
+}
+
+impl fmt::Display for DataDirSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataDirSource::Env => f.write_str("$STOCK_DATA_DIR"),
+            DataDirSource::WorkingDir => f.write_str(&tr!("working directory, which has state", "工作目录, 已有状态文件")),
+            DataDirSource::Platform => f.write_str(&tr!("platform directory", "平台目录")),
+        }
+    }
+}
+
+pub fn data_dir_source() -> DataDirSource {
+    if stock_data_dir().is_some() {
+        DataDirSource::Env
+    } else if project_dirs().is_none() || local_state() {
+        DataDirSource::WorkingDir
+    } else {
+        DataDirSource::Platform
+    }
+}
+
+/// Where state files live: `$STOCK_DATA_DIR`, the same convention as the
+/// grid backtester's data cache, else the working directory if it already
+/// holds state, else the platform's data directory.
+pub fn data_dir() -> PathBuf {
+    match (data_dir_source(), project_dirs()) {
+        (DataDirSource::Env, _) => stock_data_dir().unwrap_or_default(),
+        (DataDirSource::Platform, Some(dirs)) => dirs.data_dir().to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Where the kline cache lives: with the state unless that is the platform's
+/// data directory, in which case the platform's cache directory, which the
+/// OS may clear.
+pub fn cache_dir() -> PathBuf {
+    match (data_dir_source(), project_dirs()) {
+        (DataDirSource::Platform, Some(dirs)) => dirs.cache_dir().to_path_buf(),
+        _ => data_dir(),
+    }
+}
+
+/// Write `contents` to `path` through a temporary file renamed over it,
+/// so a crash mid-write leaves the old file whole.
+pub fn write_atomic(path: &Path, contents: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, path)
+}
+
+/// `stem.ext`, or `stem.PROFILE.ext` under a profile, so each profile keeps
+/// its own copy of a state file.
+pub fn profiled(stem: &str, ext: &str, profile: Option<&str>) -> String {
+    match profile {
+        Some(name) => format!("{}.{}.{}", stem, name, ext),
+        None => format!("{}.{}", stem, ext),
+    }
+}
+
+/// Flat environment variables, handled outside figment's nested mapping.
+const FLAT_ENV: &[&str] = &[
+    "BIGA_WATCHLIST",
+    "BIGA_SLACK_WEBHOOK",
+    "BIGA_DISCORD_WEBHOOK",
+    "BIGA_DESKTOP",
+    "BIGA_COOLDOWN",
+    "BIGA_DELIVER",
+    "BIGA_INTERVAL",
+];
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    /// money.finance.sina.com.cn klines and hq.sinajs.cn quotes
+    Sina,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Whole-request timeout, seconds.
+    pub timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+    /// Codes not fetched this long after a run starts are reported as timed
+    /// out instead of waited for.
+    pub deadline_secs: Option<u64>,
+    /// How many codes are fetched at once.
+    pub concurrency: usize,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig { timeout_secs: 10, connect_timeout_secs: 5, deadline_secs: None, concurrency: 4 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndicatorConfig {
+    /// Donchian breakout lookback.
+    pub channel_days: usize,
+    /// Lookback for the close's percentile in its high-low range.
+    pub range_days: usize,
+    /// History used for return distributions and similar-decline stats.
+    pub history_days: usize,
+    pub rsi_period: usize,
+    /// Closes the regression trend channel is fitted to.
+    pub regression_days: usize,
+}
+
+impl Default for IndicatorConfig {
+    fn default() -> Self {
+        IndicatorConfig { channel_days: 20, range_days: 20, history_days: 500, rsi_period: RSI_PERIOD, regression_days: 60 }
+    }
+}
+
+/// Screen settings for one code that differ from the global ones.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CodeOverrides {
+    pub channel_days: Option<usize>,
+    pub range_days: Option<usize>,
+    pub history_days: Option<usize>,
+    pub rsi_period: Option<usize>,
+    pub regression_days: Option<usize>,
+    pub min_adx: Option<f64>,
+    pub max_adx: Option<f64>,
+    /// Row filters like `--filter`, each replacing the global one on the
+    /// same metric, e.g. ["decline<=-3"].
+    pub filter: Vec<MetricFilter>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    pub desktop: bool,
+    pub slack_webhook: Option<String>,
+    pub discord_webhook: Option<String>,
+    pub cooldown_minutes: u64,
+    pub deliver: Vec<DeliveryWindow>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            desktop: false,
+            slack_webhook: None,
+            discord_webhook: None,
+            cooldown_minutes: 60,
+            deliver: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WatchConfig {
+    pub interval_minutes: u64,
+    /// Address the health endpoint listens on, e.g. "127.0.0.1:9184".
+    pub health_addr: Option<String>,
+    /// Shanghai-time windows in which each pass refetches every code's
+    /// klines, starting with a pass as each opens; after the close the
+    /// bars are saved for one-off screens too.
+    pub warmup: Vec<DeliveryWindow>,
+    /// Absolute price levels checked against quotes every minute, see
+    /// `levels`.
+    pub price_alerts: Vec<PriceAlert>,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        WatchConfig {
+            interval_minutes: 5,
+            health_addr: None,
+            warmup: DEFAULT_WARMUP.to_vec(),
+            price_alerts: Vec::new(),
+        }
+    }
+}
+
+/// Just after the open and just after the close.
+const DEFAULT_WARMUP: &[DeliveryWindow] = &[schedule::OPENING_WARMUP, schedule::CLOSING_WARMUP];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortfolioConfig {
+    /// Shares held per code.
+    pub holdings: BTreeMap<String, u64>,
+    /// Uninvested cash, counted in the total value.
+    pub cash: f64,
+    /// Code the portfolio's return is compared against.
+    pub benchmark: String,
+}
+
+impl Default for PortfolioConfig {
+    fn default() -> Self {
+        PortfolioConfig { holdings: BTreeMap::new(), cash: 0.0, benchmark: "510300".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LiquidityConfig {
+    /// Flag ETFs trading less than this per day on average, CNY.
+    pub min_turnover: f64,
+    /// Flag ETFs quoted wider than this, basis points.
+    pub max_spread_bps: f64,
+}
+
+impl Default for LiquidityConfig {
+    fn default() -> Self {
+        LiquidityConfig { min_turnover: 5_000_000.0, max_spread_bps: 30.0 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnomalyConfig {
+    /// Flag a latest daily return this many standard deviations from the
+    /// code's own mean; 0 turns anomalies off.
+    pub sigma: f64,
+    /// Daily returns before the latest that the mean and deviation are
+    /// taken over.
+    pub lookback_days: usize,
+}
+
+impl Default for AnomalyConfig {
+    fn default() -> Self {
+        AnomalyConfig { sigma: 4.0, lookback_days: 250 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmoothingConfig {
+    /// Smoothing ahead of the indicators, see `smooth`; commands' `--smooth`
+    /// overrides it.
+    pub method: Smoothing,
+    /// Kalman process-to-measurement noise ratio; smaller is smoother.
+    pub kalman_ratio: f64,
+    /// Bars the Hull moving average spans.
+    pub hull_period: usize,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        SmoothingConfig { method: Smoothing::Off, kalman_ratio: 0.1, hull_period: 9 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrelationConfig {
+    /// Alert when a code's correlation to the benchmark moves this much
+    /// over `lookback_days`; 0 turns the alerts off.
+    pub min_shift: f64,
+    /// Daily returns each correlation is taken over.
+    pub window: usize,
+    /// Trading days back the current correlation is compared with.
+    pub lookback_days: usize,
+}
+
+impl Default for CorrelationConfig {
+    fn default() -> Self {
+        CorrelationConfig { min_shift: 0.0, window: 60, lookback_days: 20 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InstrumentsConfig {
+    /// Settlement rule per code, for codes the built-in table gets wrong.
+    pub settlement: BTreeMap<String, Settlement>,
+    /// Fund kind per code, for LOFs and closed-end funds outside the usual
+    /// code ranges.
+    pub kind: BTreeMap<String, FundKind>,
+    /// Futures product behind a commodity ETF, e.g. "M" for soybean meal,
+    /// for codes `basis` doesn't know.
+    pub futures: BTreeMap<String, String>,
+    /// Fund metadata per code, filling in or correcting the fetched copy.
+    pub funds: BTreeMap<String, FundInfo>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LadderConfig {
+    /// Tranche depths below the latest close, in ATRs.
+    pub atr_multiples: Vec<f64>,
+    /// Cash split across one ETF's tranches.
+    pub cash: f64,
+    /// Also ladder rows scoring at least this, besides those at a channel
+    /// low or the lower Keltner band.
+    pub min_score: Option<f64>,
+}
+
+impl Default for LadderConfig {
+    fn default() -> Self {
+        LadderConfig { atr_multiples: vec![1.0, 2.0, 3.0], cash: 30_000.0, min_score: None }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DividendsConfig {
+    /// Alert this many days before a watchlist fund goes ex-dividend; 0
+    /// turns the alerts off.
+    pub alert_days: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PaperConfig {
+    /// Virtual cash the paper account opens with.
+    pub cash: f64,
+    /// Cash put into each simulated buy.
+    pub order_cash: f64,
+    /// Sell a position once it is up this many percent on its cost.
+    pub take_profit_pct: f64,
+    /// Sell a position once it is down this many percent on its cost.
+    pub stop_loss_pct: f64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        PaperConfig { cash: 100_000.0, order_cash: 10_000.0, take_profit_pct: 5.0, stop_loss_pct: 8.0 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportConfig {
+    /// Fields written per row, as `--columns` takes them.
+    pub columns: Vec<String>,
+    pub google: Option<GoogleSheetConfig>,
+    pub feishu: Option<FeishuSheetConfig>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        let columns = ["date", "code", "name", "close", "decline", "rsi", "adx", "zscore", "drawdown", "score"];
+        ExportConfig { columns: columns.iter().map(|c| c.to_string()).collect(), google: None, feishu: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoogleSheetConfig {
+    /// The ID in the spreadsheet's URL.
+    pub spreadsheet_id: String,
+    /// Tab the rows are appended to.
+    #[serde(default = "default_tab")]
+    pub tab: String,
+    /// Service-account key file; share the spreadsheet with its email.
+    pub credentials: PathBuf,
+}
+
+fn default_tab() -> String {
+    "Sheet1".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FeishuSheetConfig {
+    /// The token in the spreadsheet's URL.
+    pub spreadsheet_token: String,
+    /// ID of the sheet the rows are appended to.
+    pub sheet_id: String,
+    /// Credentials of a custom app with edit access to the spreadsheet.
+    pub app_id: String,
+    pub app_secret: String,
+}
+
+/// Needs a build with `--features postgres`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PostgresConfig {
+    /// Connection string, e.g. "postgres://biga@warehouse/market?sslmode=require".
+    pub url: String,
+    /// Tables written, each created if missing; may be schema-qualified.
+    #[serde(default = "default_klines_table")]
+    pub klines_table: String,
+    #[serde(default = "default_metrics_table")]
+    pub metrics_table: String,
+    #[serde(default = "default_signals_table")]
+    pub signals_table: String,
+}
+
+fn default_klines_table() -> String {
+    "biga_klines".to_string()
+}
+
+fn default_metrics_table() -> String {
+    "biga_metrics".to_string()
+}
+
+fn default_signals_table() -> String {
+    "biga_signals".to_string()
+}
+
+/// Needs a build with `--features redis`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedisConfig {
+    /// Connection URL, e.g. "redis://127.0.0.1/".
+    pub url: String,
+    /// Pub/sub channel every message is published to.
+    #[serde(default = "default_channel")]
+    pub channel: String,
+    /// Stream every message is also appended to, for consumers that can't
+    /// miss one.
+    pub stream: Option<String>,
+    /// Entries the stream is trimmed to, roughly.
+    #[serde(default = "default_stream_len")]
+    pub stream_len: usize,
+}
+
+fn default_channel() -> String {
+    "biga".to_string()
+}
+
+fn default_stream_len() -> usize {
+    10_000
+}
+
+/// Needs a build with `--features mqtt`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfig {
+    pub host: String,
+    pub port: u16,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topic each new alert goes to as JSON; `{code}` is filled in.
+    pub alert_topic: String,
+    /// Topic each metric goes to, retained, as a bare number; `{code}` and
+    /// `{metric}` are filled in.
+    pub metric_topic: String,
+    /// Fields of each row published, as `--columns` takes them.
+    pub metrics: Vec<String>,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        MqttConfig {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: "biga".to_string(),
+            username: None,
+            password: None,
+            alert_topic: "biga/{code}/alert".to_string(),
+            metric_topic: "biga/{code}/{metric}".to_string(),
+            metrics: ["close", "decline", "rsi", "score"].iter().map(|m| m.to_string()).collect(),
+        }
+    }
+}
+
+/// Who may use `serve`, and as whom.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServeConfig {
+    /// Credentials for this config itself. With none set, anyone who can
+    /// reach the server may use it.
+    #[serde(flatten)]
+    pub credentials: Credentials,
+    /// Further users by profile name: a request with their credentials is
+    /// served that profile, with its own watchlist, portfolio and alerts.
+    /// Requires the base credentials too.
+    pub users: BTreeMap<String, Credentials>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Credentials {
+    /// Key clients send as `Authorization: Bearer <key>` or `X-API-Key`.
+    pub api_key: Option<String>,
+    /// Basic-auth credentials, which browsers opening the dashboard prompt
+    /// for.
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Credentials {
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn is_empty(&self) -> bool {
+        self.api_key.is_none() && self.username.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ArchiveConfig {
+    /// Keep each day's report, see `history`.
+    pub enabled: bool,
+    /// Days of reports kept; 0 keeps them all.
+    pub keep_days: u32,
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        ArchiveConfig { enabled: true, keep_days: 365 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Years of daily bars `cache prune` keeps.
+    pub daily_years: u32,
+    /// Days of minute bars `cache prune` keeps.
+    pub minute_days: u32,
+    /// Largest the history store may grow, MiB; `cache prune` drops the
+    /// oldest bars beyond it.
+    pub max_mb: Option<u64>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig { daily_years: 10, minute_days: 30, max_mb: None }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub source: Source,
+    /// Codes screened when a command isn't given its own list.
+    pub watchlist: Vec<String>,
+    pub output: OutputFormat,
+    /// Language of text output and messages.
+    pub lang: Lang,
+    pub http: HttpConfig,
+    pub indicators: IndicatorConfig,
+    /// Composite score formula, see `score`.
+    pub score: Option<Formula>,
+    pub context: Vec<ContextItem>,
+    pub notify: NotifyConfig,
+    pub watch: WatchConfig,
+    pub portfolio: PortfolioConfig,
+    pub paper: PaperConfig,
+    pub ladder: LadderConfig,
+    pub instruments: InstrumentsConfig,
+    pub liquidity: LiquidityConfig,
+    pub anomaly: AnomalyConfig,
+    pub correlation: CorrelationConfig,
+    pub smoothing: SmoothingConfig,
+    pub dividends: DividendsConfig,
+    pub cache: CacheConfig,
+    pub archive: ArchiveConfig,
+    /// Spreadsheets each day's results are appended to, see `export`.
+    pub export: ExportConfig,
+    /// Warehouse every run's klines, metrics and signals are upserted into.
+    pub postgres: Option<PostgresConfig>,
+    /// Where signals and quote updates are published as they are computed.
+    pub redis: Option<RedisConfig>,
+    /// Broker alerts and key metrics are published to, e.g. for Home
+    /// Assistant automations.
+    pub mqtt: Option<MqttConfig>,
+    /// Credentials `serve` asks for.
+    pub serve: ServeConfig,
+    /// Where WebAssembly indicator plugins are loaded from, see `plugins`
+    /// [default: $STOCK_DATA_DIR/plugins].
+    pub plugins_dir: Option<PathBuf>,
+    /// Rhai scripts computing extra metrics, by metric name, see `scripts`.
+    pub scripts: BTreeMap<String, String>,
+    /// Per-code settings merged over the global ones.
+    pub overrides: BTreeMap<String, CodeOverrides>,
+    /// The `--profile` this config was loaded with; keeps its runs' state
+    /// apart from other profiles'.
+    #[serde(skip)]
+    pub profile: Option<String>,
+    /// Set by `watch --dry-run`, `--fixture` and `--replay`: nothing is
+    /// recorded in the event log, archived, exported, published or sent.
+    #[serde(skip)]
+    pub dry_run: bool,
+    /// The `--config` file this config was loaded from, so its profiles can
+    /// be loaded too.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            source: Source::Sina,
+            watchlist: ETF_CODES.iter().map(|c| c.to_string()).collect(),
+            output: OutputFormat::Text,
+            lang: Lang::En,
+            http: HttpConfig::default(),
+            indicators: IndicatorConfig::default(),
+            score: None,
+            context: DEFAULT_CONTEXT.to_vec(),
+            notify: NotifyConfig::default(),
+            watch: WatchConfig::default(),
+            portfolio: PortfolioConfig::default(),
+            paper: PaperConfig::default(),
+            ladder: LadderConfig::default(),
+            instruments: InstrumentsConfig::default(),
+            liquidity: LiquidityConfig::default(),
+            anomaly: AnomalyConfig::default(),
+            correlation: CorrelationConfig::default(),
+            smoothing: SmoothingConfig::default(),
+            dividends: DividendsConfig::default(),
+            cache: CacheConfig::default(),
+            archive: ArchiveConfig::default(),
+            export: ExportConfig::default(),
+            postgres: None,
+            redis: None,
+            mqtt: None,
+            serve: ServeConfig::default(),
+            plugins_dir: None,
+            scripts: BTreeMap::new(),
+            overrides: BTreeMap::new(),
+            profile: None,
+            dry_run: false,
+            file: None,
+        }
+    }
+}
+
+impl Config {
+    /// This config with `code`'s indicator overrides applied.
+    pub fn for_code(&self, code: &str) -> Cow<'_, Config> {
+        let Some(o) = self.overrides.get(code) else {
+            return Cow::Borrowed(self);
+        };
+        let mut cfg = self.clone();
+        let ind = &mut cfg.indicators;
+        ind.channel_days = o.channel_days.unwrap_or(ind.channel_days);
+        ind.range_days = o.range_days.unwrap_or(ind.range_days);
+        ind.history_days = o.history_days.unwrap_or(ind.history_days);
+        ind.rsi_period = o.rsi_period.unwrap_or(ind.rsi_period);
+        ind.regression_days = o.regression_days.unwrap_or(ind.regression_days);
+        Cow::Owned(cfg)
+    }
+
+    /// Merge defaults, the config file, `profile`'s table in it and the
+    /// environment. An explicit `path` must exist; the default file (see
+    /// `default_config_file`) is optional.
+    pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<Config, Box<dyn std::error::Error>> {
+        let file = match path {
+            Some(path) => {
+                if !path.exists() {
+                    return Err(format!("config file not found: {}", path.display()).into());
+                }
+                path.to_path_buf()
+            }
+            None => default_config_file(),
+        };
+        let ignored: Vec<String> = FLAT_ENV.iter().map(|v| v["BIGA_".len()..].to_lowercase()).collect();
+        let ignored: Vec<&str> = ignored.iter().map(String::as_str).collect();
+        let toml = Figment::from(Toml::file(file));
+        let mut figment = Figment::from(Serialized::defaults(Config::default())).merge(toml.clone());
+        if let Some(name) = profile {
+            let key = format!("profiles.{}", name);
+            if !toml.contains(&key) {
+                return Err(format!("unknown profile '{}': no [{}] table in the config file", name, key).into());
+            }
+            figment = figment.merge(toml.focus(&key));
+        }
+        let mut config: Config = figment
+            .merge(Env::prefixed("BIGA_").ignore(&ignored).split("__"))
+            .extract()
+            // figment's Display names the offending key and source.
+            .map_err(|e| e.to_string())?;
+        config.apply_flat_env(|name| std::env::var(name).ok())?;
+        config.normalize_codes()?;
+        config.profile = profile.map(str::to_string);
+        config.file = path.map(Path::to_path_buf);
+        Ok(config)
+    }
+
+    /// Rewrite every code in the config to its bare form, so `sh513500`
+    /// in the watchlist and `513500.SH` in the holdings key the same data.
+    fn normalize_codes(&mut self) -> Result<(), String> {
+        fn keys<V>(map: &mut BTreeMap<String, V>, section: &str) -> Result<(), String> {
+            let entries = std::mem::take(map);
+            for (code, value) in entries {
+                let code = symbols::parse_code(&code).map_err(|e| format!("{}: {}", section, e))?;
+                map.insert(code, value);
+            }
+            Ok(())
+        }
+        self.watchlist = self
+            .watchlist
+            .iter()
+            .map(|code| symbols::parse_code(code).map_err(|e| format!("watchlist: {}", e)))
+            .collect::<Result<_, _>>()?;
+        self.portfolio.benchmark =
+            symbols::parse_code(&self.portfolio.benchmark).map_err(|e| format!("portfolio.benchmark: {}", e))?;
+        keys(&mut self.portfolio.holdings, "portfolio.holdings")?;
+        keys(&mut self.instruments.settlement, "instruments.settlement")?;
+        keys(&mut self.instruments.kind, "instruments.kind")?;
+        keys(&mut self.instruments.futures, "instruments.futures")?;
+        keys(&mut self.instruments.funds, "instruments.funds")?;
+        keys(&mut self.overrides, "overrides")?;
+        Ok(())
+    }
+
+    /// Apply the flat `FLAT_ENV` variables; `lookup` reads one by name.
+    fn apply_flat_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        let list = |value: String| -> Vec<String> {
+            value.split(',').map(str::trim).filter(|s| !s.is_empty()).map(str::to_string).collect()
+        };
+        let number = |name: &str, value: String| -> Result<u64, String> {
+            value.trim().parse().map_err(|_| format!("{} must be a whole number, got '{}'", name, value))
+        };
+
+        if let Some(v) = lookup("BIGA_WATCHLIST") {
+            self.watchlist = list(v);
+        }
+        if let Some(v) = lookup("BIGA_SLACK_WEBHOOK") {
+            self.notify.slack_webhook = Some(v).filter(|v| !v.is_empty());
+        }
+        if let Some(v) = lookup("BIGA_DISCORD_WEBHOOK") {
+            self.notify.discord_webhook = Some(v).filter(|v| !v.is_empty());
+        }
+        if let Some(v) = lookup("BIGA_DESKTOP") {
+            self.notify.desktop = matches!(v.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on");
+        }
+        if let Some(v) = lookup("BIGA_COOLDOWN") {
+            self.notify.cooldown_minutes = number("BIGA_COOLDOWN", v)?;
+        }
+        if let Some(v) = lookup("BIGA_DELIVER") {
+            self.notify.deliver = list(v).iter().map(|w| w.parse()).collect::<Result<_, _>>()?;
+        }
+        if let Some(v) = lookup("BIGA_INTERVAL") {
+            self.watch.interval_minutes = number("BIGA_INTERVAL", v)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use clap::Parser;
+    use figment::Jail;
+
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        watch: crate::watch::WatchArgs,
+    }
+
+    // Jail's closure returns figment's own, large, error.
+    #[allow(clippy::result_large_err)]
+    #[test]
+    fn layers_apply_in_order() {
+        Jail::expect_with(|jail| {
+            jail.create_file(
+                "biga.toml",
+                r#"
+                    watchlist = ["sh513500", "159941.SZ"]
+                    output = "json"
+
+                    [http]
+                    timeout_secs = 20
+                    concurrency = 2
+
+                    [watch]
+                    interval_minutes = 15
+
+                    [notify]
+                    cooldown_minutes = 30
+
+                    [profiles.bonds]
+                    watchlist = ["SSE:511010"]
+                    http = { concurrency = 1 }
+                "#,
+            )?;
+            jail.set_env("BIGA_HTTP__TIMEOUT_SECS", "7");
+            jail.set_env("BIGA_INTERVAL", "10");
+            let path = Path::new("biga.toml");
+
+            let cfg = Config::load(Some(path), None).map_err(|e| e.to_string())?;
+            // Defaults where nothing sets a key.
+            assert_eq!(cfg.http.connect_timeout_secs, 5);
+            assert_eq!(cfg.lang, Lang::En);
+            // The file over the defaults, its codes normalized.
+            assert_eq!(cfg.watchlist, ["513500", "159941"]);
+            assert_eq!(cfg.output, OutputFormat::Json);
+            assert_eq!(cfg.http.concurrency, 2);
+            assert_eq!(cfg.notify.cooldown_minutes, 30);
+            // Nested and flat environment variables over the file.
+            assert_eq!(cfg.http.timeout_secs, 7);
+            assert_eq!(cfg.watch.interval_minutes, 10);
+
+            // A profile over the file, below the environment.
+            let bonds = Config::load(Some(path), Some("bonds")).map_err(|e| e.to_string())?;
+            assert_eq!(bonds.watchlist, ["511010"]);
+            assert_eq!(bonds.http.concurrency, 1);
+            assert_eq!(bonds.http.timeout_secs, 7);
+            assert_eq!(bonds.output, OutputFormat::Json);
+            assert_eq!(bonds.profile.as_deref(), Some("bonds"));
+
+            // Command-line flags over everything.
+            let mut cli = cfg.clone();
+            Cli::try_parse_from(["biga", "--interval", "2", "--cooldown", "5"])
+                .map_err(|e| e.to_string())?
+                .watch
+                .apply(&mut cli);
+            assert_eq!(cli.watch.interval_minutes, 2);
+            assert_eq!(cli.notify.cooldown_minutes, 5);
+            assert_eq!(cli.http.timeout_secs, 7);
+
+            assert!(Config::load(Some(path), Some("stocks")).is_err());
+            assert!(Config::load(Some(Path::new("missing.toml")), None).is_err());
+            Ok(())
+        });
+    }
+
+    #[test]
+    fn flat_environment_alone_configures_a_run() {
+        let mut cfg = Config::default();
+        cfg.apply_flat_env(env(&[
+            ("BIGA_WATCHLIST", " sh513500, ,159941 "),
+            ("BIGA_SLACK_WEBHOOK", "https://hooks.slack.com/services/T/B/X"),
+            ("BIGA_DISCORD_WEBHOOK", ""),
+            ("BIGA_DESKTOP", "Yes"),
+            ("BIGA_COOLDOWN", "45"),
+            ("BIGA_DELIVER", "09:30-11:30, 13:00-15:00"),
+            ("BIGA_INTERVAL", " 3 "),
+        ]))
+        .unwrap();
+        cfg.normalize_codes().unwrap();
+        assert_eq!(cfg.watchlist, ["513500", "159941"]);
+        assert_eq!(cfg.notify.slack_webhook.as_deref(), Some("https://hooks.slack.com/services/T/B/X"));
+        assert_eq!(cfg.notify.discord_webhook, None);
+        assert!(cfg.notify.desktop);
+        assert_eq!(cfg.notify.cooldown_minutes, 45);
+        let deliver: Vec<String> = cfg.notify.deliver.iter().map(|w| w.to_string()).collect();
+        assert_eq!(deliver, ["09:30-11:30", "13:00-15:00"]);
+        assert_eq!(cfg.watch.interval_minutes, 3);
+
+        // Unset variables leave the config alone.
+        let mut untouched = Config::default();
+        untouched.apply_flat_env(env(&[])).unwrap();
+        assert_eq!(untouched.watchlist, Config::default().watchlist);
+        assert_eq!(untouched.notify.cooldown_minutes, 60);
+
+        let mut off = cfg.clone();
+        off.apply_flat_env(env(&[("BIGA_DESKTOP", "0"), ("BIGA_SLACK_WEBHOOK", "")])).unwrap();
+        assert!(!off.notify.desktop);
+        assert_eq!(off.notify.slack_webhook, None);
+    }
+
+    #[test]
+    fn flat_environment_rejects_malformed_values() {
+        for (name, value) in [
+            ("BIGA_COOLDOWN", "an hour"),
+            ("BIGA_COOLDOWN", "-5"),
+            ("BIGA_INTERVAL", "2.5"),
+            ("BIGA_DELIVER", "09:30-11:30,15:00-13:00"),
+            ("BIGA_DELIVER", "morning"),
+        ] {
+            let mut cfg = Config::default();
+            assert!(cfg.apply_flat_env(env(&[(name, value)])).is_err(), "{}={}", name, value);
+        }
+        let mut cfg = Config::default();
+        cfg.apply_flat_env(env(&[("BIGA_WATCHLIST", "51350")])).unwrap();
+        assert!(cfg.normalize_codes().unwrap_err().starts_with("watchlist: "));
+    }
+
+    #[test]
+    fn normalize_codes_keys_every_section_by_bare_code() {
+        let mut cfg = Config {
+            watchlist: vec!["sh513500".into(), "159941.SZ".into(), "SSE:510300".into(), "btc-usd".into()],
+            ..Config::default()
+        };
+        cfg.portfolio.benchmark = "sh510300".into();
+        cfg.portfolio.holdings.insert("513500.SH".into(), 1000);
+        cfg.overrides.insert("sz159941".into(), CodeOverrides::default());
+        cfg.normalize_codes().unwrap();
+        assert_eq!(cfg.watchlist, ["513500", "159941", "510300", "BTC-USD"]);
+        assert_eq!(cfg.portfolio.benchmark, "510300");
+        assert_eq!(cfg.portfolio.holdings.keys().collect::<Vec<_>>(), ["513500"]);
+        assert_eq!(cfg.overrides.keys().collect::<Vec<_>>(), ["159941"]);
+
+        let mut bad = Config::default();
+        bad.portfolio.holdings.insert("513500.HK".into(), 1000);
+        assert!(bad.normalize_codes().unwrap_err().starts_with("portfolio.holdings: "));
+    }
+}
@@ -0,0 +1,198 @@
+//! Market-wide context lines printed above the decline report.
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::http;
+use crate::i18n::tr;
+use crate::sina;
+
+const NORTHBOUND_URL: &str = "https://push2his.eastmoney.com/api/qt/kamt.kline/get?fields1=f1,f3,f5&fields2=f51,f52&klt=101&lmt=5";
+
+const MARGIN_URL: &str = "https://datacenter-web.eastmoney.com/api/data/v1/get?reportName=RPTA_RZRQ_LSHJ&columns=ALL&sortColumns=DIM_DATE&sortTypes=-1&pageNumber=1&pageSize=6";
+
+/// A 5-day drop in financing balance larger than this (percent) is flagged
+/// as a leverage flush.
+const MARGIN_FLUSH_PCT: f64 = -2.0;
+
+/// Parse `"YYYY-MM-DD,value"` rows under `data.s2n`, dropping days without a
+/// figure (the exchanges publish `-` on days they withhold the number).
+fn parse_northbound(body: &Value) -> Vec<(String, f64)> {
+    body["data"]["s2n"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| {
+            let (day, value) = row.as_str()?.split_once(',')?;
+            Some((day.to_string(), value.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Daily northbound (HK -> A share) net buying, in 100M CNY, oldest first.
+pub async fn fetch_northbound() -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let body: Value = http::get(NORTHBOUND_URL, &[]).await?.json()?;
+    // Eastmoney reports in units of 10k CNY.
+    Ok(parse_northbound(&body).into_iter().map(|(d, v)| (d, v / 1e4)).collect())
+}
+
+fn northbound_line(flows: &[(String, f64)]) -> String {
+    match flows.last() {
+        Some((day, latest)) => {
+            let total: f64 = flows.iter().map(|(_, v)| v).sum();
+            tr!(
+                "Northbound: {:+.2} 亿 on {} | {:+.2} 亿 over {} days",
+                "北向资金: {1} 净流入 {0:+.2} 亿 | 近 {3} 日 {2:+.2} 亿",
+                latest,
+                day,
+                total,
+                flows.len()
+            )
+        }
+        None => tr!("Northbound: no data published", "北向资金: 暂无数据"),
+    }
+}
+
+/// Parse `(date, financing balance)` rows, newest first as returned.
+fn parse_margin(body: &Value) -> Vec<(String, f64)> {
+    body["result"]["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| {
+            let day = row["DIM_DATE"].as_str()?.split_whitespace().next()?.to_string();
+            Some((day, row["RZYE"].as_f64()?))
+        })
+        .collect()
+}
+
+/// Shanghai + Shenzhen margin financing balance in 100M CNY, oldest first.
+pub async fn fetch_margin_balance() -> Result<Vec<(String, f64)>, Box<dyn std::error::Error>> {
+    let body: Value = http::get(MARGIN_URL, &[]).await?.json()?;
+    let mut rows: Vec<(String, f64)> = parse_margin(&body).into_iter().map(|(d, v)| (d, v / 1e8)).collect();
+    rows.reverse();
+    Ok(rows)
+}
+
+fn margin_line(rows: &[(String, f64)]) -> String {
+    let change = |from: f64, to: f64| if from > 0.0 { (to - from) / from * 100.0 } else { 0.0 };
+    let Some((day, latest)) = rows.last() else {
+        return tr!("Margin balance: no data published", "融资余额: 暂无数据");
+    };
+    if rows.len() < 2 {
+        return tr!("Margin balance: {:.0} 亿 on {}", "融资余额: {1} {0:.0} 亿", latest, day);
+    }
+    let prev = rows[rows.len() - 2].1;
+    let week = change(rows[0].1, *latest);
+    let flush = if week <= MARGIN_FLUSH_PCT { tr!(" | leverage flush", " | 杠杆出清") } else { String::new() };
+    tr!(
+        "Margin balance: {:.0} 亿 on {} | {:+.2}% 1d | {:+.2}% {}d{}",
+        "融资余额: {1} {0:.0} 亿 | 1 日 {2:+.2}% | {4} 日 {3:+.2}%{5}",
+        latest,
+        day,
+        change(prev, *latest),
+        week,
+        rows.len() - 1,
+        flush
+    )
+}
+
+/// One line of the context header.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextItem {
+    Northbound,
+    Margin,
+    Usdcny,
+    Spx,
+    Ndx,
+    Gold,
+}
+
+pub const DEFAULT_CONTEXT: &[ContextItem] = &[
+    ContextItem::Northbound,
+    ContextItem::Margin,
+    ContextItem::Usdcny,
+    ContextItem::Spx,
+    ContextItem::Ndx,
+    ContextItem::Gold,
+];
+
+impl ContextItem {
+    /// The hq.sinajs.cn symbol for items quoted there.
+    fn sina_symbol(self) -> Option<&'static str> {
+        match self {
+            ContextItem::Usdcny => Some("fx_susdcny"),
+            ContextItem::Spx => Some("gb_$inx"),
+            ContextItem::Ndx => Some("gb_ixic"),
+            ContextItem::Gold => Some("hf_XAU"),
+            ContextItem::Northbound | ContextItem::Margin => None,
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            ContextItem::Northbound => tr!("Northbound", "北向资金"),
+            ContextItem::Margin => tr!("Margin balance", "融资余额"),
+            ContextItem::Usdcny => tr!("USDCNY", "美元/人民币"),
+            ContextItem::Spx => tr!("S&P 500", "标普 500"),
+            ContextItem::Ndx => tr!("Nasdaq", "纳斯达克"),
+            ContextItem::Gold => tr!("Gold spot", "现货黄金"),
+        }
+    }
+}
+
+/// `(last, change %)` from a Sina quote; the field layout differs by market.
+fn quote_change(item: ContextItem, fields: &[String]) -> Option<(f64, f64)> {
+    let num = |i: usize| fields.get(i)?.parse::<f64>().ok();
+    let pct = |last: f64, prev: f64| if prev > 0.0 { (last - prev) / prev * 100.0 } else { 0.0 };
+    match item {
+        // time, buy, sell, prev close, ..., latest at 8
+        ContextItem::Usdcny => {
+            let (last, prev) = (num(8)?, num(3)?);
+            Some((last, pct(last, prev)))
+        }
+        // name, price, change %, ...
+        ContextItem::Spx | ContextItem::Ndx => Some((num(1)?, num(2)?)),
+        // price, ..., prev close at 7
+        ContextItem::Gold => {
+            let (last, prev) = (num(0)?, num(7)?);
+            Some((last, pct(last, prev)))
+        }
+        ContextItem::Northbound | ContextItem::Margin => None,
+    }
+}
+
+/// Context lines for the report header, in the order requested; a failed
+/// source becomes an "unavailable" line instead of failing the whole report.
+pub async fn market_context(items: &[ContextItem]) -> Vec<String> {
+    let symbols: Vec<&str> = items.iter().filter_map(|i| i.sina_symbol()).collect();
+    let quotes = if symbols.is_empty() { Ok(Default::default()) } else { sina::fetch_hq(&symbols).await };
+
+    let mut lines = Vec::new();
+    for &item in items {
+        let line = match item {
+            ContextItem::Northbound => match fetch_northbound().await {
+                Ok(flows) => northbound_line(&flows),
+                Err(e) => tr!("{}: unavailable ({})", "{}: 无法获取 ({})", item.label(), e),
+            },
+            ContextItem::Margin => match fetch_margin_balance().await {
+                Ok(rows) => margin_line(&rows),
+                Err(e) => tr!("{}: unavailable ({})", "{}: 无法获取 ({})", item.label(), e),
+            },
+            _ => {
+                let symbol = item.sina_symbol().unwrap_or_default();
+                match &quotes {
+                    Ok(quotes) => match quotes.get(symbol).and_then(|f| quote_change(item, f)) {
+                        Some((last, change)) => format!("{}: {:.4} ({:+.2}%)", item.label(), last, change),
+                        None => tr!("{}: no quote", "{}: 无报价", item.label()),
+                    },
+                    Err(e) => tr!("{}: unavailable ({})", "{}: 无法获取 ({})", item.label(), e),
+                }
+            }
+        };
+        lines.push(line);
+    }
+    lines
+}
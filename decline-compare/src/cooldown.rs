@@ -0,0 +1,229 @@
+//! Suppress repeat notifications for alert rules that stay triggered.
+//!
+//! A rule is one alert metric on one code. It notifies when it first
+//! triggers, again only after it clears and re-crosses, or once the cooldown
+//! has passed while it stays triggered, and never twice off the same latest
+//! bar, so an overlapping run's pass over the same bars is a no-op. State is
+//! persisted between runs so restarting watch mode doesn't replay the day's
+//! alerts.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Alert;
+use crate::config;
+use crate::i18n::tr;
+use crate::sina::Candle;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct RuleState {
+    /// Unix seconds of the last notification for this rule.
+    last_sent: u64,
+    /// Whether the rule was triggered on the most recent pass.
+    active: bool,
+    /// The code's latest bar at the last notification.
+    #[serde(default)]
+    bar: Option<BarStamp>,
+}
+
+/// What tells one pass's latest bar from another's: a live bar keeps its
+/// date and time all session, but its close and volume move with every
+/// trade.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct BarStamp {
+    time: NaiveDateTime,
+    close: f64,
+    volume: f64,
+}
+
+impl BarStamp {
+    fn of(candle: &Candle) -> BarStamp {
+        BarStamp { time: candle.time, close: candle.close, volume: candle.volume }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct AlertState {
+    rules: BTreeMap<String, RuleState>,
+    /// Alerts held back outside the delivery windows, sent as one summary
+    /// when the next window opens.
+    #[serde(default)]
+    pending: Vec<Alert>,
+    /// Price levels by their `levels::PriceAlert` form.
+    #[serde(default)]
+    levels: BTreeMap<String, LevelState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+struct LevelState {
+    /// Whether the level has notified before.
+    fired: bool,
+    /// Whether it held on the most recent check.
+    active: bool,
+}
+
+/// `$STOCK_DATA_DIR/alert_state.json`, one per profile.
+pub fn default_state_path(profile: Option<&str>) -> PathBuf {
+    config::data_dir().join(config::profiled("alert_state", "json", profile))
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn rule_key(alert: &Alert) -> String {
+    format!("{}|{}", alert.code, alert.kind.metric())
+}
+
+impl AlertState {
+    /// Load saved state, starting fresh if there is none yet. A file that
+    /// can't be read or parsed is an error, so held alerts and fired levels
+    /// aren't saved over.
+    pub fn load(path: &Path) -> Result<AlertState, Box<dyn std::error::Error>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(AlertState::default()),
+            Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        config::write_atomic(path, &serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Hold `alerts` for the next delivery window.
+    pub fn defer(&mut self, alerts: Vec<Alert>) {
+        self.pending.extend(alerts);
+    }
+
+    /// What is held back so far.
+    pub fn pending(&self) -> &[Alert] {
+        &self.pending
+    }
+
+    /// Take everything held back so far.
+    pub fn take_pending(&mut self) -> Vec<Alert> {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// Record whether the price level `key` holds on this check, and return
+    /// whether that should notify: on reaching it, only the first time
+    /// unless `repeat`.
+    pub fn level_reached(&mut self, key: &str, holds: bool, repeat: bool) -> bool {
+        let level = self.levels.entry(key.to_string()).or_default();
+        let send = holds && !level.active && (repeat || !level.fired);
+        level.active = holds;
+        level.fired |= send;
+        send
+    }
+
+    /// Record this pass's alerts, raised on `bars`, and return the ones that
+    /// should notify.
+    pub fn filter(
+        &mut self,
+        alerts: &[Alert],
+        bars: &HashMap<String, Vec<Candle>>,
+        now: u64,
+        cooldown_secs: u64,
+    ) -> Vec<Alert> {
+        let mut fresh = Vec::new();
+        let mut seen = Vec::new();
+        for alert in alerts {
+            let key = rule_key(alert);
+            let bar = bars.get(&alert.code).and_then(|candles| candles.last()).map(BarStamp::of);
+            let send = match self.rules.get(&key) {
+                Some(rule) if bar.is_some() && rule.bar == bar => false,
+                Some(rule) if rule.active => now.saturating_sub(rule.last_sent) >= cooldown_secs,
+                _ => true,
+            };
+            if send {
+                fresh.push(alert.clone());
+                self.rules.insert(key.clone(), RuleState { last_sent: now, active: true, bar });
+            } else if let Some(rule) = self.rules.get_mut(&key) {
+                rule.active = true;
+            }
+            seen.push(key);
+        }
+        for (key, rule) in self.rules.iter_mut() {
+            if !seen.contains(key) {
+                rule.active = false;
+            }
+        }
+        fresh
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use crate::alerts::AlertKind;
+    use crate::schedule;
+
+    const COOLDOWN: u64 = 3600;
+
+    fn alert() -> Alert {
+        Alert { code: "510300".to_string(), kind: AlertKind::KeltnerLower { level: 3.5, low: 3.48 } }
+    }
+
+    /// The watchlist's bars with a latest bar closing at `close` on
+    /// `volume` so far.
+    fn bars(close: f64, volume: f64) -> HashMap<String, Vec<Candle>> {
+        let time = NaiveDate::from_ymd_opt(2024, 3, 4).unwrap().and_time(schedule::SESSION_CLOSE);
+        let candle = Candle { time, open: 3.55, high: 3.56, low: 3.48, close, volume };
+        HashMap::from([("510300".to_string(), vec![candle])])
+    }
+
+    #[test]
+    fn load_refuses_a_corrupt_file() {
+        let path = std::env::temp_dir().join(format!("biga-alert-state-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert!(AlertState::load(&path).unwrap().rules.is_empty());
+
+        let mut state = AlertState::default();
+        state.defer(vec![alert()]);
+        state.save(&path).unwrap();
+        assert_eq!(AlertState::load(&path).unwrap().pending().len(), 1);
+
+        fs::write(&path, "{\"rules\": {").unwrap();
+        assert!(AlertState::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn re_crossing_the_same_day_alerts_again() {
+        let mut state = AlertState::default();
+        assert_eq!(state.filter(&[alert()], &bars(3.49, 1e6), 0, COOLDOWN).len(), 1);
+        assert!(state.filter(&[], &bars(3.52, 2e6), 300, COOLDOWN).is_empty());
+        assert_eq!(state.filter(&[alert()], &bars(3.47, 3e6), 600, COOLDOWN).len(), 1);
+    }
+
+    #[test]
+    fn a_rule_that_stays_triggered_waits_out_the_cooldown() {
+        let mut state = AlertState::default();
+        assert_eq!(state.filter(&[alert()], &bars(3.49, 1e6), 0, COOLDOWN).len(), 1);
+        assert!(state.filter(&[alert()], &bars(3.48, 2e6), COOLDOWN - 1, COOLDOWN).is_empty());
+        assert_eq!(state.filter(&[alert()], &bars(3.47, 3e6), COOLDOWN, COOLDOWN).len(), 1);
+    }
+
+    #[test]
+    fn a_duplicate_pass_over_the_same_bars_is_a_no_op() {
+        let mut state = AlertState::default();
+        assert_eq!(state.filter(&[alert()], &bars(3.49, 1e6), 0, COOLDOWN).len(), 1);
+        // An overlapping run, even once the cooldown is over.
+        assert!(state.filter(&[alert()], &bars(3.49, 1e6), 10 * COOLDOWN, COOLDOWN).is_empty());
+        // Cleared and back on the same bar is still the same pass's data.
+        state.filter(&[], &bars(3.49, 1e6), 10 * COOLDOWN, COOLDOWN);
+        assert!(state.filter(&[alert()], &bars(3.49, 1e6), 10 * COOLDOWN, COOLDOWN).is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+//! Alerts when a code's rolling correlation to the benchmark shifts, often
+//! the first sign of a regime change: a bond fund starting to move with
+//! equities, or a sector decoupling from the index, matters to how
+//! diversified the watchlist really is.
+//!
+//! The correlation is of daily returns on the days both trade, over
+//! `[correlation] window` days, compared with the same measure
+//! `lookback_days` earlier. The benchmark is `[portfolio] benchmark`.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+
+use crate::alerts::AlertKind;
+use crate::config::CorrelationConfig;
+use crate::indicators;
+use crate::sina::Candle;
+
+/// Daily returns of `code_bars` and `bench` on the days both have a bar and
+/// the one before it.
+fn paired_returns(code_bars: &[Candle], bench: &[Candle]) -> (Vec<f64>, Vec<f64>) {
+    let bench: HashMap<NaiveDate, f64> = bench.iter().map(|c| (c.date(), c.close)).collect();
+    let shared: Vec<(f64, f64)> =
+        code_bars.iter().filter_map(|c| Some((c.close, *bench.get(&c.date())?))).collect();
+    shared.windows(2).filter(|w| w[0].0 > 0.0 && w[0].1 > 0.0).map(|w| (w[1].0 / w[0].0 - 1.0, w[1].1 / w[0].1 - 1.0)).unzip()
+}
+
+/// A shift alert if `code_bars`' correlation to `benchmark`'s `bench` moved
+/// by at least `cfg.min_shift` over the lookback.
+pub fn shift(code_bars: &[Candle], benchmark: &str, bench: &[Candle], cfg: &CorrelationConfig) -> Option<AlertKind> {
+    let (a, b) = paired_returns(code_bars, bench);
+    let (window, lookback) = (cfg.window, cfg.lookback_days);
+    if a.len() < window + lookback {
+        return None;
+    }
+    let at = |end: usize| indicators::correlation(&a[end - window..end], &b[end - window..end]);
+    let now = at(a.len())?;
+    let before = at(a.len() - lookback)?;
+    ((now - before).abs() >= cfg.min_shift).then(|| AlertKind::CorrelationShift {
+        benchmark: benchmark.to_string(),
+        before,
+        now,
+        threshold: cfg.min_shift,
+    })
+}
@@ -0,0 +1,1927 @@
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate};
+use clap::{Args, ValueEnum};
+use futures::stream::{self, Stream, StreamExt};
+use indicatif::{ProgressBar, ProgressStyle};
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize};
+use tokio::time::Instant;
+
+use crate::alerts::{self, Alert};
+use crate::anomaly::{self, Anomaly};
+use crate::archive;
+use crate::cache::{self, KlineCache};
+use crate::config::{Config, OutputFormat};
+use crate::context::{self, ContextItem};
+use crate::correlation;
+use crate::cooldown;
+use crate::dividends;
+use crate::events;
+use crate::export;
+use crate::fundinfo;
+use crate::http;
+use crate::i18n::tr;
+use crate::ladder::{self, Ladder};
+use crate::instruments::{self, DurationBucket, FundKind, Settlement};
+use crate::indicators::{self, ADX_PERIOD, ATR_PERIOD, SAR_MAX_STEP, SAR_STEP, SarPoint, ZSCORE_PERIOD};
+use crate::mqtt;
+use crate::notify;
+use crate::plugins;
+use crate::postgres;
+use crate::pubsub;
+use crate::recovery::{self, RecoveryStats};
+use crate::schedule::{self, BarSession};
+use crate::scripts;
+use crate::score::{self, Formula};
+use crate::sina::{self, Candle};
+use crate::smooth::{self, Smoothing};
+use crate::snooze::Snoozes;
+use crate::spot;
+use crate::template::{Template, Value};
+use crate::trend::{self, Trend};
+use crate::valuation::{self, IndexValuation};
+
+/// Metric names available to `--score` formulas.
+pub const METRICS: &[&str] = &[
+    "decline", "half_decline", "adx", "rsi", "zscore", "drawdown", "range_pct", "return_pctile", "recovery_days", "fwd20",
+    "fee", "turnover", "spread_bps", "pe_pctile", "pb_pctile", "div_yield", "premium", "yield_proxy",
+    "drawdown_bps", "trend", "reg_slope", "reg_r2", "reg_pos",
+];
+
+/// Row fields `--columns` and `--format` take besides the metrics.
+const FIELDS: &[&str] = &["code", "name", "date", "kind", "close", "atr", "score"];
+
+/// Days without a bar after which a suspended code is worth dropping from
+/// the watchlist.
+const REMOVAL_DAYS: i64 = 30;
+
+/// Bars averaged for a row's daily turnover.
+const LIQUIDITY_DAYS: usize = 20;
+
+/// Most a kline close and the quote's may differ, percent, before the
+/// sources count as disagreeing. Ex-dividend days move the quoted previous
+/// close by the payout.
+const MAX_SOURCE_GAP: f64 = 0.5;
+
+// ADX needs 2 * period bars to seed; fetch extra so Wilder smoothing settles.
+const INDICATOR_HISTORY: usize = 4 * ADX_PERIOD;
+
+#[derive(Args, Debug, Clone)]
+pub struct DeclineArgs {
+    /// Number of trading days to measure the decline over
+    #[arg(default_value_t = 5)]
+    day: usize,
+
+    /// Only keep ETFs whose ADX(14) is at least this value (trending); bond
+    /// funds aren't filtered
+    #[arg(long)]
+    min_adx: Option<f64>,
+
+    /// Only keep ETFs whose ADX(14) is at most this value (range-bound); bond
+    /// funds aren't filtered
+    #[arg(long)]
+    max_adx: Option<f64>,
+
+    /// Lookback for the Donchian channel breakout check [default: 20]
+    #[arg(long)]
+    channel_days: Option<usize>,
+
+    /// Lookback for the close's percentile within the high-low range [default: 20]
+    #[arg(long)]
+    range_days: Option<usize>,
+
+    /// Trading days of history used for the N-day return distribution [default: 500]
+    #[arg(long)]
+    history_days: Option<usize>,
+
+    /// Closes the regression trend channel is fitted to [default: 60]
+    #[arg(long)]
+    regression_days: Option<usize>,
+
+    /// Smooth the bars the indicators read: off, kalman or hull [default: off]
+    #[arg(long, value_enum)]
+    smooth: Option<Smoothing>,
+
+    /// Composite score over metrics, e.g. "0.4*zscore + 0.3*rsi_rank + 0.3*drawdown_rank".
+    /// Metrics: decline, half_decline, adx, rsi, zscore, drawdown, range_pct,
+    /// return_pctile, recovery_days, fwd20, fee (expense ratio), turnover
+    /// (average daily CNY), spread_bps (quoted bid-ask), pe_pctile and
+    /// pb_pctile (tracked index's valuation percentiles), div_yield
+    /// (trailing 12-month distributions over the close), premium (close over
+    /// NAV, percent), trend (-2 strong down to 2 strong up), reg_slope,
+    /// reg_r2 and reg_pos (the regression channel's annualized slope, fit
+    /// and the close's place in it, 0-100), yield_proxy and drawdown_bps
+    /// (bond funds only), and
+    /// any loaded plugin's or configured script's name; append _rank
+    /// for the 0-1 percentile rank across the result set
+    #[arg(long)]
+    score: Option<Formula>,
+
+    /// Only keep rows whose metric passes, e.g. "rsi<30", "momentum>=0.5" or
+    /// "trend<=down" (strong-down, down, range, up, strong-up); repeatable,
+    /// and rows without the metric are dropped
+    #[arg(long)]
+    filter: Vec<MetricFilter>,
+
+    /// Skip the market context header
+    #[arg(long)]
+    no_context: bool,
+
+    /// Context lines to show above the report, comma separated
+    /// [default: northbound,margin,usdcny,spx,ndx,gold]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    context: Option<Vec<ContextItem>>,
+
+    /// Report format [default: text]
+    #[arg(long, value_enum)]
+    output: Option<OutputFormat>,
+
+    /// Columns of the text report, comma separated, e.g. code,name,decline,rsi;
+    /// any metric, or code, name, date, kind, close, atr, score and trend
+    #[arg(long, value_delimiter = ',')]
+    columns: Vec<String>,
+
+    /// Template for each text report line, e.g. "{code} {decline:.2}% rsi {rsi:.0}";
+    /// takes the same fields as --columns
+    #[arg(long, conflicts_with = "columns")]
+    format: Option<Template>,
+
+    /// Order the report by decline (deepest first) or score (highest first)
+    #[arg(long, value_enum, default_value_t = SortKey::Decline)]
+    sort: SortKey,
+
+    /// Print each row as soon as its data arrives, then the sorted report
+    #[arg(long)]
+    stream: bool,
+
+    /// Refetch even if bars cached after today's close are still current
+    #[arg(long)]
+    refresh: bool,
+
+    /// Fail, listing why, if any code is skipped, timed out, suspended or
+    /// short of history, or its close disagrees between sources
+    #[arg(long, conflicts_with = "stream")]
+    strict: bool,
+
+    /// Show only the most liquid, cheapest and closest-to-NAV ETF of those
+    /// tracking the same index
+    #[arg(long)]
+    dedupe_index: bool,
+
+    /// Flag ETFs averaging less daily turnover than this, CNY [default: 5000000]
+    #[arg(long)]
+    min_turnover: Option<f64>,
+
+    /// Flag ETFs quoted wider than this bid-ask spread, bps [default: 30]
+    #[arg(long)]
+    max_spread_bps: Option<f64>,
+
+    /// Show the PE and PB percentiles of the index each ETF tracks
+    #[arg(long)]
+    valuation: bool,
+
+    /// Suggest a ladder of limit buys below the close for each flagged dip
+    #[arg(long)]
+    ladder: bool,
+
+    /// Ladder tranche depths in ATRs, comma separated [default: 1,2,3]
+    #[arg(long, value_delimiter = ',')]
+    ladder_atr: Option<Vec<f64>>,
+
+    /// Cash split across one ETF's ladder [default: 30000]
+    #[arg(long)]
+    ladder_cash: Option<f64>,
+
+    /// Also ladder rows whose --score is at least this [default: none]
+    #[arg(long)]
+    ladder_min_score: Option<f64>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    Decline,
+    Score,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(into = "String")]
+pub struct MetricFilter {
+    metric: String,
+    op: &'static str,
+    value: f64,
+}
+
+// By hand rather than `try_from`: the derive can't see past `op`'s
+// 'static lifetime.
+impl<'de> Deserialize<'de> for MetricFilter {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<MetricFilter> for String {
+    fn from(f: MetricFilter) -> String {
+        format!("{}{}{}", f.metric, f.op, f.value)
+    }
+}
+
+impl FromStr for MetricFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Two-character operators first, so ">=" isn't read as ">".
+        for op in [">=", "<=", ">", "<"] {
+            if let Some((metric, value)) = s.split_once(op) {
+                let (metric, value) = (metric.trim(), value.trim());
+                // Regimes compare by name as well as by number.
+                let regime = || (metric == "trend").then(|| Trend::from_str(value, true).ok()).flatten().map(Trend::value);
+                let value = value
+                    .parse()
+                    .ok()
+                    .or_else(regime)
+                    .ok_or_else(|| format!("expected a number after '{}' in '{}'", op, s))?;
+                return Ok(MetricFilter { metric: metric.to_string(), op, value });
+            }
+        }
+        Err(format!("expected METRIC<op>VALUE with one of >=, <=, >, <, got '{}'", s))
+    }
+}
+
+impl MetricFilter {
+    fn passes(&self, metrics: &BTreeMap<&'static str, f64>) -> bool {
+        let Some(&v) = metrics.get(self.metric.as_str()) else {
+            return false;
+        };
+        match self.op {
+            ">=" => v >= self.value,
+            "<=" => v <= self.value,
+            ">" => v > self.value,
+            _ => v < self.value,
+        }
+    }
+}
+
+/// Whether `name` is a built-in metric or a loaded plugin's or script's.
+fn is_metric(name: &str) -> bool {
+    METRICS.contains(&name)
+        || plugins::all().iter().any(|p| p.name == name)
+        || scripts::all().iter().any(|s| s.name == name)
+}
+
+impl DeclineArgs {
+    /// Layer the flags given on the command line over `cfg`.
+    pub fn apply(&self, cfg: &mut Config) {
+        if let Some(v) = self.channel_days {
+            cfg.indicators.channel_days = v;
+        }
+        if let Some(v) = self.range_days {
+            cfg.indicators.range_days = v;
+        }
+        if let Some(v) = self.history_days {
+            cfg.indicators.history_days = v;
+        }
+        if let Some(v) = self.regression_days {
+            cfg.indicators.regression_days = v;
+        }
+        if let Some(v) = self.smooth {
+            cfg.smoothing.method = v;
+        }
+        if let Some(v) = &self.score {
+            cfg.score = Some(v.clone());
+        }
+        if let Some(v) = &self.context {
+            cfg.context = v.clone();
+        }
+        if self.no_context {
+            cfg.context.clear();
+        }
+        if let Some(v) = self.output {
+            cfg.output = v;
+        }
+        if let Some(v) = self.min_turnover {
+            cfg.liquidity.min_turnover = v;
+        }
+        if let Some(v) = self.max_spread_bps {
+            cfg.liquidity.max_spread_bps = v;
+        }
+        if let Some(v) = &self.ladder_atr {
+            cfg.ladder.atr_multiples = v.clone();
+        }
+        if let Some(v) = self.ladder_cash {
+            cfg.ladder.cash = v;
+        }
+        if let Some(v) = self.ladder_min_score {
+            cfg.ladder.min_score = Some(v);
+        }
+    }
+
+    /// Fields named by `--columns` and `--format`.
+    fn fields(&self) -> impl Iterator<Item = &str> {
+        self.columns.iter().map(String::as_str).chain(self.format.iter().flat_map(Template::fields))
+    }
+
+    /// These flags over a window of `day` trading days instead.
+    #[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+    pub fn with_day(&self, day: usize) -> DeclineArgs {
+        DeclineArgs { day, ..self.clone() }
+    }
+
+    /// Bars to request so every indicator and distribution has its history.
+    pub fn fetch_len(&self, cfg: &Config) -> usize {
+        let ind = &cfg.indicators;
+        let overridden =
+            cfg.overrides.values().flat_map(|o| [o.range_days, o.history_days, o.regression_days]).flatten();
+        self.day
+            .max(INDICATOR_HISTORY)
+            .max(ind.range_days)
+            .max(ind.history_days)
+            .max(ind.regression_days)
+            .max(overridden.max().unwrap_or(0))
+    }
+
+    /// Whether `adx` is within `code`'s ADX bounds, from its overrides or
+    /// else the flags. Without bounds every row passes.
+    fn adx_passes(&self, adx: Option<f64>, code: &str, cfg: &Config) -> bool {
+        let overrides = cfg.overrides.get(code);
+        let min = overrides.and_then(|o| o.min_adx).or(self.min_adx);
+        let max = overrides.and_then(|o| o.max_adx).or(self.max_adx);
+        if min.is_none() && max.is_none() {
+            return true;
+        }
+        let Some(adx) = adx else {
+            return false;
+        };
+        min.is_none_or(|min| adx >= min) && max.is_none_or(|max| adx <= max)
+    }
+
+    /// The row filters for `code`: the flags, with any on a metric its
+    /// overrides also filter replaced by those.
+    fn filters<'a>(&'a self, code: &str, cfg: &'a Config) -> Vec<&'a MetricFilter> {
+        let own: &[MetricFilter] = cfg.overrides.get(code).map_or(&[], |o| &o.filter);
+        self.filter.iter().filter(|f| !own.iter().any(|o| o.metric == f.metric)).chain(own).collect()
+    }
+}
+
+#[derive(Serialize)]
+pub struct DeclineRow {
+    code: String,
+    /// Fund name, when `--columns` or `--format` asks for it.
+    name: Option<String>,
+    /// Trading day of the latest bar.
+    date: NaiveDate,
+    settlement: Settlement,
+    kind: FundKind,
+    /// Set for bond funds, with the duration bucket if known. Trend and
+    /// momentum indicators are left out for them.
+    bond: Option<Bond>,
+    rate: f64,
+    half_rate: f64,
+    /// Days the rates span when the history is shorter than `--day`, as
+    /// for a newly listed fund; `None` for the full window.
+    span: Option<usize>,
+    close: f64,
+    atr: Option<f64>,
+    sar: Option<SarPoint>,
+    /// Trend regime, left out for bond funds.
+    trend: Option<Trend>,
+    notes: Vec<String>,
+    metrics: BTreeMap<&'static str, f64>,
+    score: Option<f64>,
+    recovery: Option<RecoveryStats>,
+    /// Below the turnover floor or wider than the spread limit.
+    illiquid: bool,
+    /// Suggested limit buys, with `--ladder` on flagged rows.
+    ladder: Option<Ladder>,
+    /// The close's premium over the latest NAV in percent, for LOFs and
+    /// closed-end funds, or every row with `--dedupe-index`.
+    premium: Option<f64>,
+    /// With `--dedupe-index`: the tracked index and the other screened ETFs
+    /// tracking it.
+    tracking_index: Option<String>,
+    alternatives: Vec<String>,
+    /// With `--valuation`: the tracked index's PE and PB.
+    valuation: Option<IndexValuation>,
+}
+
+// Read by the optional sinks only.
+impl DeclineRow {
+    #[cfg_attr(not(any(feature = "postgres", feature = "redis", feature = "mqtt", feature = "grpc")), allow(dead_code))]
+    pub fn code(&self) -> &str {
+        &self.code
+    }
+
+    #[cfg_attr(not(any(feature = "postgres", feature = "redis", feature = "grpc")), allow(dead_code))]
+    pub fn date(&self) -> NaiveDate {
+        self.date
+    }
+
+    /// Every number the row carries by name: the metrics, the close and
+    /// the score.
+    #[cfg_attr(not(any(feature = "postgres", feature = "redis", feature = "grpc")), allow(dead_code))]
+    pub fn values(&self) -> impl Iterator<Item = (&str, f64)> {
+        let extra = [("close", Some(self.close)), ("score", self.score)];
+        self.metrics.iter().map(|(&k, &v)| (k, v)).chain(extra.into_iter().filter_map(|(k, v)| Some((k, v?))))
+    }
+}
+
+#[derive(Serialize)]
+struct Bond {
+    duration: Option<DurationBucket>,
+}
+
+/// Trading days in the window a bond fund's yield proxy is annualized from.
+const YIELD_PROXY_DAYS: usize = 250;
+
+/// Annualized price return over the last `YIELD_PROXY_DAYS` bars, percent.
+/// Bond ETFs mostly accrue coupons into NAV rather than paying them out, so
+/// this stands in for their yield.
+fn yield_proxy(closes: &[f64]) -> Option<f64> {
+    let window = &closes[closes.len().saturating_sub(YIELD_PROXY_DAYS + 1)..];
+    let (first, last) = (*window.first()?, *window.last()?);
+    let days = window.len() - 1;
+    (days >= YIELD_PROXY_DAYS / 4 && first > 0.0)
+        .then(|| ((last / first).powf(YIELD_PROXY_DAYS as f64 / days as f64) - 1.0) * 100.0)
+}
+
+fn calculate(older: f64, newer: f64) -> f64 {
+    if older > 0.0 {
+        (newer - older) / older * 100.0
+    } else {
+        0.0
+    }
+}
+
+/// Decline row for `code` if its close over the last `day` bars fell.
+/// Indicators read `smoothed`, the bars after any `[smoothing]`.
+#[allow(clippy::too_many_arguments)]
+fn analyze(
+    code: &str,
+    candles: &[Candle],
+    smoothed: &[Candle],
+    args: &DeclineArgs,
+    cfg: &Config,
+    sar_points: &[SarPoint],
+    notes: Vec<String>,
+    bond: Option<Option<DurationBucket>>,
+) -> Option<DeclineRow> {
+    let day = args.day.min(candles.len());
+    let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+    let smooth_closes: Vec<f64> = smoothed.iter().map(|c| c.close).collect();
+    let prices = &closes[closes.len() - day..];
+    let price_pre = prices[0];
+    let price_today = prices[day - 1];
+    if price_today >= price_pre {
+        return None;
+    }
+
+    let today_decline_rate = calculate(price_pre, price_today);
+
+    let half_day_idx = if day > 1 { day / 2 } else { 0 };
+    let half_day_decline_rate = if half_day_idx < prices.len() && day > 1 {
+        let price_half = prices[day - 1 - half_day_idx];
+        calculate(price_pre, price_half)
+    } else {
+        0.0
+    };
+
+    let mut metrics = BTreeMap::new();
+    metrics.insert("decline", today_decline_rate);
+    metrics.insert("half_decline", half_day_decline_rate);
+    // Average daily traded value; Sina reports fund volume in shares.
+    let recent = &candles[candles.len().saturating_sub(LIQUIDITY_DAYS)..];
+    metrics.insert("turnover", recent.iter().map(|c| c.close * c.volume).sum::<f64>() / recent.len() as f64);
+    let equity = bond.is_none();
+    let optional = [
+        ("adx", indicators::adx(smoothed, ADX_PERIOD).filter(|_| equity)),
+        ("rsi", indicators::rsi(&smooth_closes, cfg.indicators.rsi_period).filter(|_| equity)),
+        ("yield_proxy", yield_proxy(&closes).filter(|_| !equity)),
+        ("drawdown_bps", indicators::drawdown(&closes).map(|d| d * 100.0).filter(|_| !equity)),
+        ("zscore", indicators::zscore(&smooth_closes, ZSCORE_PERIOD)),
+        ("drawdown", indicators::drawdown(&closes)),
+        ("range_pct", indicators::range_percentile(smoothed, cfg.indicators.range_days)),
+        (
+            "return_pctile",
+            indicators::percentile_of(today_decline_rate, &indicators::rolling_returns(&closes, day)),
+        ),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            metrics.insert(name, value);
+        }
+    }
+    if let Some(fit) = indicators::regression(&smooth_closes, cfg.indicators.regression_days) {
+        metrics.insert("reg_slope", fit.annual_slope);
+        metrics.insert("reg_r2", fit.r_squared);
+        metrics.insert("reg_pos", fit.position);
+    }
+    let trend = trend::classify(&smooth_closes, metrics.get("adx").copied());
+    if let Some(trend) = trend {
+        metrics.insert("trend", trend.value());
+    }
+
+    for plugin in plugins::all() {
+        match plugin.compute(candles) {
+            Ok(Some(value)) => {
+                metrics.insert(plugin.name, value);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", tr!("Plugin {} failed on {}: {}", "插件 {} 计算 {} 失败: {}", plugin.name, code, e)),
+        }
+    }
+    // Scripts run last so they can read every other metric.
+    for script in scripts::all() {
+        match script.eval(candles, &metrics) {
+            Ok(Some(value)) => {
+                metrics.insert(script.name, value);
+            }
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", tr!("Script {} failed on {}: {}", "脚本 {} 计算 {} 失败: {}", script.name, code, e)),
+        }
+    }
+
+    let recovery = recovery::similar_declines(&closes, day, today_decline_rate);
+    if let Some(stats) = &recovery {
+        if let Some(days) = stats.median_days {
+            metrics.insert("recovery_days", days);
+        }
+        if let Some(fwd) = stats.median_forward {
+            metrics.insert("fwd20", fwd);
+        }
+    }
+
+    Some(DeclineRow {
+        name: None,
+        code: code.to_string(),
+        date: candles[candles.len() - 1].date(),
+        settlement: instruments::settlement(code, cfg),
+        kind: instruments::kind(code, cfg),
+        bond: bond.map(|duration| Bond { duration }),
+        rate: today_decline_rate,
+        half_rate: half_day_decline_rate,
+        span: (day < args.day).then_some(day),
+        close: price_today,
+        atr: indicators::atr(candles, ATR_PERIOD),
+        sar: sar_points.last().copied(),
+        trend,
+        notes,
+        metrics,
+        score: None,
+        recovery,
+        illiquid: false,
+        ladder: None,
+        premium: None,
+        tracking_index: None,
+        alternatives: Vec::new(),
+        valuation: None,
+    })
+}
+
+/// Everything one pass of the decline screen produces, before rendering.
+#[derive(Serialize)]
+pub struct Report {
+    /// When the screen ran, in exchange time.
+    pub generated_at: DateTime<FixedOffset>,
+    /// Version of the tool that made the report.
+    pub version: &'static str,
+    /// Where each screened code's bars came from.
+    pub sources: Vec<BarSource>,
+    /// Newest bar date across the watchlist and whether it is still trading.
+    pub as_of: Option<NaiveDate>,
+    pub session: Option<BarSession>,
+    pub context: Vec<String>,
+    pub rows: Vec<DeclineRow>,
+    pub alerts: Vec<Alert>,
+    /// Latest daily returns far outside their code's usual range.
+    pub anomalies: Vec<Anomaly>,
+    /// Codes still unfetched when the deadline passed.
+    pub timed_out: Vec<String>,
+    /// Watchlist codes left out by `snooze`.
+    pub snoozed: Vec<String>,
+    /// Codes whose bars stop short of the rest of their feed's.
+    pub suspended: Vec<Suspension>,
+    /// Codes left out of the rows for want of data.
+    pub skipped: Vec<Skip>,
+    /// Rows whose close the realtime quote doesn't bear out.
+    pub disagreements: Vec<Disagreement>,
+    /// Portfolio cash left if every ladder fills; `None` when no cash is
+    /// configured, so ladders aren't capped.
+    pub ladder_cash_left: Option<f64>,
+}
+
+/// A code that has stopped trading, left out of the rows and alerts.
+#[derive(Debug, Clone, Serialize)]
+pub struct Suspension {
+    pub code: String,
+    /// First weekday without a bar; `None` when there are no bars at all,
+    /// as for a delisted or mistyped code.
+    pub since: Option<NaiveDate>,
+    /// Gone long enough to take off the watchlist.
+    pub remove: bool,
+}
+
+/// A code with no row because its data fell short.
+#[derive(Debug, Clone, Serialize)]
+pub struct Skip {
+    pub code: String,
+    pub reason: String,
+}
+
+/// A close the kline and the realtime quote give differently.
+#[derive(Debug, Clone, Serialize)]
+pub struct Disagreement {
+    pub code: String,
+    pub date: NaiveDate,
+    pub kline: f64,
+    pub quote: f64,
+}
+
+/// How a code's bars were obtained on this run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Origin {
+    /// Fetched as daily klines.
+    Live,
+    /// Reused from the bars saved after the last close.
+    Cache,
+    /// Kept from an earlier watch pass and brought up to date from a
+    /// realtime quote.
+    Quote,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BarSource {
+    pub code: String,
+    /// Data feed the bars come from.
+    pub feed: &'static str,
+    pub origin: Origin,
+    /// When the bars were fetched, if known.
+    pub fetched_at: Option<DateTime<FixedOffset>>,
+}
+
+/// The feed `sina::fetch_etf_kline` gets `code`'s bars from.
+fn feed(code: &str) -> &'static str {
+    if !spot::is_spot(code) {
+        "sina"
+    } else if code.contains('-') {
+        "coinbase"
+    } else {
+        "sina-global"
+    }
+}
+
+impl Report {
+    /// Mark `codes` as obtained by `origin` at `fetched_at`, for callers
+    /// that know more about reused bars than `build` does.
+    pub fn stamp(&mut self, codes: &[String], origin: Origin, fetched_at: Option<DateTime<FixedOffset>>) {
+        for source in self.sources.iter_mut().filter(|s| codes.contains(&s.code)) {
+            source.origin = origin;
+            source.fetched_at = fetched_at;
+        }
+    }
+}
+
+/// Fetch every watchlist code and build the scored, sorted report. `cfg`
+/// should already have this command's flags applied.
+pub async fn collect(args: &DeclineArgs, cfg: &Config) -> Result<Report, Box<dyn std::error::Error>> {
+    validate(args, cfg)?;
+    let deadline = http::deadline(&cfg.http);
+    let live = args.stream && cfg.output == OutputFormat::Text;
+    if live {
+        println!("\n {}", tr!("Rows as they arrive:", "实时结果:"));
+        println!("-----------------------------------------");
+    }
+
+    let fetch_len = args.fetch_len(cfg);
+    let cache_path = cache::default_path();
+    let cache = if args.refresh || cache::bypassed() {
+        KlineCache::default()
+    } else {
+        KlineCache::load(&cache_path, fetch_len, cooldown::now_secs())
+    };
+    let mut bars = BTreeMap::new();
+    let mut missing = Vec::new();
+    for (i, code) in cfg.watchlist.iter().enumerate() {
+        match cache.bars(code, fetch_len) {
+            Some(candles) => {
+                bars.insert(code.clone(), candles);
+            }
+            None => missing.push(i),
+        }
+    }
+    let cached: Vec<String> = bars.keys().cloned().collect();
+    if !bars.is_empty() {
+        eprintln!(
+            "{}",
+            tr!(
+                "Using bars cached after the close for {} of {} codes (--refresh to refetch)",
+                "使用收盘后缓存的 {} / {} 个代码数据 (--refresh 重新获取)",
+                bars.len(),
+                cfg.watchlist.len()
+            )
+        );
+    }
+
+    let show = |row: &Option<DeclineRow>| {
+        if live && let Some(row) = row {
+            println!("{}", row_line(row, args, cfg, None, false));
+        }
+    };
+    let bonds = instruments::bonds(&cfg.watchlist, cfg);
+    let loaded = cfg
+        .watchlist
+        .iter()
+        .enumerate()
+        .filter_map(|(i, code)| Some((i, code.clone(), (bars.get(code)?.clone(), None))))
+        .collect();
+    let mut outcomes = process_loaded(loaded, args, cfg, &bonds);
+    for (_, outcome) in &outcomes {
+        show(&outcome.row);
+    }
+
+    let codes: Vec<String> = missing.iter().map(|&i| cfg.watchlist[i].clone()).collect();
+    let progress = progress_bar(codes.len());
+    let fetched: Vec<(usize, Outcome, Option<Vec<Candle>>)> = fetch_stream(&codes, args, cfg, deadline, &progress)
+        .map(|(i, fetch)| {
+            let fresh = match &fetch {
+                Some(Ok((candles, status))) if status.is_none_or(|s| s == 200) && !candles.is_empty() => {
+                    Some(candles.clone())
+                }
+                _ => None,
+            };
+            // Keep diagnostics and live rows from tearing the progress bar.
+            let outcome = progress.suspend(|| process(&codes[i], fetch, args, cfg, &bonds));
+            progress.suspend(|| show(&outcome.row));
+            (missing[i], outcome, fresh)
+        })
+        .collect()
+        .await;
+    progress.finish_and_clear();
+
+    let mut refetched = false;
+    for (i, outcome, fresh) in fetched {
+        if let Some(candles) = fresh {
+            bars.insert(cfg.watchlist[i].clone(), candles);
+            refetched = true;
+        }
+        outcomes.push((i, outcome));
+    }
+    outcomes.sort_by_key(|(i, _)| *i);
+    let mut report = finish(args, cfg, outcomes.into_iter().map(|(_, o)| o).collect(), deadline).await?;
+    report.stamp(&cached, Origin::Cache, Some(schedule::at(cache.written_at()).fixed_offset()));
+
+    // Only a closed session's bars are final enough to reuse.
+    if refetched
+        && !cache::bypassed()
+        && report.session.is_some_and(|s| s != BarSession::Live)
+        && let Err(e) = KlineCache::save(&cache_path, fetch_len, bars, cooldown::now_secs())
+    {
+        eprintln!("{}", tr!("Failed to save cache to {}: {}", "保存缓存到 {} 失败: {}", cache_path.display(), e));
+    }
+    Ok(report)
+}
+
+/// Reject score formulas and sort orders that can't be satisfied before
+/// anything is fetched.
+pub fn validate(args: &DeclineArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(formula) = &cfg.score {
+        for var in formula.variables() {
+            let base = var.strip_suffix("_rank").unwrap_or(var);
+            if !is_metric(base) {
+                return Err(format!("unknown metric '{}' in score formula (available: {})", var, available()).into());
+            }
+        }
+    } else if args.sort == SortKey::Score {
+        return Err("--sort score needs a --score formula".into());
+    }
+    for filter in &args.filter {
+        if !is_metric(&filter.metric) {
+            return Err(format!("unknown metric '{}' in --filter (available: {})", filter.metric, available()).into());
+        }
+    }
+    for (code, overrides) in &cfg.overrides {
+        if let Some(filter) = overrides.filter.iter().find(|f| !is_metric(&f.metric)) {
+            return Err(format!(
+                "unknown metric '{}' in the filter for {} (available: {})",
+                filter.metric,
+                code,
+                available()
+            )
+            .into());
+        }
+    }
+    for field in args.fields() {
+        if !FIELDS.contains(&field) && !is_metric(field) {
+            return Err(format!(
+                "unknown field '{}' in --columns or --format (available: {}, {})",
+                field,
+                FIELDS.join(", "),
+                available()
+            )
+            .into());
+        }
+    }
+    if cfg.notify.desktop && !notify::DESKTOP {
+        return Err("desktop notifications are on, but this build lacks the desktop feature".into());
+    }
+    if !cfg.scripts.is_empty() && !scripts::AVAILABLE {
+        return Err("[scripts] is configured, but this build lacks the scripts feature".into());
+    }
+    if cfg.postgres.is_some() && !postgres::AVAILABLE {
+        return Err("[postgres] is configured, but this build lacks the postgres feature".into());
+    }
+    if cfg.redis.is_some() && !pubsub::AVAILABLE {
+        return Err("[redis] is configured, but this build lacks the redis feature".into());
+    }
+    if let Some(mqtt) = &cfg.mqtt {
+        if !mqtt::AVAILABLE {
+            return Err("[mqtt] is configured, but this build lacks the mqtt feature".into());
+        }
+        if let Some(metric) = mqtt.metrics.iter().find(|m| !FIELDS.contains(&m.as_str()) && !is_metric(m)) {
+            return Err(format!(
+                "unknown field '{}' in mqtt.metrics (available: {}, {})",
+                metric,
+                FIELDS.join(", "),
+                available()
+            )
+            .into());
+        }
+    }
+    if export::enabled(cfg)
+        && let Some(column) = cfg.export.columns.iter().find(|c| !FIELDS.contains(&c.as_str()) && !is_metric(c))
+    {
+        return Err(format!(
+            "unknown field '{}' in export.columns (available: {}, {})",
+            column,
+            FIELDS.join(", "),
+            available()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+fn available() -> String {
+    METRICS
+        .iter()
+        .copied()
+        .chain(plugins::all().iter().map(|p| p.name))
+        .chain(scripts::all().iter().map(|s| s.name))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// One code's kline fetch; `None` when the deadline passed first.
+pub type Fetch = Option<Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>>>;
+
+/// A code that fetched, with its position in the watchlist.
+pub type Loaded = (usize, String, (Vec<Candle>, Option<u16>));
+
+fn progress_bar(len: usize) -> ProgressBar {
+    // Draws on stderr only when it is a terminal, so pipes and cron logs stay clean.
+    let progress = ProgressBar::new(len as u64);
+    progress.set_style(
+        ProgressStyle::with_template("{bar:30} {pos}/{len} {msg}").unwrap_or_else(|_| ProgressStyle::default_bar()),
+    );
+    progress
+}
+
+/// Fetch daily bars for `codes` concurrently, yielding each with its index
+/// in `codes` as soon as it completes.
+fn fetch_stream<'a>(
+    codes: &'a [String],
+    args: &DeclineArgs,
+    cfg: &Config,
+    deadline: Option<Instant>,
+    progress: &'a ProgressBar,
+) -> impl Stream<Item = (usize, Fetch)> + 'a {
+    let fetch_len = args.fetch_len(cfg);
+    stream::iter(codes.iter().enumerate().map(move |(i, code)| async move {
+        let fetch = http::until(deadline, sina::fetch_etf_kline(code, fetch_len)).await;
+        let status = match &fetch {
+            Some(Ok((_, Some(status)))) if *status != 200 => format!("HTTP {}", status),
+            Some(Ok(_)) => tr!("ok", "成功"),
+            Some(Err(_)) => tr!("failed", "失败"),
+            None => tr!("timed out", "超时"),
+        };
+        progress.set_message(format!("{} {}", code, status));
+        progress.inc(1);
+        (i, fetch)
+    }))
+    .buffer_unordered(cfg.http.concurrency.max(1))
+}
+
+/// Fetch daily bars for `codes` concurrently, in order.
+pub async fn fetch_klines(
+    codes: &[String],
+    args: &DeclineArgs,
+    cfg: &Config,
+    deadline: Option<Instant>,
+) -> Vec<(String, Fetch)> {
+    let progress = progress_bar(codes.len());
+    let mut fetched: Vec<(usize, Fetch)> = fetch_stream(codes, args, cfg, deadline, &progress).collect().await;
+    progress.finish_and_clear();
+    fetched.sort_by_key(|(i, _)| *i);
+    fetched.into_iter().map(|(i, fetch)| (codes[i].clone(), fetch)).collect()
+}
+
+/// What one code contributes to the report.
+#[derive(Default)]
+pub struct Outcome {
+    code: String,
+    source: Option<BarSource>,
+    row: Option<DeclineRow>,
+    alerts: Vec<Alert>,
+    anomaly: Option<Anomaly>,
+    as_of: Option<NaiveDate>,
+    timed_out: bool,
+    /// The feed returned no bars at all.
+    empty: bool,
+    /// Why the code has no row for want of data, if so.
+    skipped: Option<String>,
+    /// The bars analyzed, kept only for the Postgres sink and correlation
+    /// alerts.
+    bars: Vec<Candle>,
+}
+
+/// Analyze one code's bars, logging why it was left out of the rows if it
+/// was. `bonds` maps bond funds to their duration bucket.
+fn process(
+    code: &str,
+    fetch: Fetch,
+    args: &DeclineArgs,
+    cfg: &Config,
+    bonds: &HashMap<String, Option<DurationBucket>>,
+) -> Outcome {
+    let day = args.day;
+    let cfg = cfg.for_code(code);
+    let cfg = cfg.as_ref();
+    let mut outcome = Outcome { code: code.to_string(), ..Outcome::default() };
+    let Some(fetch) = fetch else {
+        outcome.timed_out = true;
+        return outcome;
+    };
+    match fetch {
+        Ok((mut candles, status_option)) => {
+            if let Some(status) = status_option
+                && status != 200
+            {
+                eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, code));
+                outcome.skipped = Some(format!("HTTP {}", status));
+                return outcome;
+            }
+            // Before the open, windows end at the last completed session
+            // rather than at a bar that hasn't traded.
+            schedule::drop_unopened(&mut candles, schedule::now());
+            // Bars without an HTTP status weren't fetched just now.
+            outcome.source = Some(BarSource {
+                code: code.to_string(),
+                feed: feed(code),
+                origin: if status_option.is_some() { Origin::Live } else { Origin::Cache },
+                fetched_at: status_option.map(|_| schedule::now().fixed_offset()),
+            });
+
+            outcome.as_of = candles.last().map(|c| c.date());
+            if candles.is_empty() {
+                eprintln!(
+                    "{}",
+                    tr!("No bars for {}: delisted or not a valid code", "{} 无K线: 已退市或代码无效", code)
+                );
+                outcome.empty = true;
+                return outcome;
+            }
+            if cfg.postgres.is_some() || cfg.correlation.min_shift > 0.0 {
+                outcome.bars = candles.clone();
+            }
+            outcome.anomaly = anomaly::detect(code, &candles, &cfg.anomaly);
+            let bond = bonds.get(code).copied();
+            let smoothed = smooth::candles(&candles, cfg.smoothing.method, &cfg.smoothing);
+            let sar_points =
+                if bond.is_some() { Vec::new() } else { indicators::parabolic_sar(&smoothed, SAR_STEP, SAR_MAX_STEP) };
+            let mut events = alerts::channel_events(&smoothed, cfg.indicators.channel_days);
+            if let Some(last) = smoothed.last() {
+                events.extend(alerts::sar_events(&sar_points, last.close));
+            }
+            let notes: Vec<String> = events.iter().map(|e| e.to_string()).collect();
+            outcome.alerts = events.into_iter().map(|kind| Alert { code: code.to_string(), kind }).collect();
+
+            // A short history, as a new listing has, is screened over
+            // what there is.
+            if day > 0 && candles.len() >= day.min(2) {
+                if let Some(row) = analyze(code, &candles, &smoothed, args, cfg, &sar_points, notes, bond) {
+                    let adx = row.metrics.get("adx").copied();
+                    if row.bond.is_none() && !args.adx_passes(adx, code, cfg) {
+                        if adx.is_none() {
+                            outcome.skipped = Some(tr!("not enough data for ADX", "数据不足以计算 ADX"));
+                            eprintln!(
+                                "{}",
+                                tr!(
+                                    "Not enough data for ADX({}) on {}: got {} days",
+                                    "{1} 数据不足以计算 ADX({0}): 仅 {2} 日",
+                                    ADX_PERIOD,
+                                    code,
+                                    candles.len()
+                                )
+                            );
+                        }
+                        return outcome;
+                    }
+                    outcome.row = Some(row);
+                }
+            } else {
+                eprintln!(
+                    "{}",
+                    tr!("Not enough data for {}: got {} days", "{} 数据不足: 仅 {} 日", code, candles.len())
+                );
+                outcome.skipped = Some(tr!("not enough data: {} days", "数据不足: {} 日", candles.len()));
+            }
+        }
+        Err(e) => {
+            outcome.skipped = Some(tr!("fetch failed: {}", "获取失败: {}", e));
+            eprintln!(
+                "{}",
+                tr!(
+                    "Failed to fetch data for {}: {} (No HTTP status available)",
+                    "获取 {} 数据失败: {} (无 HTTP 状态)",
+                    code,
+                    e
+                )
+            );
+        }
+    }
+    outcome
+}
+
+/// Process codes whose bars are loaded, each with its index, across
+/// threads: the indicator suites are CPU-bound and independent per code.
+/// Returns the outcomes in index order.
+pub fn process_loaded(
+    loaded: Vec<Loaded>,
+    args: &DeclineArgs,
+    cfg: &Config,
+    bonds: &HashMap<String, Option<DurationBucket>>,
+) -> Vec<(usize, Outcome)> {
+    let mut outcomes: Vec<(usize, Outcome)> =
+        loaded.into_par_iter().map(|(i, code, bars)| (i, process(&code, Some(Ok(bars)), args, cfg, bonds))).collect();
+    outcomes.sort_by_key(|(i, _)| *i);
+    outcomes
+}
+
+/// Build the scored, sorted report from fetched bars.
+pub async fn build(
+    args: &DeclineArgs,
+    cfg: &Config,
+    fetched: Vec<(String, Fetch)>,
+    deadline: Option<Instant>,
+) -> Result<Report, Box<dyn std::error::Error>> {
+    let bonds = instruments::bonds(&cfg.watchlist, cfg);
+    // Failed and timed-out fetches only need reporting, here; the rest are
+    // analyzed in parallel.
+    let mut outcomes = Vec::new();
+    let mut loaded = Vec::new();
+    for (i, (code, fetch)) in fetched.into_iter().enumerate() {
+        match fetch {
+            Some(Ok(bars)) => loaded.push((i, code, bars)),
+            fetch => outcomes.push((i, process(&code, fetch, args, cfg, &bonds))),
+        }
+    }
+    outcomes.extend(process_loaded(loaded, args, cfg, &bonds));
+    outcomes.sort_by_key(|(i, _)| *i);
+    finish(args, cfg, outcomes.into_iter().map(|(_, o)| o).collect(), deadline).await
+}
+
+/// Score and sort the rows and gather the market context.
+async fn finish(
+    args: &DeclineArgs,
+    cfg: &Config,
+    outcomes: Vec<Outcome>,
+    deadline: Option<Instant>,
+) -> Result<Report, Box<dyn std::error::Error>> {
+    let mut results = Vec::new();
+    let mut alerts: Vec<Alert> = Vec::new();
+    let mut anomalies = Vec::new();
+    let mut as_of: Option<NaiveDate> = None;
+    let mut timed_out = Vec::new();
+    let mut screened = Vec::new();
+    let mut sources = Vec::new();
+    let snoozes = Snoozes::active(cfg)?;
+    let mut snoozed = Vec::new();
+    let mut suspended = Vec::new();
+    let mut skipped = Vec::new();
+    // Codes checked for correlation shifts: screened, and neither snoozed
+    // nor suspended.
+    let mut correlated = Vec::new();
+    // A code whose last bar is older than its feed's newest didn't trade
+    // since; comparing within a feed keeps one market's holidays from
+    // flagging another's codes.
+    let mut newest: HashMap<&'static str, NaiveDate> = HashMap::new();
+    for outcome in &outcomes {
+        if let (Some(source), Some(date)) = (&outcome.source, outcome.as_of) {
+            let entry = newest.entry(source.feed).or_insert(date);
+            *entry = (*entry).max(date);
+        }
+    }
+    let today = schedule::now().date_naive();
+    let mut bars = BTreeMap::new();
+    for mut outcome in outcomes {
+        if !outcome.bars.is_empty() {
+            bars.insert(outcome.code.clone(), std::mem::take(&mut outcome.bars));
+        }
+        if snoozes.contains(&outcome.code) {
+            snoozed.push(outcome.code);
+            continue;
+        }
+        let stale = match (&outcome.source, outcome.as_of) {
+            (Some(source), Some(date)) => newest.get(source.feed).is_some_and(|&n| date < n),
+            _ => false,
+        };
+        screened.push(outcome.code.clone());
+        sources.extend(outcome.source);
+        if outcome.empty || stale {
+            let since = outcome.as_of.map(next_weekday);
+            suspended.push(Suspension {
+                remove: since.is_none_or(|d| (today - d).num_days() >= REMOVAL_DAYS),
+                code: outcome.code,
+                since,
+            });
+            continue;
+        }
+        if let Some(reason) = outcome.skipped.take() {
+            skipped.push(Skip { code: outcome.code.clone(), reason });
+        }
+        correlated.push(outcome.code.clone());
+        if outcome.timed_out {
+            timed_out.push(outcome.code);
+        }
+        as_of = as_of.max(outcome.as_of);
+        alerts.extend(outcome.alerts);
+        anomalies.extend(outcome.anomaly);
+        results.extend(outcome.row);
+    }
+
+    let corr = &cfg.correlation;
+    if corr.min_shift > 0.0 && !correlated.is_empty() {
+        let benchmark = &cfg.portfolio.benchmark;
+        let bench = match bars.get(benchmark) {
+            Some(bench) => Some(bench.clone()),
+            None => match http::until(deadline, sina::fetch_etf_kline(benchmark, args.fetch_len(cfg))).await {
+                Some(Ok((candles, None | Some(200)))) => Some(candles),
+                Some(Ok((_, Some(status)))) => {
+                    eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, benchmark));
+                    None
+                }
+                Some(Err(e)) => {
+                    eprintln!("{}", tr!("Failed to fetch {}: {}", "获取 {} 失败: {}", benchmark, e));
+                    None
+                }
+                None => None,
+            },
+        };
+        if let Some(bench) = bench {
+            for code in correlated.iter().filter(|&c| c != benchmark) {
+                let shift = bars.get(code).and_then(|b| correlation::shift(b, benchmark, &bench, corr));
+                alerts.extend(shift.map(|kind| Alert { code: code.clone(), kind }));
+            }
+        }
+    }
+
+    // One batched quote request gives every row's current bid-ask spread
+    // and a second source for its close.
+    let mut disagreements = Vec::new();
+    if !results.is_empty() {
+        let codes: Vec<String> = results.iter().map(|r| r.code.clone()).collect();
+        match http::until(deadline, sina::fetch_quotes(&codes)).await {
+            Some(Ok(quotes)) => {
+                for row in &mut results {
+                    let Some(quote) = quotes.get(&row.code) else {
+                        continue;
+                    };
+                    if let Some(spread) = quote.spread_bps() {
+                        row.metrics.insert("spread_bps", spread);
+                    }
+                    if let Some(quoted) = quoted_close(row.date, quote)
+                        && row.close > 0.0
+                        && ((quoted - row.close) / row.close * 100.0).abs() > MAX_SOURCE_GAP
+                    {
+                        eprintln!(
+                            "{}",
+                            tr!(
+                                "Sources disagree on {}'s {} close: kline {:.3}, quote {:.3}",
+                                "{} {} 收盘价来源不一致: K线 {:.3}, 行情 {:.3}",
+                                row.code,
+                                row.date,
+                                row.close,
+                                quoted
+                            )
+                        );
+                        disagreements.push(Disagreement {
+                            code: row.code.clone(),
+                            date: row.date,
+                            kline: row.close,
+                            quote: quoted,
+                        });
+                    }
+                }
+            }
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch quotes: {}", "获取实时行情失败: {}", e)),
+            None => {}
+        }
+    }
+    let liquidity = &cfg.liquidity;
+    for row in results.iter_mut().filter(|r| r.kind != FundKind::Spot) {
+        let turnover = row.metrics.get("turnover").copied().unwrap_or(0.0);
+        if turnover < liquidity.min_turnover {
+            row.illiquid = true;
+            row.notes.push(tr!(
+                "illiquid: turnover {:.1}M < {:.1}M",
+                "流动性不足: 日均成交 {:.1} 百万 < {:.1} 百万",
+                turnover / 1e6,
+                liquidity.min_turnover / 1e6
+            ));
+        }
+        if let Some(&spread) = row.metrics.get("spread_bps")
+            && spread > liquidity.max_spread_bps
+        {
+            row.illiquid = true;
+            row.notes.push(tr!(
+                "wide spread: {:.0}bps > {:.0}bps",
+                "价差过大: {:.0} 基点 > {:.0} 基点",
+                spread,
+                liquidity.max_spread_bps
+            ));
+        }
+    }
+
+    let uses = |metric: &str| {
+        cfg.score
+            .as_ref()
+            .is_some_and(|f| f.variables().iter().any(|v| v.strip_suffix("_rank").unwrap_or(v) == metric))
+            || args.filter.iter().chain(cfg.overrides.values().flat_map(|o| &o.filter)).any(|f| f.metric == metric)
+            || args.fields().any(|f| f == metric)
+            || (export::enabled(cfg) && cfg.export.columns.iter().any(|c| c == metric))
+            || cfg.mqtt.as_ref().is_some_and(|m| m.metrics.iter().any(|c| c == metric))
+    };
+
+    // LOFs and closed-end funds always get their premium to NAV; ETFs only
+    // when it is needed, since creation and redemption keep theirs small.
+    let all_navs = args.dedupe_index || uses("premium");
+    let nav_codes: Vec<String> =
+        results.iter().filter(|r| all_navs || r.kind.trades_off_nav()).map(|r| r.code.clone()).collect();
+    if !nav_codes.is_empty() {
+        let navs = match http::until(deadline, sina::fetch_navs(&nav_codes)).await {
+            Some(Ok(navs)) => navs,
+            Some(Err(e)) => {
+                eprintln!("{}", tr!("Failed to fetch NAVs: {}", "获取基金净值失败: {}", e));
+                Default::default()
+            }
+            None => Default::default(),
+        };
+        for row in &mut results {
+            row.premium = navs.get(&row.code).map(|nav| (row.close - nav) / nav * 100.0);
+            if let Some(premium) = row.premium {
+                row.metrics.insert("premium", premium);
+            }
+        }
+    }
+
+    // Fund metadata comes from a separate store, only looked up when the
+    // report uses names, fees or valuations, or duplicates are being dropped.
+    let wants_valuation = args.valuation || uses("pe_pctile") || uses("pb_pctile");
+    if uses("name") || uses("fee") || wants_valuation || args.dedupe_index {
+        let codes: Vec<String> = results.iter().map(|r| r.code.clone()).collect();
+        let funds = fundinfo::lookup(&codes, false, cfg, deadline).await;
+        for row in &mut results {
+            let info = funds.get(&row.code);
+            if let Some(fee) = info.and_then(|f| f.expense_ratio()) {
+                row.metrics.insert("fee", fee);
+            }
+            row.tracking_index = info.and_then(|f| f.tracking_index.clone());
+            row.name = info.and_then(|f| f.name.clone());
+        }
+        if wants_valuation {
+            let table = match http::until(deadline, valuation::fetch_all()).await {
+                Some(Ok(table)) => table,
+                Some(Err(e)) => {
+                    eprintln!("{}", tr!("Failed to fetch index valuations: {}", "获取指数估值失败: {}", e));
+                    Vec::new()
+                }
+                None => Vec::new(),
+            };
+            for row in &mut results {
+                row.valuation = funds.get(&row.code).and_then(|f| valuation::find(&table, f)).cloned();
+                if let Some(v) = &row.valuation {
+                    for (name, value) in [("pe_pctile", v.pe_pctile), ("pb_pctile", v.pb_pctile)] {
+                        if let Some(value) = value {
+                            row.metrics.insert(name, value);
+                        }
+                    }
+                }
+            }
+        }
+        if args.dedupe_index {
+            results = dedupe_index(results);
+        }
+    }
+    // Distributions are looked up for the whole watchlist when ex-dividend
+    // alerts are on, since a fund can go ex-dividend without declining.
+    let alert_days = cfg.dividends.alert_days;
+    if alert_days > 0 || uses("div_yield") {
+        let codes = if alert_days > 0 { screened } else { results.iter().map(|r| r.code.clone()).collect() };
+        let distributions = dividends::lookup(&codes, false, cfg, deadline).await;
+        let today = schedule::now().date_naive();
+        for row in &mut results {
+            if let Some(list) = distributions.get(&row.code) {
+                row.metrics.insert("div_yield", dividends::trailing_cash(list, today) / row.close * 100.0);
+            }
+        }
+        if alert_days > 0 {
+            alerts.extend(dividends::alerts(&distributions, today, alert_days));
+        }
+    }
+    if !args.filter.is_empty() || cfg.overrides.values().any(|o| !o.filter.is_empty()) {
+        results.retain(|r| args.filters(&r.code, cfg).iter().all(|f| f.passes(&r.metrics)));
+    }
+    if let Some(formula) = &cfg.score {
+        let metrics: Vec<_> = results.iter().map(|r| r.metrics.clone()).collect();
+        for (row, score) in results.iter_mut().zip(score::score_rows(formula, &metrics)) {
+            row.score = score;
+        }
+    }
+    match args.sort {
+        SortKey::Decline => results.sort_by(|a, b| a.rate.total_cmp(&b.rate)),
+        // Rows the formula could not score go last.
+        SortKey::Score => results.sort_by(|a, b| match (a.score, b.score) {
+            (Some(x), Some(y)) => y.total_cmp(&x),
+            (Some(_), None) => std::cmp::Ordering::Less,
+            (None, Some(_)) => std::cmp::Ordering::Greater,
+            (None, None) => std::cmp::Ordering::Equal,
+        }),
+    }
+    // Ladders draw on the portfolio's cash in report order until it runs out.
+    let mut ladder_cash_left = (args.ladder && cfg.portfolio.cash > 0.0).then_some(cfg.portfolio.cash);
+    if args.ladder {
+        let lc = &cfg.ladder;
+        for row in &mut results {
+            let scored = lc.min_score.is_some_and(|min| row.score.is_some_and(|s| s >= min));
+            if let Some(atr) = row.atr
+                && (scored || ladder::is_dip(&row.code, &alerts))
+            {
+                let cash = ladder_cash_left.map_or(lc.cash, |left| lc.cash.min(left));
+                let ladder = ladder::ladder(row.close, atr, &lc.atr_multiples, cash);
+                if let Some(left) = &mut ladder_cash_left {
+                    *left -= ladder.spent;
+                }
+                row.ladder = Some(ladder).filter(|l| !l.tranches.is_empty());
+            }
+        }
+    }
+
+    let context = if cfg.context.is_empty() {
+        Vec::new()
+    } else {
+        http::until(deadline, context::market_context(&cfg.context)).await.unwrap_or_else(|| {
+            vec![tr!("Market context: timed out before the deadline", "市场环境: 截止时间前未获取")]
+        })
+    };
+
+    let now = schedule::now();
+    let session = as_of.map(|d| schedule::bar_session(d, now));
+    if session == Some(BarSession::AheadOfClock)
+        && let Some(date) = as_of
+    {
+        eprintln!(
+            "{}",
+            tr!(
+                "The latest bar, {}, is dated after today ({}) on the system clock; check the clock",
+                "最新K线日期 {} 晚于系统时钟的今天 ({}); 请检查时钟",
+                date,
+                now.date_naive()
+            )
+        );
+    }
+    let report = Report {
+        generated_at: now.fixed_offset(),
+        version: env!("CARGO_PKG_VERSION"),
+        sources,
+        as_of,
+        session,
+        context,
+        rows: results,
+        alerts,
+        anomalies,
+        timed_out,
+        snoozed,
+        suspended,
+        skipped,
+        disagreements,
+        ladder_cash_left,
+    };
+    // A run `--strict` is about to reject must not reach the event log or
+    // the sinks that act on signals.
+    if cfg.dry_run || (args.strict && !issues(&report, args).is_empty()) {
+        return Ok(report);
+    }
+    let date = report.as_of.unwrap_or_else(|| now.date_naive());
+    let fresh = events::log(&report.alerts, date, cfg.profile.as_deref());
+    postgres::write(cfg, &report, &bars).await;
+    pubsub::publish(cfg, &report, &fresh).await;
+    mqtt::publish(cfg, &report, &fresh).await;
+    Ok(report)
+}
+
+/// The quote's close for the session dated `date`, once it is over: the
+/// last trade after the close, or the previous close on a later day.
+fn quoted_close(date: NaiveDate, quote: &sina::Quote) -> Option<f64> {
+    let time = quote.time?;
+    let close = if time.date() > date {
+        quote.prev_close
+    } else if time.date() == date && time.time() >= schedule::SESSION_CLOSE {
+        quote.last
+    } else {
+        return None;
+    };
+    Some(close).filter(|&c| c > 0.0)
+}
+
+fn next_weekday(date: NaiveDate) -> NaiveDate {
+    let mut day = date.succ_opt().unwrap_or(date);
+    while day.weekday().num_days_from_monday() >= 5 {
+        day = day.succ_opt().unwrap_or(day);
+    }
+    day
+}
+
+/// Keep one row per tracked index: the one with the best average rank on
+/// turnover (higher is better), expense ratio and absolute premium to NAV
+/// (lower is better) among the rows tracking it. Criteria a row has no data
+/// for count as its group's worst. Rows without a known index are kept.
+fn dedupe_index(rows: Vec<DeclineRow>) -> Vec<DeclineRow> {
+    let mut groups: BTreeMap<String, Vec<DeclineRow>> = BTreeMap::new();
+    let mut kept = Vec::new();
+    for row in rows {
+        match row.tracking_index.clone() {
+            Some(index) => groups.entry(index).or_default().push(row),
+            None => kept.push(row),
+        }
+    }
+    for (_, mut group) in groups {
+        let column = |f: &dyn Fn(&DeclineRow) -> Option<f64>| {
+            score::percentile_ranks(&group.iter().map(f).collect::<Vec<_>>())
+        };
+        let liquidity = column(&|r| r.metrics.get("turnover").copied());
+        let cheapness = column(&|r| r.metrics.get("fee").map(|f| -f));
+        let closeness = column(&|r| r.premium.map(|p| -p.abs()));
+        let best = (0..group.len())
+            .max_by(|&a, &b| {
+                let rank = |i: usize| {
+                    liquidity[i].unwrap_or(0.0) + cheapness[i].unwrap_or(0.0) + closeness[i].unwrap_or(0.0)
+                };
+                rank(a).partial_cmp(&rank(b)).unwrap()
+            })
+            .unwrap_or(0);
+        let mut pick = group.swap_remove(best);
+        pick.alternatives = group.into_iter().map(|r| r.code).collect();
+        kept.push(pick);
+    }
+    kept
+}
+
+/// One report line. `as_of` flags rows whose latest bar is older than it;
+/// `scored` is false for rows printed before the formula has run.
+fn row_line(row: &DeclineRow, args: &DeclineArgs, cfg: &Config, as_of: Option<NaiveDate>, scored: bool) -> String {
+    let day = row.span.unwrap_or(args.day);
+    let na = || "n/a".to_string();
+    let hald_day = day / 2;
+    let adx = row.metrics.get("adx").map_or_else(na, |v| format!("{:.1}", v));
+    let trend = row.trend.map_or_else(na, |t| t.to_string());
+    let sar = row.sar.map_or_else(na, |p| {
+        let side = if p.long { tr!("below", "下方") } else { tr!("above", "上方") };
+        format!("{:.3} {}", p.sar, side)
+    });
+    let range = row.metrics.get("range_pct").map_or_else(na, |v| format!("{:.0}%", v));
+    let regression = match (row.metrics.get("reg_slope"), row.metrics.get("reg_r2"), row.metrics.get("reg_pos")) {
+        (Some(slope), Some(r2), Some(pos)) => {
+            tr!("{:+.1}%/yr, R² {:.2}, at {:.0}%", "年化 {:+.1}%, R² {:.2}, 位于 {:.0}%", slope, r2, pos)
+        }
+        _ => na(),
+    };
+    let unusual = row.metrics.get("return_pctile").map_or_else(na, |p| {
+        tr!("worse than {:.0}% of {}d periods", "差于 {:.0}% 的 {} 日区间", 100.0 - p, day)
+    });
+    let similar = match &row.recovery {
+        Some(stats) if stats.instances > 0 => tr!(
+            "Similar: {}x, recovered {}, median {} | Fwd{}: {}",
+            "相似跌幅: {} 次, 收复 {} 次, 中位 {} | 后 {} 日: {}",
+            stats.instances,
+            stats.recovered,
+            stats.median_days.map_or_else(na, |d| tr!("{:.0}d", "{:.0} 日", d)),
+            recovery::FORWARD_DAYS,
+            stats.median_forward.map_or_else(na, |f| format!("{:+.2}%", f)),
+        ),
+        Some(_) => tr!("Similar: none", "相似跌幅: 无"),
+        None => tr!("Similar: n/a", "相似跌幅: n/a"),
+    };
+    let score = match (&cfg.score, row.score) {
+        (Some(_), _) if !scored => String::new(),
+        (None, _) => String::new(),
+        (Some(_), Some(v)) => tr!(" | Score: {:.3}", " | 评分: {:.3}", v),
+        (Some(_), None) => tr!(" | Score: n/a", " | 评分: n/a"),
+    };
+    let mut notes = row.notes.clone();
+    if let Some(bond) = &row.bond {
+        notes.push(tr!(
+            "bond fund, {} | yield proxy {} | drawdown {}",
+            "债券基金, {} | 收益率估算 {} | 回撤 {}",
+            bond.duration.map_or_else(|| tr!("duration unknown", "久期未知"), |d| d.to_string()),
+            row.metrics.get("yield_proxy").map_or_else(na, |y| format!("{:.2}%", y)),
+            row.metrics.get("drawdown_bps").map_or_else(na, |d| tr!("{:.0}bps", "{:.0} 基点", d))
+        ));
+    }
+    if row.kind.trades_off_nav()
+        && let Some(premium) = row.premium
+    {
+        notes.push(if premium < 0.0 {
+            tr!("{:.2}% discount to NAV", "折价 {:.2}%", -premium)
+        } else {
+            tr!("{:.2}% premium to NAV", "溢价 {:.2}%", premium)
+        });
+    }
+    if let Some(v) = &row.valuation {
+        let fmt = |value: Option<f64>, pctile: Option<f64>| match (value, pctile) {
+            (Some(value), Some(p)) => format!("{:.2} ({:.0}%)", value, p),
+            (Some(value), None) => format!("{:.2}", value),
+            _ => na(),
+        };
+        notes.push(tr!("{} PE {} PB {}", "{} 市盈率 {} 市净率 {}", v.name, fmt(v.pe, v.pe_pctile), fmt(v.pb, v.pb_pctile)));
+    }
+    if let Some(index) = row.tracking_index.as_ref().filter(|_| !row.alternatives.is_empty()) {
+        notes.push(tr!(
+            "best of {} tracking {}, also {}",
+            "{1} 的 {0} 只跟踪基金中最优, 另有 {2}",
+            row.alternatives.len() + 1,
+            index,
+            row.alternatives.join("/")
+        ));
+    }
+    if let Some(span) = row.span {
+        notes.insert(0, tr!("only {} days of history, short of {}", "仅 {} 日历史, 不足 {} 日", span, args.day));
+    }
+    if as_of.is_some_and(|d| row.date < d) {
+        // Suspended or not yet updated; its numbers are older than the rest.
+        notes.insert(0, tr!("last bar {}", "最新K线 {}", row.date));
+    }
+    let notes = if notes.is_empty() { String::new() } else { format!(" | {}", notes.join(", ")) };
+    format!(
+        "{} | {} | {} | ADX({}): {} | {} | SAR: {} | {} | {} | {} | {}{}{}",
+        match row.kind {
+            FundKind::Etf => tr!("Code: {} ({})", "代码: {} ({})", row.code, row.settlement),
+            FundKind::Spot => tr!("Code: {} ({})", "代码: {} ({})", row.code, row.kind),
+            kind => tr!("Code: {} ({}, {})", "代码: {} ({}, {})", row.code, kind, row.settlement),
+        },
+        tr!("Rate(Today/{} days ago): {:.2}%", "涨跌(今日/{}日前): {:.2}%", day, row.rate),
+        tr!("Rate({} days ago/{} days ago): {:.2}%", "涨跌({}日前/{}日前): {:.2}%", hald_day, day, row.half_rate),
+        ADX_PERIOD,
+        adx,
+        tr!("Trend: {}", "趋势: {}", trend),
+        sar,
+        tr!("Range({}d): {}", "区间位置({}日): {}", cfg.for_code(&row.code).indicators.range_days, range),
+        tr!("Channel({}d): {}", "回归通道({}日): {}", cfg.for_code(&row.code).indicators.regression_days, regression),
+        unusual,
+        similar,
+        score,
+        notes
+    )
+}
+
+pub fn print(args: &DeclineArgs, cfg: &Config, report: &Report) {
+    if cfg.output == OutputFormat::Json {
+        match serde_json::to_string_pretty(report) {
+            Ok(json) => println!("{}", json),
+            Err(e) => eprintln!("{}", tr!("Failed to render JSON report: {}", "生成 JSON 报告失败: {}", e)),
+        }
+        return;
+    }
+    println!("{}", render(args, cfg, report));
+}
+
+/// `report` as text, the way `print` shows it.
+pub fn render(args: &DeclineArgs, cfg: &Config, report: &Report) -> String {
+    let mut out = Vec::new();
+    let day = args.day;
+    if !report.context.is_empty() {
+        out.push(format!("\n {}", tr!("Market context:", "市场环境:")));
+        out.push("-----------------------------------------".to_string());
+        for line in &report.context {
+            out.push(line.clone());
+        }
+    }
+
+    out.push(format!("\n {}", tr!("ETF Decline over {} days:", "ETF {} 日跌幅:", day)));
+    out.push("-----------------------------------------".to_string());
+    let generated = report.generated_at.format("%Y-%m-%d %H:%M UTC%:z");
+    match (report.as_of, report.session) {
+        (Some(date), Some(session)) => out.push(
+            tr!("Generated {} | Latest bar: {} ({})", "生成于 {} | 最新K线: {} ({})", generated, date, session)
+        ),
+        _ => out.push(tr!("Generated {}", "生成于 {}", generated)),
+    }
+    if report.rows.is_empty() {
+        out.push(tr!("No ETF data", "无 ETF 数据"));
+    } else if let Some(template) = &args.format {
+        for row in &report.rows {
+            out.push(template.render(|name| field(row, name), 2));
+        }
+    } else if !args.columns.is_empty() {
+        out.extend(column_lines(&report.rows, &args.columns));
+    } else {
+        for row in &report.rows {
+            out.push(row_line(row, args, cfg, report.as_of, true));
+        }
+    }
+    if report.rows.iter().any(|r| r.ladder.is_some()) {
+        out.push(format!("\n {}", tr!("Limit-buy ladders:", "限价买入阶梯:")));
+        out.push("-----------------------------------------".to_string());
+        for row in &report.rows {
+            let Some(ladder) = &row.ladder else {
+                continue;
+            };
+            let tranches: Vec<String> = ladder
+                .tranches
+                .iter()
+                .map(|t| tr!("-{} ATR @ {:.3} x {}", "-{} ATR @ {:.3} x {} 股", t.atr_multiple, t.price, t.shares))
+                .collect();
+            out.push(
+                tr!(
+                    "Code: {} | Close: {:.3} | ATR({}): {:.3} | {} | Cost: {:.2} | Leftover: {:.2}",
+                    "代码: {} | 收盘: {:.3} | ATR({}): {:.3} | {} | 金额: {:.2} | 剩余: {:.2}",
+                    row.code,
+                    row.close,
+                    ATR_PERIOD,
+                    row.atr.unwrap_or_default(),
+                    tranches.join(" | "),
+                    ladder.spent,
+                    ladder.leftover
+                )
+            );
+        }
+        if let Some(left) = report.ladder_cash_left {
+            out.push(tr!("Portfolio cash left if all fill: {:.2}", "全部成交后组合剩余现金: {:.2}", left));
+        }
+    }
+    if !report.timed_out.is_empty() {
+        out.push(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", report.timed_out.join(", ")));
+    }
+    if !report.snoozed.is_empty() {
+        out.push(tr!("Snoozed: {}", "已暂停: {}", report.snoozed.join(", ")));
+    }
+    for suspension in &report.suspended {
+        match suspension.since {
+            Some(since) => out.push(tr!("{}: suspended since {}", "{}: 自 {} 起停牌", suspension.code, since)),
+            None => out.push(tr!("{}: no bars, delisted or invalid", "{}: 无K线, 已退市或代码无效", suspension.code)),
+        }
+    }
+    let remove: Vec<&str> = report.suspended.iter().filter(|s| s.remove).map(|s| s.code.as_str()).collect();
+    if !remove.is_empty() {
+        out.push(
+            tr!("Consider removing from the watchlist: {}", "建议从自选列表中移除: {}", remove.join(", "))
+        );
+    }
+
+    if !report.anomalies.is_empty() {
+        out.push(format!("\n {}", tr!("Anomalies:", "异常波动:")));
+        out.push("-----------------------------------------".to_string());
+        for a in &report.anomalies {
+            out.push(tr!(
+                "{} on {}: {:+.2}% in a day, {:+.1} sigma",
+                "{} {}: 单日 {:+.2}%, {:+.1} 倍标准差",
+                a.code,
+                a.date,
+                a.change,
+                a.sigma
+            ));
+        }
+    }
+
+    if !report.alerts.is_empty() {
+        out.push(format!("\n {}", tr!("Alerts:", "预警:")));
+        out.push("-----------------------------------------".to_string());
+        for alert in &report.alerts {
+            out.push(alert.to_string());
+        }
+    }
+    out.push(format!("\n{}", provenance_line(report)));
+    out.join("\n")
+}
+
+/// A `--columns` or `--format` field of `row`.
+pub fn field(row: &DeclineRow, name: &str) -> Option<Value> {
+    let text = |s: String| Some(Value::Text(s));
+    match name {
+        "code" => text(row.code.clone()),
+        "name" => text(row.name.clone()?),
+        "date" => text(row.date.to_string()),
+        "kind" => text(row.kind.to_string()),
+        "close" => Some(Value::Number(row.close)),
+        "atr" => Some(Value::Number(row.atr?)),
+        "score" => Some(Value::Number(row.score?)),
+        "trend" => text(row.trend?.to_string()),
+        metric => row.metrics.get(metric).map(|&v| Value::Number(v)),
+    }
+}
+
+/// The rows as a table of `columns`, each padded to its widest value.
+fn column_lines(rows: &[DeclineRow], columns: &[String]) -> Vec<String> {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|c| field(row, c).map_or_else(|| "n/a".to_string(), |v| v.render(None, 2)))
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, c)| cells.iter().map(|r| r[i].chars().count()).chain([c.len()]).max().unwrap_or(0))
+        .collect();
+    let line = |values: &[String]| {
+        let padded: Vec<String> = values.iter().zip(&widths).map(|(v, &w)| format!("{:<w$}", v, w = w)).collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    std::iter::once(line(columns)).chain(cells.iter().map(|row| line(row))).collect()
+}
+
+/// Footer saying where the bars came from, so a saved report can be
+/// checked later.
+fn provenance_line(report: &Report) -> String {
+    let mut feeds: Vec<&str> = report.sources.iter().map(|s| s.feed).collect();
+    feeds.sort();
+    feeds.dedup();
+    let count = |origin: Origin| report.sources.iter().filter(|s| s.origin == origin).count();
+    let times: Vec<DateTime<FixedOffset>> = report.sources.iter().filter_map(|s| s.fetched_at).collect();
+    let fetched = match (times.iter().min(), times.iter().max()) {
+        (Some(first), Some(last)) => {
+            format!("{} - {}", first.format("%Y-%m-%d %H:%M"), last.format("%Y-%m-%d %H:%M"))
+        }
+        _ => "n/a".to_string(),
+    };
+    tr!(
+        "Data: {} | Bars: {} live, {} cached, {} quote-updated | Fetched: {} | decline-compare {}",
+        "数据源: {} | K线: {} 实时, {} 缓存, {} 行情更新 | 获取时间: {} | decline-compare {}",
+        if feeds.is_empty() { "n/a".to_string() } else { feeds.join(", ") },
+        count(Origin::Live),
+        count(Origin::Cache),
+        count(Origin::Quote),
+        fetched,
+        report.version
+    )
+}
+
+/// Every data quality problem in `report`, one line each, for `--strict`.
+fn issues(report: &Report, args: &DeclineArgs) -> Vec<String> {
+    let mut issues: Vec<String> = report
+        .skipped
+        .iter()
+        .map(|s| tr!("{}: skipped, {}", "{}: 已跳过, {}", s.code, s.reason))
+        .collect();
+    issues.extend(report.timed_out.iter().map(|code| tr!("{}: timed out", "{}: 超时", code)));
+    issues.extend(report.suspended.iter().map(|s| match s.since {
+        Some(since) => tr!("{}: no bars since {}", "{}: 自 {} 起无K线", s.code, since),
+        None => tr!("{}: no bars at all", "{}: 无任何K线", s.code),
+    }));
+    issues.extend(report.rows.iter().filter_map(|row| {
+        let span = row.span?;
+        Some(tr!("{}: only {} days of history, short of {}", "{}: 仅 {} 日历史, 不足 {} 日", row.code, span, args.day))
+    }));
+    issues.extend(report.disagreements.iter().map(|d| {
+        tr!(
+            "{}: {} close is {:.3} in the kline but {:.3} in the quote",
+            "{}: {} 收盘价 K线为 {:.3}, 行情为 {:.3}",
+            d.code,
+            d.date,
+            d.kline,
+            d.quote
+        )
+    }));
+    issues
+}
+
+pub async fn run(args: &DeclineArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let report = collect(args, cfg).await?;
+    if args.strict {
+        let issues = issues(&report, args);
+        if !issues.is_empty() {
+            eprintln!("\n {}", tr!("Data quality issues:", "数据质量问题:"));
+            eprintln!("-----------------------------------------");
+            for issue in &issues {
+                eprintln!("{}", issue);
+            }
+            return Err(tr!("--strict: {} data quality issues", "--strict: {} 个数据质量问题", issues.len()).into());
+        }
+    }
+    print(args, cfg, &report);
+    archive::save(args, cfg, &report);
+    export::daily(cfg, &report).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, TimeZone};
+    use chrono_tz::Asia::Shanghai;
+    use clap::Parser;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        decline: DeclineArgs,
+    }
+
+    fn args(extra: &[&str]) -> DeclineArgs {
+        Cli::parse_from(std::iter::once("decline").chain(extra.iter().copied())).decline
+    }
+
+    /// A year of weekday bars: a slow wave around `base` with a drop over
+    /// the last week, so each code lands in the report with alerts.
+    fn bars(base: f64, phase: f64) -> Vec<Candle> {
+        let start = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        (0..250)
+            .map(|i| {
+                let t = i as f64;
+                let drop = if i >= 245 { (i - 244) as f64 * 0.015 } else { 0.0 };
+                let close = base * (1.0 + 0.1 * (t / 20.0 + phase).sin() + 0.0004 * t - drop);
+                Candle {
+                    time: (start + Duration::days(i / 5 * 7 + i % 5)).and_time(schedule::SESSION_CLOSE),
+                    open: close * 0.998,
+                    high: close * 1.01,
+                    low: close * 0.99,
+                    close,
+                    volume: 2e7 + 1e6 * (t / 7.0).cos(),
+                }
+            })
+            .collect()
+    }
+
+    /// The report for two fixed codes, assembled without `finish` since that
+    /// goes to the network for quotes.
+    fn report(args: &DeclineArgs, cfg: &Config) -> Report {
+        let bonds = HashMap::new();
+        let (mut rows, mut sources, mut alerts) = (Vec::new(), Vec::new(), Vec::new());
+        for (code, bars) in [("510300", bars(4.0, 0.0)), ("512880", bars(1.2, 1.5))] {
+            let outcome = process(code, Some(Ok((bars, None))), args, cfg, &bonds);
+            rows.extend(outcome.row);
+            sources.extend(outcome.source);
+            alerts.extend(outcome.alerts);
+        }
+        rows.sort_by(|a: &DeclineRow, b| a.rate.total_cmp(&b.rate));
+        Report {
+            generated_at: Shanghai.with_ymd_and_hms(2024, 12, 13, 15, 30, 0).unwrap().fixed_offset(),
+            version: "test",
+            sources,
+            as_of: rows.first().map(|r| r.date),
+            session: Some(BarSession::TodayClose),
+            context: vec!["000300: -1.20%".to_string()],
+            rows,
+            alerts,
+            anomalies: Vec::new(),
+            timed_out: vec!["159915".to_string()],
+            snoozed: Vec::new(),
+            suspended: vec![Suspension {
+                code: "501050".to_string(),
+                since: NaiveDate::from_ymd_opt(2024, 12, 9),
+                remove: false,
+            }],
+            skipped: Vec::new(),
+            disagreements: Vec::new(),
+            ladder_cash_left: None,
+        }
+    }
+
+    #[test]
+    fn text_report() {
+        let (args, cfg) = (args(&[]), Config::default());
+        insta::assert_snapshot!(render(&args, &cfg, &report(&args, &cfg)));
+    }
+
+    #[test]
+    fn column_report() {
+        let (args, cfg) = (args(&["--columns", "code,date,close,decline,rsi,adx,trend"]), Config::default());
+        insta::assert_snapshot!(render(&args, &cfg, &report(&args, &cfg)));
+    }
+
+    #[test]
+    fn template_report() {
+        let (args, cfg) = (args(&["--format", "{code} {decline:.2}% rsi {rsi:.0} z {zscore:.2}"]), Config::default());
+        insta::assert_snapshot!(render(&args, &cfg, &report(&args, &cfg)));
+    }
+
+    #[test]
+    fn json_report() {
+        let (args, cfg) = (args(&[]), Config::default());
+        insta::assert_json_snapshot!(report(&args, &cfg));
+    }
+}
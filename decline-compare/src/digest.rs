@@ -0,0 +1,264 @@
+//! A week's or month's screens summed up from the archived daily reports,
+//! short enough to read on a phone: each code's move over the period, the
+//! alerts raised and the portfolio's change. `--notify` sends it through
+//! the configured Slack, Discord and desktop notifiers.
+//!
+//! A code's move runs from its close in the last report at or before the
+//! period's start (the first report inside it when there's none) to its
+//! close in the newest.
+
+use std::collections::BTreeMap;
+
+use chrono::{Duration, Months, NaiveDate};
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::archive;
+use crate::config::{Config, OutputFormat};
+use crate::events::{self, EventLog};
+use crate::i18n::tr;
+use crate::portfolio;
+use crate::schedule;
+use crate::watch;
+
+#[derive(Args, Debug)]
+pub struct DigestArgs {
+    /// Span to summarise, ending today
+    #[arg(long, value_enum, default_value_t = Period::Week)]
+    period: Period,
+
+    /// Biggest losers and gainers to list
+    #[arg(long, default_value_t = 5)]
+    top: usize,
+
+    /// Also send the digest through the configured notifiers
+    #[arg(long)]
+    notify: bool,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Period {
+    Week,
+    Month,
+}
+
+impl Period {
+    fn start(self, end: NaiveDate) -> NaiveDate {
+        match self {
+            Period::Week => end - Duration::days(7),
+            Period::Month => end.checked_sub_months(Months::new(1)).unwrap_or(end),
+        }
+    }
+}
+
+/// The parts of an archived report the digest reads.
+#[derive(Deserialize)]
+struct Archived {
+    rows: Vec<ArchivedRow>,
+}
+
+#[derive(Deserialize)]
+struct ArchivedRow {
+    code: String,
+    name: Option<String>,
+    close: f64,
+}
+
+#[derive(Clone, Serialize)]
+struct Move {
+    code: String,
+    name: Option<String>,
+    from: f64,
+    to: f64,
+    /// Percent change from `from` to `to`.
+    change: f64,
+}
+
+#[derive(Serialize)]
+struct PortfolioChange {
+    from: NaiveDate,
+    to: NaiveDate,
+    start: f64,
+    end: f64,
+    /// Percent change in total value.
+    change: f64,
+}
+
+#[derive(Serialize)]
+struct Digest {
+    period: Period,
+    start: NaiveDate,
+    end: NaiveDate,
+    /// Archived reports the moves were taken from.
+    reports: usize,
+    losers: Vec<Move>,
+    gainers: Vec<Move>,
+    /// Alerts first seen in the period, per rule.
+    alerts: BTreeMap<String, usize>,
+    alerted_codes: usize,
+    portfolio: Option<PortfolioChange>,
+}
+
+fn read(profile: Option<&str>, date: NaiveDate) -> Result<Archived, Box<dyn std::error::Error>> {
+    Ok(serde_json::from_str(&archive::read(profile, date)?)?)
+}
+
+fn build(args: &DigestArgs, cfg: &Config) -> Result<Digest, Box<dyn std::error::Error>> {
+    let profile = cfg.profile.as_deref();
+    let end = schedule::now().date_naive();
+    let start = args.period.start(end);
+
+    let dates = archive::dates(profile);
+    let inside: Vec<NaiveDate> = dates.iter().copied().filter(|d| (start..=end).contains(d)).collect();
+    let (Some(&first), Some(&last)) = (inside.first(), inside.last()) else {
+        return Err(tr!(
+            "No reports archived between {} and {}; `decline` and `watch` archive them as they run",
+            "{} 至 {} 间没有归档报告; `decline` 和 `watch` 运行时会归档",
+            start,
+            end
+        )
+        .into());
+    };
+    let base = dates.iter().copied().rfind(|&d| d <= start).unwrap_or(first);
+
+    let before: BTreeMap<String, f64> = read(profile, base)?.rows.into_iter().map(|r| (r.code, r.close)).collect();
+    let mut moves: Vec<Move> = read(profile, last)?
+        .rows
+        .into_iter()
+        .filter_map(|row| {
+            let from = *before.get(&row.code)?;
+            (from > 0.0).then(|| Move {
+                change: (row.close - from) / from * 100.0,
+                code: row.code,
+                name: row.name,
+                from,
+                to: row.close,
+            })
+        })
+        .collect();
+    moves.sort_by(|a, b| a.change.total_cmp(&b.change));
+    let losers: Vec<Move> = moves.iter().filter(|m| m.change < 0.0).take(args.top).cloned().collect();
+    let gainers: Vec<Move> = moves.iter().rev().filter(|m| m.change > 0.0).take(args.top).cloned().collect();
+
+    let path = events::default_db_path(profile);
+    let logged = EventLog::open(&path)?.since(&start.to_string())?;
+    let mut alerts: BTreeMap<String, usize> = BTreeMap::new();
+    for event in &logged {
+        *alerts.entry(event.rule.clone()).or_default() += 1;
+    }
+    let mut alerted: Vec<&str> = logged.iter().map(|e| e.code.as_str()).collect();
+    alerted.sort_unstable();
+    alerted.dedup();
+
+    let history = portfolio::load_history(&portfolio::default_history_path(profile))?;
+    let portfolio = history.iter().rfind(|v| v.date <= start).or_else(|| history.first()).zip(history.last()).and_then(
+        |(from, to)| {
+            (from.date < to.date && from.total > 0.0).then(|| PortfolioChange {
+                from: from.date,
+                to: to.date,
+                start: from.total,
+                end: to.total,
+                change: (to.total - from.total) / from.total * 100.0,
+            })
+        },
+    );
+
+    Ok(Digest {
+        period: args.period,
+        start,
+        end,
+        reports: inside.len(),
+        losers,
+        gainers,
+        alerts,
+        alerted_codes: alerted.len(),
+        portfolio,
+    })
+}
+
+impl Move {
+    fn line(&self) -> String {
+        let label = match &self.name {
+            Some(name) => format!("{} {}", self.code, name),
+            None => self.code.clone(),
+        };
+        format!("{:<20} {:>+8.2}%  {:.3} -> {:.3}", label, self.change, self.from, self.to)
+    }
+}
+
+fn heading(digest: &Digest) -> String {
+    match digest.period {
+        Period::Week => tr!("Weekly digest, {} to {}", "周报, {} 至 {}", digest.start, digest.end),
+        Period::Month => tr!("Monthly digest, {} to {}", "月报, {} 至 {}", digest.start, digest.end),
+    }
+}
+
+/// The digest as text, one section per part.
+fn render(digest: &Digest) -> String {
+    let mut lines = Vec::new();
+    let mut section = |title: String, body: Vec<String>| {
+        lines.push(format!("\n {}", title));
+        lines.push("-----------------------------------------".to_string());
+        lines.extend(body);
+    };
+
+    let moves = |moves: &[Move]| {
+        if moves.is_empty() {
+            vec![tr!("None", "无")]
+        } else {
+            moves.iter().map(Move::line).collect()
+        }
+    };
+    section(tr!("Biggest losers:", "跌幅最大:"), moves(&digest.losers));
+    section(tr!("Biggest gainers:", "涨幅最大:"), moves(&digest.gainers));
+
+    let total: usize = digest.alerts.values().sum();
+    let mut body = vec![tr!("{} alerts on {} codes", "{} 条信号, 涉及 {} 个代码", total, digest.alerted_codes)];
+    body.extend(digest.alerts.iter().map(|(rule, count)| format!("  {:<32} {:>4}", rule, count)));
+    section(tr!("Alerts:", "信号:"), body);
+
+    let body = match &digest.portfolio {
+        Some(p) => vec![tr!(
+            "{:.2} -> {:.2} ({:+.2}%), {} to {}",
+            "{:.2} -> {:.2} ({:+.2}%), {} 至 {}",
+            p.start,
+            p.end,
+            p.change,
+            p.from,
+            p.to
+        )],
+        None => vec![tr!("No recorded valuations to compare", "没有可比较的持仓估值记录")],
+    };
+    section(tr!("Portfolio:", "持仓:"), body);
+
+    lines.push(tr!("From {} archived reports", "基于 {} 份归档报告", digest.reports));
+    lines.join("\n")
+}
+
+pub async fn run(args: &DigestArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let digest = build(args, cfg)?;
+    let text = render(&digest);
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&digest)?);
+    } else {
+        println!("\n {}", heading(&digest));
+        println!("=========================================");
+        println!("{}", text);
+    }
+
+    if args.notify && cfg.dry_run {
+        eprintln!("{}", tr!("Dry run, digest not sent", "试运行, 未发送摘要"));
+    } else if args.notify {
+        let notifiers = watch::notifiers(&cfg.notify);
+        if notifiers.is_empty() {
+            eprintln!("{}", tr!("No notifiers configured in [notify]", "[notify] 中未配置通知渠道"));
+        }
+        for notifier in &notifiers {
+            if let Err(e) = notifier.send_text(&heading(&digest), text.trim_start()).await {
+                eprintln!("{}", tr!("Failed to send {} notification: {}", "发送 {} 通知失败: {}", notifier.name(), e));
+            }
+        }
+    }
+    Ok(())
+}
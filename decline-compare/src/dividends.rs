@@ -0,0 +1,291 @@
+//! Cash distributions of dividend ETFs: trailing yield and ex-dividend
+//! dates, from Eastmoney's fund distribution pages.
+//!
+//! Distribution lists are kept in a local store refreshed daily, since an
+//! ex-dividend date is usually announced only a few days ahead.
+//! `[dividends] alert_days` turns on alerts that many days before each
+//! ex-dividend date.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDate};
+use clap::Args;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config::{self, Config, OutputFormat};
+use crate::cooldown;
+use crate::fundinfo;
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina;
+use crate::spot;
+use crate::symbols;
+
+const STORE_FILE: &str = "dividends.json";
+
+/// Refetch a fund's distributions once they are this old.
+const MAX_AGE_SECS: u64 = 24 * 3600;
+
+#[derive(Args, Debug)]
+pub struct DividendsArgs {
+    /// Codes to show; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Refetch distributions even if the stored copy is from today
+    #[arg(long)]
+    refresh: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Distribution {
+    pub record_date: Option<NaiveDate>,
+    pub ex_date: NaiveDate,
+    /// Cash per share, CNY.
+    pub amount: f64,
+    pub pay_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Entry {
+    fetched_at: u64,
+    /// Newest first, as published.
+    distributions: Vec<Distribution>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DividendStore {
+    funds: BTreeMap<String, Entry>,
+}
+
+type FetchResult = Result<Vec<Distribution>, Box<dyn std::error::Error>>;
+
+pub fn default_path() -> PathBuf {
+    config::data_dir().join(STORE_FILE)
+}
+
+impl DividendStore {
+    /// The store at `path`; empty if there is none yet.
+    pub fn load(path: &Path) -> Result<DividendStore, Box<dyn std::error::Error>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(DividendStore::default()),
+            Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        config::write_atomic(path, &serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Fetch every code that is missing or older than `MAX_AGE_SECS` (all of
+    /// them with `force`); spot entries have nothing to fetch. Returns
+    /// whether anything was fetched.
+    pub async fn update(&mut self, codes: &[String], force: bool, cfg: &Config, deadline: Option<Instant>) -> bool {
+        let now = cooldown::now_secs();
+        let stale: Vec<&String> = codes
+            .iter()
+            .filter(|c| !spot::is_spot(c))
+            .filter(|c| force || self.funds.get(*c).is_none_or(|e| now.saturating_sub(e.fetched_at) >= MAX_AGE_SECS))
+            .collect();
+        let fetched: Vec<(&String, Option<FetchResult>)> = stream::iter(stale)
+            .map(|code| async move { (code, http::until(deadline, fetch(code)).await) })
+            .buffer_unordered(cfg.http.concurrency.max(1))
+            .collect()
+            .await;
+        let mut updated = false;
+        for (code, result) in fetched {
+            match result {
+                Some(Ok(distributions)) => {
+                    self.funds.insert(code.clone(), Entry { fetched_at: now, distributions });
+                    updated = true;
+                }
+                Some(Err(e)) => {
+                    eprintln!("{}", tr!("Failed to fetch distributions for {}: {}", "获取 {} 分红记录失败: {}", code, e))
+                }
+                None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+            }
+        }
+        updated
+    }
+
+    pub fn get(&self, code: &str) -> &[Distribution] {
+        self.funds.get(code).map_or(&[], |e| &e.distributions)
+    }
+}
+
+/// Distributions for `codes` from the local store, fetching what is missing
+/// or stale and saving the store if anything changed. A store that can't be
+/// read is reported and left alone; this run fetches everything afresh.
+pub async fn lookup(
+    codes: &[String],
+    force: bool,
+    cfg: &Config,
+    deadline: Option<Instant>,
+) -> HashMap<String, Vec<Distribution>> {
+    let path = default_path();
+    let (mut store, readable) = match DividendStore::load(&path) {
+        Ok(store) => (store, true),
+        Err(e) => {
+            eprintln!("{}", e);
+            (DividendStore::default(), false)
+        }
+    };
+    if store.update(codes, force, cfg, deadline).await
+        && readable
+        && let Err(e) = store.save(&path)
+    {
+        eprintln!("{}", tr!("Failed to save distributions to {}: {}", "保存分红记录到 {} 失败: {}", path.display(), e));
+    }
+    codes.iter().map(|code| (code.clone(), store.get(code).to_vec())).collect()
+}
+
+/// Cash paid per share with an ex-dividend date in the year up to `today`.
+pub fn trailing_cash(distributions: &[Distribution], today: NaiveDate) -> f64 {
+    let since = today - Duration::days(365);
+    distributions.iter().filter(|d| d.ex_date > since && d.ex_date <= today).map(|d| d.amount).sum()
+}
+
+/// The nearest announced ex-dividend date on or after `today`.
+pub fn upcoming(distributions: &[Distribution], today: NaiveDate) -> Option<&Distribution> {
+    distributions.iter().filter(|d| d.ex_date >= today).min_by_key(|d| d.ex_date)
+}
+
+/// An alert for each code going ex-dividend within `days` days of `today`.
+pub fn alerts(distributions: &HashMap<String, Vec<Distribution>>, today: NaiveDate, days: u32) -> Vec<Alert> {
+    let mut alerts: Vec<Alert> = distributions
+        .iter()
+        .filter_map(|(code, list)| {
+            let next = upcoming(list, today)?;
+            let away = (next.ex_date - today).num_days();
+            (away <= days as i64).then(|| Alert {
+                code: code.clone(),
+                kind: AlertKind::ExDividend { ex_date: next.ex_date, amount: next.amount, days: away },
+            })
+        })
+        .collect();
+    alerts.sort_by(|a, b| a.code.cmp(&b.code));
+    alerts
+}
+
+/// Fetch one fund's distribution history from Eastmoney F10.
+pub async fn fetch(code: &str) -> Result<Vec<Distribution>, Box<dyn std::error::Error>> {
+    let url = format!("https://fundf10.eastmoney.com/fhsp_{}.html", code);
+    let resp = http::get(&url, &[]).await?;
+    if !resp.is_success() {
+        return Err(format!("HTTP {}", resp.status).into());
+    }
+    Ok(parse_distributions(&resp.text))
+}
+
+/// Read the distribution table: rows of year, record date, ex-dividend
+/// date, "每份派现金0.0350元" and payment date. A fund that never paid has
+/// no such rows.
+fn parse_distributions(html: &str) -> Vec<Distribution> {
+    let date = |s: &str| NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d").ok();
+    html.split("<tr")
+        .filter_map(|row| {
+            let cells: Vec<String> =
+                row.split("<td").skip(1).map(|cell| fundinfo::strip_tags(&format!("<td{}", cell)).trim().to_string()).collect();
+            let [_, record, ex, cash, pay, ..] = cells.as_slice() else {
+                return None;
+            };
+            let amount = cash.split("派现金").nth(1)?.trim_end_matches('元').trim().parse().ok()?;
+            Some(Distribution { record_date: date(record), ex_date: date(ex)?, amount, pay_date: date(pay) })
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct DividendRow {
+    code: String,
+    price: Option<f64>,
+    /// Cash paid over the past year, per share.
+    trailing_cash: f64,
+    /// Trailing cash over the current price, percent.
+    trailing_yield: Option<f64>,
+    last: Option<Distribution>,
+    next: Option<Distribution>,
+}
+
+/// Trailing yield and ex-dividend dates for the watchlist.
+pub async fn run(args: &DividendsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let deadline = http::deadline(&cfg.http);
+    let distributions = lookup(&codes, args.refresh, cfg, deadline).await;
+    let quotes = match http::until(deadline, sina::fetch_quotes(&codes)).await {
+        Some(Ok(quotes)) => quotes,
+        Some(Err(e)) => {
+            eprintln!("{}", tr!("Failed to fetch quotes: {}", "获取实时行情失败: {}", e));
+            Default::default()
+        }
+        None => Default::default(),
+    };
+    let today = schedule::now().date_naive();
+
+    let rows: Vec<DividendRow> = codes
+        .iter()
+        .map(|code| {
+            let list = distributions.get(code).map_or(&[][..], Vec::as_slice);
+            let price = quotes.get(code).map(|q| if q.last > 0.0 { q.last } else { q.prev_close }).filter(|&p| p > 0.0);
+            let cash = trailing_cash(list, today);
+            DividendRow {
+                code: code.clone(),
+                price,
+                trailing_cash: cash,
+                trailing_yield: price.map(|p| cash / p * 100.0),
+                last: list.iter().filter(|d| d.ex_date < today).max_by_key(|d| d.ex_date).cloned(),
+                next: upcoming(list, today).cloned(),
+            }
+        })
+        .collect();
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    println!("\n {}", tr!("Distributions:", "分红:"));
+    println!("-----------------------------------------");
+    let na = || "n/a".to_string();
+    for row in &rows {
+        if row.last.is_none() && row.next.is_none() {
+            println!("{}", tr!("Code: {} | no distributions", "代码: {} | 无分红记录", row.code));
+            continue;
+        }
+        let last =
+            row.last.as_ref().map_or_else(na, |d| tr!("{} ({:.4}/share)", "{} (每份 {:.4})", d.ex_date, d.amount));
+        let next = row.next.as_ref().map_or_else(na, |d| {
+            tr!(
+                "{} ({:.4}/share, in {} days)",
+                "{} (每份 {:.4}, {} 日后)",
+                d.ex_date,
+                d.amount,
+                (d.ex_date - today).num_days()
+            )
+        });
+        println!(
+            "{}",
+            tr!(
+                "Code: {} | TTM cash: {:.4} | TTM yield: {} | Last ex-date: {} | Next ex-date: {}",
+                "代码: {} | 近一年派现: {:.4} | 近一年股息率: {} | 上次除息: {} | 下次除息: {}",
+                row.code,
+                row.trailing_cash,
+                row.trailing_yield.map_or_else(na, |y| format!("{:.2}%", y)),
+                last,
+                next
+            )
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,330 @@
+//! `doctor`: check a setup end to end before leaving it to cron or a
+//! service. Each data source the config uses must answer, every watchlist
+//! code must return bars, the stored state must read back with the schema
+//! this build expects, and each notifier gets a test message. Each
+//! source's error rate, latency and circuit breaker are listed too.
+//!
+//! Fails (exit status 1) if any check does, so it can gate a deploy.
+
+use std::fs;
+use std::future::Future;
+use std::path::Path;
+use std::time::Instant;
+
+use clap::Args;
+use futures::stream::{self, StreamExt};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+
+use crate::breaker::{self, State};
+use crate::cache::{self, KlineCache};
+use crate::config::{Config, OutputFormat};
+use crate::cooldown;
+use crate::events;
+use crate::fundinfo;
+use crate::history;
+use crate::http;
+use crate::i18n::tr;
+use crate::paper;
+use crate::schedule;
+use crate::sina;
+use crate::spot;
+use crate::valuation;
+use crate::watch;
+
+/// A code each source is probed with.
+const PROBE_CODE: &str = "510050";
+
+/// Tables and columns the SQLite stores are created with.
+const EVENTS_SCHEMA: &[(&str, &[&str])] =
+    &[("events", &["id", "time", "date", "code", "rule", "value", "threshold", "kind"])];
+const HISTORY_SCHEMA: &[(&str, &[&str])] =
+    &[("bars", &["code", "date", "open", "high", "low", "close", "volume"])];
+const PAPER_SCHEMA: &[(&str, &[&str])] = &[
+    ("account", &["id", "starting_cash", "cash", "opened_at"]),
+    ("positions", &["code", "shares", "cost"]),
+    ("orders", &["id", "time", "code", "side", "shares", "price", "reason", "pnl"]),
+];
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Don't send the test message to each notifier
+    #[arg(long)]
+    no_ping: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Group {
+    Source,
+    Code,
+    Health,
+    Storage,
+    Notifier,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    group: Group,
+    name: String,
+    ok: bool,
+    detail: String,
+}
+
+impl Check {
+    fn new(group: Group, name: &str, result: Result<String, String>) -> Check {
+        let (ok, detail) = match result {
+            Ok(detail) => (true, detail),
+            Err(detail) => (false, detail),
+        };
+        Check { group, name: name.to_string(), ok, detail }
+    }
+}
+
+/// Await `fut`, adding how long it took to its message.
+async fn timed<F>(fut: F) -> Result<String, String>
+where
+    F: Future<Output = Result<String, Box<dyn std::error::Error>>>,
+{
+    let started = Instant::now();
+    let result = fut.await.map_err(|e| e.to_string())?;
+    Ok(tr!("{} in {} ms", "{}, 耗时 {} ms", result, started.elapsed().as_millis()))
+}
+
+fn bars_detail(fetched: (Vec<sina::Candle>, Option<u16>)) -> Result<String, Box<dyn std::error::Error>> {
+    match fetched {
+        (_, Some(status)) if status != 200 => Err(format!("HTTP {}", status).into()),
+        (candles, _) => match candles.last() {
+            Some(bar) => Ok(tr!("last bar {}", "最新K线 {}", bar.date())),
+            None => Err(tr!("no bars returned", "未返回K线").into()),
+        },
+    }
+}
+
+async fn sources(cfg: &Config) -> Vec<Check> {
+    let mut checks = vec![
+        Check::new(
+            Group::Source,
+            "sina klines",
+            timed(async { bars_detail(sina::fetch_kline(PROBE_CODE, sina::DAILY_SCALE, 5).await?) }).await,
+        ),
+        Check::new(
+            Group::Source,
+            "sina quotes",
+            timed(async {
+                let quotes = sina::fetch_quotes(&[PROBE_CODE.to_string()]).await?;
+                match quotes.get(PROBE_CODE) {
+                    Some(_) => Ok(tr!("quote received", "已获取行情")),
+                    None => Err(tr!("no quote returned", "未返回行情").into()),
+                }
+            })
+            .await,
+        ),
+        Check::new(
+            Group::Source,
+            "eastmoney funds",
+            timed(async {
+                let info = fundinfo::fetch(PROBE_CODE).await?;
+                info.name.ok_or_else(|| tr!("no fund name in the page", "页面中无基金名称").into())
+            })
+            .await,
+        ),
+        Check::new(
+            Group::Source,
+            "danjuan valuations",
+            timed(async {
+                let table = valuation::fetch_all().await?;
+                Ok(tr!("{} indices", "{} 个指数", table.len()))
+            })
+            .await,
+        ),
+    ];
+    // The spot feeds only matter to watchlists that use them.
+    let spots: Vec<&String> = cfg.watchlist.iter().filter(|c| spot::is_spot(c)).collect();
+    if spots.iter().any(|c| !c.contains('-')) {
+        checks.push(Check::new(
+            Group::Source,
+            "sina global futures",
+            timed(async { bars_detail(spot::fetch_kline("XAUUSD", 5).await?) }).await,
+        ));
+    }
+    if spots.iter().any(|c| c.contains('-')) {
+        checks.push(Check::new(
+            Group::Source,
+            "coinbase",
+            timed(async { bars_detail(spot::fetch_kline("BTC-USD", 5).await?) }).await,
+        ));
+    }
+    checks
+}
+
+async fn codes(cfg: &Config) -> Vec<Check> {
+    let mut checks: Vec<(usize, Check)> = stream::iter(cfg.watchlist.iter().enumerate())
+        .map(|(i, code)| async move {
+            let result =
+                sina::fetch_etf_kline(code, 5).await.and_then(bars_detail).map_err(|e| e.to_string());
+            (i, Check::new(Group::Code, code, result))
+        })
+        .buffer_unordered(cfg.http.concurrency.max(1))
+        .collect()
+        .await;
+    checks.sort_by_key(|(i, _)| *i);
+    checks.into_iter().map(|(_, check)| check).collect()
+}
+
+/// Whether the SQLite file at `path` has `schema`'s tables and columns.
+/// A file not created yet passes; it gets the schema on first use.
+fn sqlite_schema(path: &Path, schema: &[(&str, &[&str])]) -> Result<String, String> {
+    if !path.exists() {
+        return Ok(tr!("{} not created yet", "{} 尚未创建", path.display()));
+    }
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|e| e.to_string())?;
+    let mut problems = Vec::new();
+    for (table, columns) in schema {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table)).map_err(|e| e.to_string())?;
+        let found: Vec<String> = stmt
+            .query_map([], |row| row.get::<_, String>(1))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?;
+        if found.is_empty() {
+            problems.push(tr!("missing table {}", "缺少表 {}", table));
+            continue;
+        }
+        let missing: Vec<&str> = columns.iter().copied().filter(|c| !found.iter().any(|f| f == c)).collect();
+        if !missing.is_empty() {
+            problems.push(tr!("{} lacks {}", "{} 缺少列 {}", table, missing.join(", ")));
+        }
+    }
+    if problems.is_empty() {
+        Ok(path.display().to_string())
+    } else {
+        Err(tr!("{}: {}", "{}: {}", path.display(), problems.join("; ")))
+    }
+}
+
+fn storage(cfg: &Config) -> Vec<Check> {
+    let cache_path = cache::default_path();
+    let cache = if cache_path.exists() {
+        fs::read_to_string(&cache_path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| match serde_json::from_str::<KlineCache>(&text) {
+                Ok(_) => Ok(cache_path.display().to_string()),
+                Err(_) if cache::plain_format(&text) => Ok(tr!(
+                    "{} is in the old format; stale, will be rewritten",
+                    "{} 为旧格式; 已过期, 将被重写",
+                    cache_path.display()
+                )),
+                Err(e) => Err(e.to_string()),
+            })
+            .map_err(|e| format!("{}: {}", cache_path.display(), e))
+    } else {
+        Ok(tr!("{} not created yet", "{} 尚未创建", cache_path.display()))
+    };
+    let profile = cfg.profile.as_deref();
+    vec![
+        Check::new(Group::Storage, "kline cache", cache),
+        Check::new(Group::Storage, "event log", sqlite_schema(&events::default_db_path(profile), EVENTS_SCHEMA)),
+        Check::new(Group::Storage, "history", sqlite_schema(&history::default_db_path(), HISTORY_SCHEMA)),
+        Check::new(Group::Storage, "paper account", sqlite_schema(&paper::default_db_path(profile), PAPER_SCHEMA)),
+    ]
+}
+
+async fn notifiers(cfg: &Config) -> Vec<Check> {
+    let mut checks = Vec::new();
+    for notifier in watch::notifiers(&cfg.notify) {
+        let result = timed(async { notifier.ping().await.map(|_| tr!("test message sent", "测试消息已发送")) }).await;
+        checks.push(Check::new(Group::Notifier, notifier.name(), result));
+    }
+    checks
+}
+
+/// Each source's record over this and earlier runs; an open breaker fails.
+fn health() -> Vec<Check> {
+    let now = cooldown::now_secs();
+    breaker::snapshot()
+        .iter()
+        .map(|(source, h)| {
+            let state = match h.state(now) {
+                State::Closed => tr!("closed", "正常"),
+                State::Open(until) => tr!("open until {}", "熔断至 {}", schedule::at(until).format("%H:%M")),
+                State::HalfOpen => tr!("probing", "试探中"),
+            };
+            let mut detail = tr!(
+                "{} requests, {:.0}% failed, {:.0} ms average, breaker {}",
+                "{} 次请求, 失败 {:.0}%, 平均 {:.0} ms, 熔断器 {}",
+                h.requests,
+                h.error_rate() * 100.0,
+                h.latency_ms,
+                state
+            );
+            if let Some(error) = h.last_error.as_ref().filter(|_| h.consecutive > 0) {
+                detail.push_str(&tr!("; last error: {}", "; 最近错误: {}", error));
+            }
+            let result = if matches!(h.state(now), State::Open(_)) { Err(detail) } else { Ok(detail) };
+            Check::new(Group::Health, source, result)
+        })
+        .collect()
+}
+
+fn heading(group: Group) -> String {
+    match group {
+        Group::Source => tr!("Data sources:", "数据源:"),
+        Group::Code => tr!("Watchlist codes:", "自选代码:"),
+        Group::Health => tr!("Source health:", "数据源状态:"),
+        Group::Storage => tr!("Stored state:", "本地存储:"),
+        Group::Notifier => tr!("Notifiers:", "通知渠道:"),
+    }
+}
+
+pub async fn run(args: &DoctorArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let deadline = http::deadline(&cfg.http);
+    let mut checks = Vec::new();
+    match http::until(deadline, async {
+        let mut checks = sources(cfg).await;
+        checks.extend(codes(cfg).await);
+        checks
+    })
+    .await
+    {
+        Some(fetched) => checks.extend(fetched),
+        None => checks.push(Check::new(
+            Group::Source,
+            "deadline",
+            Err(tr!("network checks did not finish before the deadline", "网络检查未在截止时间前完成")),
+        )),
+    }
+    checks.extend(health());
+    checks.extend(storage(cfg));
+    if !args.no_ping {
+        checks.extend(notifiers(cfg).await);
+    }
+    let failed = checks.iter().filter(|c| !c.ok).count();
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&checks)?);
+    } else {
+        for group in [Group::Source, Group::Code, Group::Health, Group::Storage, Group::Notifier] {
+            if group == Group::Notifier && args.no_ping {
+                continue;
+            }
+            println!("\n {}", heading(group));
+            println!("-----------------------------------------");
+            let rows: Vec<&Check> = checks.iter().filter(|c| c.group == group).collect();
+            if rows.is_empty() {
+                println!("{}", tr!("None configured", "未配置"));
+            }
+            for check in rows {
+                let status = if check.ok { tr!("ok", "正常") } else { tr!("FAIL", "失败") };
+                println!("{:<5} {}: {}", status, check.name, check.detail);
+            }
+        }
+        println!();
+        if failed == 0 {
+            println!("{}", tr!("All {} checks passed", "全部 {} 项检查通过", checks.len()));
+        }
+    }
+    if failed > 0 {
+        return Err(tr!("{} of {} checks failed", "{} 项检查失败 (共 {} 项)", failed, checks.len()).into());
+    }
+    Ok(())
+}
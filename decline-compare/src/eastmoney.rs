@@ -0,0 +1,63 @@
+//! Daily klines from Eastmoney's push2his service: the backfill's source
+//! for history older than Sina serves, and the stand-in for Sina's klines
+//! while its breaker is open.
+
+use std::time::Instant;
+
+use chrono::NaiveDate;
+use serde_json::Value;
+
+use crate::breaker;
+use crate::http;
+use crate::schedule;
+use crate::sina::Candle;
+use crate::symbols;
+
+/// Eastmoney's `secid`: market 1 for Shanghai, 0 for Shenzhen and Beijing.
+async fn secid(code: &str) -> String {
+    let market = if symbols::sina_code(code).await.starts_with("sh") { 1 } else { 0 };
+    format!("{}.{}", market, code)
+}
+
+/// Rows read `date,open,close,high,low,volume`, with volume in lots.
+fn parse(body: &Value) -> Vec<Candle> {
+    body["data"]["klines"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|row| {
+            let fields: Vec<&str> = row.as_str()?.split(',').collect();
+            let num = |i: usize| fields.get(i)?.parse::<f64>().ok();
+            let date = NaiveDate::parse_from_str(fields.first()?, "%Y-%m-%d").ok()?;
+            Some(Candle {
+                time: date.and_time(schedule::SESSION_CLOSE),
+                open: num(1)?,
+                close: num(2)?,
+                high: num(3)?,
+                low: num(4)?,
+                volume: num(5)? * 100.0,
+            })
+        })
+        .collect()
+}
+
+async fn get(query: &str) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+    let url = format!(
+        "https://push2his.eastmoney.com/api/qt/stock/kline/get?{}&fields1=f1&fields2=f51,f52,f53,f54,f55,f56&klt=101&fqt=0",
+        query
+    );
+    let started = Instant::now();
+    let body: Result<Value, Box<dyn std::error::Error>> = async { Ok(http::get(&url, &[]).await?.json()?) }.await;
+    breaker::record(breaker::EASTMONEY_KLINES, started, body.as_ref().err().map(|e| e.to_string()));
+    Ok(parse(&body?))
+}
+
+/// Unadjusted daily bars for `code` from `from` to `to`, oldest first.
+pub async fn fetch_klines(code: &str, from: NaiveDate, to: NaiveDate) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+    get(&format!("secid={}&beg={}&end={}", secid(code).await, from.format("%Y%m%d"), to.format("%Y%m%d"))).await
+}
+
+/// The last `len` unadjusted daily bars for `code`, oldest first.
+pub async fn fetch_latest(code: &str, len: usize) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+    get(&format!("secid={}&end=20500101&lmt={}", secid(code).await, len)).await
+}
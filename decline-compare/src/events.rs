@@ -0,0 +1,389 @@
+//! A log of every alert the tool has raised, kept in SQLite so past signals
+//! can be reviewed with `events`.
+//!
+//! Each screen run (`decline`, `watch`, `paper run`) and each `spread` check
+//! logs its alerts. An alert is logged once per code, rule and bar date, so
+//! a watch re-running through the session doesn't repeat it; the first time
+//! it was seen is kept. Each `--profile` keeps its own log.
+//!
+//! `events stats` scores each rule against what happened next: the return
+//! from the close on the event's bar date over the following trading days,
+//! and how often it went the way the alert pointed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, NaiveDate};
+use clap::{Args, Subcommand};
+use futures::stream::{self, StreamExt};
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config::{self, Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina::{self, Candle};
+use crate::symbols;
+
+
+/// How far back `events` looks without `--since`.
+const DEFAULT_SINCE_SECS: u64 = 7 * 86_400;
+
+#[derive(Args, Debug)]
+pub struct EventsArgs {
+    #[command(subcommand)]
+    command: Option<EventsCommand>,
+
+    /// Only events first seen this recently, e.g. 12h, 7d or 4w [default: 7d,
+    /// or every event for stats]
+    #[arg(long, global = true, value_name = "DURATION", value_parser = http::parse_secs)]
+    since: Option<u64>,
+
+    /// Only events for this code; repeat for several
+    #[arg(long = "code", global = true, value_name = "CODE", value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Only rules containing this text, e.g. "Keltner" or "low breakout"
+    #[arg(long, global = true)]
+    rule: Option<String>,
+
+    /// Event database [default: $STOCK_DATA_DIR/events.db, or events.PROFILE.db]
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum EventsCommand {
+    /// Each rule's forward returns and hit rate over its logged events
+    Stats(StatsArgs),
+}
+
+#[derive(Args, Debug)]
+struct StatsArgs {
+    /// Trading days after the event to measure returns over, comma separated
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 5, 20])]
+    horizon: Vec<usize>,
+}
+
+type FetchResult = Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>>;
+
+pub fn default_db_path(profile: Option<&str>) -> PathBuf {
+    config::data_dir().join(config::profiled("events", "db", profile))
+}
+
+/// A logged alert.
+#[derive(Debug, Clone, Serialize)]
+pub struct Event {
+    /// When it was first seen, exchange time.
+    pub time: String,
+    /// Bar date it fired on.
+    pub date: NaiveDate,
+    pub code: String,
+    /// `AlertKind::metric`, so not localized.
+    pub rule: String,
+    pub value: f64,
+    pub threshold: f64,
+    pub kind: AlertKind,
+}
+
+pub struct EventLog {
+    conn: Connection,
+}
+
+impl EventLog {
+    pub fn open(path: &Path) -> Result<EventLog, Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS events (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 time TEXT NOT NULL,
+                 date TEXT NOT NULL,
+                 code TEXT NOT NULL,
+                 rule TEXT NOT NULL,
+                 value REAL NOT NULL,
+                 threshold REAL NOT NULL,
+                 kind TEXT NOT NULL,
+                 UNIQUE (code, rule, date)
+             );",
+        )?;
+        Ok(EventLog { conn })
+    }
+
+    /// Log `alerts` as firing on `date`, skipping any already logged for
+    /// that date. Returns the ones that were new.
+    pub fn record(&mut self, alerts: &[Alert], date: NaiveDate) -> Result<Vec<Alert>, Box<dyn std::error::Error>> {
+        let time = schedule::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        let tx = self.conn.transaction()?;
+        let mut added = Vec::new();
+        for alert in alerts {
+            let inserted = tx.execute(
+                "INSERT OR IGNORE INTO events (time, date, code, rule, value, threshold, kind)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    time,
+                    date.to_string(),
+                    alert.code,
+                    alert.kind.metric(),
+                    alert.kind.value(),
+                    alert.kind.threshold(),
+                    serde_json::to_string(&alert.kind)?
+                ],
+            )?;
+            if inserted > 0 {
+                added.push(alert.clone());
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Events first seen at or after `since` (a `%Y-%m-%d %H:%M:%S` time),
+    /// oldest first.
+    pub fn since(&self, since: &str) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT time, date, code, rule, value, threshold, kind FROM events WHERE time >= ?1 ORDER BY time, id",
+        )?;
+        let rows = stmt.query_map([since], |r| {
+            Ok((
+                r.get::<_, String>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, String>(2)?,
+                r.get::<_, String>(3)?,
+                r.get::<_, f64>(4)?,
+                r.get::<_, f64>(5)?,
+                r.get::<_, String>(6)?,
+            ))
+        })?;
+        let mut events = Vec::new();
+        for row in rows {
+            let (time, date, code, rule, value, threshold, kind) = row?;
+            events.push(Event {
+                time,
+                date: date.parse()?,
+                code,
+                rule,
+                value,
+                threshold,
+                kind: serde_json::from_str(&kind)?,
+            });
+        }
+        Ok(events)
+    }
+}
+
+/// Log `alerts` to `profile`'s default database, reporting rather than
+/// failing on errors so a locked or unwritable log never stops a screen.
+/// Returns the alerts not logged before.
+pub fn log(alerts: &[Alert], date: NaiveDate, profile: Option<&str>) -> Vec<Alert> {
+    if alerts.is_empty() {
+        return Vec::new();
+    }
+    let path = default_db_path(profile);
+    match EventLog::open(&path).and_then(|mut events| events.record(alerts, date)) {
+        Ok(added) => added,
+        Err(e) => {
+            eprintln!("{}", tr!("Failed to log events to {}: {}", "记录事件到 {} 失败: {}", path.display(), e));
+            Vec::new()
+        }
+    }
+}
+
+/// The logged events matching the shared filters; `since` is `None` for
+/// every event.
+fn matching(args: &EventsArgs, cfg: &Config, since: Option<&str>) -> Result<Vec<Event>, Box<dyn std::error::Error>> {
+    let path = args.db.clone().unwrap_or_else(|| default_db_path(cfg.profile.as_deref()));
+    let mut events = EventLog::open(&path)?.since(since.unwrap_or_default())?;
+    events.retain(|e| {
+        (args.codes.is_empty() || args.codes.contains(&e.code))
+            && args.rule.as_ref().is_none_or(|r| e.rule.to_lowercase().contains(&r.to_lowercase()))
+    });
+    Ok(events)
+}
+
+fn cutoff(secs: u64) -> String {
+    (schedule::now() - Duration::seconds(secs as i64)).format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+pub async fn run(args: &EventsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    match &args.command {
+        Some(EventsCommand::Stats(stats_args)) => stats(args, stats_args, cfg).await,
+        None => list(args, cfg),
+    }
+}
+
+/// The alerts logged over the requested window.
+fn list(args: &EventsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let since = cutoff(args.since.unwrap_or(DEFAULT_SINCE_SECS));
+    let events = matching(args, cfg, Some(&since))?;
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&events)?);
+        return Ok(());
+    }
+
+    println!("\n {}", tr!("Events since {}:", "{} 以来的事件:", since));
+    println!("-----------------------------------------");
+    if events.is_empty() {
+        println!("{}", tr!("No events logged", "无事件记录"));
+    }
+    for event in &events {
+        println!(
+            "{}",
+            tr!(
+                "{} | Bar: {} | {}: {}",
+                "{} | K线: {} | {}: {}",
+                event.time,
+                event.date,
+                event.code,
+                event.kind
+            )
+        );
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+struct HorizonStats {
+    /// Trading days after the event.
+    days: usize,
+    /// Events with that many bars after them so far.
+    samples: usize,
+    /// Mean return from the event's close, percent.
+    mean_return: Option<f64>,
+    /// Share of samples that moved the way the alert pointed, percent;
+    /// `None` for rules that don't call a direction.
+    hit_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct RuleStats {
+    rule: String,
+    events: usize,
+    horizons: Vec<HorizonStats>,
+}
+
+/// Return from the close on `date` (or the last bar before it) to the close
+/// `days` bars later, percent.
+fn forward_return(candles: &[Candle], date: NaiveDate, days: usize) -> Option<f64> {
+    let entry = candles.partition_point(|c| c.date() <= date).checked_sub(1)?;
+    let exit = candles.get(entry + days)?;
+    let base = candles[entry].close;
+    (base > 0.0).then(|| (exit.close - base) / base * 100.0)
+}
+
+fn rule_stats(rule: &str, events: &[&Event], bars: &HashMap<String, Vec<Candle>>, horizons: &[usize]) -> RuleStats {
+    let horizons = horizons
+        .iter()
+        .map(|&days| {
+            let moves: Vec<(f64, Option<bool>)> = events
+                .iter()
+                .filter_map(|e| {
+                    let ret = forward_return(bars.get(&e.code)?, e.date, days)?;
+                    Some((ret, e.kind.expects_rise()))
+                })
+                .collect();
+            let samples = moves.len();
+            let mean_return = (samples > 0).then(|| moves.iter().map(|(r, _)| r).sum::<f64>() / samples as f64);
+            let called: Vec<bool> = moves.iter().filter_map(|&(r, rise)| rise.map(|up| (r > 0.0) == up)).collect();
+            let hit_rate =
+                (!called.is_empty()).then(|| called.iter().filter(|&&h| h).count() as f64 / called.len() as f64 * 100.0);
+            HorizonStats { days, samples, mean_return, hit_rate }
+        })
+        .collect();
+    RuleStats { rule: rule.to_string(), events: events.len(), horizons }
+}
+
+/// Forward returns and hit rate per rule, from each code's daily bars since
+/// its oldest event.
+async fn stats(args: &EventsArgs, stats_args: &StatsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if stats_args.horizon.contains(&0) {
+        return Err("--horizon must be positive".into());
+    }
+    let mut horizons = stats_args.horizon.clone();
+    horizons.sort();
+    horizons.dedup();
+    let since = args.since.map(cutoff);
+    let events = matching(args, cfg, since.as_deref())?;
+
+    // Spread events are keyed by a pair, not a code; there are no bars to
+    // follow them with.
+    let today = schedule::now().date_naive();
+    let mut oldest: BTreeMap<&str, NaiveDate> = BTreeMap::new();
+    for event in events.iter().filter(|e| !e.code.contains('/')) {
+        let date = oldest.entry(&event.code).or_insert(event.date);
+        *date = (*date).min(event.date);
+    }
+    let deadline = http::deadline(&cfg.http);
+    let fetched: Vec<(&str, Option<FetchResult>)> = stream::iter(oldest)
+        .map(|(code, date)| async move {
+            // Calendar days since the event bound the trading days, plus a
+            // few bars to find the event's close.
+            let len = (today - date).num_days().max(0) as usize + 5;
+            (code, http::until(deadline, sina::fetch_etf_kline(code, len)).await)
+        })
+        .buffer_unordered(cfg.http.concurrency.max(1))
+        .collect()
+        .await;
+    let mut bars = HashMap::new();
+    for (code, result) in fetched {
+        match result {
+            Some(Ok((candles, None | Some(200)))) => {
+                bars.insert(code.to_string(), candles);
+            }
+            Some(Ok((_, Some(status)))) => {
+                eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, code))
+            }
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch {}: {}", "获取 {} 失败: {}", code, e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+        }
+    }
+
+    let mut by_rule: BTreeMap<&str, Vec<&Event>> = BTreeMap::new();
+    for event in &events {
+        by_rule.entry(&event.rule).or_default().push(event);
+    }
+    let rows: Vec<RuleStats> =
+        by_rule.iter().map(|(rule, events)| rule_stats(rule, events, &bars, &horizons)).collect();
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    match &since {
+        Some(since) => println!("\n {}", tr!("Rule hit rates for events since {}:", "{} 以来各规则命中率:", since)),
+        None => println!("\n {}", tr!("Rule hit rates for all logged events:", "全部事件各规则命中率:")),
+    }
+    println!("-----------------------------------------");
+    if rows.is_empty() {
+        println!("{}", tr!("No events logged", "无事件记录"));
+    }
+    let na = || "n/a".to_string();
+    for row in &rows {
+        let horizons: Vec<String> = row
+            .horizons
+            .iter()
+            .map(|h| match h.mean_return {
+                Some(mean) => tr!(
+                    "{}d: {:+.2}%, hit {} ({} events)",
+                    "{} 日: {:+.2}%, 命中 {} ({} 次)",
+                    h.days,
+                    mean,
+                    h.hit_rate.map_or_else(na, |r| format!("{:.0}%", r)),
+                    h.samples
+                ),
+                None => tr!("{}d: n/a", "{} 日: n/a", h.days),
+            })
+            .collect();
+        println!(
+            "{}",
+            tr!("Rule: {} | Events: {} | {}", "规则: {} | 事件: {} | {}", row.rule, row.events, horizons.join(" | "))
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,310 @@
+//! Each trading day's results appended to a Google Sheets or Feishu (Lark)
+//! spreadsheet, one row per code under a header row, so the running history
+//! can be browsed by anyone the sheet is shared with.
+//!
+//! A day is pushed once its bars are final, by the one-off screen or the
+//! first `watch` pass after the close. The last date pushed to each sheet
+//! is kept in `$STOCK_DATA_DIR/export_state.json`, so reruns don't add the
+//! same day twice.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::config::{self, Config, FeishuSheetConfig, GoogleSheetConfig};
+use crate::cooldown;
+use crate::decline::{self, Report};
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule::BarSession;
+use crate::template;
+
+const STATE_FILE: &str = "export_state.json";
+
+const GOOGLE_SCOPE: &str = "https://www.googleapis.com/auth/spreadsheets";
+const FEISHU_API: &str = "https://open.feishu.cn/open-apis";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ExportState {
+    /// Latest trading date pushed, per sheet.
+    exported: BTreeMap<String, NaiveDate>,
+}
+
+fn default_path() -> PathBuf {
+    config::data_dir().join(STATE_FILE)
+}
+
+impl ExportState {
+    /// The state file; empty if nothing was exported yet.
+    fn load() -> Result<ExportState, Box<dyn std::error::Error>> {
+        let path = default_path();
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(ExportState::default()),
+            Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+    }
+
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        config::write_atomic(&default_path(), &serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+enum Sheet<'a> {
+    Google(&'a GoogleSheetConfig),
+    Feishu(&'a FeishuSheetConfig),
+}
+
+impl Sheet<'_> {
+    /// Key in the export state, unique per spreadsheet and tab.
+    fn key(&self) -> String {
+        match self {
+            Sheet::Google(g) => format!("google:{}:{}", g.spreadsheet_id, g.tab),
+            Sheet::Feishu(f) => format!("feishu:{}:{}", f.spreadsheet_token, f.sheet_id),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Sheet::Google(_) => "Google Sheets",
+            Sheet::Feishu(_) => "Feishu",
+        }
+    }
+
+    /// Append `rows`, after `header` if the sheet is empty.
+    async fn append(&self, header: Vec<Value>, rows: Vec<Vec<Value>>) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Sheet::Google(g) => google_append(g, header, rows).await,
+            Sheet::Feishu(f) => feishu_append(f, header, rows).await,
+        }
+    }
+}
+
+fn sheets(cfg: &Config) -> Vec<Sheet<'_>> {
+    let export = &cfg.export;
+    export.google.iter().map(Sheet::Google).chain(export.feishu.iter().map(Sheet::Feishu)).collect()
+}
+
+/// Whether any spreadsheet is configured.
+pub fn enabled(cfg: &Config) -> bool {
+    !sheets(cfg).is_empty()
+}
+
+#[derive(Deserialize)]
+struct ServiceAccount {
+    client_email: String,
+    private_key: String,
+    token_uri: String,
+}
+
+#[derive(Serialize)]
+struct Claims<'a> {
+    iss: &'a str,
+    scope: &'a str,
+    aud: &'a str,
+    iat: u64,
+    exp: u64,
+}
+
+/// An access token for the service account in `credentials`, from a
+/// signed JWT.
+async fn google_token(credentials: &Path) -> Result<String, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(credentials)
+        .map_err(|e| format!("failed to read {}: {}", credentials.display(), e))?;
+    let account: ServiceAccount = serde_json::from_str(&text)?;
+    let now = cooldown::now_secs();
+    let claims = Claims {
+        iss: &account.client_email,
+        scope: GOOGLE_SCOPE,
+        aud: &account.token_uri,
+        iat: now,
+        exp: now + 3600,
+    };
+    let key = EncodingKey::from_rsa_pem(account.private_key.as_bytes())?;
+    let assertion = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key)?;
+    let body: Value = http::client()
+        .post(&account.token_uri)
+        .form(&[("grant_type", "urn:ietf:params:oauth:grant-type:jwt-bearer"), ("assertion", &assertion)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(body["access_token"].as_str().ok_or("no access_token in the token response")?.to_string())
+}
+
+/// The Sheets API URL for `range` of `sheet`, with `suffix` (like
+/// ":append") after it.
+fn google_url(sheet: &GoogleSheetConfig, range: &str, suffix: &str) -> Result<Url, Box<dyn std::error::Error>> {
+    let mut url = Url::parse("https://sheets.googleapis.com/v4/spreadsheets")?;
+    url.path_segments_mut()
+        .map_err(|_| "bad Sheets URL")?
+        .extend(&[sheet.spreadsheet_id.as_str(), "values", &format!("{}!{}{}", sheet.tab, range, suffix)]);
+    Ok(url)
+}
+
+async fn google_append(
+    sheet: &GoogleSheetConfig,
+    header: Vec<Value>,
+    rows: Vec<Vec<Value>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let token = google_token(&sheet.credentials).await?;
+    let first: Value = http::client()
+        .get(google_url(sheet, "A1:A1", "")?)
+        .bearer_auth(&token)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let mut values = Vec::new();
+    if first.get("values").is_none() {
+        values.push(header);
+    }
+    values.extend(rows);
+    let mut url = google_url(sheet, "A1", ":append")?;
+    url.query_pairs_mut().append_pair("valueInputOption", "USER_ENTERED");
+    http::client()
+        .post(url)
+        .bearer_auth(&token)
+        .json(&json!({ "values": values }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// `data` of a Feishu response, or its `msg` as the error.
+async fn feishu_data(request: reqwest::RequestBuilder) -> Result<Value, Box<dyn std::error::Error>> {
+    let body: Value = request.send().await?.json().await?;
+    match body["code"].as_i64() {
+        Some(0) => Ok(body["data"].clone()),
+        code => Err(format!("Feishu error {}: {}", code.unwrap_or(-1), body["msg"].as_str().unwrap_or("")).into()),
+    }
+}
+
+async fn feishu_append(
+    sheet: &FeishuSheetConfig,
+    header: Vec<Value>,
+    rows: Vec<Vec<Value>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let client = http::client();
+    // The app token endpoint answers at the top level, not under `data`.
+    let auth: Value = client
+        .post(format!("{}/auth/v3/tenant_access_token/internal", FEISHU_API))
+        .json(&json!({ "app_id": sheet.app_id, "app_secret": sheet.app_secret }))
+        .send()
+        .await?
+        .json()
+        .await?;
+    let token = auth["tenant_access_token"]
+        .as_str()
+        .ok_or_else(|| format!("Feishu sign-in failed: {}", auth["msg"].as_str().unwrap_or("")))?;
+
+    let values = format!("{}/sheets/v2/spreadsheets/{}/values", FEISHU_API, sheet.spreadsheet_token);
+    let first =
+        feishu_data(client.get(format!("{}/{}!A1:A1", values, sheet.sheet_id)).bearer_auth(token)).await?;
+    let empty = first["valueRange"]["values"]
+        .as_array()
+        .and_then(|rows| rows.first())
+        .and_then(|row| row.as_array())
+        .is_none_or(|row| row.iter().all(Value::is_null));
+    let mut table = Vec::new();
+    if empty {
+        table.push(header);
+    }
+    table.extend(rows);
+    let range = format!("{}!A1:{}1", sheet.sheet_id, column_letter(table[0].len()));
+    feishu_data(
+        client
+            .post(format!("{}_append", values))
+            .bearer_auth(token)
+            .json(&json!({ "valueRange": { "range": range, "values": table } })),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Spreadsheet column name of the `n`th column, 1-based: A, B, ..., Z, AA.
+fn column_letter(mut n: usize) -> String {
+    let mut name = Vec::new();
+    while n > 0 {
+        n -= 1;
+        name.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// The report's rows as cells of `columns`; numbers stay numbers so the
+/// sheet can chart them.
+fn cells(report: &Report, columns: &[String]) -> Vec<Vec<Value>> {
+    report
+        .rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|c| match decline::field(row, c) {
+                    Some(template::Value::Number(n)) => json!((n * 1e4).round() / 1e4),
+                    Some(template::Value::Text(text)) => json!(text),
+                    None => json!(""),
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Append `report` to each configured sheet that doesn't have its trading
+/// day yet. Live bars aren't final, so they wait for the close.
+pub async fn daily(cfg: &Config, report: &Report) {
+    let sheets = sheets(cfg);
+    let (Some(date), false, false) = (report.as_of, sheets.is_empty(), cfg.dry_run) else {
+        return;
+    };
+    if report.session == Some(BarSession::Live) || report.rows.is_empty() {
+        return;
+    }
+    // Without the state every sheet would get the day again.
+    let mut state = match ExportState::load() {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{}", tr!("Skipping the export: {}", "跳过导出: {}", e));
+            return;
+        }
+    };
+    let header: Vec<Value> = cfg.export.columns.iter().map(|c| json!(c)).collect();
+    let rows = cells(report, &cfg.export.columns);
+    let mut changed = false;
+    for sheet in sheets {
+        let key = sheet.key();
+        if state.exported.get(&key).is_some_and(|&done| done >= date) {
+            continue;
+        }
+        let deadline = http::deadline(&cfg.http);
+        match http::until(deadline, sheet.append(header.clone(), rows.clone())).await {
+            Some(Ok(())) => {
+                eprintln!("{}", tr!("Exported {} rows for {} to {}", "已导出 {} 行 ({}) 到 {}", rows.len(), date, sheet.name()));
+                state.exported.insert(key, date);
+                changed = true;
+            }
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to export to {}: {}", "导出到 {} 失败: {}", sheet.name(), e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", sheet.name())),
+        }
+    }
+    if changed && let Err(e) = state.save() {
+        let path = default_path();
+        eprintln!("{}", tr!("Failed to save export state to {}: {}", "保存导出状态到 {} 失败: {}", path.display(), e));
+    }
+}
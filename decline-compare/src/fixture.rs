@@ -0,0 +1,87 @@
+//! Canned klines in place of the network, for demos, tests and replaying a
+//! user's report. With `--fixture DIR` each code's daily bars come from
+//! `DIR/CODE.json` and its minute bars from `DIR/CODE.SCALE.json`, e.g.
+//! `510300.5.json`, in the form Sina's kline API returns them, so a file
+//! can be captured with curl from the URL `sina::fetch_kline` requests.
+//!
+//! So that the same files always give the same output, the exchange clock
+//! stops at the close of the newest bar across them, realtime quotes and
+//! NAVs come back empty and the kline cache is neither read nor written.
+//! A fixture run is also a dry run: nothing goes to the event log, the
+//! report archive, the export sheets, Postgres, Redis or MQTT, and `watch`
+//! only previews its notifications.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::DateTime;
+use chrono_tz::Asia::Shanghai;
+use chrono_tz::Tz;
+
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina::{self, Candle};
+
+struct Fixture {
+    dir: PathBuf,
+    /// The close of the newest bar, where the exchange clock stands.
+    now: DateTime<Tz>,
+}
+
+static FIXTURE: OnceLock<Fixture> = OnceLock::new();
+
+fn read(path: &Path) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+    let text =
+        fs::read_to_string(path).map_err(|e| tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e))?;
+    sina::parse_klines(&text)
+        .ok_or_else(|| tr!("{} is not a Sina kline response", "{} 不是新浪K线数据", path.display()).into())
+}
+
+/// Serve klines from the files in `dir` from now on. Fails if one of them
+/// doesn't parse or there are none.
+pub fn init(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut newest = None;
+    let entries = fs::read_dir(dir).map_err(|e| tr!("Failed to read {}: {}", "读取 {} 失败: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "json") {
+            newest = newest.max(read(&path)?.last().map(|c| c.time));
+        }
+    }
+    let now = newest
+        .map(|time| time.max(time.date().and_time(schedule::SESSION_CLOSE)))
+        .and_then(|time| time.and_local_timezone(Shanghai).single())
+        .ok_or_else(|| tr!("No klines in {}", "{} 中没有K线", dir.display()))?;
+    let _ = FIXTURE.set(Fixture { dir: dir.to_path_buf(), now });
+    Ok(())
+}
+
+/// Whether klines come from fixture files.
+pub fn active() -> bool {
+    FIXTURE.get().is_some()
+}
+
+/// The stopped exchange clock, when klines come from fixture files.
+pub fn now() -> Option<DateTime<Tz>> {
+    FIXTURE.get().map(|f| f.now)
+}
+
+/// The last `datalen` bars of `scale` minutes for `code`, as a successful
+/// fetch returns them.
+pub fn kline(
+    code: &str,
+    scale: u32,
+    datalen: usize,
+) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    let fixture = FIXTURE.get().ok_or("no fixture directory")?;
+    let name =
+        if scale == sina::DAILY_SCALE { format!("{}.json", code) } else { format!("{}.{}.json", code, scale) };
+    let path = fixture.dir.join(name);
+    if !path.exists() {
+        return Err(tr!("No fixture for {}: {} not found", "{} 无测试数据: 未找到 {}", code, path.display()).into());
+    }
+    let mut candles = read(&path)?;
+    candles.drain(..candles.len().saturating_sub(datalen));
+    Ok((candles, Some(200)))
+}
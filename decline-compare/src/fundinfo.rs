@@ -0,0 +1,238 @@
+//! Fund metadata (fees, tracking index, issuer, inception) from Eastmoney's
+//! fund database, kept in a local store and refreshed monthly.
+//!
+//! `[instruments.funds.<code>]` in the config fills in or corrects any field,
+//! which also covers funds the database doesn't know.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::config::{self, Config};
+use crate::cooldown;
+use crate::http;
+use crate::i18n::tr;
+use crate::instruments::DurationBucket;
+use crate::spot;
+
+const STORE_FILE: &str = "fund_info.json";
+
+/// Refetch a fund's metadata once it is this old.
+const MAX_AGE_SECS: u64 = 30 * 24 * 3600;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FundInfo {
+    pub name: Option<String>,
+    pub issuer: Option<String>,
+    /// Eastmoney's fund type, e.g. "指数型-股票" or "债券型-长债".
+    pub fund_type: Option<String>,
+    /// Duration bucket of a bond fund, when its name doesn't give it away.
+    pub duration: Option<DurationBucket>,
+    pub tracking_index: Option<String>,
+    /// The tracked index's code in Danjuan's valuation table, e.g.
+    /// "SH000300"; only ever set from the config.
+    pub index_code: Option<String>,
+    /// Annual management fee, percent.
+    pub management_fee: Option<f64>,
+    /// Annual custody fee, percent.
+    pub custody_fee: Option<f64>,
+    /// Fund inception date.
+    pub listed: Option<NaiveDate>,
+    /// Unix seconds when fetched; 0 for config-only entries.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub fetched_at: u64,
+}
+
+fn is_zero(v: &u64) -> bool {
+    *v == 0
+}
+
+impl FundInfo {
+    /// Management plus custody fee, percent per year.
+    pub fn expense_ratio(&self) -> Option<f64> {
+        self.management_fee.map(|m| m + self.custody_fee.unwrap_or(0.0))
+    }
+
+    /// `self` with every field `other` sets replacing its own.
+    fn overlay(mut self, other: &FundInfo) -> FundInfo {
+        if other.name.is_some() {
+            self.name = other.name.clone();
+        }
+        if other.issuer.is_some() {
+            self.issuer = other.issuer.clone();
+        }
+        if other.fund_type.is_some() {
+            self.fund_type = other.fund_type.clone();
+        }
+        self.duration = other.duration.or(self.duration);
+        if other.tracking_index.is_some() {
+            self.tracking_index = other.tracking_index.clone();
+        }
+        if other.index_code.is_some() {
+            self.index_code = other.index_code.clone();
+        }
+        self.management_fee = other.management_fee.or(self.management_fee);
+        self.custody_fee = other.custody_fee.or(self.custody_fee);
+        self.listed = other.listed.or(self.listed);
+        self
+    }
+}
+
+type FetchResult = Result<FundInfo, Box<dyn std::error::Error>>;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FundStore {
+    funds: BTreeMap<String, FundInfo>,
+}
+
+pub fn default_path() -> PathBuf {
+    config::data_dir().join(STORE_FILE)
+}
+
+impl FundStore {
+    /// The store at `path`; empty if there is none yet.
+    pub fn load(path: &Path) -> Result<FundStore, Box<dyn std::error::Error>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(FundStore::default()),
+            Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        config::write_atomic(path, &serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Fetch every code that is missing or older than `MAX_AGE_SECS` (all of
+    /// them with `force`); spot entries have nothing to fetch. Returns
+    /// whether anything was fetched.
+    pub async fn update(&mut self, codes: &[String], force: bool, cfg: &Config, deadline: Option<Instant>) -> bool {
+        let now = cooldown::now_secs();
+        let stale: Vec<&String> = codes
+            .iter()
+            .filter(|c| !spot::is_spot(c))
+            .filter(|c| force || self.funds.get(*c).is_none_or(|f| now.saturating_sub(f.fetched_at) >= MAX_AGE_SECS))
+            .collect();
+        let fetched: Vec<(&String, Option<FetchResult>)> = stream::iter(stale)
+            .map(|code| async move { (code, http::until(deadline, fetch(code)).await) })
+            .buffer_unordered(cfg.http.concurrency.max(1))
+            .collect()
+            .await;
+        let mut updated = false;
+        for (code, result) in fetched {
+            match result {
+                Some(Ok(mut info)) => {
+                    info.fetched_at = now;
+                    self.funds.insert(code.clone(), info);
+                    updated = true;
+                }
+                Some(Err(e)) => {
+                    eprintln!("{}", tr!("Failed to fetch fund info for {}: {}", "获取 {} 基金资料失败: {}", code, e))
+                }
+                None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+            }
+        }
+        updated
+    }
+
+    /// Metadata for `code`, with config overrides applied.
+    pub fn get(&self, code: &str, cfg: &Config) -> Option<FundInfo> {
+        let fetched = self.funds.get(code);
+        let configured = cfg.instruments.funds.get(code);
+        match (fetched, configured) {
+            (Some(f), Some(c)) => Some(f.clone().overlay(c)),
+            (Some(f), None) => Some(f.clone()),
+            (None, Some(c)) => Some(c.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Metadata for `codes` from the local store, fetching what is missing or
+/// stale and saving the store if anything changed. A store that can't be
+/// read is reported and left alone; this run fetches everything afresh.
+pub async fn lookup(codes: &[String], force: bool, cfg: &Config, deadline: Option<Instant>) -> HashMap<String, FundInfo> {
+    let path = default_path();
+    let (mut store, readable) = match FundStore::load(&path) {
+        Ok(store) => (store, true),
+        Err(e) => {
+            eprintln!("{}", e);
+            (FundStore::default(), false)
+        }
+    };
+    if store.update(codes, force, cfg, deadline).await
+        && readable
+        && let Err(e) = store.save(&path)
+    {
+        eprintln!("{}", tr!("Failed to save fund info to {}: {}", "保存基金资料到 {} 失败: {}", path.display(), e));
+    }
+    codes.iter().filter_map(|code| Some((code.clone(), store.get(code, cfg)?))).collect()
+}
+
+/// Fetch one fund's profile page from Eastmoney F10.
+pub async fn fetch(code: &str) -> Result<FundInfo, Box<dyn std::error::Error>> {
+    let url = format!("https://fundf10.eastmoney.com/jbgk_{}.html", code);
+    let resp = http::get(&url, &[]).await?;
+    if !resp.is_success() {
+        return Err(format!("HTTP {}", resp.status).into());
+    }
+    let info = parse_profile(&resp.text);
+    if info.name.is_none() {
+        return Err("no fund profile on the page".into());
+    }
+    Ok(info)
+}
+
+/// Read the profile table: each field is a `<th>label</th><td>value</td>` pair.
+fn parse_profile(html: &str) -> FundInfo {
+    // Fees read like "0.50%（每年）".
+    let percent = |s: String| s.split('%').next()?.trim().parse().ok();
+    FundInfo {
+        name: field(html, "基金简称"),
+        issuer: field(html, "基金管理人"),
+        fund_type: field(html, "基金类型"),
+        duration: None,
+        tracking_index: field(html, "跟踪标的").filter(|s| !s.contains("无跟踪标的")),
+        index_code: None,
+        management_fee: field(html, "管理费率").and_then(percent),
+        custody_fee: field(html, "托管费率").and_then(percent),
+        listed: field(html, "成立日期/规模")
+            .and_then(|s| NaiveDate::parse_from_str(s.split('/').next()?.trim(), "%Y年%m月%d日").ok()),
+        fetched_at: 0,
+    }
+}
+
+/// Text of the table cell following the header `label`.
+fn field(html: &str, label: &str) -> Option<String> {
+    let at = html.find(&format!(">{}<", label))?;
+    let rest = &html[at..];
+    let cell = &rest[rest.find("<td")?..];
+    let body = &cell[cell.find('>')? + 1..cell.find("</td>")?];
+    let text = strip_tags(body);
+    let text = text.trim();
+    (!text.is_empty() && text != "--" && text != "---").then(|| text.to_string())
+}
+
+pub fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.replace("&nbsp;", " ")
+}
@@ -0,0 +1,496 @@
+//! `serve`: the analysis engine over gRPC, so strongly-typed clients in
+//! other languages can use it. The service is defined in
+//! `proto/biga.proto`; stubs for any language can be generated from it.
+//! Built with `--features grpc`.
+//!
+//! `GetDecline` runs the screen on demand with the flags `serve` was
+//! started with, `GetQuote` fetches realtime quotes, and `StreamSignals`
+//! follows a screen the server reruns every `--interval` minutes, sending
+//! each alert once per code, rule and bar date.
+//!
+//! With `--http` (and `--features openapi`), `GetDecline` and `GetQuote`
+//! are also served as JSON over HTTP, described by an OpenAPI document at
+//! `/openapi.json` with Swagger UI at `/docs`; see [`rest`].
+//!
+//! Both ask for the `[serve]` credentials, when set: an API key as a
+//! Bearer token or `X-API-Key`, or basic auth. `[serve.users.NAME]` adds
+//! a user per profile, so one server can host a household: a request with
+//! a user's credentials is served their profile, with its own watchlist,
+//! portfolio, event log and signal stream; the base config then needs
+//! credentials of its own. HTTP settings, language, plugins and scripts
+//! are set up once per process, from the base config.
+
+use clap::Args;
+
+use crate::config::Config;
+use crate::decline::DeclineArgs;
+
+#[derive(Args, Debug)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct ServeArgs {
+    #[command(flatten)]
+    pub decline: DeclineArgs,
+
+    /// Address to listen on [default: 127.0.0.1:50051]
+    #[arg(long)]
+    addr: Option<String>,
+
+    /// Minutes between the screens StreamSignals follows [default: 5]
+    #[arg(long)]
+    interval: Option<u64>,
+
+    /// Also serve JSON over HTTP on this address, e.g. 127.0.0.1:8080,
+    /// with Swagger UI at /docs (needs --features openapi)
+    #[arg(long)]
+    http: Option<String>,
+}
+
+impl ServeArgs {
+    /// Layer the flags given on the command line over `cfg`.
+    pub fn apply(&self, cfg: &mut Config) {
+        self.decline.apply(cfg);
+        if let Some(minutes) = self.interval {
+            cfg.watch.interval_minutes = minutes;
+        }
+    }
+}
+
+#[cfg(not(feature = "grpc"))]
+pub async fn run(_args: &ServeArgs, _cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    Err("serve needs a build with --features grpc".into())
+}
+
+#[cfg(feature = "grpc")]
+pub use server::run;
+
+#[cfg(feature = "openapi")]
+mod rest;
+
+#[cfg(feature = "grpc")]
+mod server {
+    use std::collections::{HashMap, HashSet};
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use base64::Engine as _;
+    use futures::Stream;
+    use futures::stream::{self, StreamExt};
+    use tokio::sync::{broadcast, mpsc, oneshot};
+    use tonic::service::interceptor::InterceptedService;
+    use tonic::{Request, Response, Status};
+
+    use super::ServeArgs;
+    use crate::alerts::Alert;
+    use crate::config::{Config, Credentials, SmoothingConfig};
+    use crate::decline::{self, DeclineArgs, Report};
+    use crate::i18n::tr;
+    use crate::service;
+    use crate::sina;
+    use crate::symbols;
+
+    pub(super) mod proto {
+        tonic::include_proto!("biga.v1");
+    }
+
+    use proto::biga_server::{Biga, BigaServer};
+
+    const DEFAULT_ADDR: &str = "127.0.0.1:50051";
+
+    /// Signals a slow subscriber may fall behind by before it skips ahead.
+    const SIGNAL_BUFFER: usize = 1024;
+
+    /// Work for the engine thread. The screen's futures hold errors that
+    /// aren't `Send`, so they run there, on a `LocalSet`, rather than on
+    /// tonic's worker threads.
+    enum Job {
+        Decline {
+            args: Box<DeclineArgs>,
+            cfg: Box<Config>,
+            reply: oneshot::Sender<Result<proto::DeclineReply, String>>,
+        },
+        Quote {
+            codes: Vec<String>,
+            reply: oneshot::Sender<Result<Vec<proto::Quote>, String>>,
+        },
+    }
+
+    /// Someone `serve` answers to: the base config or a profile.
+    struct User {
+        credentials: Credentials,
+        cfg: Config,
+        signals: broadcast::Sender<proto::Signal>,
+    }
+
+    /// Which user a request authenticated as, an index into `Engine::users`.
+    #[derive(Debug, Clone, Copy)]
+    pub(super) struct Caller(usize);
+
+    pub(super) struct Engine {
+        args: DeclineArgs,
+        /// Profiles first, so the base config's credentials (or their
+        /// absence) don't catch the profiles' requests.
+        users: Vec<User>,
+        jobs: mpsc::UnboundedSender<Job>,
+    }
+
+    fn signal(alert: &Alert, report: &Report) -> proto::Signal {
+        proto::Signal {
+            code: alert.code.clone(),
+            date: report.as_of.map(|d| d.to_string()).unwrap_or_default(),
+            rule: alert.kind.metric(),
+            value: alert.kind.value(),
+            threshold: alert.kind.threshold(),
+            message: alert.to_string(),
+            kind_json: serde_json::to_string(&alert.kind).unwrap_or_default(),
+        }
+    }
+
+    fn reply(report: &Report) -> proto::DeclineReply {
+        let rows = report
+            .rows
+            .iter()
+            .map(|row| {
+                let metrics: HashMap<String, f64> = row.values().map(|(k, v)| (k.to_string(), v)).collect();
+                proto::DeclineRow {
+                    code: row.code().to_string(),
+                    date: row.date().to_string(),
+                    close: metrics.get("close").copied().unwrap_or_default(),
+                    decline: metrics.get("decline").copied().unwrap_or_default(),
+                    score: metrics.get("score").copied(),
+                    metrics,
+                }
+            })
+            .collect();
+        proto::DeclineReply {
+            generated_at: report.generated_at.to_rfc3339(),
+            as_of: report.as_of.map(|d| d.to_string()).unwrap_or_default(),
+            session: report
+                .session
+                .and_then(|s| serde_json::to_value(s).ok())
+                .and_then(|v| v.as_str().map(str::to_string))
+                .unwrap_or_default(),
+            rows,
+            signals: report.alerts.iter().map(|a| signal(a, report)).collect(),
+            timed_out: report.timed_out.clone(),
+        }
+    }
+
+    async fn fetch_quotes(codes: &[String]) -> Result<Vec<proto::Quote>, Box<dyn std::error::Error>> {
+        let quotes = sina::fetch_quotes(codes).await?;
+        Ok(codes
+            .iter()
+            .filter_map(|code| {
+                let q = quotes.get(code)?;
+                Some(proto::Quote {
+                    code: code.clone(),
+                    name: q.name.clone(),
+                    last: q.last,
+                    prev_close: q.prev_close,
+                    open: q.open,
+                    high: q.high,
+                    low: q.low,
+                    bid: q.bid,
+                    ask: q.ask,
+                    volume: q.volume,
+                    amount: q.amount,
+                    time: q.time.map(|t| t.to_string()).unwrap_or_default(),
+                })
+            })
+            .collect())
+    }
+
+    fn parse_codes(given: &[String]) -> Result<Vec<String>, Status> {
+        given.iter().map(|c| symbols::parse_code(c)).collect::<Result<_, _>>().map_err(Status::invalid_argument)
+    }
+
+    /// Compare without stopping at the first difference, so timing doesn't
+    /// give away how much of a guess was right.
+    fn same(a: &str, b: &str) -> bool {
+        a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+    }
+
+    /// Whether a request's `Authorization` and `X-API-Key` headers meet
+    /// `serve`'s credentials.
+    fn admits(serve: &Credentials, authorization: Option<&str>, api_key: Option<&str>) -> bool {
+        if serve.is_empty() {
+            return true;
+        }
+        let (scheme, credentials) = authorization.and_then(|a| a.trim().split_once(' ')).unwrap_or_default();
+        let key = if scheme.eq_ignore_ascii_case("bearer") { Some(credentials.trim()) } else { api_key };
+        if let (Some(want), Some(given)) = (&serve.api_key, key)
+            && same(want, given)
+        {
+            return true;
+        }
+        let basic = scheme
+            .eq_ignore_ascii_case("basic")
+            .then(|| base64::engine::general_purpose::STANDARD.decode(credentials.trim()).ok())
+            .flatten()
+            .and_then(|decoded| String::from_utf8(decoded).ok());
+        match (&serve.username, basic) {
+            (Some(user), Some(given)) => {
+                same(&format!("{}:{}", user, serve.password.as_deref().unwrap_or_default()), &given)
+            }
+            _ => false,
+        }
+    }
+
+    impl Engine {
+        /// The user whose credentials a request's `Authorization` and
+        /// `X-API-Key` headers carry, if any.
+        pub(super) fn caller(&self, authorization: Option<&str>, api_key: Option<&str>) -> Option<Caller> {
+            self.users.iter().position(|u| admits(&u.credentials, authorization, api_key)).map(Caller)
+        }
+
+        /// Whether failed requests should be told to send basic auth, so
+        /// that browsers prompt for it.
+        #[cfg_attr(not(feature = "openapi"), allow(dead_code))]
+        pub(super) fn wants_basic(&self) -> bool {
+            self.users.iter().any(|u| u.credentials.username.is_some())
+        }
+
+        /// The profile `caller` is served, `None` for the base config.
+        #[cfg_attr(not(feature = "openapi"), allow(dead_code))]
+        pub(super) fn profile(&self, caller: Caller) -> Option<&str> {
+            self.users[caller.0].cfg.profile.as_deref()
+        }
+
+        /// The caller's `[smoothing]` settings.
+        #[cfg_attr(not(feature = "openapi"), allow(dead_code))]
+        pub(super) fn smoothing(&self, caller: Caller) -> &SmoothingConfig {
+            &self.users[caller.0].cfg.smoothing
+        }
+
+        /// Hand `job` to the engine thread and wait for its answer.
+        async fn ask<T>(&self, job: impl FnOnce(oneshot::Sender<T>) -> Job) -> Result<T, Status> {
+            let (tx, rx) = oneshot::channel();
+            self.jobs.send(job(tx)).map_err(|_| Status::unavailable("the engine has stopped"))?;
+            rx.await.map_err(|_| Status::internal("the engine dropped the request"))
+        }
+
+        /// `caller`'s screen over `days` (0 for the server's window) and
+        /// `codes` (none for their watchlist).
+        pub(super) async fn decline(
+            &self,
+            caller: Caller,
+            days: u32,
+            codes: &[String],
+        ) -> Result<proto::DeclineReply, Status> {
+            let mut cfg = self.users[caller.0].cfg.clone();
+            if !codes.is_empty() {
+                cfg.watchlist = parse_codes(codes)?;
+            }
+            let args = if days > 0 { self.args.with_day(days as usize) } else { self.args.clone() };
+            let reply = self.ask(|reply| Job::Decline { args: Box::new(args), cfg: Box::new(cfg), reply }).await?;
+            reply.map_err(Status::internal)
+        }
+
+        pub(super) async fn quotes(&self, codes: &[String]) -> Result<Vec<proto::Quote>, Status> {
+            let codes = parse_codes(codes)?;
+            self.ask(|reply| Job::Quote { codes, reply }).await?.map_err(Status::unavailable)
+        }
+    }
+
+    #[tonic::async_trait]
+    impl Biga for Engine {
+        async fn get_decline(
+            &self,
+            request: Request<proto::DeclineRequest>,
+        ) -> Result<Response<proto::DeclineReply>, Status> {
+            let caller = caller(&request)?;
+            let request = request.into_inner();
+            Ok(Response::new(self.decline(caller, request.days, &request.codes).await?))
+        }
+
+        async fn get_quote(&self, request: Request<proto::QuoteRequest>) -> Result<Response<proto::QuoteReply>, Status> {
+            let quotes = self.quotes(&request.into_inner().codes).await?;
+            Ok(Response::new(proto::QuoteReply { quotes }))
+        }
+
+        type StreamSignalsStream = Pin<Box<dyn Stream<Item = Result<proto::Signal, Status>> + Send>>;
+
+        async fn stream_signals(
+            &self,
+            request: Request<proto::StreamSignalsRequest>,
+        ) -> Result<Response<Self::StreamSignalsStream>, Status> {
+            let signals = self.users[caller(&request)?.0].signals.subscribe();
+            let wanted = parse_codes(&request.into_inner().codes)?;
+            let signals = stream::unfold(signals, |mut rx| async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(signal) => return Some((signal, rx)),
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    }
+                }
+            })
+            .filter(move |s| std::future::ready(wanted.is_empty() || wanted.contains(&s.code)))
+            .map(Ok);
+            Ok(Response::new(Box::pin(signals)))
+        }
+    }
+
+    /// The `Caller` `check` found for `request`.
+    fn caller<T>(request: &Request<T>) -> Result<Caller, Status> {
+        request.extensions().get::<Caller>().copied().ok_or_else(|| Status::internal("no caller"))
+    }
+
+    /// Turn away gRPC calls without `[serve]` credentials, and note whose
+    /// the rest are.
+    fn check(engine: Arc<Engine>) -> impl Fn(Request<()>) -> Result<Request<()>, Status> + Clone {
+        move |mut request: Request<()>| {
+            let header = |name| request.metadata().get(name).and_then(|v| v.to_str().ok());
+            let caller = engine.caller(header("authorization"), header("x-api-key"));
+            let caller = caller.ok_or_else(|| Status::unauthenticated("missing or wrong credentials"))?;
+            request.extensions_mut().insert(caller);
+            Ok(request)
+        }
+    }
+
+    /// Rerun the screen every interval, broadcasting alerts not sent yet.
+    async fn follow(args: DeclineArgs, cfg: Config, signals: broadcast::Sender<proto::Signal>) {
+        let mut sent = HashSet::new();
+        let mut ticker = tokio::time::interval(Duration::from_secs(cfg.watch.interval_minutes * 60));
+        loop {
+            ticker.tick().await;
+            let report = match decline::collect(&args, &cfg).await {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("{}", tr!("Screen failed: {}", "筛选失败: {}", e));
+                    continue;
+                }
+            };
+            for alert in &report.alerts {
+                if sent.insert((alert.code.clone(), alert.kind.metric(), report.as_of)) {
+                    // No subscribers is not an error; the signal is just dropped.
+                    let _ = signals.send(signal(alert, &report));
+                }
+            }
+        }
+    }
+
+    /// The engine thread: answer jobs until the server shuts down and drops
+    /// the sender, following each user's screen for `StreamSignals`
+    /// meanwhile.
+    fn engine(
+        args: DeclineArgs,
+        follows: Vec<(Config, broadcast::Sender<proto::Signal>)>,
+        mut jobs: mpsc::UnboundedReceiver<Job>,
+    ) {
+        let runtime = match tokio::runtime::Builder::new_current_thread().enable_all().build() {
+            Ok(runtime) => runtime,
+            Err(e) => return eprintln!("{}", tr!("Failed to start the engine: {}", "启动引擎失败: {}", e)),
+        };
+        let local = tokio::task::LocalSet::new();
+        for (cfg, signals) in follows {
+            local.spawn_local(follow(args.clone(), cfg, signals));
+        }
+        local.block_on(&runtime, async {
+            while let Some(job) = jobs.recv().await {
+                tokio::task::spawn_local(async move {
+                    match job {
+                        Job::Decline { args, cfg, reply } => {
+                            let report = decline::collect(&args, &cfg).await;
+                            let _ = reply.send(report.map(|r| self::reply(&r)).map_err(|e| e.to_string()));
+                        }
+                        Job::Quote { codes, reply } => {
+                            let _ = reply.send(fetch_quotes(&codes).await.map_err(|e| e.to_string()));
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    /// The base config and each `[serve.users]` profile, loaded with the
+    /// same flags, checked for credentials only they hold.
+    fn users(args: &ServeArgs, cfg: &Config) -> Result<Vec<User>, Box<dyn std::error::Error>> {
+        let mut users = Vec::new();
+        for (name, credentials) in &cfg.serve.users {
+            if credentials.is_empty() {
+                return Err(format!("[serve.users.{}] needs an api_key or a username", name).into());
+            }
+            let mut profile = Config::load(cfg.file.as_deref(), Some(name))?;
+            // Set up once per process, so the base config's hold for everyone.
+            profile.http = cfg.http.clone();
+            profile.lang = cfg.lang;
+            args.apply(&mut profile);
+            decline::validate(&args.decline, &profile).map_err(|e| format!("profile '{}': {}", name, e))?;
+            users.push((format!("[serve.users.{}]", name), credentials.clone(), profile));
+        }
+        // Open to anyone, the base user would admit every request the
+        // others' credentials don't.
+        if !users.is_empty() && cfg.serve.credentials.is_empty() {
+            return Err("[serve] needs an api_key or a username when [serve.users] is set".into());
+        }
+        users.push(("[serve]".to_string(), cfg.serve.credentials.clone(), cfg.clone()));
+
+        for (i, (table, credentials, _)) in users.iter().enumerate() {
+            if credentials.username.is_some() != credentials.password.is_some() {
+                return Err(format!("{} needs both a username and a password for basic auth", table).into());
+            }
+            for (other, theirs, _) in &users[..i] {
+                let shared = |a: &Option<String>, b: &Option<String>| a.is_some() && a == b;
+                if shared(&credentials.api_key, &theirs.api_key) || shared(&credentials.username, &theirs.username) {
+                    return Err(format!("{} and {} share credentials; each user needs their own", other, table).into());
+                }
+            }
+        }
+        Ok(users
+            .into_iter()
+            .map(|(_, credentials, cfg)| User { credentials, cfg, signals: broadcast::channel(SIGNAL_BUFFER).0 })
+            .collect())
+    }
+
+    pub async fn run(args: &ServeArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        if cfg.watch.interval_minutes == 0 {
+            return Err("--interval must be at least 1 minute".into());
+        }
+        decline::validate(&args.decline, cfg)?;
+        #[cfg(not(feature = "openapi"))]
+        if args.http.is_some() {
+            return Err("--http needs a build with --features openapi".into());
+        }
+        let users = users(args, cfg)?;
+        if cfg.serve.credentials.is_empty() {
+            eprintln!(
+                "{}",
+                tr!(
+                    "Warning: no [serve] credentials set; anyone who can reach the server can use it",
+                    "警告: 未设置 [serve] 凭据，任何能访问服务的人都可使用"
+                )
+            );
+        }
+        let addr = args.addr.as_deref().unwrap_or(DEFAULT_ADDR).parse()?;
+        let (jobs, queue) = mpsc::unbounded_channel();
+        {
+            let follows = users.iter().map(|u| (u.cfg.clone(), u.signals.clone())).collect();
+            let args = args.decline.clone();
+            std::thread::spawn(move || engine(args, follows, queue));
+        }
+        let engine = Arc::new(Engine { args: args.decline.clone(), users, jobs });
+        eprintln!("{}", tr!("Serving gRPC on {}", "gRPC 服务监听于 {}", addr));
+        let grpc = async {
+            tonic::transport::Server::builder()
+                .add_service(InterceptedService::new(BigaServer::from_arc(engine.clone()), check(engine.clone())))
+                .serve_with_shutdown(addr, service::terminated())
+                .await
+                .map_err(Box::<dyn std::error::Error>::from)
+        };
+        #[cfg(feature = "openapi")]
+        let http = async {
+            match &args.http {
+                Some(addr) => super::rest::serve(addr, engine.clone()).await,
+                None => Ok(()),
+            }
+        };
+        #[cfg(not(feature = "openapi"))]
+        let http = async { Ok(()) };
+        service::notify("READY=1");
+        let served = tokio::try_join!(grpc, http);
+        service::notify("STOPPING=1");
+        served?;
+        Ok(())
+    }
+}
@@ -0,0 +1,297 @@
+//! `GetDecline` and `GetQuote` as JSON over HTTP, for clients with no gRPC
+//! stack, and a dashboard built on them. The replies are the gRPC messages
+//! serialized as JSON, so both interfaces stay in step; `StreamSignals` is
+//! gRPC only.
+//!
+//! - `GET /v1/decline?days=&codes=`: the screen;
+//! - `GET /v1/quote?codes=`: realtime quotes;
+//! - `GET /v1/closes?days=&codes=&smooth=`: recent daily closes from the
+//!   kline cache, for charts, optionally smoothed;
+//! - `GET /v1/events?days=&codes=`: alerts from the event log;
+//! - `GET /openapi.json`: the OpenAPI document, for generating clients;
+//! - `GET /docs`: Swagger UI over it;
+//! - `GET /`: the dashboard, the files under `dashboard/` built into the
+//!   binary, so there is no frontend to deploy.
+//!
+//! Codes are comma-separated, in any form the CLI accepts. Every path, the
+//! docs and dashboard included, asks for `[serve]` credentials; the screen
+//! and the event log are the caller's.
+
+use std::sync::Arc;
+
+use axum::extract::{Extension, Path, Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use chrono::Duration;
+use rust_embed::Embed;
+use serde::{Deserialize, Serialize};
+use tonic::{Code, Status};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+use utoipa_swagger_ui::SwaggerUi;
+
+use super::server::{Caller, Engine};
+use super::server::proto::{DeclineReply, QuoteReply};
+use crate::cache::{self, KlineCache};
+use crate::events::{self, EventLog};
+use crate::i18n::tr;
+use crate::schedule;
+use crate::service;
+use crate::smooth::{self, Smoothing};
+use crate::symbols;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "biga", description = "The decline screen and realtime quotes of decline-compare."),
+    paths(decline, quote, closes, recent_events)
+)]
+struct ApiDoc;
+
+#[derive(Embed)]
+#[folder = "dashboard/"]
+struct Dashboard;
+
+#[derive(Serialize, ToSchema)]
+struct ErrorBody {
+    error: String,
+}
+
+/// A gRPC status as the matching HTTP one, with its message as the body.
+struct Failure(Status);
+
+impl IntoResponse for Failure {
+    fn into_response(self) -> Response {
+        let status = match self.0.code() {
+            Code::InvalidArgument => StatusCode::BAD_REQUEST,
+            Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(ErrorBody { error: self.0.message().to_string() })).into_response()
+    }
+}
+
+/// How far back the charts and alert history look by default.
+const DEFAULT_CLOSES: usize = 60;
+const DEFAULT_EVENT_DAYS: u32 = 7;
+
+fn split(codes: Option<&str>) -> Vec<String> {
+    codes.into_iter().flat_map(|c| c.split(',')).map(str::trim).filter(|c| !c.is_empty()).map(str::to_string).collect()
+}
+
+#[derive(Deserialize, IntoParams)]
+struct DeclineQuery {
+    /// Trading days to measure the decline over; 0 or none uses the server's setting.
+    days: Option<u32>,
+    /// Comma-separated codes, e.g. 513500,159941; none screens the watchlist.
+    codes: Option<String>,
+}
+
+/// Run the decline screen now.
+#[utoipa::path(
+    get,
+    path = "/v1/decline",
+    params(DeclineQuery),
+    responses(
+        (status = 200, description = "The screen's rows and alerts", body = DeclineReply),
+        (status = 400, description = "A code isn't recognised", body = ErrorBody),
+        (status = 500, description = "The screen failed", body = ErrorBody),
+    )
+)]
+async fn decline(
+    State(engine): State<Arc<Engine>>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<DeclineQuery>,
+) -> Result<Json<DeclineReply>, Failure> {
+    let codes = split(query.codes.as_deref());
+    engine.decline(caller, query.days.unwrap_or(0), &codes).await.map(Json).map_err(Failure)
+}
+
+#[derive(Deserialize, IntoParams)]
+struct QuoteQuery {
+    /// Comma-separated exchange-listed codes, e.g. 513500,159941.
+    codes: String,
+}
+
+/// Realtime quotes.
+#[utoipa::path(
+    get,
+    path = "/v1/quote",
+    params(QuoteQuery),
+    responses(
+        (status = 200, description = "A quote per code the feed knows", body = QuoteReply),
+        (status = 400, description = "A code isn't recognised", body = ErrorBody),
+        (status = 503, description = "The quote feed is unreachable", body = ErrorBody),
+    )
+)]
+async fn quote(State(engine): State<Arc<Engine>>, Query(query): Query<QuoteQuery>) -> Result<Json<QuoteReply>, Failure> {
+    let quotes = engine.quotes(&split(Some(&query.codes))).await.map_err(Failure)?;
+    Ok(Json(QuoteReply { quotes }))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct ClosesQuery {
+    /// Closes per code, newest last [default: 60].
+    days: Option<usize>,
+    /// Comma-separated codes.
+    codes: String,
+    /// Smoothing for a less noisy chart [default: the `[smoothing]` method].
+    smooth: Option<Smoothing>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Closes {
+    code: String,
+    /// Trading days, YYYY-MM-DD, oldest first.
+    dates: Vec<String>,
+    closes: Vec<f64>,
+}
+
+/// Recent daily closes per code, from the kline cache the screen keeps;
+/// codes not cached yet are left out.
+#[utoipa::path(
+    get,
+    path = "/v1/closes",
+    params(ClosesQuery),
+    responses(
+        (status = 200, description = "Closes per cached code", body = Vec<Closes>),
+        (status = 400, description = "A code isn't recognised", body = ErrorBody),
+    )
+)]
+async fn closes(
+    State(engine): State<Arc<Engine>>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<ClosesQuery>,
+) -> Result<Json<Vec<Closes>>, Failure> {
+    let codes = split(Some(&query.codes))
+        .iter()
+        .map(|c| symbols::parse_code(c))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| Failure(Status::invalid_argument(e)))?;
+    let days = query.days.unwrap_or(DEFAULT_CLOSES);
+    let smoothing = engine.smoothing(caller);
+    let method = query.smooth.unwrap_or(smoothing.method);
+    let cached = KlineCache::read(&cache::default_path());
+    let series = codes
+        .into_iter()
+        .filter_map(|code| {
+            // Minute bars share the cache; only the daily ones chart here.
+            let daily: Vec<_> =
+                cached.bars(&code, usize::MAX)?.into_iter().filter(|c| c.time.time() == schedule::SESSION_CLOSE).collect();
+            // Smoothed over the whole history, so the first days shown
+            // aren't still settling.
+            let closes: Vec<f64> = daily.iter().map(|c| c.close).collect();
+            let closes = smooth::series(&closes, method, smoothing);
+            let start = daily.len().saturating_sub(days);
+            Some(Closes {
+                code,
+                dates: daily[start..].iter().map(|c| c.date().to_string()).collect(),
+                closes: closes[start..].to_vec(),
+            })
+        })
+        .collect();
+    Ok(Json(series))
+}
+
+#[derive(Deserialize, IntoParams)]
+struct EventsQuery {
+    /// Alerts first seen over this many days [default: 7].
+    days: Option<u32>,
+    /// Comma-separated codes; none for every code.
+    codes: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+struct LoggedEvent {
+    /// When it was first seen, exchange time, YYYY-MM-DD HH:MM:SS.
+    time: String,
+    /// Bar date it fired on, YYYY-MM-DD.
+    date: String,
+    code: String,
+    /// Rule name, as in the event log.
+    rule: String,
+    value: f64,
+    threshold: f64,
+    /// Human-readable description.
+    message: String,
+}
+
+/// Alerts from the caller's event log, oldest first.
+#[utoipa::path(
+    get,
+    path = "/v1/events",
+    params(EventsQuery),
+    responses(
+        (status = 200, description = "The logged alerts", body = Vec<LoggedEvent>),
+        (status = 500, description = "The event log can't be read", body = ErrorBody),
+    )
+)]
+async fn recent_events(
+    State(engine): State<Arc<Engine>>,
+    Extension(caller): Extension<Caller>,
+    Query(query): Query<EventsQuery>,
+) -> Result<Json<Vec<LoggedEvent>>, Failure> {
+    let codes = split(query.codes.as_deref());
+    let since = schedule::now() - Duration::days(query.days.unwrap_or(DEFAULT_EVENT_DAYS).into());
+    let logged = EventLog::open(&events::default_db_path(engine.profile(caller)))
+        .and_then(|log| log.since(&since.format("%Y-%m-%d %H:%M:%S").to_string()))
+        .map_err(|e| Failure(Status::internal(e.to_string())))?;
+    let logged = logged
+        .into_iter()
+        .filter(|e| codes.is_empty() || codes.iter().any(|c| symbols::parse_code(c).is_ok_and(|c| c == e.code)))
+        .map(|e| LoggedEvent {
+            message: format!("{}: {}", e.code, e.kind),
+            time: e.time,
+            date: e.date.to_string(),
+            code: e.code,
+            rule: e.rule,
+            value: e.value,
+            threshold: e.threshold,
+        })
+        .collect();
+    Ok(Json(logged))
+}
+
+/// A dashboard file, `index.html` for `/`.
+async fn asset(path: Option<Path<String>>) -> Response {
+    let path = path.map(|Path(p)| p).unwrap_or_else(|| "index.html".to_string());
+    match Dashboard::get(&path) {
+        Some(file) => ([(header::CONTENT_TYPE, file.metadata.mimetype().to_string())], file.data).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Turn away requests without `[serve]` credentials, and note whose the
+/// rest are.
+async fn guard(State(engine): State<Arc<Engine>>, headers: HeaderMap, mut request: Request, next: Next) -> Response {
+    let value = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if let Some(caller) = engine.caller(value("authorization"), value("x-api-key")) {
+        request.extensions_mut().insert(caller);
+        return next.run(request).await;
+    }
+    let denied = (StatusCode::UNAUTHORIZED, Json(ErrorBody { error: "missing or wrong credentials".to_string() }));
+    if engine.wants_basic() {
+        ([(header::WWW_AUTHENTICATE, "Basic realm=\"biga\"")], denied).into_response()
+    } else {
+        denied.into_response()
+    }
+}
+
+/// Serve the JSON endpoints and their docs on `addr` until SIGTERM.
+pub async fn serve(addr: &str, engine: Arc<Engine>) -> Result<(), Box<dyn std::error::Error>> {
+    let app = Router::new()
+        .route("/v1/decline", get(decline))
+        .route("/v1/quote", get(quote))
+        .route("/v1/closes", get(closes))
+        .route("/v1/events", get(recent_events))
+        .with_state(engine.clone())
+        .merge(SwaggerUi::new("/docs").url("/openapi.json", ApiDoc::openapi()))
+        .route("/", get(asset))
+        .route("/{*path}", get(asset))
+        .layer(middleware::from_fn_with_state(engine, guard));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    eprintln!("{}", tr!("Serving the dashboard on http://{}, API docs at /docs", "仪表盘服务监听于 http://{}，API 文档见 /docs", addr));
+    axum::serve(listener, app).with_graceful_shutdown(service::terminated()).await?;
+    Ok(())
+}
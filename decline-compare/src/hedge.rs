@@ -0,0 +1,222 @@
+//! Hedge ratio between two instruments, for sizing an offsetting position:
+//! the OLS slope of one's closes on the other's over a window, with an
+//! Engle-Granger check that the spread it leaves actually mean-reverts.
+//!
+//! The ratio is shares of the second code per share of the first, so
+//! holding one share of NUMERATOR against ratio shares of DENOMINATOR
+//! leaves the regression's residual as the position's value. Its ADF
+//! statistic is compared with the Engle-Granger critical values for two
+//! series; below the 5% value the pair counts as co-integrated, and the
+//! half-life says how many days a gap takes to close by half.
+
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use clap::Args;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::lots;
+use crate::sina::Candle;
+use crate::spread;
+
+/// Engle-Granger critical values of the residual ADF statistic, two series
+/// with a constant: 1%, 5% and 10%.
+const CRITICAL: [(f64, f64); 3] = [(1.0, -3.90), (5.0, -3.34), (10.0, -3.04)];
+
+#[derive(Args, Debug)]
+pub struct HedgeArgs {
+    /// Pair as NUMERATOR/DENOMINATOR, e.g. 510050/510300
+    #[arg(value_parser = spread::parse_pair)]
+    pair: String,
+
+    /// Trading days the regression runs over
+    #[arg(long, default_value_t = 120)]
+    window: usize,
+
+    /// Value of NUMERATOR to hedge, CNY; sizes the offsetting DENOMINATOR
+    /// position in whole lots
+    #[arg(long)]
+    notional: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Sizing {
+    /// NUMERATOR shares `--notional` buys.
+    shares: u64,
+    /// DENOMINATOR shares that offset them, rounded to whole lots.
+    hedge_shares: u64,
+    hedge_value: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Hedge {
+    pair: String,
+    date: NaiveDate,
+    days: usize,
+    /// DENOMINATOR shares per NUMERATOR share.
+    ratio: f64,
+    intercept: f64,
+    r_squared: f64,
+    /// Slope of NUMERATOR's daily returns on DENOMINATOR's.
+    return_beta: Option<f64>,
+    /// The latest residual in standard deviations of the window's.
+    residual_z: f64,
+    /// ADF t-statistic of the residuals.
+    adf: Option<f64>,
+    /// Smallest of the 1%, 5% and 10% levels the ADF statistic passes.
+    significance: Option<f64>,
+    /// Days for a residual to halve, when it mean-reverts.
+    half_life: Option<f64>,
+    sizing: Option<Sizing>,
+}
+
+/// Slope, intercept and R² of `y` on `x`; `None` if `x` is flat.
+fn ols(x: &[f64], y: &[f64]) -> Option<(f64, f64, f64)> {
+    let n = x.len() as f64;
+    let (mx, my) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let sxx: f64 = x.iter().map(|v| (v - mx).powi(2)).sum();
+    let syy: f64 = y.iter().map(|v| (v - my).powi(2)).sum();
+    let sxy: f64 = x.iter().zip(y).map(|(a, b)| (a - mx) * (b - my)).sum();
+    if sxx <= 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    let r_squared = if syy > 0.0 { sxy * sxy / (sxx * syy) } else { 1.0 };
+    Some((slope, my - slope * mx, r_squared))
+}
+
+/// Dickey-Fuller regression of the residuals' changes on their previous
+/// level, without a constant: the coefficient and its t-statistic.
+fn adf(residuals: &[f64]) -> Option<(f64, f64)> {
+    let (lagged, changes): (Vec<f64>, Vec<f64>) = residuals.windows(2).map(|w| (w[0], w[1] - w[0])).unzip();
+    let sxx: f64 = lagged.iter().map(|v| v * v).sum();
+    if lagged.len() < 3 || sxx <= 0.0 {
+        return None;
+    }
+    let gamma = lagged.iter().zip(&changes).map(|(x, y)| x * y).sum::<f64>() / sxx;
+    let sse: f64 = lagged.iter().zip(&changes).map(|(x, y)| (y - gamma * x).powi(2)).sum();
+    let se = (sse / (lagged.len() - 1) as f64 / sxx).sqrt();
+    (se > 0.0).then(|| (gamma, gamma / se))
+}
+
+/// Both codes' closes on the days both traded, oldest first.
+fn aligned(a: &[Candle], b: &[Candle]) -> Vec<(NaiveDate, f64, f64)> {
+    let b: HashMap<NaiveDate, f64> = b.iter().map(|c| (c.date(), c.close)).collect();
+    a.iter().filter_map(|c| Some((c.date(), c.close, *b.get(&c.date())?))).collect()
+}
+
+fn hedge(pair: &str, closes: &[(NaiveDate, f64, f64)], notional: Option<f64>) -> Option<Hedge> {
+    let (&(date, last_a, last_b), _) = closes.split_last()?;
+    let (a, b): (Vec<f64>, Vec<f64>) = closes.iter().map(|&(_, a, b)| (a, b)).unzip();
+    let (ratio, intercept, r_squared) = ols(&b, &a)?;
+
+    let residuals: Vec<f64> = a.iter().zip(&b).map(|(a, b)| a - intercept - ratio * b).collect();
+    let sd = (residuals.iter().map(|r| r * r).sum::<f64>() / (residuals.len() - 1) as f64).sqrt();
+    let residual_z = if sd > 0.0 { residuals[residuals.len() - 1] / sd } else { 0.0 };
+    let (gamma, adf) = adf(&residuals).unzip();
+    let significance = adf.and_then(|t| CRITICAL.iter().find(|&&(_, c)| t < c).map(|&(level, _)| level));
+    let half_life = gamma.filter(|&g| g < 0.0 && g > -1.0).map(|g| -(2f64.ln()) / (1.0 + g).ln());
+
+    let returns = |v: &[f64]| v.windows(2).map(|w| w[1] / w[0] - 1.0).collect::<Vec<f64>>();
+    let return_beta = ols(&returns(&b), &returns(&a)).map(|(beta, _, _)| beta);
+
+    let sizing = notional.map(|cash| {
+        let (shares, _) = lots::round_down(cash, last_a);
+        let lot = lots::LOT_SIZE as f64;
+        let hedge_shares = ((shares as f64 * ratio).abs() / lot).round() as u64 * lots::LOT_SIZE;
+        Sizing { shares, hedge_shares, hedge_value: hedge_shares as f64 * last_b }
+    });
+
+    Some(Hedge {
+        pair: pair.to_string(),
+        date,
+        days: closes.len(),
+        ratio,
+        intercept,
+        r_squared,
+        return_beta,
+        residual_z,
+        adf,
+        significance,
+        half_life,
+        sizing,
+    })
+}
+
+pub async fn run(args: &HedgeArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if args.window < 20 {
+        return Err("--window must be at least 20".into());
+    }
+    // `parse_pair` made sure of the slash.
+    let (num, den) = args.pair.split_once('/').unwrap_or_default();
+    let len = args.window + 10;
+    let deadline = http::deadline(&cfg.http);
+    let Some((a, b)) =
+        http::until(deadline, async { (spread::fetch(num, len).await, spread::fetch(den, len).await) }).await
+    else {
+        return Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", args.pair).into());
+    };
+    let closes = aligned(&a?, &b?);
+    let window = &closes[closes.len().saturating_sub(args.window)..];
+    let Some(h) = (window.len() == args.window).then(|| hedge(&args.pair, window, args.notional)).flatten() else {
+        return Err(
+            tr!("Not enough overlapping data for {}: got {} days", "{} 重叠数据不足: 仅 {} 日", args.pair, closes.len())
+                .into(),
+        );
+    };
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&h)?);
+        return Ok(());
+    }
+    let na = || "n/a".to_string();
+    println!("\n {}", tr!("Hedge {} over {} days to {}:", "{} {} 日对冲 (截至 {}):", h.pair, h.days, h.date));
+    println!("-----------------------------------------");
+    println!(
+        "{}",
+        tr!(
+            "Ratio: {:.4} {} per {} | R²: {:.3} | Return beta: {}",
+            "比例: 每份 {2} 对应 {0:.4} 份 {1} | R²: {3:.3} | 收益率 Beta: {4}",
+            h.ratio,
+            den,
+            num,
+            h.r_squared,
+            h.return_beta.map_or_else(na, |b| format!("{:.3}", b))
+        )
+    );
+    let verdict = match h.significance {
+        Some(level) => tr!("co-integrated at {}%", "在 {}% 水平协整", level),
+        None => tr!("not co-integrated", "未协整"),
+    };
+    println!(
+        "{}",
+        tr!(
+            "ADF: {} ({}) | Half-life: {} | Residual z-score: {:+.2}",
+            "ADF: {} ({}) | 半衰期: {} | 残差 Z 值: {:+.2}",
+            h.adf.map_or_else(na, |t| format!("{:.2}", t)),
+            verdict,
+            h.half_life.map_or_else(na, |d| tr!("{:.1}d", "{:.1} 日", d)),
+            h.residual_z
+        )
+    );
+    if let Some(s) = &h.sizing {
+        let side = if h.ratio >= 0.0 { tr!("sell", "卖出") } else { tr!("buy", "买入") };
+        println!(
+            "{}",
+            tr!(
+                "Hedge {} shares of {} by {} {} shares of {} ({:.2})",
+                "为 {1} 的 {0} 股对冲: {2} {4} {3} 股 ({5:.2})",
+                s.shares,
+                num,
+                side,
+                s.hedge_shares,
+                den,
+                s.hedge_value
+            )
+        );
+    }
+    Ok(())
+}
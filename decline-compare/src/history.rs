@@ -0,0 +1,411 @@
+//! Multi-year daily history kept in SQLite, for statistics that look back
+//! further than one Sina request reaches.
+//!
+//! `backfill` takes the newest bars from Sina, then pages back through
+//! Eastmoney a year at a time until a year comes back empty (before the
+//! fund listed) or `--years` is covered. Runs only add bars the store
+//! doesn't have yet. Once a code is backfilled, a request for more daily
+//! bars than Sina serves is topped up from the store.
+//!
+//! Progress is saved after every page next to the database, so a run cut
+//! short by Ctrl-C or a lost connection picks up at the code and year it
+//! stopped at when started again with the same codes and `--years`.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, NaiveDate};
+use clap::Args;
+use rusqlite::{Connection, OpenFlags, params};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, OutputFormat};
+use crate::eastmoney;
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina::{self, Candle};
+use crate::spot;
+use crate::symbols;
+
+const DB_FILE: &str = "history.db";
+
+#[derive(Args, Debug)]
+pub struct BackfillArgs {
+    /// Codes to backfill; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Years of history to reach back
+    #[arg(long, default_value_t = 10)]
+    years: u32,
+
+    /// History database [default: $STOCK_DATA_DIR/history.db]
+    #[arg(long)]
+    db: Option<PathBuf>,
+
+    /// Start over instead of resuming an interrupted run
+    #[arg(long)]
+    restart: bool,
+}
+
+pub fn default_db_path() -> PathBuf {
+    config::data_dir().join(DB_FILE)
+}
+
+/// The database at `path` and its write-ahead log, bytes.
+pub fn disk_size(path: &Path) -> u64 {
+    let mut wal = path.as_os_str().to_owned();
+    wal.push("-wal");
+    [path, Path::new(&wal)].iter().map(|p| fs::metadata(p).map_or(0, |m| m.len())).sum()
+}
+
+pub struct History {
+    conn: Connection,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<History, Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        // Backfills write in long bursts; WAL keeps readers going meanwhile.
+        conn.execute_batch(
+            "PRAGMA journal_mode=WAL;
+             CREATE TABLE IF NOT EXISTS bars (
+                 code TEXT NOT NULL,
+                 date TEXT NOT NULL,
+                 open REAL NOT NULL,
+                 high REAL NOT NULL,
+                 low REAL NOT NULL,
+                 close REAL NOT NULL,
+                 volume REAL NOT NULL,
+                 PRIMARY KEY (code, date)
+             );",
+        )?;
+        Ok(History { conn })
+    }
+
+    /// Add `candles` for `code`, keeping bars already stored. Returns how
+    /// many were new.
+    pub fn insert(&mut self, code: &str, candles: &[Candle]) -> Result<usize, Box<dyn std::error::Error>> {
+        let tx = self.conn.transaction()?;
+        let mut added = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO bars (code, date, open, high, low, close, volume)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            )?;
+            for c in candles {
+                added += stmt.execute(params![code, c.date().to_string(), c.open, c.high, c.low, c.close, c.volume])?;
+            }
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Up to `limit` bars for `code` dated before `before`, oldest first.
+    pub fn before(&self, code: &str, before: NaiveDate, limit: usize) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT date, open, high, low, close, volume FROM bars
+             WHERE code = ?1 AND date < ?2 ORDER BY date DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(params![code, before.to_string(), limit as i64], |row| {
+            Ok((row.get::<_, String>(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?))
+        })?;
+        let mut candles = Vec::new();
+        for row in rows {
+            let (date, open, high, low, close, volume) = row?;
+            let date = NaiveDate::parse_from_str(&date, "%Y-%m-%d")?;
+            candles.push(Candle { time: date.and_time(schedule::SESSION_CLOSE), open, high, low, close, volume });
+        }
+        candles.reverse();
+        Ok(candles)
+    }
+
+    /// Drop bars dated before `cutoff`, then the oldest across all codes
+    /// until the file should fit in `max_bytes`. Nothing is removed when
+    /// `dry_run`; either way returns how many bars would go.
+    pub fn prune(
+        &mut self,
+        path: &Path,
+        cutoff: NaiveDate,
+        max_bytes: Option<u64>,
+        dry_run: bool,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let tx = self.conn.transaction()?;
+        let count = |tx: &rusqlite::Transaction| -> rusqlite::Result<i64> {
+            tx.query_row("SELECT COUNT(*) FROM bars", [], |row| row.get(0))
+        };
+        let before = count(&tx)?;
+        let mut removed = tx.execute("DELETE FROM bars WHERE date < ?1", params![cutoff.to_string()])?;
+        let rows = count(&tx)?;
+        // The file only shrinks at the VACUUM, so estimate its size once the
+        // cutoff is gone. Rows are all about the same size.
+        let bytes = if before > 0 { (disk_size(path) as f64 * rows as f64 / before as f64) as u64 } else { 0 };
+        if let Some(max_bytes) = max_bytes.filter(|&max| bytes > max) {
+            // Drop the same share of the rows as of the bytes.
+            let over = (rows as f64 * (1.0 - max_bytes as f64 / bytes as f64)).ceil() as i64;
+            removed += tx.execute(
+                "DELETE FROM bars WHERE rowid IN (SELECT rowid FROM bars ORDER BY date LIMIT ?1)",
+                params![over],
+            )?;
+        }
+        if dry_run {
+            return Ok(removed);
+        }
+        tx.commit()?;
+        if removed > 0 {
+            // Hand the freed pages back to the filesystem.
+            self.conn.execute_batch("VACUUM; PRAGMA wal_checkpoint(TRUNCATE);")?;
+        }
+        Ok(removed)
+    }
+
+    /// What is stored for `code`.
+    fn span(&self, code: &str) -> Result<Option<Span>, Box<dyn std::error::Error>> {
+        let (first, last, bars): (Option<String>, Option<String>, i64) = self.conn.query_row(
+            "SELECT MIN(date), MAX(date), COUNT(*) FROM bars WHERE code = ?1",
+            params![code],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        Ok(first.zip(last).map(|(first, last)| Span { first, last, bars: bars as usize }))
+    }
+}
+
+/// First and last stored dates and the bar count of one code.
+#[derive(Debug, Serialize)]
+struct Span {
+    first: String,
+    last: String,
+    bars: usize,
+}
+
+/// Prepend stored bars to `candles` until there are `len`, if the store
+/// exists and has older bars for `code`. The store is only read, never
+/// created, here.
+pub fn extend(code: &str, candles: &mut Vec<Candle>, len: usize) {
+    let path = default_db_path();
+    let (Some(first), true) = (candles.first().map(|c| c.date()), path.exists()) else {
+        return;
+    };
+    let older = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+        .map_err(|e| e.into())
+        .and_then(|conn| History { conn }.before(code, first, len.saturating_sub(candles.len())));
+    match older {
+        Ok(older) if !older.is_empty() => {
+            candles.splice(0..0, older);
+        }
+        Ok(_) => {}
+        Err(e) => eprintln!("{}", tr!("Failed to read history for {}: {}", "读取 {} 历史数据失败: {}", code, e)),
+    }
+}
+
+/// Where an unfinished run over `codes` got to.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Progress {
+    years: u32,
+    codes: Vec<String>,
+    done: BTreeSet<String>,
+    /// Newest date still to page back from, for codes started but not done.
+    cursor: BTreeMap<String, NaiveDate>,
+}
+
+impl Progress {
+    fn path(db: &Path) -> PathBuf {
+        db.with_extension("progress.json")
+    }
+
+    /// The saved progress if it is for the same run, else a fresh start.
+    fn load(path: &Path, codes: &[String], years: u32) -> Progress {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|text| serde_json::from_str::<Progress>(&text).ok())
+            .filter(|p| p.codes == codes && p.years == years)
+            .unwrap_or_else(|| Progress { years, codes: codes.to_vec(), ..Progress::default() })
+    }
+
+    fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BackfillRow {
+    code: String,
+    added: usize,
+    stored: Option<Span>,
+    error: Option<String>,
+}
+
+/// Page `code`'s history into `store`, newest first, from where `progress`
+/// left it; returns the bars added.
+async fn backfill(
+    store: &mut History,
+    code: &str,
+    progress: &mut Progress,
+    progress_path: &Path,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let today = schedule::now().date_naive();
+    let mut added = 0;
+    let mut end = match progress.cursor.get(code) {
+        Some(&cursor) => cursor,
+        None => {
+            let (recent, status) = sina::fetch_kline(code, sina::DAILY_SCALE, sina::MAX_DAILY_BARS).await?;
+            if let Some(status) = status.filter(|&s| s != 200) {
+                return Err(format!("HTTP {} for code: {}", status, code).into());
+            }
+            added += store.insert(code, &recent)?;
+            recent.first().map_or(today, |c| c.date()).pred_opt().unwrap_or(today)
+        }
+    };
+
+    let start = today.with_year(today.year() - progress.years as i32).unwrap_or(today);
+    while end >= start {
+        progress.cursor.insert(code.to_string(), end);
+        progress.save(progress_path)?;
+        let from = end.with_year(end.year() - 1).and_then(|d| d.succ_opt()).unwrap_or(start).max(start);
+        let chunk = eastmoney::fetch_klines(code, from, end).await?;
+        // A whole year without bars means the fund hadn't listed yet.
+        if chunk.is_empty() {
+            break;
+        }
+        added += store.insert(code, &chunk)?;
+        let Some(before) = from.pred_opt() else {
+            break;
+        };
+        end = before;
+    }
+    progress.cursor.remove(code);
+    progress.done.insert(code.to_string());
+    progress.save(progress_path)?;
+    Ok(added)
+}
+
+pub async fn run(args: &BackfillArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.db.clone().unwrap_or_else(default_db_path);
+    let mut store = History::open(&path)?;
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let (spots, codes): (Vec<String>, Vec<String>) = codes.into_iter().partition(|c| spot::is_spot(c));
+    if !spots.is_empty() {
+        eprintln!("{}", tr!("Spot prices aren't backfilled: {}", "现货价格不回填: {}", spots.join(", ")));
+    }
+    let progress_path = Progress::path(&path);
+    let mut progress = if args.restart { Progress::default() } else { Progress::load(&progress_path, &codes, args.years) };
+    progress.years = args.years;
+    progress.codes = codes.clone();
+    if !progress.done.is_empty() || !progress.cursor.is_empty() {
+        eprintln!(
+            "{}",
+            tr!(
+                "Resuming an interrupted backfill: {} of {} codes done",
+                "继续未完成的回填: 已完成 {} / {} 个代码",
+                progress.done.len(),
+                codes.len()
+            )
+        );
+    }
+    let deadline = http::deadline(&cfg.http);
+
+    let mut rows = Vec::new();
+    let left: Vec<String> = codes.iter().filter(|c| !progress.done.contains(*c)).cloned().collect();
+    for code in &left {
+        let result = match http::until(deadline, backfill(&mut store, code, &mut progress, &progress_path)).await {
+            Some(result) => result.map_err(|e| e.to_string()),
+            None => Err(tr!("timed out before the deadline", "截止时间前未完成")),
+        };
+        rows.push(BackfillRow {
+            code: code.clone(),
+            added: *result.as_ref().unwrap_or(&0),
+            stored: store.span(code)?,
+            error: result.err(),
+        });
+    }
+
+    let finished = progress.done.len() == codes.len();
+    if finished && let Err(e) = fs::remove_file(&progress_path)
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        eprintln!("{}", tr!("Failed to remove {}: {}", "删除 {} 失败: {}", progress_path.display(), e));
+    }
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+    println!("\n {}", tr!("Backfilled history ({}):", "历史数据回填 ({}):", path.display()));
+    println!("-----------------------------------------");
+    for row in &rows {
+        let (bars, span) = match &row.stored {
+            Some(span) => (span.bars, format!("{} - {}", span.first, span.last)),
+            None => (0, "n/a".to_string()),
+        };
+        println!(
+            "{}",
+            tr!(
+                "Code: {} | Stored: {} bars, {} | New: {}",
+                "代码: {} | 已存: {} 根K线, {} | 新增: {}",
+                row.code,
+                bars,
+                span,
+                row.added
+            )
+        );
+        if let Some(error) = &row.error {
+            eprintln!("{}", tr!("Backfill failed for {}: {}", "{} 回填失败: {}", row.code, error));
+        }
+    }
+    if !finished {
+        println!("{}", tr!("Run backfill again to resume the codes left", "再次运行 backfill 以继续剩余代码"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    /// A store of `days` consecutive daily bars for one code, from 2020.
+    fn store(name: &str, days: i64) -> (History, PathBuf) {
+        let path = std::env::temp_dir().join(format!("biga-history-{}-{}.db", name, std::process::id()));
+        for suffix in ["", "-wal", "-shm"] {
+            let mut file = path.as_os_str().to_owned();
+            file.push(suffix);
+            let _ = fs::remove_file(file);
+        }
+        let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+        let candles: Vec<Candle> = (0..days)
+            .map(|i| Candle {
+                time: (start + Duration::days(i)).and_time(schedule::SESSION_CLOSE),
+                open: 1.0,
+                high: 1.1,
+                low: 0.9,
+                close: 1.05,
+                volume: 1e6,
+            })
+            .collect();
+        let mut history = History::open(&path).unwrap();
+        history.insert("510300", &candles).unwrap();
+        (history, path)
+    }
+
+    #[test]
+    fn prune_sizes_the_store_after_the_cutoff() {
+        let (mut history, path) = store("cutoff", 2000);
+        let cutoff = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap() + Duration::days(1000);
+        let bytes = disk_size(&path);
+
+        // The cutoff alone brings the store to half, under a 60% limit.
+        let removed = history.prune(&path, cutoff, Some(bytes * 6 / 10), true).unwrap();
+        assert_eq!(removed, 1000);
+
+        // Under a 30% limit, 40% of the remaining half goes as well.
+        let removed = history.prune(&path, cutoff, Some(bytes * 3 / 10), true).unwrap();
+        assert!((1399..=1401).contains(&removed), "removed {}", removed);
+    }
+}
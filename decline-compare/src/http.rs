@@ -0,0 +1,92 @@
+//! The shared HTTP client, configured once at startup.
+
+use std::future::Future;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::time::Instant;
+
+use crate::config::HttpConfig;
+use crate::replay;
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// Build the client from the HTTP settings. Later calls are ignored.
+pub fn init(cfg: &HttpConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(cfg.timeout_secs))
+        .connect_timeout(Duration::from_secs(cfg.connect_timeout_secs))
+        .build()?;
+    let _ = CLIENT.set(client);
+    Ok(())
+}
+
+/// The configured client, or reqwest's defaults if `init` never ran.
+pub fn client() -> reqwest::Client {
+    CLIENT.get_or_init(reqwest::Client::new).clone()
+}
+
+/// A data provider's answer to a GET.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub text: String,
+}
+
+impl Response {
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> serde_json::Result<T> {
+        serde_json::from_str(&self.text)
+    }
+}
+
+/// GET `url` from a data provider with `headers`. Saved under `--record`
+/// and answered from the recording under `--replay`.
+pub async fn get(url: &str, headers: &[(&str, &str)]) -> Result<Response, Box<dyn std::error::Error>> {
+    if let Some(recorded) = replay::lookup(url) {
+        return recorded;
+    }
+    let mut request = client().get(url);
+    for &(name, value) in headers {
+        request = request.header(name, value);
+    }
+    let resp = request.send().await?;
+    let response = Response { status: resp.status().as_u16(), text: resp.text().await? };
+    replay::save(url, &response);
+    Ok(response)
+}
+
+/// Seconds from `60s`, `2m`, `1h`, `7d`, `2w` or a bare number of seconds.
+pub fn parse_secs(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let (digits, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let n: u64 = digits.parse().map_err(|_| format!("expected a duration like 60s or 2m, got '{}'", s))?;
+    match unit {
+        "s" => Ok(n),
+        "m" => Ok(n * 60),
+        "h" => Ok(n * 3600),
+        "d" => Ok(n * 86_400),
+        "w" => Ok(n * 7 * 86_400),
+        _ => Err(format!("unknown duration unit '{}' in '{}' (use s, m, h, d or w)", unit, s)),
+    }
+}
+
+/// When a run starting now has to be done by, if a deadline is configured.
+pub fn deadline(cfg: &HttpConfig) -> Option<Instant> {
+    cfg.deadline_secs.map(|secs| Instant::now() + Duration::from_secs(secs))
+}
+
+/// Await `fut`, or give up with `None` once `deadline` passes.
+pub async fn until<F: Future>(deadline: Option<Instant>, fut: F) -> Option<F::Output> {
+    match deadline {
+        Some(at) => tokio::time::timeout_at(at, fut).await.ok(),
+        None => Some(fut.await),
+    }
+}
@@ -0,0 +1,43 @@
+//! Output language for reports and messages.
+//!
+//! The language is chosen once at startup; `tr!` picks the English or
+//! Chinese form of a message and formats it like `format!`. Strings that
+//! are matched or stored (JSON keys, cooldown rule names) stay English.
+
+use std::sync::OnceLock;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lang {
+    #[default]
+    En,
+    /// 简体中文
+    Zh,
+}
+
+static LANG: OnceLock<Lang> = OnceLock::new();
+
+/// Set the output language. Later calls are ignored.
+pub fn init(lang: Lang) {
+    let _ = LANG.set(lang);
+}
+
+/// The configured language, or English if `init` never ran.
+pub fn lang() -> Lang {
+    LANG.get().copied().unwrap_or_default()
+}
+
+/// `tr!("English {}", "中文 {}", args...)` formats whichever form matches
+/// the configured language.
+macro_rules! tr {
+    ($en:literal, $zh:literal $(, $arg:expr)* $(,)?) => {
+        match $crate::i18n::lang() {
+            $crate::i18n::Lang::En => format!($en $(, $arg)*),
+            $crate::i18n::Lang::Zh => format!($zh $(, $arg)*),
+        }
+    };
+}
+pub(crate) use tr;
@@ -0,0 +1,338 @@
+use serde::Serialize;
+
+use crate::sina::Candle;
+
+pub const ADX_PERIOD: usize = 14;
+
+/// True range of each candle against the previous close; one shorter than
+/// the input since the first candle has no previous close.
+fn true_ranges(candles: &[Candle]) -> Vec<f64> {
+    candles
+        .windows(2)
+        .map(|w| {
+            let (prev, cur) = (&w[0], &w[1]);
+            (cur.high - cur.low)
+                .max((cur.high - prev.close).abs())
+                .max((cur.low - prev.close).abs())
+        })
+        .collect()
+}
+
+/// Average Directional Index using Wilder's smoothing.
+///
+/// Returns `None` when there are fewer than `2 * period` candles, which is the
+/// minimum needed to seed both the DI smoothing and the ADX average.
+pub fn adx(candles: &[Candle], period: usize) -> Option<f64> {
+    if period == 0 || candles.len() < 2 * period {
+        return None;
+    }
+
+    let tr = true_ranges(candles);
+    let mut plus_dm = Vec::with_capacity(candles.len() - 1);
+    let mut minus_dm = Vec::with_capacity(candles.len() - 1);
+    for w in candles.windows(2) {
+        let (prev, cur) = (&w[0], &w[1]);
+        let up = cur.high - prev.high;
+        let down = prev.low - cur.low;
+        plus_dm.push(if up > down && up > 0.0 { up } else { 0.0 });
+        minus_dm.push(if down > up && down > 0.0 { down } else { 0.0 });
+    }
+
+    let p = period as f64;
+    let mut tr_s: f64 = tr[..period].iter().sum();
+    let mut plus_s: f64 = plus_dm[..period].iter().sum();
+    let mut minus_s: f64 = minus_dm[..period].iter().sum();
+
+    let dx = |tr_s: f64, plus_s: f64, minus_s: f64| {
+        if tr_s <= 0.0 {
+            return 0.0;
+        }
+        let plus_di = 100.0 * plus_s / tr_s;
+        let minus_di = 100.0 * minus_s / tr_s;
+        let sum = plus_di + minus_di;
+        if sum > 0.0 {
+            100.0 * (plus_di - minus_di).abs() / sum
+        } else {
+            0.0
+        }
+    };
+
+    let mut dxs = vec![dx(tr_s, plus_s, minus_s)];
+    for i in period..tr.len() {
+        tr_s = tr_s - tr_s / p + tr[i];
+        plus_s = plus_s - plus_s / p + plus_dm[i];
+        minus_s = minus_s - minus_s / p + minus_dm[i];
+        dxs.push(dx(tr_s, plus_s, minus_s));
+    }
+
+    let mut adx: f64 = dxs[..period].iter().sum::<f64>() / p;
+    for &v in &dxs[period..] {
+        adx = (adx * (p - 1.0) + v) / p;
+    }
+    Some(adx)
+}
+
+pub const KELTNER_EMA_PERIOD: usize = 20;
+pub const KELTNER_ATR_PERIOD: usize = 10;
+pub const KELTNER_MULTIPLIER: f64 = 2.0;
+
+/// Exponential moving average of `values`, seeded with the simple average of
+/// the first `period` values. Returns the value at the last element.
+pub fn ema(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() < period {
+        return None;
+    }
+    let k = 2.0 / (period as f64 + 1.0);
+    let mut ema = values[..period].iter().sum::<f64>() / period as f64;
+    for &v in &values[period..] {
+        ema = v * k + ema * (1.0 - k);
+    }
+    Some(ema)
+}
+
+/// Average True Range using Wilder's smoothing, as of the last candle.
+pub fn atr(candles: &[Candle], period: usize) -> Option<f64> {
+    if period == 0 || candles.len() < period + 1 {
+        return None;
+    }
+    let tr = true_ranges(candles);
+    let p = period as f64;
+    let mut atr = tr[..period].iter().sum::<f64>() / p;
+    for &v in &tr[period..] {
+        atr = (atr * (p - 1.0) + v) / p;
+    }
+    Some(atr)
+}
+
+/// Donchian channel `(low, high)` over the `period` bars *before* the last
+/// one, so the last bar can be compared against it for a breakout.
+pub fn donchian(candles: &[Candle], period: usize) -> Option<(f64, f64)> {
+    if period == 0 || candles.len() < period + 1 {
+        return None;
+    }
+    let window = &candles[candles.len() - 1 - period..candles.len() - 1];
+    let low = window.iter().map(|c| c.low).fold(f64::INFINITY, f64::min);
+    let high = window.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max);
+    Some((low, high))
+}
+
+/// Keltner channel `(lower, middle, upper)` as of the last candle: an EMA of
+/// closes with bands `multiplier` ATRs away.
+pub fn keltner(candles: &[Candle], ema_period: usize, atr_period: usize, multiplier: f64) -> Option<(f64, f64, f64)> {
+    let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+    let mid = ema(&closes, ema_period)?;
+    let atr = atr(candles, atr_period)?;
+    Some((mid - multiplier * atr, mid, mid + multiplier * atr))
+}
+
+pub const SAR_STEP: f64 = 0.02;
+pub const SAR_MAX_STEP: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct SarPoint {
+    pub sar: f64,
+    /// True while SAR sits below price (uptrend).
+    pub long: bool,
+}
+
+/// Wilder's Parabolic SAR, one point per candle starting from the second.
+pub fn parabolic_sar(candles: &[Candle], step: f64, max_step: f64) -> Vec<SarPoint> {
+    let mut points = Vec::new();
+    if candles.len() < 2 {
+        return points;
+    }
+
+    let (c0, c1) = (&candles[0], &candles[1]);
+    let mut long = c1.close >= c0.close;
+    let mut af = step;
+    let mut ep = if long { c0.high.max(c1.high) } else { c0.low.min(c1.low) };
+    let mut sar = if long { c0.low.min(c1.low) } else { c0.high.max(c1.high) };
+    points.push(SarPoint { sar, long });
+
+    for i in 2..candles.len() {
+        let cur = &candles[i];
+        let mut next = sar + af * (ep - sar);
+        if long {
+            next = next.min(candles[i - 1].low).min(candles[i - 2].low);
+            if cur.low < next {
+                long = false;
+                next = ep;
+                ep = cur.low;
+                af = step;
+            } else if cur.high > ep {
+                ep = cur.high;
+                af = (af + step).min(max_step);
+            }
+        } else {
+            next = next.max(candles[i - 1].high).max(candles[i - 2].high);
+            if cur.high > next {
+                long = true;
+                next = ep;
+                ep = cur.high;
+                af = step;
+            } else if cur.low < ep {
+                ep = cur.low;
+                af = (af + step).min(max_step);
+            }
+        }
+        sar = next;
+        points.push(SarPoint { sar, long });
+    }
+    points
+}
+
+/// Volume-weighted average of the typical price `(high + low + close) / 3`.
+/// Returns `None` if the bars carry no volume.
+pub fn vwap(candles: &[Candle]) -> Option<f64> {
+    let (pv, volume) = candles.iter().fold((0.0, 0.0), |(pv, vol), c| {
+        let typical = (c.high + c.low + c.close) / 3.0;
+        (pv + typical * c.volume, vol + c.volume)
+    });
+    if volume > 0.0 { Some(pv / volume) } else { None }
+}
+
+pub const RSI_PERIOD: usize = 14;
+pub const ATR_PERIOD: usize = 14;
+pub const ZSCORE_PERIOD: usize = 20;
+
+/// Wilder's RSI of `closes` as of the last value, in `[0, 100]`.
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() < period + 1 {
+        return None;
+    }
+    let changes: Vec<f64> = closes.windows(2).map(|w| w[1] - w[0]).collect();
+    let p = period as f64;
+    let mut gain = changes[..period].iter().filter(|&&c| c > 0.0).sum::<f64>() / p;
+    let mut loss = -changes[..period].iter().filter(|&&c| c < 0.0).sum::<f64>() / p;
+    for &c in &changes[period..] {
+        gain = (gain * (p - 1.0) + c.max(0.0)) / p;
+        loss = (loss * (p - 1.0) + (-c).max(0.0)) / p;
+    }
+    if loss == 0.0 {
+        return Some(if gain == 0.0 { 50.0 } else { 100.0 });
+    }
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
+
+/// How many standard deviations the last close sits from the mean of the
+/// last `period` closes.
+pub fn zscore(closes: &[f64], period: usize) -> Option<f64> {
+    if period < 2 || closes.len() < period {
+        return None;
+    }
+    let window = &closes[closes.len() - period..];
+    let mean = window.iter().sum::<f64>() / period as f64;
+    let var = window.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (period - 1) as f64;
+    let sd = var.sqrt();
+    // A flat window leaves only rounding in `sd`.
+    if sd > mean.abs() * 1e-12 { Some((closes[closes.len() - 1] - mean) / sd) } else { Some(0.0) }
+}
+
+/// Percentage distance of the last close below the highest close in
+/// `closes`; always `<= 0`.
+pub fn drawdown(closes: &[f64]) -> Option<f64> {
+    let last = *closes.last()?;
+    let peak = closes.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if peak > 0.0 { Some((last - peak) / peak * 100.0) } else { None }
+}
+
+/// Where the last close sits within the high-low range of the last `period`
+/// bars, from 0 (at the low) to 100 (at the high).
+pub fn range_percentile(candles: &[Candle], period: usize) -> Option<f64> {
+    if period == 0 || candles.len() < period {
+        return None;
+    }
+    let window = &candles[candles.len() - period..];
+    let low = window.iter().map(|c| c.low).fold(f64::INFINITY, f64::min);
+    let high = window.iter().map(|c| c.high).fold(f64::NEG_INFINITY, f64::max);
+    let close = window[period - 1].close;
+    if high > low { Some(((close - low) / (high - low) * 100.0).clamp(0.0, 100.0)) } else { Some(50.0) }
+}
+
+/// Percentage returns of every overlapping window of `span` closes, measured
+/// from the first close of the window to the last.
+pub fn rolling_returns(closes: &[f64], span: usize) -> Vec<f64> {
+    if span < 2 {
+        return Vec::new();
+    }
+    closes
+        .windows(span)
+        .filter(|w| w[0] > 0.0)
+        .map(|w| (w[span - 1] - w[0]) / w[0] * 100.0)
+        .collect()
+}
+
+/// Share of `samples` strictly below `value`, in `[0, 100]`.
+pub fn percentile_of(value: f64, samples: &[f64]) -> Option<f64> {
+    if samples.is_empty() {
+        return None;
+    }
+    let below = samples.iter().filter(|&&s| s < value).count();
+    Some(below as f64 / samples.len() as f64 * 100.0)
+}
+
+/// Percentage change of the `period`-bar simple moving average over the
+/// last `lag` bars.
+pub fn ma_slope(closes: &[f64], period: usize, lag: usize) -> Option<f64> {
+    if period == 0 || closes.len() < period + lag {
+        return None;
+    }
+    let ma = |end: usize| closes[end - period..end].iter().sum::<f64>() / period as f64;
+    let (before, now) = (ma(closes.len() - lag), ma(closes.len()));
+    if before > 0.0 { Some((now - before) / before * 100.0) } else { None }
+}
+
+/// Pearson correlation of two equally long series; `None` with fewer than
+/// two points or if either is flat.
+pub fn correlation(a: &[f64], b: &[f64]) -> Option<f64> {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return None;
+    }
+    let (a, b) = (&a[..n], &b[..n]);
+    let mean = |v: &[f64]| v.iter().sum::<f64>() / n as f64;
+    let (ma, mb) = (mean(a), mean(b));
+    let cov: f64 = a.iter().zip(b).map(|(x, y)| (x - ma) * (y - mb)).sum();
+    let va: f64 = a.iter().map(|x| (x - ma).powi(2)).sum();
+    let vb: f64 = b.iter().map(|y| (y - mb).powi(2)).sum();
+    if va > 0.0 && vb > 0.0 { Some(cov / (va * vb).sqrt()) } else { None }
+}
+
+/// Trading days per year, for annualizing the regression slope.
+const TRADING_DAYS_PER_YEAR: f64 = 250.0;
+
+/// A least-squares line through log closes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Regression {
+    /// The line's slope as a yearly return, percent.
+    pub annual_slope: f64,
+    pub r_squared: f64,
+    /// Where the last close sits in the channel two standard errors either
+    /// side of the line, from 0 (lower edge) to 100 (upper).
+    pub position: f64,
+}
+
+/// Regression of the last `period` log closes on time.
+pub fn regression(closes: &[f64], period: usize) -> Option<Regression> {
+    if period < 3 || closes.len() < period || closes.iter().rev().take(period).any(|&c| c <= 0.0) {
+        return None;
+    }
+    let y: Vec<f64> = closes[closes.len() - period..].iter().map(|c| c.ln()).collect();
+    let n = period as f64;
+    let mx = (n - 1.0) / 2.0;
+    let my = y.iter().sum::<f64>() / n;
+    let sxx: f64 = (0..period).map(|i| (i as f64 - mx).powi(2)).sum();
+    let sxy: f64 = y.iter().enumerate().map(|(i, v)| (i as f64 - mx) * (v - my)).sum();
+    let syy: f64 = y.iter().map(|v| (v - my).powi(2)).sum();
+    let slope = sxy / sxx;
+    let fitted = |i: usize| my + slope * (i as f64 - mx);
+    let sse: f64 = y.iter().enumerate().map(|(i, v)| (v - fitted(i)).powi(2)).sum();
+    let se = (sse / (n - 2.0)).sqrt();
+    let residual = y[period - 1] - fitted(period - 1);
+    let position = if se > 0.0 { ((residual / se + 2.0) / 4.0 * 100.0).clamp(0.0, 100.0) } else { 50.0 };
+    Some(Regression {
+        annual_slope: ((slope * TRADING_DAYS_PER_YEAR).exp() - 1.0) * 100.0,
+        r_squared: if syy > 0.0 { 1.0 - sse / syy } else { 1.0 },
+        position,
+    })
+}
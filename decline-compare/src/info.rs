@@ -0,0 +1,86 @@
+use clap::Args;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::fundinfo::{self, FundInfo};
+use crate::http;
+use crate::i18n::tr;
+use crate::instruments::{self, FundKind, Settlement};
+use crate::symbols;
+
+#[derive(Args, Debug)]
+pub struct InfoArgs {
+    /// Codes to show; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Refetch metadata even if the stored copy is less than a month old
+    #[arg(long)]
+    refresh: bool,
+}
+
+#[derive(Serialize)]
+struct InfoRow {
+    code: String,
+    kind: FundKind,
+    settlement: Settlement,
+    #[serde(flatten)]
+    info: Option<FundInfo>,
+    expense_ratio: Option<f64>,
+}
+
+/// Fund details: issuer, tracking index, fees, inception, kind and settlement.
+pub async fn run(args: &InfoArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let mut funds = fundinfo::lookup(&codes, args.refresh, cfg, http::deadline(&cfg.http)).await;
+    let rows: Vec<InfoRow> = codes
+        .iter()
+        .map(|code| {
+            let info = funds.remove(code);
+            InfoRow {
+                code: code.clone(),
+                kind: instruments::kind(code, cfg),
+                settlement: instruments::settlement(code, cfg),
+                expense_ratio: info.as_ref().and_then(FundInfo::expense_ratio),
+                info,
+            }
+        })
+        .collect();
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    println!("\n {}", tr!("ETF details:", "ETF 基本资料:"));
+    println!("-----------------------------------------");
+    let na = || "n/a".to_string();
+    for row in &rows {
+        let Some(info) = &row.info else {
+            println!(
+                "{}",
+                tr!("Code: {} ({}, {}) | no fund info", "代码: {} ({}, {}) | 无基金资料", row.code, row.kind, row.settlement)
+            );
+            continue;
+        };
+        let pct = |v: Option<f64>| v.map_or_else(na, |v| format!("{:.2}%", v));
+        println!(
+            "{}",
+            tr!(
+                "Code: {}{} ({}, {}) | Issuer: {} | Tracks: {} | Mgmt fee: {} | Custody fee: {} | Expense: {} | Since: {}",
+                "代码: {}{} ({}, {}) | 管理人: {} | 跟踪标的: {} | 管理费: {} | 托管费: {} | 总费率: {} | 成立: {}",
+                row.code,
+                info.name.as_ref().map_or_else(String::new, |n| format!(" {}", n)),
+                row.kind,
+                row.settlement,
+                info.issuer.clone().unwrap_or_else(na),
+                info.tracking_index.clone().unwrap_or_else(na),
+                pct(info.management_fee),
+                pct(info.custody_fee),
+                pct(row.expense_ratio),
+                info.listed.map_or_else(na, |d| d.to_string())
+            )
+        );
+    }
+    Ok(())
+}
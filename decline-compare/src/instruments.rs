@@ -0,0 +1,193 @@
+//! Per-instrument metadata the exchange doesn't put in quotes.
+//!
+//! Settlement: cross-border, gold, bond and money-market ETFs can be sold
+//! the day they are bought (T+0); domestic equity ETFs and LOFs only from
+//! the next trading day (T+1). Shanghai funds are classified by code range;
+//! Shenzhen ranges mix both, so only the common T+0 funds are listed.
+//! `[instruments.settlement]` in the config overrides either.
+//!
+//! Kind: LOFs and closed-end funds list alongside ETFs but can't be created
+//! or redeemed in kind on the exchange, so their price drifts from NAV and
+//! the discount matters as much as the price. They are also classified by
+//! code range, with `[instruments.kind]` as the override.
+//!
+//! Bond funds: the Shanghai 511 range, and any fund whose stored or
+//! configured fund type is fixed income. Their duration bucket comes from
+//! the name (1-3年, 10年 and the like) unless the config sets it.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::fundinfo::{self, FundStore};
+use crate::i18n::tr;
+use crate::spot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Settlement {
+    T0,
+    T1,
+}
+
+impl fmt::Display for Settlement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Settlement::T0 => "T+0",
+            Settlement::T1 => "T+1",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FundKind {
+    Etf,
+    Lof,
+    ClosedEnd,
+    /// A spot price with no listing, see `spot`.
+    Spot,
+}
+
+impl FundKind {
+    /// LOFs and closed-end funds, whose price isn't held to NAV by creation
+    /// and redemption.
+    pub fn trades_off_nav(self) -> bool {
+        matches!(self, FundKind::Lof | FundKind::ClosedEnd)
+    }
+}
+
+impl fmt::Display for FundKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            FundKind::Etf => "ETF".to_string(),
+            FundKind::Lof => "LOF".to_string(),
+            FundKind::ClosedEnd => tr!("closed-end", "封闭式"),
+            FundKind::Spot => tr!("spot", "现货"),
+        };
+        f.write_str(&text)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DurationBucket {
+    /// Money market, short-term notes and bonds up to about 3 years.
+    Short,
+    /// 3 to 7 years.
+    Medium,
+    /// 7 years and longer.
+    Long,
+}
+
+impl fmt::Display for DurationBucket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            DurationBucket::Short => tr!("short duration", "短久期"),
+            DurationBucket::Medium => tr!("medium duration", "中久期"),
+            DurationBucket::Long => tr!("long duration", "长久期"),
+        };
+        f.write_str(&text)
+    }
+}
+
+/// Name fragments that place a bond fund in a duration bucket; the first
+/// one found wins.
+const DURATION_HINTS: &[(&str, DurationBucket)] = &[
+    ("货币", DurationBucket::Short),
+    ("日利", DurationBucket::Short),
+    ("短融", DurationBucket::Short),
+    ("短债", DurationBucket::Short),
+    ("同业存单", DurationBucket::Short),
+    ("0-3年", DurationBucket::Short),
+    ("1-3年", DurationBucket::Short),
+    ("3-5年", DurationBucket::Medium),
+    ("5年", DurationBucket::Medium),
+    ("7-10年", DurationBucket::Long),
+    ("10年", DurationBucket::Long),
+    ("十年", DurationBucket::Long),
+    ("30年", DurationBucket::Long),
+    ("长债", DurationBucket::Long),
+];
+
+/// Shanghai bond and money-market range.
+const BOND_PREFIXES: &[&str] = &["511"];
+
+/// Bond funds among `codes`, each with its duration bucket if known. Uses
+/// the fund metadata already stored and the config; nothing is fetched.
+pub fn bonds(codes: &[String], cfg: &Config) -> HashMap<String, Option<DurationBucket>> {
+    let store = FundStore::load(&fundinfo::default_path()).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        FundStore::default()
+    });
+    codes
+        .iter()
+        .filter_map(|code| {
+            let info = store.get(code, cfg).unwrap_or_default();
+            let fixed_income =
+                info.fund_type.as_deref().is_some_and(|t| ["债", "固收", "货币"].iter().any(|k| t.contains(k)));
+            if !fixed_income && !BOND_PREFIXES.iter().any(|p| code.starts_with(p)) {
+                return None;
+            }
+            let names = [info.name.as_deref(), info.tracking_index.as_deref(), info.fund_type.as_deref()];
+            let hinted = DURATION_HINTS
+                .iter()
+                .find(|(hint, _)| names.iter().flatten().any(|n| n.contains(hint)))
+                .map(|&(_, bucket)| bucket);
+            Some((code.clone(), info.duration.or(hinted)))
+        })
+        .collect()
+}
+
+/// Shanghai (501, 502) and Shenzhen (16) LOF code ranges.
+const LOF_PREFIXES: &[&str] = &["501", "502", "16"];
+
+/// Shanghai (505, 506) and Shenzhen (184) closed-end fund code ranges.
+const CLOSED_END_PREFIXES: &[&str] = &["505", "506", "184"];
+
+/// Shanghai cross-border (QDII) code ranges, and the Shenzhen Hang Seng,
+/// Nasdaq 100 and Nikkei 225 trackers.
+const CROSS_BORDER_PREFIXES: &[&str] = &["513", "520"];
+const CROSS_BORDER_CODES: &[&str] = &["159920", "159941", "159866"];
+
+/// Other funds that settle T+0: Shanghai gold (518) and bond or
+/// money-market (511) ranges, and the Shenzhen gold ETFs.
+const T0_PREFIXES: &[&str] = &["518", "511"];
+const T0_CODES: &[&str] = &["159934", "159937"];
+
+/// Whether `code` is a cross-border (QDII) fund, whose NAV is published a
+/// day late and whose price can drift well away from it.
+pub fn is_cross_border(code: &str) -> bool {
+    CROSS_BORDER_PREFIXES.iter().any(|p| code.starts_with(p)) || CROSS_BORDER_CODES.contains(&code)
+}
+
+/// What kind of listed fund `code` is, from the config override if there
+/// is one.
+pub fn kind(code: &str, cfg: &Config) -> FundKind {
+    if let Some(&kind) = cfg.instruments.kind.get(code) {
+        return kind;
+    }
+    if spot::is_spot(code) {
+        FundKind::Spot
+    } else if LOF_PREFIXES.iter().any(|p| code.starts_with(p)) {
+        FundKind::Lof
+    } else if CLOSED_END_PREFIXES.iter().any(|p| code.starts_with(p)) {
+        FundKind::ClosedEnd
+    } else {
+        FundKind::Etf
+    }
+}
+
+/// Settlement rule for `code`, from the config override if there is one.
+pub fn settlement(code: &str, cfg: &Config) -> Settlement {
+    if let Some(&rule) = cfg.instruments.settlement.get(code) {
+        return rule;
+    }
+    if is_cross_border(code) || T0_PREFIXES.iter().any(|p| code.starts_with(p)) || T0_CODES.contains(&code) {
+        Settlement::T0
+    } else {
+        Settlement::T1
+    }
+}
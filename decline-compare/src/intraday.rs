@@ -0,0 +1,135 @@
+use chrono::NaiveDate;
+use clap::Args;
+
+use crate::config::Config;
+use crate::http;
+use crate::i18n::tr;
+use crate::indicators;
+use crate::schedule;
+use crate::sina;
+use crate::smooth::{self, Smoothing};
+use crate::symbols;
+
+// One A-share session is 240 minutes, so this covers a full day of bars at
+// any supported scale.
+const SESSION_MINUTES: usize = 240;
+
+#[derive(Args, Debug)]
+pub struct IntradayArgs {
+    /// Codes to show; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Minute bar size (5, 15, 30 or 60)
+    #[arg(long, default_value_t = 5)]
+    scale: u32,
+
+    /// Smooth the minute bars first: off, kalman or hull [default: the
+    /// [smoothing] method]
+    #[arg(long, value_enum)]
+    smooth: Option<Smoothing>,
+}
+
+struct IntradayRow {
+    code: String,
+    session: NaiveDate,
+    last: f64,
+    vwap: f64,
+}
+
+pub async fn run(args: &IntradayArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if args.scale == 0 {
+        return Err("--scale must be positive".into());
+    }
+    let codes: Vec<String> = if args.codes.is_empty() {
+        cfg.watchlist.clone()
+    } else {
+        args.codes.clone()
+    };
+    let datalen = SESSION_MINUTES.div_ceil(args.scale as usize);
+    let method = args.smooth.unwrap_or(cfg.smoothing.method);
+
+    let deadline = http::deadline(&cfg.http);
+    let mut results = Vec::new();
+    let mut timed_out = Vec::new();
+    for code in &codes {
+        let Some(fetch) = http::until(deadline, sina::fetch_kline(code, args.scale, datalen)).await else {
+            timed_out.push(code.as_str());
+            continue;
+        };
+        match fetch {
+            Ok((candles, status_option)) => {
+                if let Some(status) = status_option
+                    && status != 200
+                {
+                    eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, code));
+                    continue;
+                }
+                let candles = smooth::candles(&candles, method, &cfg.smoothing);
+                let Some(last) = candles.last() else {
+                    eprintln!("{}", tr!("No minute bars for {}", "{} 无分钟线数据", code));
+                    continue;
+                };
+
+                let session = last.date();
+                let start = candles.iter().position(|c| c.date() == session).unwrap_or(0);
+                match indicators::vwap(&candles[start..]) {
+                    Some(vwap) => results.push(IntradayRow {
+                        code: code.clone(),
+                        session,
+                        last: last.close,
+                        vwap,
+                    }),
+                    None => eprintln!("{}", tr!("No volume in today's session for {}", "{} 今日无成交量", code)),
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "Failed to fetch data for {}: {} (No HTTP status available)",
+                        "获取 {} 数据失败: {} (无 HTTP 状态)",
+                        code,
+                        e
+                    )
+                );
+            }
+        }
+    }
+
+    println!(
+        "\n {}",
+        tr!("ETF price vs session VWAP ({}-minute bars):", "ETF 价格与当日 VWAP ({} 分钟线):", args.scale)
+    );
+    if method != Smoothing::Off {
+        println!("{}", tr!("Bars smoothed: {}", "已平滑K线: {}", method));
+    }
+    println!("-----------------------------------------");
+    if results.is_empty() {
+        println!("{}", tr!("No ETF data", "无 ETF 数据"));
+    } else {
+        let deviation = |r: &IntradayRow| (r.last - r.vwap) / r.vwap * 100.0;
+        results.sort_by(|a, b| deviation(a).partial_cmp(&deviation(b)).unwrap());
+        let now = schedule::now();
+        for row in &results {
+            println!(
+                "{}",
+                tr!(
+                    "Code: {} | Session: {} ({}) | Last: {:.3} | VWAP: {:.3} | vs VWAP: {:+.2}%",
+                    "代码: {} | 交易日: {} ({}) | 最新: {:.3} | VWAP: {:.3} | 偏离 VWAP: {:+.2}%",
+                    row.code,
+                    row.session,
+                    schedule::bar_session(row.session, now),
+                    row.last,
+                    row.vwap,
+                    deviation(row)
+                )
+            );
+        }
+    }
+    if !timed_out.is_empty() {
+        println!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", timed_out.join(", ")));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Suggested limit-buy ladders for ETFs the screen flags as dips.
+//!
+//! Each tranche sits a multiple of ATR below the latest close. Deeper
+//! tranches get proportionally more of the cash, so a ladder at 1/2/3 ATR
+//! splits it 1:2:3. Sizes are rounded down to whole lots.
+
+use serde::Serialize;
+
+use crate::alerts::{Alert, AlertKind};
+use crate::lots;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Tranche {
+    pub atr_multiple: f64,
+    pub price: f64,
+    pub shares: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Ladder {
+    pub tranches: Vec<Tranche>,
+    /// Cash the ladder was sized for.
+    pub cash: f64,
+    /// What the tranches cost if they all fill.
+    pub spent: f64,
+    /// Cash left over after rounding to whole lots.
+    pub leftover: f64,
+}
+
+/// Whether any of `alerts` marks `code` as trading at the bottom of its range.
+pub fn is_dip(code: &str, alerts: &[Alert]) -> bool {
+    alerts
+        .iter()
+        .any(|a| a.code == code && matches!(a.kind, AlertKind::DonchianLow { .. } | AlertKind::KeltnerLower { .. }))
+}
+
+/// Tranches `multiples` ATRs below `close`, sharing `cash` in proportion to
+/// their depth. Tranches that would be priced at or below zero, or that
+/// can't afford a single lot, are dropped.
+pub fn ladder(close: f64, atr: f64, multiples: &[f64], cash: f64) -> Ladder {
+    let total: f64 = multiples.iter().filter(|&&m| m > 0.0).sum();
+    let mut tranches = Vec::new();
+    if total > 0.0 && atr > 0.0 {
+        for &m in multiples.iter().filter(|&&m| m > 0.0) {
+            let price = close - m * atr;
+            if price <= 0.0 {
+                continue;
+            }
+            let (shares, _) = lots::round_down(cash * m / total, price);
+            if shares > 0 {
+                tranches.push(Tranche { atr_multiple: m, price, shares });
+            }
+        }
+    }
+    let spent: f64 = tranches.iter().map(|t| t.shares as f64 * t.price).sum();
+    Ladder { tranches, cash, spent, leftover: cash - spent }
+}
@@ -0,0 +1,196 @@
+//! Alerts on absolute price levels, e.g. `513100 <= 1.25`, checked in watch
+//! mode against realtime quotes every minute, apart from the screen's
+//! indicator rules. A level notifies when the last trade first reaches it;
+//! after that it stays quiet for good, unless it ends in `repeat`, in which
+//! case it notifies again each time price leaves the level and comes back.
+//! What has fired is kept in the alert state file, so a restart doesn't
+//! replay it. Snoozed codes aren't checked until the snooze runs out.
+//! Levels need an exchange-listed code: spot entries have no realtime
+//! quote to check them against.
+//!
+//! ```toml
+//! [watch]
+//! price_alerts = ["513100 <= 1.25", "518880 >= 6.50 repeat"]
+//! ```
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::alerts::{Alert, AlertKind};
+use crate::cooldown::AlertState;
+use crate::http;
+use crate::i18n::tr;
+use crate::sina;
+use crate::spot;
+use crate::symbols;
+
+/// Seconds between checks of the levels in watch mode.
+pub const POLL_SECS: u64 = 60;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PriceAlert {
+    pub code: String,
+    /// Reached at or below `level`, rather than at or above.
+    pub below: bool,
+    pub level: f64,
+    /// Notify on every crossing instead of only the first.
+    pub repeat: bool,
+}
+
+impl TryFrom<String> for PriceAlert {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<PriceAlert> for String {
+    fn from(a: PriceAlert) -> String {
+        a.to_string()
+    }
+}
+
+impl FromStr for PriceAlert {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let usage = || format!("expected CODE <= PRICE or CODE >= PRICE, optionally followed by repeat, got '{}'", s);
+        let (code, rest, below) = match (s.split_once("<="), s.split_once(">=")) {
+            (Some((code, rest)), None) => (code, rest, true),
+            (None, Some((code, rest))) => (code, rest, false),
+            _ => return Err(usage()),
+        };
+        let rest = rest.trim();
+        let (level, repeat) = match rest.strip_suffix("repeat") {
+            Some(level) => (level.trim(), true),
+            None => (rest, false),
+        };
+        let level: f64 = level.parse().map_err(|_| usage())?;
+        if !(level.is_finite() && level > 0.0) {
+            return Err(format!("price level must be positive, got '{}'", s));
+        }
+        let code = symbols::parse_code(code.trim())?;
+        if spot::is_spot(&code) {
+            return Err(format!("price levels need an exchange-listed code, {} has no realtime quote", code));
+        }
+        Ok(PriceAlert { code, below, level, repeat })
+    }
+}
+
+impl fmt::Display for PriceAlert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.code, if self.below { "<=" } else { ">=" }, self.level)?;
+        if self.repeat {
+            f.write_str(" repeat")?;
+        }
+        Ok(())
+    }
+}
+
+impl PriceAlert {
+    fn holds(&self, price: f64) -> bool {
+        if self.below { price <= self.level } else { price >= self.level }
+    }
+}
+
+/// Check `levels` against one batched quote request and return the alerts
+/// they raise, recording in `state` which hold. Levels without a trade to
+/// go on keep their state.
+pub async fn check(levels: &[PriceAlert], state: &mut AlertState, deadline: Option<Instant>) -> Vec<Alert> {
+    if levels.is_empty() {
+        return Vec::new();
+    }
+    let mut codes: Vec<String> = levels.iter().map(|l| l.code.clone()).collect();
+    codes.sort_unstable();
+    codes.dedup();
+    let quotes = match http::until(deadline, sina::fetch_quotes(&codes)).await {
+        Some(Ok(quotes)) => quotes,
+        Some(Err(e)) => {
+            eprintln!("{}", tr!("Failed to fetch quotes: {}", "获取实时行情失败: {}", e));
+            return Vec::new();
+        }
+        None => {
+            eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn"));
+            return Vec::new();
+        }
+    };
+
+    let mut raised = Vec::new();
+    for level in levels {
+        // No trade yet this session.
+        let Some(price) = quotes.get(&level.code).map(|q| q.last).filter(|&p| p > 0.0) else {
+            continue;
+        };
+        if state.level_reached(&level.to_string(), level.holds(price), level.repeat) {
+            raised.push(Alert {
+                code: level.code.clone(),
+                kind: AlertKind::PriceLevel { below: level.below, level: level.level, price },
+            });
+        }
+    }
+    raised
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level(s: &str) -> PriceAlert {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_and_displays_the_config_form() {
+        for (input, shown) in [
+            ("513100 <= 1.25", "513100 <= 1.25"),
+            ("518880>=6.5 repeat", "518880 >= 6.5 repeat"),
+            ("  513100   <=   1.250  ", "513100 <= 1.25"),
+        ] {
+            let alert = level(input);
+            assert_eq!(alert.to_string(), shown);
+            assert_eq!(level(shown), alert);
+        }
+        let alert = level("518880 >= 6.50 repeat");
+        assert_eq!((alert.below, alert.level, alert.repeat), (false, 6.5, true));
+    }
+
+    #[test]
+    fn rejects_malformed_levels() {
+        let malformed = ["513100 < 1.25", "513100 <= ", "513100 <= abc", "513100 <= -1", "513100 <= 1 >= 2"];
+        // Spot entries have no realtime quote to check against.
+        for input in malformed.into_iter().chain(["XAUUSD >= 2000", "BTC-USD >= 60000"]) {
+            assert!(input.parse::<PriceAlert>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn holds_at_and_beyond_the_level() {
+        let below = level("513100 <= 1.25");
+        assert!(below.holds(1.25) && below.holds(1.2) && !below.holds(1.26));
+        let above = level("518880 >= 6.5");
+        assert!(above.holds(6.5) && above.holds(7.0) && !above.holds(6.49));
+    }
+
+    /// Whether each price in turn notifies for `alert`.
+    fn crossings(alert: &PriceAlert, prices: &[f64]) -> Vec<bool> {
+        let mut state = AlertState::default();
+        prices.iter().map(|&p| state.level_reached(&alert.to_string(), alert.holds(p), alert.repeat)).collect()
+    }
+
+    #[test]
+    fn a_one_shot_level_notifies_once() {
+        let prices = [1.30, 1.25, 1.24, 1.30, 1.20];
+        assert_eq!(crossings(&level("513100 <= 1.25"), &prices), [false, true, false, false, false]);
+    }
+
+    #[test]
+    fn a_repeating_level_notifies_on_each_crossing() {
+        let prices = [1.30, 1.25, 1.24, 1.30, 1.20];
+        assert_eq!(crossings(&level("513100 <= 1.25 repeat"), &prices), [false, true, false, false, true]);
+    }
+}
@@ -0,0 +1,70 @@
+//! The screen and everything it draws on; `main.rs` is the command line
+//! over it.
+
+pub mod alerts;
+pub mod anomaly;
+pub mod archive;
+pub mod backtest;
+pub mod basis;
+pub mod breaker;
+pub mod cache;
+pub mod config;
+pub mod context;
+pub mod cooldown;
+pub mod correlation;
+pub mod decline;
+pub mod digest;
+pub mod dividends;
+pub mod doctor;
+pub mod eastmoney;
+pub mod events;
+pub mod export;
+pub mod fixture;
+pub mod fundinfo;
+pub mod grpc;
+pub mod hedge;
+pub mod history;
+pub mod http;
+pub mod i18n;
+pub mod indicators;
+pub mod info;
+pub mod instruments;
+pub mod intraday;
+pub mod ladder;
+pub mod levels;
+pub mod lock;
+pub mod lots;
+pub mod montecarlo;
+pub mod mqtt;
+pub mod notify;
+pub mod paper;
+pub mod paths;
+pub mod plugins;
+pub mod portfolio;
+pub mod postgres;
+pub mod premium;
+pub mod pubsub;
+pub mod quote;
+pub mod recovery;
+pub mod replay;
+pub mod schedule;
+pub mod scripts;
+pub mod score;
+pub mod seasonality;
+pub mod service;
+pub mod sina;
+pub mod smooth;
+pub mod snooze;
+pub mod spot;
+pub mod spread;
+pub mod symbols;
+pub mod template;
+pub mod trend;
+pub mod valuation;
+pub mod watch;
+
+pub const ETF_CODES: &[&str] = &[
+    "513520", "513350", "513870", "512800", "515000", "513030", "516810", "518880", "513500",
+    "512660", "510050", "512000", "513730", "512670", "512400", "513080", "517090", "513800",
+    "515750", "520580", "501090", "515710", "516970", "520830", "515220", "513110", "561360",
+];
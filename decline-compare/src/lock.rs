@@ -0,0 +1,49 @@
+//! One run at a time per command, so a cron job that fires while the last
+//! one is still going skips instead of fetching and notifying twice.
+//!
+//! The lock is an OS file lock on `$STOCK_DATA_DIR/<command>.lock` (one per
+//! profile, so different profiles' runs don't block each other), released
+//! when the process exits however it ends, so a crash never leaves a stale
+//! lock behind. The file holds the owner's pid for the skip message.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, Write};
+
+use crate::config;
+use crate::i18n::tr;
+
+/// Held for as long as the run lasts.
+pub struct RunLock {
+    _file: File,
+}
+
+/// Take the lock for `command`, or say who has it and return `None`.
+pub fn acquire(command: &str, profile: Option<&str>) -> Result<Option<RunLock>, Box<dyn std::error::Error>> {
+    let dir = config::data_dir();
+    fs::create_dir_all(&dir)?;
+    let path = dir.join(config::profiled(command, "lock", profile));
+    let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+    match file.try_lock() {
+        Ok(()) => {
+            file.set_len(0)?;
+            file.rewind()?;
+            write!(file, "{}", std::process::id())?;
+            file.flush()?;
+            Ok(Some(RunLock { _file: file }))
+        }
+        Err(fs::TryLockError::WouldBlock) => {
+            let pid = fs::read_to_string(&path).unwrap_or_default();
+            eprintln!(
+                "{}",
+                tr!(
+                    "Another {} run is in progress (pid {}); skipping",
+                    "另一个 {} 进程正在运行 (pid {}), 跳过本次",
+                    command,
+                    pid.trim()
+                )
+            );
+            Ok(None)
+        }
+        Err(fs::TryLockError::Error(e)) => Err(e.into()),
+    }
+}
@@ -0,0 +1,15 @@
+//! A-share order sizes: exchange-traded buys go in whole board lots.
+
+/// Shares per board lot.
+pub const LOT_SIZE: u64 = 100;
+
+/// The most shares `cash` buys at `price` in whole lots, and the cash left
+/// over.
+pub fn round_down(cash: f64, price: f64) -> (u64, f64) {
+    if cash <= 0.0 || price <= 0.0 {
+        return (0, cash.max(0.0));
+    }
+    let lots = (cash / (price * LOT_SIZE as f64)).floor() as u64;
+    let shares = lots * LOT_SIZE;
+    (shares, cash - shares as f64 * price)
+}
@@ -1,135 +1,221 @@
-use reqwest;
-use serde::Deserialize;
-use tokio;
-
-const ETF_CODES: &[&str] = &[
-    "513520", "513350", "513870", "512800", "515000", "513030", "516810", "518880", "513500",
-    "512660", "510050", "512000", "513730", "512670", "512400", "513080", "517090", "513800",
-    "515750", "520580", "501090", "515710", "516970", "520830", "515220", "513110", "561360",
-];
-
-fn to_sina_code(code: &str) -> String {
-    let prefix = if code.starts_with('5') { "sh" } else { "sz" };
-    format!("{}{}", prefix, code)
-}
+mod completions;
 
-async fn fetch_etf_kline(code: &str, day: usize) -> Result<(Vec<f64>, Option<u16>), Box<dyn std::error::Error>> {
-    let sina_code = to_sina_code(code);
-    let url = format!(
-        "https://money.finance.sina.com.cn/quotes_service/api/json_v2.php/CN_MarketData.getKLineData?symbol={}&scale=240&ma=no&datalen={}",
-        sina_code, day
-    );
-
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await;
-    
-    match response {
-        Ok(resp) => {
-            let status = resp.status().as_u16();
-            let text = resp.text().await?;
-            
-            match serde_json::from_str::<Vec<SinaKLine>>(&text) {
-                Ok(data) => {
-                    let mut closes = Vec::new();
-                    for item in data {
-                        match item.close.parse::<f64>() {
-                            Ok(close) => closes.push(close),
-                            Err(_) => return Ok((Vec::new(), Some(status))),
-                        }
-                    }
-                    Ok((closes, Some(status)))
-                }
-                Err(_) => {
-                    Ok((Vec::new(), Some(status)))
-                }
-            }
-        }
-        Err(e) => {
-            Err(Box::new(e))
-        }
-    }
-}
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use decline_compare::config::{self, Config, Source};
+use decline_compare::{
+    archive, backtest, basis, breaker, cache, decline, digest, dividends, doctor, events, fixture, grpc, hedge, history,
+    http, i18n, info, intraday, lock, paper, paths, plugins, portfolio, premium, quote, replay, scripts, seasonality,
+    snooze, spread, watch,
+};
+
+#[derive(Parser, Debug)]
+#[command(about = "Rank ETFs by their decline over the last N trading days")]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    /// Config file (TOML) [default: ./biga.toml if present, else biga.toml
+    /// in the config directory; see `paths`]
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Directory to read the default biga.toml from, instead of the
+    /// platform's config directory
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "config")]
+    config_dir: Option<PathBuf>,
+
+    /// Data source for klines and quotes [default: sina]
+    #[arg(long, global = true, value_enum)]
+    source: Option<Source>,
+
+    /// Per-request timeout, e.g. 10s [default: 10s]
+    #[arg(long, global = true, value_name = "DURATION", value_parser = http::parse_secs)]
+    timeout: Option<u64>,
+
+    /// Connection timeout, e.g. 5s [default: 5s]
+    #[arg(long, global = true, value_name = "DURATION", value_parser = http::parse_secs)]
+    connect_timeout: Option<u64>,
+
+    /// Stop waiting for data this long after the run starts, e.g. 60s; codes
+    /// still outstanding are reported as timed out [default: none]
+    #[arg(long, global = true, value_name = "DURATION", value_parser = http::parse_secs)]
+    deadline: Option<u64>,
+
+    /// Config profile to layer over the file's settings, from its
+    /// [profiles.NAME] table
+    #[arg(long, global = true)]
+    profile: Option<String>,
+
+    /// Language for report headers, labels and messages [default: en]
+    #[arg(long, global = true, value_enum)]
+    lang: Option<i18n::Lang>,
+
+    /// Read klines from the Sina JSON files in this directory, CODE.json,
+    /// instead of the network, for reproducible output
+    #[arg(long, global = true, value_name = "DIR", conflicts_with_all = ["record", "replay"])]
+    fixture: Option<PathBuf>,
+
+    /// Save every data provider response to this directory
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "replay")]
+    record: Option<PathBuf>,
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-struct SinaKLine {
-    open: String,
-    high: String,
-    low: String,
-    close: String,
-    volume: String,
-    day: String,
+    /// Answer data provider requests from a directory --record wrote,
+    /// without the network
+    #[arg(long, global = true, value_name = "DIR")]
+    replay: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    decline: decline::DeclineArgs,
 }
 
-fn calculate(older: f64, newer: f64) -> f64 {
-    if older > 0.0 {
-        (newer - older) / older * 100.0
-    } else {
-        0.0
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Store years of daily history per code, for statistics beyond one request's reach
+    Backfill(history::BackfillArgs),
+    /// Replay the screen's dip rule over daily history, e.g. `backtest optimize`
+    Backtest(backtest::BacktestArgs),
+    /// Futures curve shape and roll timing behind commodity ETFs
+    Basis(basis::BasisArgs),
+    /// How much disk the kline cache, the history store and other state take
+    Cache(cache::CacheArgs),
+    /// Summarise the archived daily reports over a week or month
+    Digest(digest::DigestArgs),
+    /// Trailing dividend yield and ex-dividend dates
+    Dividends(dividends::DividendsArgs),
+    /// Check data sources, watchlist codes, stored state and notifiers
+    Doctor(doctor::DoctorArgs),
+    /// Review the alerts raised by past runs
+    Events(events::EventsArgs),
+    /// OLS hedge ratio and co-integration of two instruments, e.g. `hedge 510050/510300`
+    Hedge(hedge::HedgeArgs),
+    /// Show past daily reports, e.g. `history 2025-03-*`, or list the archived dates
+    History(archive::HistoryArgs),
+    /// Fund details: issuer, tracking index, fees, inception, kind and settlement
+    Info(info::InfoArgs),
+    /// Show today's session VWAP from minute bars and where price sits against it
+    Intraday(intraday::IntradayArgs),
+    /// Trade the screen's signals on a virtual account against live quotes
+    Paper(paper::PaperArgs),
+    /// Print where the config file, state and caches are looked for
+    Paths(paths::PathsArgs),
+    /// Value the configured holdings and show their recorded history
+    Portfolio(portfolio::PortfolioArgs),
+    /// Record QDII and listed funds' premium to NAV and put today's against its history
+    Premium(premium::PremiumArgs),
+    /// Realtime quotes for the watchlist, batched into as few requests as possible
+    Quote(quote::QuoteArgs),
+    /// Average returns by month of the year and day of the week, e.g. `seasonality 518880`
+    Seasonality(seasonality::SeasonalityArgs),
+    /// Serve the screen, quotes and a signal stream over gRPC (needs --features grpc)
+    Serve(Box<grpc::ServeArgs>),
+    /// Leave codes out of reports and alerts for a while
+    Snooze(snooze::SnoozeArgs),
+    /// Track the price ratio of two instruments and its z-score vs the rolling mean
+    Spread(spread::SpreadArgs),
+    /// Re-run the decline screen periodically and send alerts to notifiers
+    Watch(Box<watch::WatchArgs>),
+    /// Print a shell completion script (bash, zsh, fish, elvish, powershell)
+    Completions(completions::CompletionsArgs),
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // cargo run 10 to calculate previous 10 days decline rate
-    let args: Vec<String> = std::env::args().collect();
-    let day = args
-        .get(1)
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(5);
-
-    let mut results = Vec::new();
-
-    for &code in ETF_CODES {
-        match fetch_etf_kline(code, day).await {
-            Ok((prices, status_option)) => {
-                if let Some(status) = status_option {
-                    if status != 200 {
-                        eprintln!("HTTP {} for code: {}", status, code);
-                        continue;
-                    }
-                }
-
-                if prices.len() >= day {
-                    let price_pre = prices[0];
-                    let price_today = prices[day - 1];
-
-                    if price_today < price_pre {
-                        let today_decline_rate = calculate(price_pre, price_today);
-
-                        let half_day_idx = if day > 1 { day / 2 } else { 0 };
-                        let half_day_decline_rate = if half_day_idx < prices.len() && day > 1 {
-                            let price_half = prices[day - 1 - half_day_idx];
-                            calculate(price_pre, price_half)
-                        } else {
-                            0.0
-                        };
-                        results.push((code, today_decline_rate, half_day_decline_rate));
-                    }
-                } else {
-                    eprintln!("Not enough data for {}: got {} days", code, prices.len());
-                }
-            }
-            Err(e) => {
-                eprintln!("Failed to fetch data for {}: {} (No HTTP status available)", code, e);
-            }
-        }
-    }
+    let cli = Cli::parse();
 
-    println!("\n ETF Decline over {} days:", day);
-    println!("-----------------------------------------");
-    if results.is_empty() {
-        println!("No ETF data");
-    } else {
-        let hald_day = day / 2;
-        results.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-        for (code, rate, new_rate) in results {
-            println!(
-                "Code: {} | Rate(Today/{} days ago): {:.2}% | Rate({} days ago/{} days ago): {:.2}%",
-                code, day, rate, hald_day, day, new_rate
-            );
-        }
+    if let Some(dir) = &cli.config_dir {
+        config::set_config_dir(dir);
+    }
+    let mut cfg = Config::load(cli.config.as_deref(), cli.profile.as_deref())?;
+    if let Some(source) = cli.source {
+        cfg.source = source;
+    }
+    if let Some(secs) = cli.timeout {
+        cfg.http.timeout_secs = secs;
+    }
+    if let Some(secs) = cli.connect_timeout {
+        cfg.http.connect_timeout_secs = secs;
+    }
+    if let Some(secs) = cli.deadline {
+        cfg.http.deadline_secs = Some(secs);
     }
+    if let Some(lang) = cli.lang {
+        cfg.lang = lang;
+    }
+    match &cli.command {
+        Some(Command::Watch(args)) => args.apply(&mut cfg),
+        Some(Command::Paper(args)) => args.apply(&mut cfg),
+        Some(Command::Serve(args)) => args.apply(&mut cfg),
+        None => cli.decline.apply(&mut cfg),
+        _ => {}
+    }
+    http::init(&cfg.http)?;
+    if let Some(dir) = &cli.fixture {
+        fixture::init(dir)?;
+        // Canned bars mustn't reach the real event log, archive or sinks.
+        cfg.dry_run = true;
+    }
+    if let Some(dir) = &cli.record {
+        replay::record(dir)?;
+    }
+    if let Some(dir) = &cli.replay {
+        replay::replay(dir)?;
+        // Replaying a run mustn't repeat its notifications and writes.
+        cfg.dry_run = true;
+    }
+    i18n::init(cfg.lang);
+    plugins::init(&cfg, decline::METRICS);
+    let taken: Vec<&str> = decline::METRICS.iter().copied().chain(plugins::all().iter().map(|p| p.name)).collect();
+    scripts::init(&cfg, &taken);
+
+    // Commands cron runs, which write state or notify, skip while a run of
+    // the same command is still going.
+    let locked = match &cli.command {
+        None => Some("decline"),
+        Some(Command::Watch(args)) if !args.dry_run => Some("watch"),
+        Some(Command::Paper(args)) if args.trades() => Some("paper"),
+        Some(Command::Portfolio(_)) => Some("portfolio"),
+        Some(Command::Premium(_)) => Some("premium"),
+        _ => None,
+    };
+    let _lock = match locked {
+        Some(command) => match lock::acquire(command, cfg.profile.as_deref())? {
+            Some(lock) => Some(lock),
+            None => return Ok(()),
+        },
+        None => None,
+    };
 
-    Ok(())
-}
\ No newline at end of file
+    let result = match &cli.command {
+        Some(Command::Backfill(args)) => history::run(args, &cfg).await,
+        Some(Command::Backtest(args)) => backtest::run(args, &cfg).await,
+        Some(Command::Basis(args)) => basis::run(args, &cfg).await,
+        Some(Command::Cache(args)) => cache::run(args, &cfg),
+        Some(Command::Digest(args)) => digest::run(args, &cfg).await,
+        Some(Command::Dividends(args)) => dividends::run(args, &cfg).await,
+        Some(Command::Doctor(args)) => doctor::run(args, &cfg).await,
+        Some(Command::Events(args)) => events::run(args, &cfg).await,
+        Some(Command::Hedge(args)) => hedge::run(args, &cfg).await,
+        Some(Command::History(args)) => archive::run(args, &cfg),
+        Some(Command::Info(args)) => info::run(args, &cfg).await,
+        Some(Command::Intraday(args)) => intraday::run(args, &cfg).await,
+        Some(Command::Paper(args)) => paper::run(args, &cfg).await,
+        Some(Command::Paths(args)) => paths::run(args, &cfg),
+        Some(Command::Portfolio(args)) => portfolio::run(args, &cfg).await,
+        Some(Command::Premium(args)) => premium::run(args, &cfg).await,
+        Some(Command::Quote(args)) => quote::run(args, &cfg).await,
+        Some(Command::Seasonality(args)) => seasonality::run(args, &cfg).await,
+        Some(Command::Serve(args)) => grpc::run(args, &cfg).await,
+        Some(Command::Snooze(args)) => snooze::run(args, &cfg),
+        Some(Command::Spread(args)) => spread::run(args, &cfg).await,
+        Some(Command::Watch(args)) => watch::run(args, &cfg).await,
+        Some(Command::Completions(args)) => completions::run(args, &cfg),
+        None => decline::run(&cli.decline, &cfg).await,
+    };
+    breaker::save();
+    result
+}
@@ -0,0 +1,95 @@
+//! Monte Carlo projection of a basket's value from the historical mean and
+//! covariance of its members' daily log returns.
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use rand_distr::{Distribution, StandardNormal};
+
+/// Trading days per month, for turning horizons into simulated days.
+pub const TRADING_DAYS_PER_MONTH: usize = 21;
+
+/// Percentiles reported for every horizon.
+pub const BANDS: [f64; 5] = [5.0, 25.0, 50.0, 75.0, 95.0];
+
+/// Daily log returns per asset from closes aligned by day (`closes[t][i]`).
+pub fn log_returns(closes: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    closes.windows(2).map(|w| w[0].iter().zip(&w[1]).map(|(a, b)| (b / a).ln()).collect()).collect()
+}
+
+/// Mean vector and sample covariance matrix of `returns[t][i]`.
+pub fn mean_cov(returns: &[Vec<f64>]) -> (Vec<f64>, Vec<Vec<f64>>) {
+    let n = returns.first().map_or(0, Vec::len);
+    let t = returns.len() as f64;
+    let mean: Vec<f64> = (0..n).map(|i| returns.iter().map(|r| r[i]).sum::<f64>() / t).collect();
+    let denom = (t - 1.0).max(1.0);
+    let cov = (0..n)
+        .map(|i| (0..n).map(|j| returns.iter().map(|r| (r[i] - mean[i]) * (r[j] - mean[j])).sum::<f64>() / denom).collect())
+        .collect();
+    (mean, cov)
+}
+
+/// Lower-triangular `L` with `L * L^T = m`, or `None` if `m` isn't positive
+/// definite (e.g. two holdings tracking the same index exactly).
+pub fn cholesky(m: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = m.len();
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let d = m[i][i] - sum;
+                if d <= 0.0 {
+                    return None;
+                }
+                l[i][i] = d.sqrt();
+            } else {
+                l[i][j] = (m[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+/// Simulate `paths` paths of the basket holding `values[i]` in each asset
+/// plus flat `cash`, and return the total at each of `horizons` (days,
+/// ascending) for every path.
+pub fn simulate(
+    values: &[f64],
+    cash: f64,
+    mean: &[f64],
+    chol: &[Vec<f64>],
+    horizons: &[usize],
+    paths: usize,
+    seed: u64,
+) -> Vec<Vec<f64>> {
+    let n = values.len();
+    let days = horizons.last().copied().unwrap_or(0);
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut out = vec![Vec::with_capacity(paths); horizons.len()];
+    let mut z = vec![0.0; n];
+    for _ in 0..paths {
+        let mut v = values.to_vec();
+        let mut next = 0;
+        for day in 1..=days {
+            for zi in z.iter_mut() {
+                *zi = StandardNormal.sample(&mut rng);
+            }
+            for i in 0..n {
+                let shock: f64 = (0..=i).map(|k| chol[i][k] * z[k]).sum();
+                v[i] *= (mean[i] + shock).exp();
+            }
+            while next < horizons.len() && horizons[next] == day {
+                out[next].push(v.iter().sum::<f64>() + cash);
+                next += 1;
+            }
+        }
+    }
+    out
+}
+
+/// The `p`th percentile (0-100) of `values` by nearest rank; sorts in place.
+pub fn percentile(values: &mut [f64], p: f64) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let rank = (p / 100.0 * (values.len() - 1) as f64).round() as usize;
+    values[rank.min(values.len() - 1)]
+}
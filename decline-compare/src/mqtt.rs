@@ -0,0 +1,96 @@
+//! Alerts and key metrics published to an MQTT broker, so home-automation
+//! setups such as Home Assistant can flash a light or make an announcement
+//! when a dip threshold hits. Built with `--features mqtt` and turned on by
+//! an `[mqtt]` table.
+//!
+//! Each new alert (once per code, rule and bar date, as the event log
+//! records it) goes to `alert_topic` as JSON. Each row's `metrics` go to
+//! `metric_topic` as bare numbers, retained, so a sensor subscribing later
+//! still gets the latest value.
+
+use crate::alerts::Alert;
+use crate::config::Config;
+use crate::decline::Report;
+
+/// Whether this build can publish to MQTT.
+pub const AVAILABLE: bool = cfg!(feature = "mqtt");
+
+/// Publish the `fresh` alerts and `report`'s metrics, if a broker is
+/// configured. Failures are reported but don't fail the run.
+pub async fn publish(cfg: &Config, report: &Report, fresh: &[Alert]) {
+    #[cfg(feature = "mqtt")]
+    if let Some(mqtt) = &cfg.mqtt
+        && let Err(e) = broker::publish(mqtt, report, fresh).await
+    {
+        eprintln!("{}", crate::i18n::tr!("Failed to publish to MQTT: {}", "发布到 MQTT 失败: {}", e));
+    }
+    #[cfg(not(feature = "mqtt"))]
+    let _ = (cfg, report, fresh);
+}
+
+#[cfg(feature = "mqtt")]
+mod broker {
+    use std::time::Duration;
+
+    use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+    use serde_json::json;
+
+    use crate::alerts::Alert;
+    use crate::config::MqttConfig;
+    use crate::decline::{self, Report};
+
+    /// How long the broker gets to acknowledge every message.
+    const PUBLISH_TIMEOUT: Duration = Duration::from_secs(30);
+
+    pub async fn publish(mqtt: &MqttConfig, report: &Report, fresh: &[Alert]) -> Result<(), Box<dyn std::error::Error>> {
+        // Topic, payload and whether the broker keeps it for late subscribers.
+        let mut messages: Vec<(String, String, bool)> = Vec::new();
+        for alert in fresh {
+            let payload = json!({
+                "code": alert.code,
+                "date": report.as_of,
+                "rule": alert.kind.metric(),
+                "value": alert.kind.value(),
+                "threshold": alert.kind.threshold(),
+                "message": alert.to_string(),
+            });
+            messages.push((mqtt.alert_topic.replace("{code}", &alert.code), payload.to_string(), false));
+        }
+        for row in &report.rows {
+            for metric in &mqtt.metrics {
+                if let Some(value) = decline::field(row, metric) {
+                    let topic = mqtt.metric_topic.replace("{code}", row.code()).replace("{metric}", metric);
+                    messages.push((topic, value.render(None, 4), true));
+                }
+            }
+        }
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let mut options = MqttOptions::new(&mqtt.client_id, &mqtt.host, mqtt.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &mqtt.username {
+            options.set_credentials(username, mqtt.password.as_deref().unwrap_or(""));
+        }
+        let (client, mut events) = AsyncClient::new(options, messages.len() + 1);
+        for (topic, payload, retain) in &messages {
+            client.publish(topic, QoS::AtLeastOnce, *retain, payload.clone()).await?;
+        }
+        let delivered = async {
+            let mut acked = 0;
+            while acked < messages.len() {
+                if let Event::Incoming(Packet::PubAck(_)) = events.poll().await? {
+                    acked += 1;
+                }
+            }
+            client.disconnect().await?;
+            // Once more to send the disconnect.
+            let _ = events.poll().await;
+            Ok::<_, Box<dyn std::error::Error>>(())
+        };
+        tokio::time::timeout(PUBLISH_TIMEOUT, delivered)
+            .await
+            .map_err(|_| format!("the broker didn't acknowledge within {} seconds", PUBLISH_TIMEOUT.as_secs()))?
+    }
+}
@@ -0,0 +1,166 @@
+//! Delivery channels for triggered alerts.
+
+use serde_json::{Value, json};
+
+use crate::alerts::Alert;
+use crate::http;
+use crate::i18n::tr;
+
+/// Whether this build can show desktop notifications.
+pub const DESKTOP: bool = cfg!(feature = "desktop");
+
+fn title() -> String {
+    tr!("ETF alerts", "ETF 预警")
+}
+
+// Slack allows 50 blocks per message (one goes to the header); Discord
+// allows 10 embeds.
+const SLACK_MAX_ALERTS: usize = 49;
+const DISCORD_MAX_EMBEDS: usize = 10;
+
+/// Longest Discord message content, in characters.
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+#[derive(Debug, Clone)]
+pub enum Notifier {
+    /// Pop-up through the OS notification system.
+    Desktop,
+    /// Slack incoming webhook URL.
+    Slack(String),
+    /// Discord channel webhook URL.
+    Discord(String),
+}
+
+fn slack_payload(alerts: &[Alert]) -> Value {
+    let mut blocks = vec![json!({
+        "type": "header",
+        "text": { "type": "plain_text", "text": title() },
+    })];
+    for alert in alerts {
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*{}* {}", alert.code, alert.kind) },
+            "fields": [
+                { "type": "mrkdwn", "text": tr!("*Metric*\n{}", "*指标*\n{}", alert.kind.metric()) },
+                { "type": "mrkdwn", "text": tr!("*Value*\n{:.3}", "*数值*\n{:.3}", alert.kind.value()) },
+                { "type": "mrkdwn", "text": tr!("*Threshold*\n{:.3}", "*阈值*\n{:.3}", alert.kind.threshold()) },
+            ],
+        }));
+    }
+    let text = alerts.iter().map(|a| a.to_string()).collect::<Vec<_>>().join("\n");
+    json!({ "text": text, "blocks": blocks })
+}
+
+fn discord_payload(alerts: &[Alert]) -> Value {
+    let embeds: Vec<Value> = alerts
+        .iter()
+        .map(|alert| {
+            json!({
+                "title": alert.code,
+                "description": alert.kind.to_string(),
+                "fields": [
+                    { "name": tr!("Metric", "指标"), "value": alert.kind.metric(), "inline": true },
+                    { "name": tr!("Value", "数值"), "value": format!("{:.3}", alert.kind.value()), "inline": true },
+                    { "name": tr!("Threshold", "阈值"), "value": format!("{:.3}", alert.kind.threshold()), "inline": true },
+                ],
+            })
+        })
+        .collect();
+    json!({ "content": title(), "embeds": embeds })
+}
+
+/// Pop up `body` under `summary`.
+async fn show_desktop(summary: String, body: String) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "desktop")]
+    {
+        // notify-rust blocks on the session bus round trip.
+        tokio::task::spawn_blocking(move || {
+            notify_rust::Notification::new().summary(&summary).body(&body).show().map(|_| ())
+        })
+        .await?
+        .map_err(|e| e.to_string())?;
+        Ok(())
+    }
+    #[cfg(not(feature = "desktop"))]
+    {
+        let _ = (summary, body);
+        Err("this build lacks the desktop feature".into())
+    }
+}
+
+async fn post_json(url: &str, payload: &Value) -> Result<(), Box<dyn std::error::Error>> {
+    let resp = http::client().post(url).json(payload).send().await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp.text().await.unwrap_or_default();
+        return Err(format!("HTTP {}: {}", status.as_u16(), body.trim()).into());
+    }
+    Ok(())
+}
+
+impl Notifier {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Notifier::Desktop => "desktop",
+            Notifier::Slack(_) => "slack",
+            Notifier::Discord(_) => "discord",
+        }
+    }
+
+    /// Deliver a test message, to check the channel works.
+    pub async fn ping(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let text = tr!("Test notification from decline-compare doctor", "decline-compare doctor 测试通知");
+        self.send_text(&title(), &text).await
+    }
+
+    /// Deliver a plain-text message under `heading`, such as a digest.
+    /// Discord's is cut short at its length limit.
+    pub async fn send_text(&self, heading: &str, text: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            Notifier::Desktop => show_desktop(heading.to_string(), text.to_string()).await,
+            Notifier::Slack(url) => post_json(url, &json!({ "text": format!("*{}*\n{}", heading, text) })).await,
+            Notifier::Discord(url) => {
+                let content = format!("**{}**\n{}", heading, text);
+                let content = match content.char_indices().nth(DISCORD_MAX_CONTENT - 1) {
+                    Some((cut, _)) => format!("{}…", &content[..cut]),
+                    None => content,
+                };
+                post_json(url, &json!({ "content": content })).await
+            }
+        }
+    }
+
+    pub async fn send(&self, alerts: &[Alert]) -> Result<(), Box<dyn std::error::Error>> {
+        if alerts.is_empty() {
+            return Ok(());
+        }
+        match self {
+            Notifier::Desktop => {
+                let body = alerts.iter().map(|a| a.to_string()).collect::<Vec<_>>().join("\n");
+                show_desktop(title(), body).await
+            }
+            Notifier::Slack(url) => {
+                for chunk in alerts.chunks(SLACK_MAX_ALERTS) {
+                    post_json(url, &slack_payload(chunk)).await?;
+                }
+                Ok(())
+            }
+            Notifier::Discord(url) => {
+                for chunk in alerts.chunks(DISCORD_MAX_EMBEDS) {
+                    post_json(url, &discord_payload(chunk)).await?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Send `alerts` through every notifier, reporting failures on stderr so one
+/// broken channel doesn't stop the others.
+pub async fn send_all(notifiers: &[Notifier], alerts: &[Alert]) {
+    for notifier in notifiers {
+        if let Err(e) = notifier.send(alerts).await {
+            eprintln!("{}", tr!("Failed to send {} notification: {}", "发送 {} 通知失败: {}", notifier.name(), e));
+        }
+    }
+}
@@ -0,0 +1,553 @@
+//! Paper trading: the watch-mode screen's dip alerts become simulated orders
+//! filled at the live quote, against a virtual account kept in SQLite.
+//!
+//! A code is bought when it breaks its channel low or touches the lower
+//! Keltner band and isn't already held, and sold once it reaches the take
+//! profit or stop loss on its average cost. Orders are only placed during
+//! market hours, in whole lots at the last traded price, with no fees. A
+//! T+1 fund bought today is held until the next session.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::{Args, Subcommand};
+use rusqlite::{Connection, OptionalExtension, params};
+use serde::Serialize;
+
+use crate::alerts::AlertKind;
+use crate::config::{self, Config, OutputFormat};
+use crate::cooldown;
+use crate::decline::{self, DeclineArgs, Report};
+use crate::http;
+use crate::i18n::tr;
+use crate::instruments::{self, Settlement};
+use crate::lots;
+use crate::schedule;
+use crate::service;
+use crate::sina::{self, Quote};
+use crate::watch;
+
+/// How many recent orders `paper status` lists.
+const RECENT_ORDERS: usize = 20;
+
+#[derive(Args, Debug)]
+pub struct PaperArgs {
+    #[command(subcommand)]
+    command: PaperCommand,
+
+    /// Virtual account database [default: $STOCK_DATA_DIR/paper.db, or paper.PROFILE.db]
+    #[arg(long, global = true)]
+    db: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum PaperCommand {
+    /// Re-run the screen periodically and trade its signals against live quotes
+    Run(Box<RunArgs>),
+    /// The virtual account's positions, simulated P&L and recent orders
+    Status,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    decline: DeclineArgs,
+
+    /// Minutes between screen runs [default: 5]
+    #[arg(long)]
+    interval: Option<u64>,
+
+    /// Opening cash when the database is created [default: 100000]
+    #[arg(long)]
+    cash: Option<f64>,
+
+    /// Cash put into each simulated buy [default: 10000]
+    #[arg(long)]
+    order_cash: Option<f64>,
+
+    /// Sell once a position is up this many percent [default: 5]
+    #[arg(long)]
+    take_profit: Option<f64>,
+
+    /// Sell once a position is down this many percent [default: 8]
+    #[arg(long)]
+    stop_loss: Option<f64>,
+}
+
+impl PaperArgs {
+    /// Whether this is `paper run`, which places orders, rather than a
+    /// read-only subcommand.
+    pub fn trades(&self) -> bool {
+        matches!(self.command, PaperCommand::Run(_))
+    }
+
+    /// Layer the flags given on the command line over `cfg`.
+    pub fn apply(&self, cfg: &mut Config) {
+        let PaperCommand::Run(args) = &self.command else {
+            return;
+        };
+        args.decline.apply(cfg);
+        if let Some(minutes) = args.interval {
+            cfg.watch.interval_minutes = minutes;
+        }
+        let paper = &mut cfg.paper;
+        if let Some(v) = args.cash {
+            paper.cash = v;
+        }
+        if let Some(v) = args.order_cash {
+            paper.order_cash = v;
+        }
+        if let Some(v) = args.take_profit {
+            paper.take_profit_pct = v;
+        }
+        if let Some(v) = args.stop_loss {
+            paper.stop_loss_pct = v;
+        }
+    }
+}
+
+pub fn default_db_path(profile: Option<&str>) -> PathBuf {
+    config::data_dir().join(config::profiled("paper", "db", profile))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Side {
+    Buy,
+    Sell,
+}
+
+impl Side {
+    fn as_str(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+}
+
+/// A simulated order, filled in full at `price`.
+#[derive(Debug, Clone, Serialize)]
+struct Order {
+    code: String,
+    side: Side,
+    shares: i64,
+    price: f64,
+    /// What triggered it; stored, so not localized.
+    reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct OrderRecord {
+    time: String,
+    code: String,
+    side: String,
+    shares: i64,
+    price: f64,
+    reason: String,
+    /// Realized P&L, for sells.
+    pnl: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+struct Position {
+    shares: i64,
+    /// Average price paid per share.
+    cost: f64,
+    /// Time of the latest buy.
+    last_buy: Option<String>,
+}
+
+impl Position {
+    /// Whether T+1 settlement still blocks selling it today.
+    fn locked(&self, code: &str, cfg: &Config) -> bool {
+        let today = schedule::now().format("%Y-%m-%d").to_string();
+        instruments::settlement(code, cfg) == Settlement::T1
+            && self.last_buy.as_deref().is_some_and(|t| t.starts_with(&today))
+    }
+}
+
+/// The virtual account: cash, open positions and every order placed.
+struct Book {
+    conn: Connection,
+}
+
+impl Book {
+    /// Open the database at `path`, creating it with `cash` if it is new.
+    fn open(path: &Path, cash: f64) -> Result<Book, Box<dyn std::error::Error>> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS account (
+                 id INTEGER PRIMARY KEY CHECK (id = 1),
+                 starting_cash REAL NOT NULL,
+                 cash REAL NOT NULL,
+                 opened_at TEXT NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS positions (
+                 code TEXT PRIMARY KEY,
+                 shares INTEGER NOT NULL,
+                 cost REAL NOT NULL
+             );
+             CREATE TABLE IF NOT EXISTS orders (
+                 id INTEGER PRIMARY KEY AUTOINCREMENT,
+                 time TEXT NOT NULL,
+                 code TEXT NOT NULL,
+                 side TEXT NOT NULL,
+                 shares INTEGER NOT NULL,
+                 price REAL NOT NULL,
+                 reason TEXT NOT NULL,
+                 pnl REAL
+             );",
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO account (id, starting_cash, cash, opened_at) VALUES (1, ?1, ?1, ?2)",
+            params![cash, timestamp()],
+        )?;
+        Ok(Book { conn })
+    }
+
+    /// `(starting cash, cash now)`.
+    fn cash(&self) -> Result<(f64, f64), Box<dyn std::error::Error>> {
+        Ok(self.conn.query_row("SELECT starting_cash, cash FROM account WHERE id = 1", [], |r| Ok((r.get(0)?, r.get(1)?)))?)
+    }
+
+    fn positions(&self) -> Result<BTreeMap<String, Position>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT code, shares, cost,
+                 (SELECT MAX(time) FROM orders o WHERE o.code = p.code AND o.side = 'buy')
+             FROM positions p ORDER BY code",
+        )?;
+        let rows = stmt
+            .query_map([], |r| Ok((r.get(0)?, Position { shares: r.get(1)?, cost: r.get(2)?, last_buy: r.get(3)? })))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    fn realized(&self) -> Result<f64, Box<dyn std::error::Error>> {
+        Ok(self.conn.query_row("SELECT COALESCE(SUM(pnl), 0) FROM orders", [], |r| r.get(0))?)
+    }
+
+    /// The most recent `limit` orders, oldest first.
+    fn recent_orders(&self, limit: usize) -> Result<Vec<OrderRecord>, Box<dyn std::error::Error>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT time, code, side, shares, price, reason, pnl FROM orders ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |r| {
+            Ok(OrderRecord {
+                time: r.get(0)?,
+                code: r.get(1)?,
+                side: r.get(2)?,
+                shares: r.get(3)?,
+                price: r.get(4)?,
+                reason: r.get(5)?,
+                pnl: r.get(6)?,
+            })
+        })?;
+        let mut orders: Vec<OrderRecord> = rows.collect::<Result<_, _>>()?;
+        orders.reverse();
+        Ok(orders)
+    }
+
+    /// Apply `order` to cash and positions and log it, in one transaction.
+    /// Returns the realized P&L of a sell.
+    fn fill(&mut self, order: &Order) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+        let tx = self.conn.transaction()?;
+        let held: Option<(i64, f64)> = tx
+            .query_row("SELECT shares, cost FROM positions WHERE code = ?1", [&order.code], |r| Ok((r.get(0)?, r.get(1)?)))
+            .optional()?;
+        let amount = order.shares as f64 * order.price;
+        let pnl = match order.side {
+            Side::Buy => {
+                let (shares, cost) = held.unwrap_or((0, 0.0));
+                let total = shares + order.shares;
+                let avg = (shares as f64 * cost + amount) / total as f64;
+                tx.execute(
+                    "INSERT INTO positions (code, shares, cost) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(code) DO UPDATE SET shares = excluded.shares, cost = excluded.cost",
+                    params![order.code, total, avg],
+                )?;
+                tx.execute("UPDATE account SET cash = cash - ?1 WHERE id = 1", [amount])?;
+                None
+            }
+            Side::Sell => {
+                let (shares, cost) = held.ok_or_else(|| format!("no paper position in {}", order.code))?;
+                if order.shares >= shares {
+                    tx.execute("DELETE FROM positions WHERE code = ?1", [&order.code])?;
+                } else {
+                    tx.execute("UPDATE positions SET shares = ?1 WHERE code = ?2", params![shares - order.shares, order.code])?;
+                }
+                tx.execute("UPDATE account SET cash = cash + ?1 WHERE id = 1", [amount])?;
+                Some((order.price - cost) * order.shares.min(shares) as f64)
+            }
+        };
+        tx.execute(
+            "INSERT INTO orders (time, code, side, shares, price, reason, pnl) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![timestamp(), order.code, order.side.as_str(), order.shares, order.price, order.reason, pnl],
+        )?;
+        tx.commit()?;
+        Ok(pnl)
+    }
+}
+
+fn timestamp() -> String {
+    schedule::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// Orders for this pass: exits for positions past their take profit or stop
+/// loss that settlement allows selling, then a buy for each dip alert on a
+/// code not already held, while the cash lasts.
+fn signals(
+    report: &Report,
+    positions: &BTreeMap<String, Position>,
+    quotes: &HashMap<String, Quote>,
+    mut cash: f64,
+    cfg: &Config,
+) -> Vec<Order> {
+    let paper = &cfg.paper;
+    let mut orders = Vec::new();
+    for (code, position) in positions {
+        let Some(price) = quotes.get(code).map(|q| q.last).filter(|&p| p > 0.0) else {
+            continue;
+        };
+        if position.locked(code, cfg) {
+            continue;
+        }
+        let change = (price - position.cost) / position.cost * 100.0;
+        let reason = if change >= paper.take_profit_pct {
+            format!("take profit {:+.2}%", change)
+        } else if change <= -paper.stop_loss_pct {
+            format!("stop loss {:+.2}%", change)
+        } else {
+            continue;
+        };
+        cash += position.shares as f64 * price;
+        orders.push(Order { code: code.clone(), side: Side::Sell, shares: position.shares, price, reason });
+    }
+
+    let mut bought = BTreeSet::new();
+    for alert in &report.alerts {
+        if !matches!(alert.kind, AlertKind::DonchianLow { .. } | AlertKind::KeltnerLower { .. })
+            || positions.contains_key(&alert.code)
+            || !bought.insert(alert.code.clone())
+        {
+            continue;
+        }
+        let Some(price) = quotes.get(&alert.code).map(|q| q.last).filter(|&p| p > 0.0) else {
+            continue;
+        };
+        let (shares, _) = lots::round_down(paper.order_cash.min(cash), price);
+        if shares == 0 {
+            continue;
+        }
+        let shares = shares as i64;
+        cash -= shares as f64 * price;
+        orders.push(Order { code: alert.code.clone(), side: Side::Buy, shares, price, reason: alert.kind.metric() });
+    }
+    orders
+}
+
+#[derive(Serialize)]
+struct PositionValue {
+    code: String,
+    settlement: Settlement,
+    /// Bought today under T+1, so it can't be sold until the next session.
+    locked: bool,
+    shares: i64,
+    cost: f64,
+    price: Option<f64>,
+    value: f64,
+    unrealized: f64,
+}
+
+#[derive(Serialize)]
+struct Summary {
+    starting_cash: f64,
+    cash: f64,
+    positions: Vec<PositionValue>,
+    total: f64,
+    realized: f64,
+    /// Percent change of the total since the account opened.
+    pnl_pct: f64,
+    orders: Vec<OrderRecord>,
+}
+
+/// Mark the account to `quotes`; positions without a quote stay at cost.
+fn summarize(book: &Book, quotes: &HashMap<String, Quote>, cfg: &Config) -> Result<Summary, Box<dyn std::error::Error>> {
+    let (starting_cash, cash) = book.cash()?;
+    let positions: Vec<PositionValue> = book
+        .positions()?
+        .into_iter()
+        .map(|(code, p)| {
+            let price = quotes.get(&code).map(|q| q.last).filter(|&p| p > 0.0);
+            let value = p.shares as f64 * price.unwrap_or(p.cost);
+            PositionValue {
+                settlement: instruments::settlement(&code, cfg),
+                locked: p.locked(&code, cfg),
+                unrealized: value - p.shares as f64 * p.cost,
+                code,
+                shares: p.shares,
+                cost: p.cost,
+                price,
+                value,
+            }
+        })
+        .collect();
+    let total = cash + positions.iter().map(|p| p.value).sum::<f64>();
+    Ok(Summary {
+        starting_cash,
+        cash,
+        positions,
+        total,
+        realized: book.realized()?,
+        pnl_pct: if starting_cash > 0.0 { (total - starting_cash) / starting_cash * 100.0 } else { 0.0 },
+        orders: book.recent_orders(RECENT_ORDERS)?,
+    })
+}
+
+fn print_order(order: &Order, pnl: Option<f64>) {
+    let side = match order.side {
+        Side::Buy => tr!("BUY", "买入"),
+        Side::Sell => tr!("SELL", "卖出"),
+    };
+    let pnl = pnl.map_or_else(String::new, |p| tr!(" | P&L: {:+.2}", " | 盈亏: {:+.2}", p));
+    println!("{} {} {} @ {:.3} ({}){}", side, order.code, order.shares, order.price, order.reason, pnl);
+}
+
+fn print_summary(summary: &Summary) {
+    println!("\n {}", tr!("Paper account:", "模拟账户:"));
+    println!("-----------------------------------------");
+    for p in &summary.positions {
+        let settlement = if p.locked {
+            tr!("{}, sellable next session", "{}, 下一交易日可卖", p.settlement)
+        } else {
+            p.settlement.to_string()
+        };
+        println!(
+            "{}",
+            tr!(
+                "Code: {} ({}) | Shares: {} | Cost: {:.3} | Last: {} | Value: {:.2} | Unrealized: {:+.2}",
+                "代码: {} ({}) | 持仓: {} | 成本: {:.3} | 最新: {} | 市值: {:.2} | 浮动盈亏: {:+.2}",
+                p.code,
+                settlement,
+                p.shares,
+                p.cost,
+                p.price.map_or_else(|| "n/a".to_string(), |v| format!("{:.3}", v)),
+                p.value,
+                p.unrealized
+            )
+        );
+    }
+    println!(
+        "{}",
+        tr!(
+            "Cash: {:.2} | Total: {:.2} | Realized: {:+.2} | Since open: {:+.2}% of {:.2}",
+            "现金: {:.2} | 总计: {:.2} | 已实现: {:+.2} | 开户以来: {:+.2}% (本金 {:.2})",
+            summary.cash,
+            summary.total,
+            summary.realized,
+            summary.pnl_pct,
+            summary.starting_cash
+        )
+    );
+}
+
+fn print_orders(orders: &[OrderRecord]) {
+    println!("\n {}", tr!("Recent paper orders:", "近期模拟委托:"));
+    println!("-----------------------------------------");
+    if orders.is_empty() {
+        println!("{}", tr!("No orders yet", "暂无委托"));
+    }
+    for o in orders {
+        let pnl = o.pnl.map_or_else(String::new, |p| tr!(" | P&L: {:+.2}", " | 盈亏: {:+.2}", p));
+        println!("{} {} {} {} @ {:.3} ({}){}", o.time, o.side, o.code, o.shares, o.price, o.reason, pnl);
+    }
+}
+
+/// Quotes for every code held and, when trading, the watchlist.
+async fn quotes_for(book: &Book, watchlist: &[String], cfg: &Config) -> Result<HashMap<String, Quote>, Box<dyn std::error::Error>> {
+    let mut codes: BTreeSet<String> = watchlist.iter().cloned().collect();
+    codes.extend(book.positions()?.into_keys());
+    if codes.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let codes: Vec<String> = codes.into_iter().collect();
+    match http::until(http::deadline(&cfg.http), sina::fetch_quotes(&codes)).await {
+        Some(quotes) => quotes,
+        None => Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn").into()),
+    }
+}
+
+async fn trade(args: &RunArgs, book: &mut Book, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let interval = cfg.watch.interval_minutes;
+    if interval == 0 {
+        return Err("--interval must be at least 1 minute".into());
+    }
+    decline::validate(&args.decline, cfg)?;
+    let mut bars = HashMap::new();
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval * 60));
+    // Checked between passes only, so a pass always finishes its fills.
+    let mut shutdown = std::pin::pin!(service::terminated());
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = &mut shutdown => break,
+        }
+        let deadline = http::deadline(&cfg.http);
+        let (fetched, _) = watch::refresh(&mut bars, &args.decline, cfg, deadline).await;
+        let report = match decline::build(&args.decline, cfg, fetched, deadline).await {
+            Ok(report) => report,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        let quotes = match quotes_for(book, &cfg.watchlist, cfg).await {
+            Ok(quotes) => quotes,
+            Err(e) => {
+                eprintln!("{}", tr!("Failed to fetch quotes: {}", "获取实时行情失败: {}", e));
+                continue;
+            }
+        };
+
+        println!("\n {}", tr!("Paper orders at {}:", "{} 模拟委托:", timestamp()));
+        println!("-----------------------------------------");
+        if !schedule::is_open(&[schedule::MARKET_HOURS], cooldown::now_secs()) {
+            println!("{}", tr!("Market closed; no orders placed", "休市中, 不下单"));
+        } else {
+            let (_, cash) = book.cash()?;
+            let orders = signals(&report, &book.positions()?, &quotes, cash, cfg);
+            if orders.is_empty() {
+                println!("{}", tr!("No signals", "无信号"));
+            }
+            for order in &orders {
+                let pnl = book.fill(order)?;
+                print_order(order, pnl);
+            }
+        }
+        print_summary(&summarize(book, &quotes, cfg)?);
+    }
+    eprintln!("{}", tr!("Shutting down", "正在退出"));
+    Ok(())
+}
+
+pub async fn run(args: &PaperArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.db.clone().unwrap_or_else(|| default_db_path(cfg.profile.as_deref()));
+    let mut book = Book::open(&path, cfg.paper.cash)?;
+    match &args.command {
+        PaperCommand::Run(run) => trade(run, &mut book, cfg).await,
+        PaperCommand::Status => {
+            let quotes = quotes_for(&book, &[], cfg).await?;
+            let summary = summarize(&book, &quotes, cfg)?;
+            if cfg.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+            } else {
+                print_summary(&summary);
+                print_orders(&summary.orders);
+            }
+            Ok(())
+        }
+    }
+}
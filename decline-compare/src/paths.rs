@@ -0,0 +1,58 @@
+//! `paths`: where the config file, state and caches resolve to on this
+//! machine, after `--config`, `--config-dir` and `$STOCK_DATA_DIR`.
+
+use std::path::PathBuf;
+
+use clap::Args;
+use serde::Serialize;
+
+use crate::cache;
+use crate::config::{self, Config, DataDirSource, OutputFormat};
+use crate::i18n::tr;
+use crate::plugins;
+
+#[derive(Args, Debug)]
+pub struct PathsArgs {}
+
+#[derive(Debug, Serialize)]
+struct Paths {
+    config_file: PathBuf,
+    /// Whether the config file exists; the default one is optional.
+    config_found: bool,
+    config_dir: PathBuf,
+    data_dir: PathBuf,
+    /// Why state goes to `data_dir`.
+    data_dir_source: DataDirSource,
+    cache_file: PathBuf,
+    plugins_dir: PathBuf,
+}
+
+pub fn run(_args: &PathsArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let config_file = cfg.file.clone().unwrap_or_else(config::default_config_file);
+    let paths = Paths {
+        config_found: config_file.exists(),
+        config_file,
+        config_dir: config::config_dir(),
+        data_dir: config::data_dir(),
+        data_dir_source: config::data_dir_source(),
+        cache_file: cache::default_path(),
+        plugins_dir: cfg.plugins_dir.clone().unwrap_or_else(plugins::default_dir),
+    };
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&paths)?);
+        return Ok(());
+    }
+    println!("\n {}", tr!("Paths:", "路径:"));
+    println!("-----------------------------------------");
+    let found = if paths.config_found { tr!("found", "存在") } else { tr!("not found", "不存在") };
+    println!("{}", tr!("Config file: {} ({})", "配置文件: {} ({})", paths.config_file.display(), found));
+    println!("{}", tr!("Config dir:  {}", "配置目录: {}", paths.config_dir.display()));
+    println!(
+        "{}",
+        tr!("Data dir:    {} ({})", "数据目录: {} ({})", paths.data_dir.display(), paths.data_dir_source)
+    );
+    println!("{}", tr!("Kline cache: {}", "K线缓存: {}", paths.cache_file.display()));
+    println!("{}", tr!("Plugins:     {}", "插件目录: {}", paths.plugins_dir.display()));
+    Ok(())
+}
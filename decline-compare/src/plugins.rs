@@ -0,0 +1,148 @@
+//! Custom indicators as WebAssembly modules, loaded at startup from the
+//! plugins directory ($STOCK_DATA_DIR/plugins unless `plugins_dir` is set).
+//!
+//! Each `*.wasm` file becomes a metric named after the file, so
+//! `momentum.wasm` can be used in `--score`, `--filter` and `--sort score`
+//! like any built-in metric. A module must export:
+//!
+//! - `memory`
+//! - `alloc(len: i32) -> i32`, returning the address of `len` writable bytes
+//! - `compute(ptr: i32, bars: i32) -> f64`, called after the host writes
+//!   `bars` records of five little-endian f64 (open, high, low, close,
+//!   volume), oldest first, at `ptr`; NaN means no value
+//!
+//! No imports are provided. Every call starts from a fresh instance and
+//! runs on a fuel budget, so a plugin that loops forever fails instead of
+//! hanging the screen. Built with the `plugins` feature, on by default;
+//! without it, modules in the directory are reported and skipped.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[cfg(feature = "plugins")]
+use wasmi::{Engine, Linker, Module, Store};
+
+use crate::config::{self, Config};
+use crate::i18n::tr;
+use crate::sina::Candle;
+
+/// Wasm instructions one `compute` call may execute, roughly.
+#[cfg(feature = "plugins")]
+const FUEL: u64 = 100_000_000;
+
+pub struct Plugin {
+    /// Metric name, from the file stem.
+    pub name: &'static str,
+    #[cfg(feature = "plugins")]
+    engine: Engine,
+    #[cfg(feature = "plugins")]
+    module: Module,
+}
+
+static PLUGINS: OnceLock<Vec<Plugin>> = OnceLock::new();
+
+pub fn default_dir() -> PathBuf {
+    config::data_dir().join("plugins")
+}
+
+/// Compile every module in the plugins directory. Modules that fail to
+/// compile, or that would shadow a built-in metric, are reported and
+/// skipped. Later calls are ignored.
+pub fn init(cfg: &Config, builtin: &[&str]) {
+    let dir = cfg.plugins_dir.clone().unwrap_or_else(default_dir);
+    let _ = PLUGINS.set(load(&dir, builtin));
+}
+
+/// The loaded plugins; empty if `init` never ran.
+pub fn all() -> &'static [Plugin] {
+    PLUGINS.get().map_or(&[], Vec::as_slice)
+}
+
+/// The `*.wasm` files in `dir`, in name order.
+fn modules(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> =
+        entries.filter_map(|e| Some(e.ok()?.path())).filter(|p| p.extension().is_some_and(|x| x == "wasm")).collect();
+    paths.sort();
+    paths
+}
+
+#[cfg(not(feature = "plugins"))]
+fn load(dir: &Path, _builtin: &[&str]) -> Vec<Plugin> {
+    let paths = modules(dir);
+    if !paths.is_empty() {
+        eprintln!(
+            "{}",
+            tr!(
+                "Skipping {} plugins in {}: this build lacks the plugins feature",
+                "跳过 {} 中的 {} 个插件: 此版本未启用 plugins 功能",
+                paths.len(),
+                dir.display()
+            )
+        );
+    }
+    Vec::new()
+}
+
+#[cfg(feature = "plugins")]
+fn load(dir: &Path, builtin: &[&str]) -> Vec<Plugin> {
+    let paths = modules(dir);
+    if paths.is_empty() {
+        return Vec::new();
+    }
+    let mut config = wasmi::Config::default();
+    config.consume_fuel(true);
+    let engine = Engine::new(&config);
+
+    let mut plugins = Vec::new();
+    for path in paths {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if builtin.contains(&name.as_str()) || name.ends_with("_rank") {
+            eprintln!("{}", tr!("Skipping plugin {}: name clashes with a metric", "跳过插件 {}: 与已有指标重名", name));
+            continue;
+        }
+        let module = fs::read(&path).map_err(|e| e.to_string()).and_then(|bytes| {
+            Module::new(&engine, bytes).map_err(|e| e.to_string())
+        });
+        match module {
+            // Loaded once per process, so the leaked names stay bounded.
+            Ok(module) => plugins.push(Plugin { name: Box::leak(name.into_boxed_str()), engine: engine.clone(), module }),
+            Err(e) => eprintln!("{}", tr!("Failed to load plugin {}: {}", "加载插件 {} 失败: {}", path.display(), e)),
+        }
+    }
+    plugins
+}
+
+impl Plugin {
+    #[cfg(not(feature = "plugins"))]
+    pub fn compute(&self, _candles: &[Candle]) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+        Err("this build lacks the plugins feature".into())
+    }
+
+    /// Run the plugin over `candles`; `None` when it returns NaN.
+    #[cfg(feature = "plugins")]
+    pub fn compute(&self, candles: &[Candle]) -> Result<Option<f64>, Box<dyn std::error::Error>> {
+        let mut store = Store::new(&self.engine, ());
+        store.set_fuel(FUEL)?;
+        let instance = Linker::<()>::new(&self.engine).instantiate_and_start(&mut store, &self.module)?;
+        let memory = instance.get_memory(&store, "memory").ok_or("no exported memory")?;
+        let alloc = instance.get_typed_func::<i32, i32>(&store, "alloc")?;
+        let compute = instance.get_typed_func::<(i32, i32), f64>(&store, "compute")?;
+
+        let bytes: Vec<u8> = candles
+            .iter()
+            .flat_map(|c| [c.open, c.high, c.low, c.close, c.volume])
+            .flat_map(f64::to_le_bytes)
+            .collect();
+        let len = i32::try_from(bytes.len())?;
+        let ptr = alloc.call(&mut store, len)?;
+        memory.write(&mut store, usize::try_from(ptr)?, &bytes)?;
+        let value = compute.call(&mut store, (ptr, i32::try_from(candles.len())?))?;
+        Ok(Some(value).filter(|v| v.is_finite()))
+    }
+}
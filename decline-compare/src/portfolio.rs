@@ -0,0 +1,530 @@
+//! Tracked holdings: what they are worth now and the recorded history of
+//! that value.
+//!
+//! Each `portfolio value` run records one valuation per trading day (a later
+//! run the same day replaces it), so running it after the close from cron
+//! builds the equity curve. Deposits and withdrawals aren't tracked and show
+//! up as returns.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use clap::{Args, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::montecarlo::{self, BANDS, TRADING_DAYS_PER_MONTH};
+use crate::schedule;
+use crate::sina;
+
+/// Width of the equity-curve bars in text output.
+const CHART_WIDTH: usize = 40;
+
+#[derive(Args, Debug)]
+pub struct PortfolioArgs {
+    #[command(subcommand)]
+    command: PortfolioCommand,
+
+    /// Where valuations are recorded
+    /// [default: $STOCK_DATA_DIR/portfolio_history.json, or portfolio_history.PROFILE.json]
+    #[arg(long, global = true)]
+    history_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+enum PortfolioCommand {
+    /// Value the holdings at the latest quotes and record today's valuation
+    Value,
+    /// Equity curve, cumulative return and drawdown of the recorded valuations
+    History,
+    /// Return against a benchmark over each period, and each position's share of it
+    Attribution(AttributionArgs),
+    /// Monte Carlo projection of the holdings' value from their historical return covariance
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    /// Horizons in months, comma separated
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 3, 12])]
+    horizon: Vec<usize>,
+
+    /// Number of simulated paths
+    #[arg(long, default_value_t = 10_000)]
+    paths: usize,
+
+    /// Trading days of history the returns are estimated from [default: 500]
+    #[arg(long)]
+    lookback: Option<usize>,
+
+    /// Random seed, for repeatable runs [default: from the clock]
+    #[arg(long)]
+    seed: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+struct AttributionArgs {
+    /// Benchmark code [default: 510300]
+    #[arg(long)]
+    benchmark: Option<String>,
+
+    /// Periods to break down, comma separated
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [Period::M1, Period::M3, Period::Ytd, Period::All])]
+    period: Vec<Period>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize)]
+enum Period {
+    #[value(name = "1w")]
+    W1,
+    #[value(name = "1m")]
+    M1,
+    #[value(name = "3m")]
+    M3,
+    #[value(name = "6m")]
+    M6,
+    #[value(name = "1y")]
+    Y1,
+    Ytd,
+    All,
+}
+
+impl Period {
+    fn label(self) -> &'static str {
+        match self {
+            Period::W1 => "1w",
+            Period::M1 => "1m",
+            Period::M3 => "3m",
+            Period::M6 => "6m",
+            Period::Y1 => "1y",
+            Period::Ytd => "ytd",
+            Period::All => "all",
+        }
+    }
+
+    /// First day the period covers when it ends on `end`.
+    fn start(self, end: NaiveDate) -> NaiveDate {
+        match self {
+            Period::W1 => end - Duration::days(7),
+            Period::M1 => end - Duration::days(30),
+            Period::M3 => end - Duration::days(91),
+            Period::M6 => end - Duration::days(182),
+            Period::Y1 => end - Duration::days(365),
+            Period::Ytd => NaiveDate::from_ymd_opt(end.year(), 1, 1).unwrap_or(end),
+            Period::All => NaiveDate::MIN,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Valuation {
+    pub date: NaiveDate,
+    pub total: f64,
+    pub cash: f64,
+    /// Market value per code.
+    pub positions: BTreeMap<String, f64>,
+}
+
+pub fn default_history_path(profile: Option<&str>) -> PathBuf {
+    config::data_dir().join(config::profiled("portfolio_history", "json", profile))
+}
+
+/// Recorded valuations, oldest first; empty if nothing was recorded yet.
+/// A file that can't be read or parsed is an error, so it isn't saved
+/// over.
+pub fn load_history(path: &Path) -> Result<Vec<Valuation>, Box<dyn std::error::Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+    };
+    serde_json::from_str(&text)
+        .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+}
+
+fn save_history(path: &Path, history: &[Valuation]) -> Result<(), Box<dyn std::error::Error>> {
+    config::write_atomic(path, &serde_json::to_string_pretty(history)?)?;
+    Ok(())
+}
+
+/// Add `valuation`, replacing any earlier one for the same day.
+fn record(history: &mut Vec<Valuation>, valuation: Valuation) {
+    history.retain(|v| v.date != valuation.date);
+    history.push(valuation);
+    history.sort_by_key(|v| v.date);
+}
+
+/// Value the configured holdings from one batched quote request. Codes
+/// without a trade yet today are priced at the previous close.
+pub async fn value_now(cfg: &Config) -> Result<Valuation, Box<dyn std::error::Error>> {
+    let holdings = &cfg.portfolio.holdings;
+    if holdings.is_empty() {
+        return Err("no holdings configured; add [portfolio.holdings] to biga.toml".into());
+    }
+    let codes: Vec<String> = holdings.keys().cloned().collect();
+    let Some(quotes) = http::until(http::deadline(&cfg.http), sina::fetch_quotes(&codes)).await else {
+        return Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn").into());
+    };
+    let quotes = quotes?;
+
+    let missing: Vec<&str> = codes.iter().filter(|c| !quotes.contains_key(*c)).map(String::as_str).collect();
+    if !missing.is_empty() {
+        return Err(format!("no quote for {}", missing.join(", ")).into());
+    }
+    let mut positions = BTreeMap::new();
+    let mut date = None;
+    for (code, &shares) in holdings {
+        let quote = &quotes[code];
+        let price = if quote.last > 0.0 { quote.last } else { quote.prev_close };
+        positions.insert(code.clone(), price * shares as f64);
+        date = date.max(quote.time.map(|t| t.date()));
+    }
+    let cash = cfg.portfolio.cash;
+    Ok(Valuation {
+        date: date.unwrap_or_else(|| schedule::now().date_naive()),
+        total: positions.values().sum::<f64>() + cash,
+        cash,
+        positions,
+    })
+}
+
+#[derive(Serialize)]
+struct HistoryRow {
+    date: NaiveDate,
+    total: f64,
+    /// Percent change since the first recorded valuation.
+    cumulative_return: f64,
+    /// Percent below the highest total recorded so far.
+    drawdown: f64,
+}
+
+fn history_rows(history: &[Valuation]) -> Vec<HistoryRow> {
+    let Some(first) = history.first().map(|v| v.total) else {
+        return Vec::new();
+    };
+    let mut peak = f64::NEG_INFINITY;
+    history
+        .iter()
+        .map(|v| {
+            peak = peak.max(v.total);
+            let pct = |from: f64| if from > 0.0 { (v.total - from) / from * 100.0 } else { 0.0 };
+            HistoryRow { date: v.date, total: v.total, cumulative_return: pct(first), drawdown: pct(peak) }
+        })
+        .collect()
+}
+
+#[derive(Serialize)]
+struct Contribution {
+    code: String,
+    /// The position's own return; `None` if it wasn't held at the start.
+    position_return: Option<f64>,
+    /// Percentage points of the portfolio's return that came from this position.
+    contribution: f64,
+}
+
+#[derive(Serialize)]
+struct Attribution {
+    period: Period,
+    start: NaiveDate,
+    end: NaiveDate,
+    portfolio_return: f64,
+    benchmark: String,
+    benchmark_return: Option<f64>,
+    positions: Vec<Contribution>,
+}
+
+/// The last close on or before `date`.
+fn close_on(candles: &[sina::Candle], date: NaiveDate) -> Option<f64> {
+    candles.iter().rev().find(|c| c.date() <= date).map(|c| c.close)
+}
+
+/// Break the recorded history down by period. A period reaching back before
+/// the first valuation starts at the first valuation instead.
+fn attribute(history: &[Valuation], periods: &[Period], benchmark: &str, bench: &[sina::Candle]) -> Vec<Attribution> {
+    let Some(last) = history.last() else {
+        return Vec::new();
+    };
+    let pct = |from: f64, to: f64| if from > 0.0 { (to - from) / from * 100.0 } else { 0.0 };
+    periods
+        .iter()
+        .map(|&period| {
+            let target = period.start(last.date);
+            let first = history.iter().rev().find(|v| v.date <= target).unwrap_or(&history[0]);
+            let codes: BTreeSet<&String> = first.positions.keys().chain(last.positions.keys()).collect();
+            let mut positions: Vec<Contribution> = codes
+                .into_iter()
+                .map(|code| {
+                    let from = first.positions.get(code).copied().unwrap_or(0.0);
+                    let to = last.positions.get(code).copied().unwrap_or(0.0);
+                    Contribution {
+                        code: code.clone(),
+                        position_return: (from > 0.0).then(|| pct(from, to)),
+                        contribution: if first.total > 0.0 { (to - from) / first.total * 100.0 } else { 0.0 },
+                    }
+                })
+                .collect();
+            // Biggest drag first.
+            positions.sort_by(|a, b| a.contribution.partial_cmp(&b.contribution).unwrap());
+            let benchmark_return = match (close_on(bench, first.date), close_on(bench, last.date)) {
+                (Some(from), Some(to)) => Some(pct(from, to)),
+                _ => None,
+            };
+            Attribution {
+                period,
+                start: first.date,
+                end: last.date,
+                portfolio_return: pct(first.total, last.total),
+                benchmark: benchmark.to_string(),
+                benchmark_return,
+                positions,
+            }
+        })
+        .collect()
+}
+
+fn print_attribution(report: &[Attribution]) {
+    if report.is_empty() {
+        println!("{}", tr!("No valuations recorded yet; run `portfolio value`", "尚无记录, 请先运行 `portfolio value`"));
+        return;
+    }
+    let na = || "n/a".to_string();
+    for a in report {
+        println!("\n {}", tr!("Attribution {} ({} to {}):", "收益归因 {} ({} 至 {}):", a.period.label(), a.start, a.end));
+        println!("-----------------------------------------");
+        let excess = a.benchmark_return.map_or_else(na, |b| format!("{:+.2}%", a.portfolio_return - b));
+        println!(
+            "{}",
+            tr!(
+                "Portfolio: {:+.2}% | Benchmark {}: {} | Excess: {}",
+                "组合: {:+.2}% | 基准 {}: {} | 超额: {}",
+                a.portfolio_return,
+                a.benchmark,
+                a.benchmark_return.map_or_else(na, |b| format!("{:+.2}%", b)),
+                excess
+            )
+        );
+        for p in &a.positions {
+            println!(
+                "{}",
+                tr!(
+                    "Code: {} | Return: {} | Contribution: {:+.2}pp",
+                    "代码: {} | 收益: {} | 贡献: {:+.2} 个百分点",
+                    p.code,
+                    p.position_return.map_or_else(na, |r| format!("{:+.2}%", r)),
+                    p.contribution
+                )
+            );
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Projection {
+    months: usize,
+    /// `(percentile, total value)` pairs for `BANDS`.
+    bands: Vec<(f64, f64)>,
+    /// Share of paths ending below today's value, in percent.
+    loss_probability: f64,
+}
+
+#[derive(Serialize)]
+struct Simulation {
+    start_value: f64,
+    history_days: usize,
+    paths: usize,
+    projections: Vec<Projection>,
+}
+
+async fn simulate(args: &SimulateArgs, cfg: &Config) -> Result<Simulation, Box<dyn std::error::Error>> {
+    let holdings = &cfg.portfolio.holdings;
+    if holdings.is_empty() {
+        return Err("no holdings configured; add [portfolio.holdings] to biga.toml".into());
+    }
+    if args.paths == 0 || args.horizon.contains(&0) {
+        return Err("--paths and --horizon must be positive".into());
+    }
+    let lookback = args.lookback.unwrap_or(cfg.indicators.history_days);
+
+    // Daily closes per code, kept only for days every holding traded.
+    let mut by_code: Vec<HashMap<NaiveDate, f64>> = Vec::new();
+    for code in holdings.keys() {
+        let (candles, status) = sina::fetch_etf_kline(code, lookback + 1).await?;
+        if let Some(status) = status
+            && status != 200
+        {
+            return Err(format!("HTTP {} for code: {}", status, code).into());
+        }
+        by_code.push(candles.iter().map(|c| (c.date(), c.close)).collect());
+    }
+    let mut days: Vec<NaiveDate> = by_code[0].keys().filter(|d| by_code.iter().all(|m| m.contains_key(d))).copied().collect();
+    days.sort();
+    if days.len() < 2 * TRADING_DAYS_PER_MONTH {
+        return Err(format!("Not enough overlapping history for the holdings: got {} days", days.len()).into());
+    }
+    let closes: Vec<Vec<f64>> = days.iter().map(|d| by_code.iter().map(|m| m[d]).collect()).collect();
+
+    let (mean, cov) = montecarlo::mean_cov(&montecarlo::log_returns(&closes));
+    let chol = montecarlo::cholesky(&cov)
+        .ok_or("return covariance is singular; two holdings may move identically, drop one of them")?;
+    let last = &closes[closes.len() - 1];
+    let values: Vec<f64> = holdings.values().zip(last).map(|(&shares, close)| shares as f64 * close).collect();
+    let start_value = values.iter().sum::<f64>() + cfg.portfolio.cash;
+
+    let mut months = args.horizon.clone();
+    months.sort();
+    months.dedup();
+    let horizons: Vec<usize> = months.iter().map(|m| m * TRADING_DAYS_PER_MONTH).collect();
+    let seed = args.seed.unwrap_or_else(crate::cooldown::now_secs);
+    let outcomes = montecarlo::simulate(&values, cfg.portfolio.cash, &mean, &chol, &horizons, args.paths, seed);
+
+    let projections = months
+        .iter()
+        .zip(outcomes)
+        .map(|(&months, mut totals)| {
+            let losses = totals.iter().filter(|&&v| v < start_value).count();
+            Projection {
+                months,
+                bands: BANDS.iter().map(|&p| (p, montecarlo::percentile(&mut totals, p))).collect(),
+                loss_probability: losses as f64 / totals.len() as f64 * 100.0,
+            }
+        })
+        .collect();
+    Ok(Simulation { start_value, history_days: days.len(), paths: args.paths, projections })
+}
+
+fn print_simulation(sim: &Simulation) {
+    println!(
+        "\n {}",
+        tr!(
+            "Monte Carlo projection ({} paths, {} days of history):",
+            "蒙特卡洛模拟 ({} 条路径, {} 日历史):",
+            sim.paths,
+            sim.history_days
+        )
+    );
+    println!("-----------------------------------------");
+    println!("{}", tr!("Start value: {:.2}", "起始市值: {:.2}", sim.start_value));
+    for p in &sim.projections {
+        let bands: Vec<String> = p.bands.iter().map(|(pct, v)| format!("P{:.0}: {:.2}", pct, v)).collect();
+        println!(
+            "{}",
+            tr!(
+                "{}m | {} | P(loss): {:.1}%",
+                "{} 个月 | {} | 亏损概率: {:.1}%",
+                p.months,
+                bands.join(" | "),
+                p.loss_probability
+            )
+        );
+    }
+}
+
+fn print_value(cfg: &Config, valuation: &Valuation) {
+    println!("\n {}", tr!("Portfolio value on {}:", "{} 组合市值:", valuation.date));
+    println!("-----------------------------------------");
+    for (code, value) in &valuation.positions {
+        let weight = if valuation.total > 0.0 { value / valuation.total * 100.0 } else { 0.0 };
+        println!(
+            "{}",
+            tr!(
+                "Code: {} | Shares: {} | Value: {:.2} | Weight: {:.1}%",
+                "代码: {} | 持仓: {} | 市值: {:.2} | 权重: {:.1}%",
+                code,
+                cfg.portfolio.holdings[code],
+                value,
+                weight
+            )
+        );
+    }
+    println!("{}", tr!("Cash: {:.2} | Total: {:.2}", "现金: {:.2} | 总计: {:.2}", valuation.cash, valuation.total));
+}
+
+fn print_history(rows: &[HistoryRow]) {
+    println!("\n {}", tr!("Portfolio history:", "组合历史:"));
+    println!("-----------------------------------------");
+    if rows.is_empty() {
+        println!("{}", tr!("No valuations recorded yet; run `portfolio value`", "尚无记录, 请先运行 `portfolio value`"));
+        return;
+    }
+    let low = rows.iter().map(|r| r.total).fold(f64::INFINITY, f64::min);
+    let high = rows.iter().map(|r| r.total).fold(f64::NEG_INFINITY, f64::max);
+    for row in rows {
+        let filled =
+            if high > low { ((row.total - low) / (high - low) * CHART_WIDTH as f64).round() as usize } else { CHART_WIDTH };
+        println!(
+            "{}",
+            tr!(
+                "{} | Total: {:.2} | Return: {:+.2}% | Drawdown: {:.2}% | {}",
+                "{} | 总计: {:.2} | 收益: {:+.2}% | 回撤: {:.2}% | {}",
+                row.date,
+                row.total,
+                row.cumulative_return,
+                row.drawdown,
+                "█".repeat(filled.max(1))
+            )
+        );
+    }
+}
+
+pub async fn run(args: &PortfolioArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = args.history_file.clone().unwrap_or_else(|| default_history_path(cfg.profile.as_deref()));
+    match &args.command {
+        PortfolioCommand::Value => {
+            let valuation = value_now(cfg).await?;
+            let mut history = load_history(&path)?;
+            record(&mut history, valuation.clone());
+            save_history(&path, &history)?;
+            if cfg.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&valuation)?);
+            } else {
+                print_value(cfg, &valuation);
+            }
+        }
+        PortfolioCommand::History => {
+            let rows = history_rows(&load_history(&path)?);
+            if cfg.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            } else {
+                print_history(&rows);
+            }
+        }
+        PortfolioCommand::Attribution(attr) => {
+            let history = load_history(&path)?;
+            let benchmark = attr.benchmark.as_deref().unwrap_or(&cfg.portfolio.benchmark);
+            let bench = match history.first() {
+                Some(first) => {
+                    // Calendar days since the first valuation cover at least that many bars.
+                    let days = (schedule::now().date_naive() - first.date).num_days().max(0) as usize + 10;
+                    let (candles, status) = sina::fetch_etf_kline(benchmark, days).await?;
+                    if let Some(status) = status
+                        && status != 200
+                    {
+                        return Err(format!("HTTP {} for code: {}", status, benchmark).into());
+                    }
+                    candles
+                }
+                None => Vec::new(),
+            };
+            let report = attribute(&history, &attr.period, benchmark, &bench);
+            if cfg.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                print_attribution(&report);
+            }
+        }
+        PortfolioCommand::Simulate(sim) => {
+            let sim = simulate(sim, cfg).await?;
+            if cfg.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&sim)?);
+            } else {
+                print_simulation(&sim);
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,168 @@
+//! Every run's klines, metrics and signals upserted into Postgres, for an
+//! existing warehouse and the BI dashboards on top of it. Built with
+//! `--features postgres` and turned on by a `[postgres]` table.
+//!
+//! The tables are created if missing and keyed so reruns and watch passes
+//! overwrite rather than duplicate:
+//!
+//! - klines: one row per code and date, the daily bars each code was
+//!   screened on;
+//! - metrics: one row per date, code and metric, every number a report row
+//!   carries, scripts' and plugins' included;
+//! - signals: one row per date, code and rule, the alerts raised, with the
+//!   same rule names and kinds as the event log.
+
+use std::collections::BTreeMap;
+
+use crate::config::Config;
+use crate::decline::Report;
+use crate::sina::Candle;
+
+/// Whether this build can write to Postgres.
+pub const AVAILABLE: bool = cfg!(feature = "postgres");
+
+/// Upsert `report` and the `bars` behind it, if a warehouse is configured.
+/// Failures are reported but don't fail the run.
+pub async fn write(cfg: &Config, report: &Report, bars: &BTreeMap<String, Vec<Candle>>) {
+    #[cfg(feature = "postgres")]
+    if let Some(pg) = &cfg.postgres
+        && let Err(e) = sink::upsert(pg, report, bars).await
+    {
+        eprintln!("{}", crate::i18n::tr!("Failed to write to Postgres: {}", "写入 Postgres 失败: {}", e));
+    }
+    #[cfg(not(feature = "postgres"))]
+    let _ = (cfg, report, bars);
+}
+
+#[cfg(feature = "postgres")]
+mod sink {
+    use std::collections::BTreeMap;
+
+    use chrono::NaiveDate;
+    use postgres_native_tls::MakeTlsConnector;
+
+    use crate::config::PostgresConfig;
+    use crate::decline::Report;
+    use crate::i18n::tr;
+    use crate::sina::Candle;
+
+    /// Table names go into the SQL as written, so they are held to plain,
+    /// optionally schema-qualified identifiers.
+    fn table(name: &str) -> Result<&str, Box<dyn std::error::Error>> {
+        let plain = |part: &str| {
+            part.chars().next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        };
+        match name.split('.').collect::<Vec<_>>()[..] {
+            [table] | [_, table] if name.split('.').all(plain) && !table.is_empty() => Ok(name),
+            _ => Err(format!("'{}' isn't a table name like biga_klines or warehouse.biga_klines", name).into()),
+        }
+    }
+
+    pub async fn upsert(
+        pg: &PostgresConfig,
+        report: &Report,
+        bars: &BTreeMap<String, Vec<Candle>>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (klines, metrics, signals) = (table(&pg.klines_table)?, table(&pg.metrics_table)?, table(&pg.signals_table)?);
+        let tls = MakeTlsConnector::new(native_tls::TlsConnector::new()?);
+        let (mut client, connection) = tokio_postgres::connect(&pg.url, tls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                eprintln!("{}", tr!("Postgres connection failed: {}", "Postgres 连接失败: {}", e));
+            }
+        });
+
+        client
+            .batch_execute(&format!(
+                "CREATE TABLE IF NOT EXISTS {klines} (
+                     code TEXT NOT NULL,
+                     date DATE NOT NULL,
+                     open DOUBLE PRECISION NOT NULL,
+                     high DOUBLE PRECISION NOT NULL,
+                     low DOUBLE PRECISION NOT NULL,
+                     close DOUBLE PRECISION NOT NULL,
+                     volume DOUBLE PRECISION NOT NULL,
+                     PRIMARY KEY (code, date)
+                 );
+                 CREATE TABLE IF NOT EXISTS {metrics} (
+                     date DATE NOT NULL,
+                     code TEXT NOT NULL,
+                     metric TEXT NOT NULL,
+                     value DOUBLE PRECISION NOT NULL,
+                     generated_at TIMESTAMPTZ NOT NULL,
+                     PRIMARY KEY (date, code, metric)
+                 );
+                 CREATE TABLE IF NOT EXISTS {signals} (
+                     date DATE NOT NULL,
+                     code TEXT NOT NULL,
+                     rule TEXT NOT NULL,
+                     value DOUBLE PRECISION NOT NULL,
+                     threshold DOUBLE PRECISION NOT NULL,
+                     kind TEXT NOT NULL,
+                     generated_at TIMESTAMPTZ NOT NULL,
+                     PRIMARY KEY (date, code, rule)
+                 );"
+            ))
+            .await?;
+
+        let tx = client.transaction().await?;
+        // Bars already stored unchanged are left alone, so repeated passes
+        // over the same history don't rewrite it.
+        let kline_sql = format!(
+            "INSERT INTO {klines} AS k (code, date, open, high, low, close, volume)
+             SELECT $1, * FROM UNNEST($2::date[], $3::float8[], $4::float8[], $5::float8[], $6::float8[], $7::float8[])
+             ON CONFLICT (code, date) DO UPDATE SET
+                 open = EXCLUDED.open, high = EXCLUDED.high, low = EXCLUDED.low,
+                 close = EXCLUDED.close, volume = EXCLUDED.volume
+             WHERE (k.open, k.high, k.low, k.close, k.volume)
+                 IS DISTINCT FROM (EXCLUDED.open, EXCLUDED.high, EXCLUDED.low, EXCLUDED.close, EXCLUDED.volume)"
+        );
+        for (code, candles) in bars {
+            let column = |f: fn(&Candle) -> f64| candles.iter().map(f).collect::<Vec<f64>>();
+            let dates: Vec<NaiveDate> = candles.iter().map(Candle::date).collect();
+            let (open, high, low) = (column(|c| c.open), column(|c| c.high), column(|c| c.low));
+            let (close, volume) = (column(|c| c.close), column(|c| c.volume));
+            tx.execute(&kline_sql, &[code, &dates, &open, &high, &low, &close, &volume]).await?;
+        }
+
+        let metric_sql = format!(
+            "INSERT INTO {metrics} (date, code, metric, value, generated_at)
+             SELECT $1, $2, * , $5 FROM UNNEST($3::text[], $4::float8[])
+             ON CONFLICT (date, code, metric) DO UPDATE SET
+                 value = EXCLUDED.value, generated_at = EXCLUDED.generated_at"
+        );
+        for row in &report.rows {
+            let (names, values): (Vec<&str>, Vec<f64>) = row.values().filter(|(_, v)| v.is_finite()).unzip();
+            tx.execute(&metric_sql, &[&row.date(), &row.code(), &names, &values, &report.generated_at]).await?;
+        }
+
+        if let Some(date) = report.as_of {
+            let signal_sql = format!(
+                "INSERT INTO {signals} (date, code, rule, value, threshold, kind, generated_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7)
+                 ON CONFLICT (date, code, rule) DO UPDATE SET
+                     value = EXCLUDED.value, threshold = EXCLUDED.threshold,
+                     kind = EXCLUDED.kind, generated_at = EXCLUDED.generated_at"
+            );
+            for alert in &report.alerts {
+                let kind = serde_json::to_string(&alert.kind)?;
+                tx.execute(
+                    &signal_sql,
+                    &[
+                        &date,
+                        &alert.code,
+                        &alert.kind.metric(),
+                        &alert.kind.value(),
+                        &alert.kind.threshold(),
+                        &kind,
+                        &report.generated_at,
+                    ],
+                )
+                .await?;
+            }
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+}
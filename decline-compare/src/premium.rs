@@ -0,0 +1,241 @@
+//! Premium or discount of cross-border (QDII) ETFs, LOFs and closed-end
+//! funds to their NAV, recorded daily so today's reading can be put against
+//! the past year's.
+//!
+//! Each `premium` run records one reading per code per day (a later run the
+//! same day replaces it), so running it after the close from cron builds
+//! the history. QDII NAVs are published a day late, so the reading compares
+//! the price with the latest NAV that is out.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDate;
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::instruments;
+use crate::schedule;
+use crate::sina;
+use crate::symbols;
+
+const HISTORY_FILE: &str = "premium_history.json";
+
+/// Width of the premium bars in text output.
+const CHART_WIDTH: usize = 30;
+
+#[derive(Args, Debug)]
+pub struct PremiumArgs {
+    /// Codes to track; defaults to the cross-border ETFs, LOFs and closed-end
+    /// funds in the watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Readings the percentile is taken over, about a year of trading days
+    #[arg(long, default_value_t = 250)]
+    lookback: usize,
+
+    /// Most recent readings to tabulate per code
+    #[arg(long, default_value_t = 20)]
+    rows: usize,
+
+    /// Show the recorded history without recording today's reading
+    #[arg(long)]
+    no_record: bool,
+
+    /// Where readings are recorded [default: $STOCK_DATA_DIR/premium_history.json]
+    #[arg(long)]
+    history_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Reading {
+    pub date: NaiveDate,
+    pub price: f64,
+    pub nav: f64,
+    /// Price over NAV, percent; negative is a discount.
+    pub premium: f64,
+}
+
+/// Readings per code, oldest first.
+type History = BTreeMap<String, Vec<Reading>>;
+
+#[derive(Serialize)]
+struct PremiumReport {
+    code: String,
+    current: Option<Reading>,
+    /// Share of the earlier readings in the lookback below the current one,
+    /// percent.
+    percentile: Option<f64>,
+    low: Option<f64>,
+    high: Option<f64>,
+    mean: Option<f64>,
+    /// Readings the statistics cover, including the current one.
+    samples: usize,
+    recent: Vec<Reading>,
+}
+
+fn default_history_path() -> PathBuf {
+    config::data_dir().join(HISTORY_FILE)
+}
+
+fn load_history(path: &Path) -> Result<History, Box<dyn std::error::Error>> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(History::default()),
+        Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+    };
+    serde_json::from_str(&text)
+        .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+}
+
+fn save_history(path: &Path, history: &History) -> Result<(), Box<dyn std::error::Error>> {
+    config::write_atomic(path, &serde_json::to_string_pretty(history)?)?;
+    Ok(())
+}
+
+/// Add `reading`, replacing any earlier one for the same day.
+fn record(readings: &mut Vec<Reading>, reading: Reading) {
+    readings.retain(|r| r.date != reading.date);
+    readings.push(reading);
+    readings.sort_by_key(|r| r.date);
+}
+
+/// Today's reading per code from one batched quote request and one NAV
+/// request. Codes without a trade yet today are priced at the previous close.
+async fn read_now(codes: &[String]) -> Result<BTreeMap<String, Reading>, Box<dyn std::error::Error>> {
+    let quotes = sina::fetch_quotes(codes).await?;
+    let navs = sina::fetch_navs(codes).await?;
+    let today = schedule::now().date_naive();
+    let mut readings = BTreeMap::new();
+    for code in codes {
+        let (Some(quote), Some(&nav)) = (quotes.get(code), navs.get(code)) else {
+            continue;
+        };
+        let price = if quote.last > 0.0 { quote.last } else { quote.prev_close };
+        if price <= 0.0 {
+            continue;
+        }
+        let date = quote.time.map_or(today, |t| t.date());
+        readings.insert(code.clone(), Reading { date, price, nav, premium: (price - nav) / nav * 100.0 });
+    }
+    Ok(readings)
+}
+
+fn report(code: &str, readings: &[Reading], lookback: usize, rows: usize) -> PremiumReport {
+    let window = &readings[readings.len().saturating_sub(lookback)..];
+    let current = window.last().cloned();
+    let premiums: Vec<f64> = window.iter().map(|r| r.premium).collect();
+    let percentile = current.as_ref().and_then(|now| {
+        let earlier = &premiums[..premiums.len() - 1];
+        (!earlier.is_empty())
+            .then(|| earlier.iter().filter(|&&p| p < now.premium).count() as f64 / earlier.len() as f64 * 100.0)
+    });
+    PremiumReport {
+        code: code.to_string(),
+        current,
+        percentile,
+        low: premiums.iter().copied().reduce(f64::min),
+        high: premiums.iter().copied().reduce(f64::max),
+        mean: (!premiums.is_empty()).then(|| premiums.iter().sum::<f64>() / premiums.len() as f64),
+        samples: premiums.len(),
+        recent: readings[readings.len().saturating_sub(rows)..].to_vec(),
+    }
+}
+
+fn print_report(report: &PremiumReport) {
+    println!("\n {}", tr!("Premium to NAV, {}:", "{} 溢价率:", report.code));
+    println!("-----------------------------------------");
+    let Some(current) = &report.current else {
+        println!("{}", tr!("No readings recorded yet", "尚无记录"));
+        return;
+    };
+    match report.percentile {
+        Some(pctile) => println!(
+            "{}",
+            tr!(
+                "Current premium {:+.2}% is higher than {:.0}% of the past {} readings",
+                "当前溢价率 {:+.2}% 高于过去 {2} 次记录中的 {1:.0}%",
+                current.premium,
+                pctile,
+                report.samples - 1
+            )
+        ),
+        None => println!(
+            "{}",
+            tr!("Current premium {:+.2}%; no earlier readings yet", "当前溢价率 {:+.2}%, 尚无更早记录", current.premium)
+        ),
+    }
+    if let (Some(low), Some(high), Some(mean)) = (report.low, report.high, report.mean) {
+        println!(
+            "{}",
+            tr!("Range: {:+.2}% to {:+.2}% | Mean: {:+.2}%", "区间: {:+.2}% 至 {:+.2}% | 均值: {:+.2}%", low, high, mean)
+        );
+    }
+
+    // Bars start from the lowest reading shown, so a discount and a
+    // premium differ in length rather than direction.
+    let low = report.recent.iter().map(|r| r.premium).fold(f64::INFINITY, f64::min);
+    let high = report.recent.iter().map(|r| r.premium).fold(f64::NEG_INFINITY, f64::max);
+    for r in &report.recent {
+        let filled =
+            if high > low { ((r.premium - low) / (high - low) * CHART_WIDTH as f64).round() as usize } else { CHART_WIDTH };
+        println!(
+            "{}",
+            tr!(
+                "{} | Price: {:.3} | NAV: {:.4} | Premium: {:+.2}% | {}",
+                "{} | 价格: {:.3} | 净值: {:.4} | 溢价率: {:+.2}% | {}",
+                r.date,
+                r.price,
+                r.nav,
+                r.premium,
+                "█".repeat(filled.max(1))
+            )
+        );
+    }
+}
+
+pub async fn run(args: &PremiumArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes: Vec<String> = if args.codes.is_empty() {
+        cfg.watchlist
+            .iter()
+            .filter(|c| instruments::is_cross_border(c) || instruments::kind(c, cfg).trades_off_nav())
+            .cloned()
+            .collect()
+    } else {
+        args.codes.clone()
+    };
+    let path = args.history_file.clone().unwrap_or_else(default_history_path);
+    let mut history = load_history(&path)?;
+
+    if !args.no_record && !codes.is_empty() {
+        let Some(readings) = http::until(http::deadline(&cfg.http), read_now(&codes)).await else {
+            return Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn").into());
+        };
+        for (code, reading) in readings? {
+            record(history.entry(code).or_default(), reading);
+        }
+        save_history(&path, &history)?;
+    }
+
+    let reports: Vec<PremiumReport> = codes
+        .iter()
+        .map(|code| report(code, history.get(code).map_or(&[][..], Vec::as_slice), args.lookback, args.rows))
+        .collect();
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+    if reports.is_empty() {
+        println!("{}", tr!("No cross-border ETFs or listed funds in the watchlist", "自选列表中没有跨境 ETF 或上市基金"));
+    }
+    for report in &reports {
+        print_report(report);
+    }
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Signals and quote updates published to Redis as they are computed, for
+//! in-house services (order routers, dashboards) that react in real time.
+//! Built with `--features redis` and turned on by a `[redis]` table.
+//!
+//! Each message is one JSON object, tagged by `type`:
+//!
+//! - `signal`: an alert, sent once per code, rule and bar date, when the
+//!   event log first records it;
+//! - `quote`: a report row's latest close and decline, sent every run and
+//!   every watch pass.
+//!
+//! Messages are PUBLISHed to the channel, and also XADDed to the stream
+//! (as its `data` field) when one is set.
+
+use crate::alerts::Alert;
+use crate::config::Config;
+use crate::decline::Report;
+
+/// Whether this build can publish to Redis.
+pub const AVAILABLE: bool = cfg!(feature = "redis");
+
+/// Publish the `fresh` alerts and `report`'s rows, if Redis is configured.
+/// Failures are reported but don't fail the run.
+pub async fn publish(cfg: &Config, report: &Report, fresh: &[Alert]) {
+    #[cfg(feature = "redis")]
+    if let Some(redis) = &cfg.redis
+        && let Err(e) = sink::publish(redis, report, fresh).await
+    {
+        eprintln!("{}", crate::i18n::tr!("Failed to publish to Redis: {}", "发布到 Redis 失败: {}", e));
+    }
+    #[cfg(not(feature = "redis"))]
+    let _ = (cfg, report, fresh);
+}
+
+#[cfg(feature = "redis")]
+mod sink {
+    use chrono::{DateTime, FixedOffset, NaiveDate};
+    use redis::streams::StreamMaxlen;
+    use serde::Serialize;
+
+    use crate::alerts::{Alert, AlertKind};
+    use crate::config::RedisConfig;
+    use crate::decline::Report;
+    use crate::schedule::BarSession;
+
+    #[derive(Serialize)]
+    #[serde(tag = "type", rename_all = "lowercase")]
+    enum Message<'a> {
+        Signal {
+            code: &'a str,
+            date: Option<NaiveDate>,
+            rule: String,
+            value: f64,
+            threshold: f64,
+            kind: &'a AlertKind,
+            generated_at: DateTime<FixedOffset>,
+        },
+        Quote {
+            code: &'a str,
+            date: NaiveDate,
+            close: Option<f64>,
+            /// Percent change over the screen's window.
+            decline: Option<f64>,
+            session: Option<BarSession>,
+            generated_at: DateTime<FixedOffset>,
+        },
+    }
+
+    pub async fn publish(redis: &RedisConfig, report: &Report, fresh: &[Alert]) -> Result<(), Box<dyn std::error::Error>> {
+        let signals = fresh.iter().map(|alert| Message::Signal {
+            code: &alert.code,
+            date: report.as_of,
+            rule: alert.kind.metric(),
+            value: alert.kind.value(),
+            threshold: alert.kind.threshold(),
+            kind: &alert.kind,
+            generated_at: report.generated_at,
+        });
+        let quotes = report.rows.iter().map(|row| {
+            let value = |name: &str| row.values().find(|(k, _)| *k == name).map(|(_, v)| v);
+            Message::Quote {
+                code: row.code(),
+                date: row.date(),
+                close: value("close"),
+                decline: value("decline"),
+                session: report.session,
+                generated_at: report.generated_at,
+            }
+        });
+        let messages = signals.chain(quotes).map(|m| serde_json::to_string(&m)).collect::<Result<Vec<_>, _>>()?;
+        if messages.is_empty() {
+            return Ok(());
+        }
+
+        let client = redis::Client::open(redis.url.as_str())?;
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        let mut pipe = redis::pipe();
+        for message in &messages {
+            pipe.publish(&redis.channel, message).ignore();
+            if let Some(stream) = &redis.stream {
+                pipe.xadd_maxlen(stream, StreamMaxlen::Approx(redis.stream_len), "*", &[("data", message)]).ignore();
+            }
+        }
+        let () = pipe.query_async(&mut conn).await?;
+        Ok(())
+    }
+}
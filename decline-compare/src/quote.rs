@@ -0,0 +1,87 @@
+use clap::Args;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::sina::{self, Quote};
+use crate::symbols;
+
+#[derive(Args, Debug)]
+pub struct QuoteArgs {
+    /// Codes to quote; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct QuoteRow {
+    code: String,
+    #[serde(flatten)]
+    quote: Quote,
+    change_pct: Option<f64>,
+}
+
+/// Realtime quotes for the watchlist, fetched in batched requests rather
+/// than one per code.
+pub async fn run(args: &QuoteArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let Some(quotes) = http::until(http::deadline(&cfg.http), sina::fetch_quotes(&codes)).await else {
+        return Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn").into());
+    };
+    let mut quotes = quotes?;
+
+    let mut rows: Vec<QuoteRow> = codes
+        .iter()
+        .filter_map(|code| {
+            let quote = quotes.remove(code)?;
+            Some(QuoteRow { code: code.clone(), change_pct: quote.change_pct(), quote })
+        })
+        .collect();
+    // Worst first, like the decline report; codes without a trade yet go last.
+    rows.sort_by(|a, b| match (a.change_pct, b.change_pct) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap(),
+        (Some(_), None) => std::cmp::Ordering::Less,
+        (None, Some(_)) => std::cmp::Ordering::Greater,
+        (None, None) => std::cmp::Ordering::Equal,
+    });
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    println!(
+        "\n {}",
+        tr!(
+            "ETF quotes ({} codes in {} request(s)):",
+            "ETF 实时行情 ({} 个代码, {} 次请求):",
+            codes.len(),
+            sina::quote_requests(codes.len())
+        )
+    );
+    println!("-----------------------------------------");
+    if rows.is_empty() {
+        println!("{}", tr!("No ETF data", "无 ETF 数据"));
+    }
+    for row in &rows {
+        let q = &row.quote;
+        let change = row.change_pct.map_or_else(|| tr!("no trades yet", "尚未成交"), |c| format!("{:+.2}%", c));
+        let time = q.time.map_or_else(|| "n/a".to_string(), |t| t.format("%H:%M:%S").to_string());
+        println!(
+            "{}",
+            tr!(
+                "Code: {} {} | Last: {:.3} | Change: {} | High: {:.3} | Low: {:.3} | Time: {}",
+                "代码: {} {} | 最新: {:.3} | 涨跌幅: {} | 最高: {:.3} | 最低: {:.3} | 时间: {}",
+                row.code,
+                q.name,
+                q.last,
+                change,
+                q.high,
+                q.low,
+                time
+            )
+        );
+    }
+    Ok(())
+}
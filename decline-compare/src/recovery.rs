@@ -0,0 +1,75 @@
+//! What happened after past declines of similar size to the current one.
+
+use serde::Serialize;
+
+pub const FORWARD_DAYS: usize = 20;
+
+/// A past decline counts as similar when its return is within this fraction
+/// of the current one (0.25 = 75%..125% of the current decline).
+pub const SIMILARITY_TOLERANCE: f64 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RecoveryStats {
+    /// Number of non-overlapping past declines of similar magnitude.
+    pub instances: usize,
+    /// How many of them regained the pre-decline close within the history.
+    pub recovered: usize,
+    /// Median bars from the end of the decline back to the pre-decline close.
+    pub median_days: Option<f64>,
+    /// Median return over the `FORWARD_DAYS` bars after the decline ended.
+    pub median_forward: Option<f64>,
+}
+
+fn median(values: &mut [f64]) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) { (values[mid - 1] + values[mid]) / 2.0 } else { values[mid] })
+}
+
+/// Scan `closes` for earlier windows of `span` closes whose return is within
+/// `SIMILARITY_TOLERANCE` of `current` (a negative percentage), skipping the
+/// current window itself.
+pub fn similar_declines(closes: &[f64], span: usize, current: f64) -> Option<RecoveryStats> {
+    if span < 2 || current >= 0.0 || closes.len() < 2 * span {
+        return None;
+    }
+    let lo = current * (1.0 + SIMILARITY_TOLERANCE);
+    let hi = current * (1.0 - SIMILARITY_TOLERANCE);
+    // Windows must end before the current window starts.
+    let last_end = closes.len() - span;
+
+    let mut days = Vec::new();
+    let mut forwards = Vec::new();
+    let mut instances = 0;
+    let mut end = span - 1;
+    while end < last_end {
+        let start_price = closes[end + 1 - span];
+        let end_price = closes[end];
+        let ret = if start_price > 0.0 { (end_price - start_price) / start_price * 100.0 } else { 0.0 };
+        if ret < 0.0 && ret >= lo && ret <= hi {
+            instances += 1;
+            if let Some(k) = closes[end + 1..].iter().position(|&c| c >= start_price) {
+                days.push((k + 1) as f64);
+            }
+            if let Some(&fwd) = closes.get(end + FORWARD_DAYS)
+                && end_price > 0.0
+            {
+                forwards.push((fwd - end_price) / end_price * 100.0);
+            }
+            // Skip ahead so one long slide isn't counted many times.
+            end += span;
+        } else {
+            end += 1;
+        }
+    }
+
+    Some(RecoveryStats {
+        instances,
+        recovered: days.len(),
+        median_days: median(&mut days),
+        median_forward: median(&mut forwards),
+    })
+}
@@ -0,0 +1,144 @@
+//! Data provider responses saved to disk and played back, to capture a
+//! format change or a user's failing run and keep it as a regression
+//! fixture.
+//!
+//! `--record DIR` saves the body of every GET to a provider as
+//! `DIR/NNNN-HOST.txt`, as it arrived, listed with its URL and HTTP status
+//! in `DIR/index.json`. `--replay DIR` then answers the same requests from
+//! there and fails the ones the recording doesn't have, without touching
+//! the network. Under replay the exchange clock stands where the recording
+//! started, so the run comes out the same each time. A recorded Sina kline
+//! body is also a `--fixture` file as it stands.
+//!
+//! Neither mode reads or writes the kline cache, so every code's bars are
+//! requested, and recorded, on each run. A replay is also a dry run: the
+//! event log, report archive, export sheets, Postgres, Redis and MQTT are
+//! left alone and `watch` only previews its notifications. A recording
+//! runs them as usual.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use chrono::{DateTime, FixedOffset, Utc};
+use chrono_tz::Asia::Shanghai;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::http::Response;
+use crate::i18n::tr;
+
+const INDEX_FILE: &str = "index.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Index {
+    /// When recording began, in exchange time.
+    started: Option<DateTime<FixedOffset>>,
+    /// Each URL's latest response.
+    responses: BTreeMap<String, Entry>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Entry {
+    status: u16,
+    /// Body file, relative to the recording directory.
+    file: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+    Record,
+    Replay,
+}
+
+struct Tape {
+    mode: Mode,
+    dir: PathBuf,
+    index: Mutex<Index>,
+}
+
+static TAPE: OnceLock<Tape> = OnceLock::new();
+
+fn read_index(dir: &Path) -> Result<Index, Box<dyn std::error::Error>> {
+    let path = dir.join(INDEX_FILE);
+    let text =
+        fs::read_to_string(&path).map_err(|e| tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e))?;
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// Save provider responses to `dir` from now on, adding to a recording
+/// already there.
+pub fn record(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(dir)?;
+    let mut index = if dir.join(INDEX_FILE).exists() { read_index(dir)? } else { Index::default() };
+    index.started.get_or_insert_with(|| Utc::now().with_timezone(&Shanghai).fixed_offset());
+    let _ = TAPE.set(Tape { mode: Mode::Record, dir: dir.to_path_buf(), index: Mutex::new(index) });
+    Ok(())
+}
+
+/// Answer provider requests from the recording in `dir` from now on.
+pub fn replay(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let index = read_index(dir)?;
+    let _ = TAPE.set(Tape { mode: Mode::Replay, dir: dir.to_path_buf(), index: Mutex::new(index) });
+    Ok(())
+}
+
+/// Whether responses are being recorded or replayed.
+pub fn active() -> bool {
+    TAPE.get().is_some()
+}
+
+/// Whether provider requests are answered from a recording.
+pub fn replaying() -> bool {
+    TAPE.get().is_some_and(|t| t.mode == Mode::Replay)
+}
+
+/// Where the exchange clock stands under replay.
+pub fn now() -> Option<DateTime<Tz>> {
+    let tape = TAPE.get().filter(|t| t.mode == Mode::Replay)?;
+    let started = tape.index.lock().ok()?.started?;
+    Some(started.with_timezone(&Shanghai))
+}
+
+/// The recorded response to `url` under replay, or an error if there is
+/// none; `None` when not replaying.
+pub fn lookup(url: &str) -> Option<Result<Response, Box<dyn std::error::Error>>> {
+    let tape = TAPE.get().filter(|t| t.mode == Mode::Replay)?;
+    let entry = tape.index.lock().ok()?.responses.get(url).cloned();
+    let Some(entry) = entry else {
+        return Some(Err(tr!("No recorded response for {}", "录制中没有 {} 的响应", url).into()));
+    };
+    Some(
+        fs::read_to_string(tape.dir.join(&entry.file))
+            .map(|text| Response { status: entry.status, text })
+            .map_err(|e| tr!("Failed to read {}: {}", "读取 {} 失败: {}", entry.file, e).into()),
+    )
+}
+
+/// Record `response` to `url` when recording. Failures are reported but
+/// don't fail the request.
+pub fn save(url: &str, response: &Response) {
+    let Some(tape) = TAPE.get().filter(|t| t.mode == Mode::Record) else {
+        return;
+    };
+    let Ok(mut index) = tape.index.lock() else {
+        return;
+    };
+    let next = index.responses.len() + 1;
+    let file = index.responses.get(url).map_or_else(
+        || {
+            let host = url.split("://").nth(1).and_then(|rest| rest.split('/').next()).unwrap_or("response");
+            format!("{:04}-{}.txt", next, host)
+        },
+        |entry| entry.file.clone(),
+    );
+    index.responses.insert(url.to_string(), Entry { status: response.status, file: file.clone() });
+    let written = fs::write(tape.dir.join(&file), &response.text)
+        .map_err(|e| e.to_string())
+        .and_then(|()| serde_json::to_string_pretty(&*index).map_err(|e| e.to_string()))
+        .and_then(|json| fs::write(tape.dir.join(INDEX_FILE), json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("{}", tr!("Failed to record {} in {}: {}", "录制 {} 到 {} 失败: {}", url, tape.dir.display(), e));
+    }
+}
@@ -0,0 +1,329 @@
+//! Exchange time (Asia/Shanghai): delivery windows and whether the latest
+//! bar is still trading.
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, NaiveDate, NaiveTime, Timelike, Utc};
+use chrono_tz::Asia::Shanghai;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::fixture;
+use crate::i18n::tr;
+use crate::replay;
+use crate::sina::Candle;
+
+/// Start of continuous trading. A bar dated today before it comes from the
+/// opening auction or is a feed's placeholder, not a session.
+pub const SESSION_OPEN: NaiveTime = NaiveTime::from_hms_opt(9, 30, 0).unwrap();
+
+/// End of the A-share afternoon session; a bar is final from here on.
+pub const SESSION_CLOSE: NaiveTime = NaiveTime::from_hms_opt(15, 0, 0).unwrap();
+
+/// A daily `HH:MM-HH:MM` window, end exclusive, on weekdays only.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DeliveryWindow {
+    start: u32,
+    end: u32,
+}
+
+pub const MARKET_HOURS: DeliveryWindow = DeliveryWindow { start: 9 * 60 + 30, end: 15 * 60 };
+
+/// The first minutes of the session and after the close, when `watch`
+/// refetches every code by default.
+pub const OPENING_WARMUP: DeliveryWindow = DeliveryWindow { start: 9 * 60 + 30, end: 9 * 60 + 35 };
+pub const CLOSING_WARMUP: DeliveryWindow = DeliveryWindow { start: 15 * 60, end: 15 * 60 + 10 };
+
+fn parse_hhmm(s: &str) -> Result<u32, String> {
+    let (h, m) = s.trim().split_once(':').ok_or_else(|| format!("expected HH:MM, got '{}'", s))?;
+    let h: u32 = h.parse().map_err(|_| format!("invalid hour in '{}'", s))?;
+    let m: u32 = m.parse().map_err(|_| format!("invalid minute in '{}'", s))?;
+    if h > 24 || m > 59 || (h == 24 && m > 0) {
+        return Err(format!("time out of range: '{}'", s));
+    }
+    Ok(h * 60 + m)
+}
+
+impl TryFrom<String> for DeliveryWindow {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DeliveryWindow> for String {
+    fn from(w: DeliveryWindow) -> String {
+        w.to_string()
+    }
+}
+
+impl FromStr for DeliveryWindow {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (start, end) = s.split_once('-').ok_or_else(|| format!("expected HH:MM-HH:MM, got '{}'", s))?;
+        let (start, end) = (parse_hhmm(start)?, parse_hhmm(end)?);
+        if start >= end {
+            return Err(format!("window '{}' ends before it starts", s));
+        }
+        Ok(DeliveryWindow { start, end })
+    }
+}
+
+impl fmt::Display for DeliveryWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02}:{:02}-{:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+/// The current time on the exchange clock, stopped under `--fixture` and
+/// `--replay`.
+pub fn now() -> DateTime<Tz> {
+    if let Some(now) = fixture::now().or_else(replay::now) {
+        return now;
+    }
+    Utc::now().with_timezone(&Shanghai)
+}
+
+/// A Unix timestamp on the exchange clock.
+pub fn at(unix_secs: u64) -> DateTime<Tz> {
+    DateTime::from_timestamp(unix_secs as i64, 0).unwrap_or_default().with_timezone(&Shanghai)
+}
+
+/// `(weekday, minute of day)` in Shanghai time for a Unix timestamp, with
+/// Monday as 0.
+pub fn shanghai_clock(unix_secs: u64) -> (u32, u32) {
+    let local = at(unix_secs);
+    (local.weekday().num_days_from_monday(), local.hour() * 60 + local.minute())
+}
+
+/// Unix seconds of the first weekday 09:30 open strictly after `unix_secs`.
+/// Exchange holidays aren't known, so they count as sessions.
+pub fn next_open(unix_secs: u64) -> u64 {
+    let local = DateTime::from_timestamp(unix_secs as i64, 0).unwrap_or_default().with_timezone(&Shanghai);
+    let mut day = local.date_naive();
+    loop {
+        let candidate = day.and_time(SESSION_OPEN).and_local_timezone(Shanghai).single();
+        if let Some(at) = candidate
+            && at > local
+            && at.weekday().num_days_from_monday() < 5
+        {
+            return at.timestamp() as u64;
+        }
+        day = day.succ_opt().unwrap_or(day);
+    }
+}
+
+/// Where the latest bar stands against the exchange clock.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BarSession {
+    /// Today's session, still trading; the bar will change.
+    Live,
+    /// Today's session has closed; the bar is final.
+    TodayClose,
+    /// An earlier trading day, e.g. on a holiday or at the weekend.
+    PreviousClose,
+    /// The last completed session, on a weekday before today's open.
+    BeforeOpen,
+    /// A day the exchange clock hasn't reached: the system clock is behind.
+    AheadOfClock,
+}
+
+pub fn bar_session(bar_date: NaiveDate, now: DateTime<Tz>) -> BarSession {
+    let today = now.date_naive();
+    if bar_date > today {
+        BarSession::AheadOfClock
+    } else if bar_date < today {
+        if now.time() < SESSION_OPEN && today.weekday().num_days_from_monday() < 5 {
+            BarSession::BeforeOpen
+        } else {
+            BarSession::PreviousClose
+        }
+    } else if now.time() < SESSION_CLOSE {
+        BarSession::Live
+    } else {
+        BarSession::TodayClose
+    }
+}
+
+impl fmt::Display for BarSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&match self {
+            BarSession::Live => tr!("live session, not final", "盘中, 未收盘"),
+            BarSession::TodayClose => tr!("today's close", "今日收盘"),
+            BarSession::PreviousClose => tr!("previous close", "前一交易日收盘"),
+            BarSession::BeforeOpen => tr!("last completed session, before today's open", "上一完整交易日, 今日未开盘"),
+            BarSession::AheadOfClock => tr!("dated after today, check the system clock", "日期晚于今天, 请检查系统时钟"),
+        })
+    }
+}
+
+/// Drop a bar dated today from before the open, so windows end at the last
+/// completed session.
+pub fn drop_unopened(candles: &mut Vec<Candle>, now: DateTime<Tz>) {
+    if now.time() < SESSION_OPEN && candles.last().is_some_and(|c| c.date() == now.date_naive()) {
+        candles.pop();
+    }
+}
+
+/// Whether alerts may be delivered now. No windows means always.
+pub fn is_open(windows: &[DeliveryWindow], unix_secs: u64) -> bool {
+    if windows.is_empty() {
+        return true;
+    }
+    let (weekday, minute) = shanghai_clock(unix_secs);
+    weekday < 5 && windows.iter().any(|w| minute >= w.start && minute < w.end)
+}
+
+/// Unix seconds the next of `windows` opens after `unix_secs`, or `None`
+/// without windows.
+pub fn next_window(windows: &[DeliveryWindow], unix_secs: u64) -> Option<u64> {
+    let local = at(unix_secs);
+    let mut day = local.date_naive();
+    // A weekday comes round within a week.
+    for _ in 0..8 {
+        let opens = windows
+            .iter()
+            .filter_map(|w| day.and_hms_opt(w.start / 60, w.start % 60, 0)?.and_local_timezone(Shanghai).single())
+            .filter(|at| *at > local && at.weekday().num_days_from_monday() < 5)
+            .min();
+        if let Some(at) = opens {
+            return Some(at.timestamp() as u64);
+        }
+        day = day.succ_opt()?;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Unix seconds of a Shanghai wall-clock time.
+    fn ts(month: u32, day: u32, h: u32, m: u32) -> u64 {
+        let local = NaiveDate::from_ymd_opt(2026, month, day).unwrap().and_hms_opt(h, m, 0).unwrap();
+        local.and_local_timezone(Shanghai).unwrap().timestamp() as u64
+    }
+
+    fn windows(specs: &[&str]) -> Vec<DeliveryWindow> {
+        specs.iter().map(|s| s.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn windows_parse_and_display() {
+        for (spec, shown) in [
+            ("09:30-15:00", Some("09:30-15:00")),
+            ("9:30-11:30", Some("09:30-11:30")),
+            (" 13:00 - 15:00 ", Some("13:00-15:00")),
+            ("00:00-24:00", Some("00:00-24:00")),
+            ("15:00-09:30", None),
+            ("09:30-09:30", None),
+            ("09:30", None),
+            ("9-15", None),
+            ("25:00-26:00", None),
+            ("09:60-10:00", None),
+            ("23:00-24:01", None),
+            ("aa:00-10:00", None),
+        ] {
+            let parsed = spec.parse::<DeliveryWindow>().ok().map(|w| w.to_string());
+            assert_eq!(parsed.as_deref(), shown, "{}", spec);
+        }
+        let w: DeliveryWindow = serde_json::from_str("\"09:30-15:00\"").unwrap();
+        assert_eq!(w, MARKET_HOURS);
+        assert!(serde_json::from_str::<DeliveryWindow>("\"15:00-09:30\"").is_err());
+    }
+
+    #[test]
+    fn lunch_break_is_open_only_inside_a_window() {
+        // 2026-10-14 is a Wednesday.
+        let spanning = windows(&["11:00-13:30"]);
+        let sessions = windows(&["09:30-11:30", "13:00-15:00"]);
+        for (time, in_spanning, in_sessions) in [
+            ((9, 29), false, false),
+            ((9, 30), false, true),
+            ((11, 29), true, true),
+            ((11, 30), true, false),
+            ((12, 15), true, false),
+            ((13, 0), true, true),
+            ((13, 30), false, true),
+            ((15, 0), false, false),
+        ] {
+            let now = ts(10, 14, time.0, time.1);
+            assert_eq!(is_open(&spanning, now), in_spanning, "{:?}", time);
+            assert_eq!(is_open(&sessions, now), in_sessions, "{:?}", time);
+        }
+        assert!(is_open(&[MARKET_HOURS], ts(10, 14, 12, 15)));
+        assert_eq!(next_window(&sessions, ts(10, 14, 12, 15)), Some(ts(10, 14, 13, 0)));
+        assert!(is_open(&[], ts(10, 17, 3, 0)));
+        assert_eq!(next_window(&[], ts(10, 14, 12, 15)), None);
+    }
+
+    #[test]
+    fn pass_just_before_a_window_opens() {
+        let before = ts(10, 14, 9, 29) + 59;
+        assert!(!is_open(&[MARKET_HOURS], before));
+        assert_eq!(next_window(&[MARKET_HOURS], before), Some(ts(10, 14, 9, 30)));
+        // At the opening second the window is open, and the next is tomorrow's.
+        assert!(is_open(&[MARKET_HOURS], ts(10, 14, 9, 30)));
+        assert_eq!(next_window(&[MARKET_HOURS], ts(10, 14, 9, 30)), Some(ts(10, 15, 9, 30)));
+        assert_eq!(next_open(before), ts(10, 14, 9, 30));
+    }
+
+    #[test]
+    fn next_window_skips_weekends_not_holidays() {
+        // Friday after the close, Saturday and Sunday all wait for Monday.
+        for now in [ts(10, 9, 15, 0), ts(10, 10, 10, 0), ts(10, 11, 23, 59)] {
+            assert!(!is_open(&[MARKET_HOURS], now));
+            assert_eq!(next_window(&[MARKET_HOURS], now), Some(ts(10, 12, 9, 30)));
+            assert_eq!(next_open(now), ts(10, 12, 9, 30));
+        }
+        // Holidays aren't known: National Day, a Thursday, counts as a session.
+        assert!(is_open(&[MARKET_HOURS], ts(10, 1, 10, 0)));
+        assert_eq!(next_window(&[MARKET_HOURS], ts(9, 30, 16, 0)), Some(ts(10, 1, 9, 30)));
+        // The earliest window of the day wins, whatever the order configured.
+        let late_first = windows(&["14:00-15:00", "09:30-10:00"]);
+        assert_eq!(next_window(&late_first, ts(10, 10, 10, 0)), Some(ts(10, 12, 9, 30)));
+    }
+
+    #[test]
+    fn bar_session_variants() {
+        let date = |month, day| NaiveDate::from_ymd_opt(2026, month, day).unwrap();
+        for (bar, now, session) in [
+            (date(10, 14), ts(10, 14, 10, 0), BarSession::Live),
+            (date(10, 14), ts(10, 14, 14, 59), BarSession::Live),
+            (date(10, 14), ts(10, 14, 15, 0), BarSession::TodayClose),
+            (date(10, 13), ts(10, 14, 9, 0), BarSession::BeforeOpen),
+            (date(10, 13), ts(10, 14, 10, 0), BarSession::PreviousClose),
+            (date(10, 9), ts(10, 10, 9, 0), BarSession::PreviousClose),
+            (date(10, 9), ts(10, 12, 9, 29), BarSession::BeforeOpen),
+            (date(10, 15), ts(10, 14, 16, 0), BarSession::AheadOfClock),
+        ] {
+            assert_eq!(bar_session(bar, at(now)), session, "{} at {}", bar, at(now));
+        }
+    }
+
+    #[test]
+    fn drop_unopened_only_drops_todays_bar_before_the_open() {
+        let candle = |day: u32| Candle {
+            time: NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_time(SESSION_CLOSE),
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 100.0,
+        };
+        let bars = vec![candle(13), candle(14)];
+        for (now, kept) in [(ts(10, 14, 9, 15), 1), (ts(10, 14, 9, 30), 2), (ts(10, 15, 9, 15), 2)] {
+            let mut candles = bars.clone();
+            drop_unopened(&mut candles, at(now));
+            assert_eq!(candles.len(), kept, "{}", at(now));
+        }
+        let mut empty = Vec::new();
+        drop_unopened(&mut empty, at(ts(10, 14, 9, 15)));
+        assert!(empty.is_empty());
+    }
+}
@@ -0,0 +1,305 @@
+//! User-defined composite score, e.g. `0.4*zscore + 0.3*rsi_rank + 0.3*drawdown_rank`.
+//!
+//! A formula is an arithmetic expression over numbers and metric names with
+//! `+ - * /` and parentheses. Any metric can be suffixed with `_rank` to use
+//! its percentile rank across the current result set instead of the raw value.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Formula {
+    source: String,
+    expr: Expr,
+}
+
+impl fmt::Display for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for Formula {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Formula> for String {
+    fn from(f: Formula) -> String {
+        f.source
+    }
+}
+
+impl FromStr for Formula {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Accept the `score = ...` form as written in the docs.
+        let body = match s.split_once('=') {
+            Some((lhs, rhs)) if lhs.trim() == "score" => rhs,
+            _ => s,
+        };
+        let tokens = tokenize(body)?;
+        let mut parser = Parser { tokens: &tokens, pos: 0 };
+        let expr = parser.expr()?;
+        if parser.pos != tokens.len() {
+            return Err(format!("unexpected '{}' in score formula", tokens[parser.pos]));
+        }
+        Ok(Formula { source: body.trim().to_string(), expr })
+    }
+}
+
+impl Formula {
+    /// Metric names the formula refers to, with any `_rank` suffix kept.
+    pub fn variables(&self) -> Vec<&str> {
+        fn walk<'a>(e: &'a Expr, out: &mut Vec<&'a str>) {
+            match e {
+                Expr::Num(_) => {}
+                Expr::Var(name) => out.push(name),
+                Expr::Neg(inner) => walk(inner, out),
+                Expr::Bin(_, a, b) => {
+                    walk(a, out);
+                    walk(b, out);
+                }
+            }
+        }
+        let mut out = Vec::new();
+        walk(&self.expr, &mut out);
+        out
+    }
+
+    /// Evaluate against a metric lookup; `None` if any referenced metric is
+    /// missing or the result is not finite.
+    pub fn eval(&self, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+        fn go(e: &Expr, lookup: &dyn Fn(&str) -> Option<f64>) -> Option<f64> {
+            match e {
+                Expr::Num(n) => Some(*n),
+                Expr::Var(name) => lookup(name),
+                Expr::Neg(inner) => go(inner, lookup).map(|v| -v),
+                Expr::Bin(op, a, b) => {
+                    let (a, b) = (go(a, lookup)?, go(b, lookup)?);
+                    Some(match op {
+                        '+' => a + b,
+                        '-' => a - b,
+                        '*' => a * b,
+                        _ => a / b,
+                    })
+                }
+            }
+        }
+        go(&self.expr, lookup).filter(|v| v.is_finite())
+    }
+}
+
+/// Percentile rank in `[0, 1]` of every value within `values`; the lowest
+/// value ranks 0 and the highest 1. Ties share the lower rank.
+pub fn percentile_ranks(values: &[Option<f64>]) -> Vec<Option<f64>> {
+    let present: Vec<f64> = values.iter().flatten().copied().collect();
+    let denom = (present.len().max(2) - 1) as f64;
+    values
+        .iter()
+        .map(|v| v.map(|v| present.iter().filter(|&&o| o < v).count() as f64 / denom))
+        .collect()
+}
+
+/// Score every row: `metrics[i]` holds row `i`'s raw metrics, and `_rank`
+/// variables are resolved across all rows.
+pub fn score_rows(formula: &Formula, metrics: &[BTreeMap<&'static str, f64>]) -> Vec<Option<f64>> {
+    let mut ranks: BTreeMap<&str, Vec<Option<f64>>> = BTreeMap::new();
+    for var in formula.variables() {
+        if let Some(base) = var.strip_suffix("_rank")
+            && !ranks.contains_key(var)
+        {
+            let column: Vec<Option<f64>> = metrics.iter().map(|m| m.get(base).copied()).collect();
+            ranks.insert(var, percentile_ranks(&column));
+        }
+    }
+
+    (0..metrics.len())
+        .map(|i| {
+            formula.eval(&|name| match ranks.get(name) {
+                Some(column) => column[i],
+                None => metrics[i].get(name).copied(),
+            })
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(f64),
+    Ident(String),
+    Op(char),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{}", n),
+            Token::Ident(s) => f.write_str(s),
+            Token::Op(c) => write!(f, "{}", c),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let chars: Vec<char> = s.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if "+-*/()".contains(c) {
+            tokens.push(Token::Op(c));
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text.parse().map_err(|_| format!("invalid number '{}' in score formula", text))?;
+            tokens.push(Token::Num(n));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(chars[start..i].iter().collect()));
+        } else {
+            return Err(format!("unexpected character '{}' in score formula", c));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn peek_op(&self) -> Option<char> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(c)) => Some(*c),
+            _ => None,
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(op @ ('+' | '-')) = self.peek_op() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek_op() {
+            self.pos += 1;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(self.factor()?));
+        }
+        Ok(lhs)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos).cloned().ok_or("score formula ended unexpectedly")?;
+        self.pos += 1;
+        match token {
+            Token::Num(n) => Ok(Expr::Num(n)),
+            Token::Ident(name) => Ok(Expr::Var(name)),
+            Token::Op('-') => Ok(Expr::Neg(Box::new(self.factor()?))),
+            Token::Op('(') => {
+                let inner = self.expr()?;
+                if self.peek_op() != Some(')') {
+                    return Err("missing ')' in score formula".to_string());
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            other => Err(format!("unexpected '{}' in score formula", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(formula: &str, vars: &[(&str, f64)]) -> Option<f64> {
+        let formula: Formula = formula.parse().unwrap();
+        formula.eval(&|name| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| *v))
+    }
+
+    #[test]
+    fn operators_bind_by_precedence() {
+        for (formula, value) in [
+            ("1 + 2 * 3", 7.0),
+            ("(1 + 2) * 3", 9.0),
+            ("10 - 4 - 3", 3.0),
+            ("8 / 4 / 2", 1.0),
+            ("-2 * 3 + 1", -5.0),
+            ("2 * -(1 - 4)", 6.0),
+            ("score = 0.5 * zscore + 2", 1.0),
+            ("0.25*zscore + 0.5*rsi_rank", -0.25),
+        ] {
+            assert_eq!(eval(formula, &[("zscore", -2.0), ("rsi_rank", 0.5)]), Some(value), "{}", formula);
+        }
+        let formula: Formula = "score = 0.4*zscore + 0.3*rsi_rank".parse().unwrap();
+        assert_eq!(formula.to_string(), "0.4*zscore + 0.3*rsi_rank");
+        assert_eq!(formula.variables(), ["zscore", "rsi_rank"]);
+    }
+
+    #[test]
+    fn unknown_metrics_and_division_by_zero_leave_no_score() {
+        assert_eq!(eval("zscore + missing", &[("zscore", 1.0)]), None);
+        assert_eq!(eval("1 / zscore", &[("zscore", 0.0)]), None);
+        assert_eq!(eval("zscore / zscore", &[("zscore", 0.0)]), None);
+        assert_eq!(eval("zscore * 2", &[("zscore", f64::NAN)]), None);
+        assert_eq!(eval("1 / 0", &[]), None);
+    }
+
+    #[test]
+    fn malformed_formulas_are_rejected() {
+        let malformed = ["", "score =", "1 +", "* 2", "(1 + 2", "1 + 2)", "1 2", "zscore rsi", "1..5", "zscore % 2", "()"];
+        for formula in malformed {
+            assert!(formula.parse::<Formula>().is_err(), "{}", formula);
+        }
+        assert!(serde_json::from_str::<Formula>("\"1 +\"").is_err());
+        let formula: Formula = serde_json::from_str("\"2 * zscore\"").unwrap();
+        assert_eq!(serde_json::to_string(&formula).unwrap(), "\"2 * zscore\"");
+    }
+
+    #[test]
+    fn rank_variables_rank_across_rows() {
+        assert_eq!(percentile_ranks(&[Some(3.0), None, Some(1.0), Some(3.0)]), [Some(0.5), None, Some(0.0), Some(0.5)]);
+        assert_eq!(percentile_ranks(&[Some(7.0)]), [Some(0.0)]);
+
+        let formula: Formula = "drawdown_rank + zscore".parse().unwrap();
+        let rows: Vec<BTreeMap<&'static str, f64>> = vec![
+            BTreeMap::from([("drawdown", 10.0), ("zscore", 1.0)]),
+            BTreeMap::from([("drawdown", 30.0), ("zscore", 1.0)]),
+            BTreeMap::from([("zscore", 1.0)]),
+            BTreeMap::from([("drawdown", 20.0), ("zscore", 1.0)]),
+        ];
+        assert_eq!(score_rows(&formula, &rows), [Some(1.0), Some(2.0), None, Some(1.5)]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Custom metrics written as Rhai scripts in the config, evaluated for each
+//! row when the report is built.
+//!
+//! `[scripts]` maps a metric name to its source. A script sees the bars as
+//! the arrays `opens`, `highs`, `lows`, `closes` and `volumes` (oldest
+//! first) and the row's metrics computed from bars, plugins' included, as
+//! the map `metrics`, and evaluates to a number; `()` means no value.
+//! Metrics fetched separately (fee, spread_bps, valuations) come later and
+//! aren't in the map. For example:
+//!
+//! ```toml
+//! [scripts]
+//! gap = "(closes[-1] - closes[-21]) / closes[-21] * 100.0 - metrics.decline"
+//! ```
+//!
+//! Scripts run with an operation limit, so a runaway loop fails with an
+//! error instead of hanging the screen. Built with the `scripts` feature,
+//! on by default.
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+#[cfg(feature = "scripts")]
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+
+use crate::config::Config;
+#[cfg(feature = "scripts")]
+use crate::i18n::tr;
+use crate::sina::Candle;
+
+/// Whether this build can run scripts.
+pub const AVAILABLE: bool = cfg!(feature = "scripts");
+
+/// Rhai operations one evaluation may run.
+#[cfg(feature = "scripts")]
+const MAX_OPERATIONS: u64 = 10_000_000;
+
+pub struct Script {
+    /// Metric name, from the config key.
+    pub name: &'static str,
+    #[cfg(feature = "scripts")]
+    ast: AST,
+}
+
+struct Compiled {
+    #[cfg(feature = "scripts")]
+    engine: Engine,
+    scripts: Vec<Script>,
+}
+
+static SCRIPTS: OnceLock<Compiled> = OnceLock::new();
+
+#[cfg(not(feature = "scripts"))]
+pub fn init(_cfg: &Config, _taken: &[&str]) {
+    // `decline::validate` has already refused a configured `[scripts]`.
+    let _ = SCRIPTS.set(Compiled { scripts: Vec::new() });
+}
+
+/// Compile the configured scripts. Scripts that fail to compile, or whose
+/// name is already a metric, are reported and skipped. Later calls are
+/// ignored.
+#[cfg(feature = "scripts")]
+pub fn init(cfg: &Config, taken: &[&str]) {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    let mut scripts = Vec::new();
+    for (name, source) in &cfg.scripts {
+        if taken.contains(&name.as_str()) || name.ends_with("_rank") {
+            eprintln!("{}", tr!("Skipping script {}: name clashes with a metric", "跳过脚本 {}: 与已有指标重名", name));
+            continue;
+        }
+        match engine.compile(source) {
+            // Compiled once per process, so the leaked names stay bounded.
+            Ok(ast) => scripts.push(Script { name: Box::leak(name.clone().into_boxed_str()), ast }),
+            Err(e) => eprintln!("{}", tr!("Failed to compile script {}: {}", "编译脚本 {} 失败: {}", name, e)),
+        }
+    }
+    let _ = SCRIPTS.set(Compiled { engine, scripts });
+}
+
+/// The compiled scripts; empty if `init` never ran.
+pub fn all() -> &'static [Script] {
+    SCRIPTS.get().map_or(&[], |c| c.scripts.as_slice())
+}
+
+impl Script {
+    #[cfg(not(feature = "scripts"))]
+    pub fn eval(&self, _candles: &[Candle], _metrics: &BTreeMap<&'static str, f64>) -> Result<Option<f64>, String> {
+        Ok(None)
+    }
+
+    /// Evaluate the script for one row; `None` when it yields no number.
+    #[cfg(feature = "scripts")]
+    pub fn eval(&self, candles: &[Candle], metrics: &BTreeMap<&'static str, f64>) -> Result<Option<f64>, String> {
+        let Some(compiled) = SCRIPTS.get() else {
+            return Ok(None);
+        };
+        let series = |f: fn(&Candle) -> f64| -> Array { candles.iter().map(|c| Dynamic::from_float(f(c))).collect() };
+        let map: Map = metrics.iter().map(|(k, v)| ((*k).into(), Dynamic::from_float(*v))).collect();
+        let mut scope = Scope::new();
+        scope.push("opens", series(|c| c.open));
+        scope.push("highs", series(|c| c.high));
+        scope.push("lows", series(|c| c.low));
+        scope.push("closes", series(|c| c.close));
+        scope.push("volumes", series(|c| c.volume));
+        scope.push("metrics", map);
+        let value: Dynamic = compiled.engine.eval_ast_with_scope(&mut scope, &self.ast).map_err(|e| e.to_string())?;
+        if value.is_unit() {
+            return Ok(None);
+        }
+        let number = value.as_float().or_else(|_| value.as_int().map(|i| i as f64)).map_err(|t| {
+            format!("expected a number, got {}", t)
+        })?;
+        Ok(Some(number).filter(|v| v.is_finite()))
+    }
+}
@@ -0,0 +1,195 @@
+//! Month-of-year and day-of-week average returns per code, from as much
+//! daily history as there is. Gold and dividend ETFs in particular show
+//! seasonal patterns worth knowing before buying a dip.
+//!
+//! A month's return runs from the previous month's last close to its own;
+//! the first month of history and the current one, both partial, are left
+//! out. A weekday's return is close to close from the bar before. History
+//! past what one Sina request serves comes from the `backfill` store.
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDate, Weekday};
+use clap::Args;
+use serde::Serialize;
+
+use crate::config::{Config, OutputFormat};
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::sina::{self, Candle};
+use crate::symbols;
+
+/// Trading days per year, for turning `--years` into bars.
+const TRADING_DAYS_PER_YEAR: usize = 250;
+
+#[derive(Args, Debug)]
+pub struct SeasonalityArgs {
+    /// Codes to show, e.g. 518880; defaults to the whole watchlist
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// Years of history to average over, as far as it reaches
+    #[arg(long, default_value_t = 10)]
+    years: u32,
+}
+
+/// Average return over one month of the year or day of the week.
+#[derive(Debug, Serialize)]
+struct Bucket {
+    /// Month 1-12, or weekday 1 (Monday) to 5.
+    period: u32,
+    /// Mean return, percent.
+    mean: f64,
+    /// Share of returns above zero, percent.
+    up_rate: f64,
+    samples: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct Seasonality {
+    code: String,
+    from: NaiveDate,
+    to: NaiveDate,
+    months: Vec<Bucket>,
+    weekdays: Vec<Bucket>,
+}
+
+fn buckets(returns: BTreeMap<u32, Vec<f64>>) -> Vec<Bucket> {
+    returns
+        .into_iter()
+        .map(|(period, values)| Bucket {
+            period,
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            up_rate: values.iter().filter(|&&r| r > 0.0).count() as f64 / values.len() as f64 * 100.0,
+            samples: values.len(),
+        })
+        .collect()
+}
+
+/// Month and weekday averages of `candles`, oldest first; `None` with
+/// fewer than two bars.
+fn seasonality(code: &str, candles: &[Candle]) -> Option<Seasonality> {
+    let (first, last) = (candles.first()?.date(), candles.last()?.date());
+    if first == last {
+        return None;
+    }
+
+    let mut weekdays: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+    for w in candles.windows(2).filter(|w| w[0].close > 0.0) {
+        let change = (w[1].close - w[0].close) / w[0].close * 100.0;
+        weekdays.entry(w[1].date().weekday().number_from_monday()).or_default().push(change);
+    }
+
+    // Each month's last close, in order.
+    let mut month_ends: Vec<((i32, u32), f64)> = Vec::new();
+    for c in candles {
+        let month = (c.date().year(), c.date().month());
+        match month_ends.last_mut() {
+            Some((m, close)) if *m == month => *close = c.close,
+            _ => month_ends.push((month, c.close)),
+        }
+    }
+    let today = schedule::now().date_naive();
+    if month_ends.last().is_some_and(|(m, _)| *m == (today.year(), today.month())) {
+        month_ends.pop();
+    }
+    let mut months: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+    for w in month_ends.windows(2).filter(|w| w[0].1 > 0.0) {
+        months.entry(w[1].0.1).or_default().push((w[1].1 - w[0].1) / w[0].1 * 100.0);
+    }
+
+    Some(Seasonality { code: code.to_string(), from: first, to: last, months: buckets(months), weekdays: buckets(weekdays) })
+}
+
+fn month_label(month: u32) -> String {
+    match month {
+        1 => tr!("Jan", "1月"),
+        2 => tr!("Feb", "2月"),
+        3 => tr!("Mar", "3月"),
+        4 => tr!("Apr", "4月"),
+        5 => tr!("May", "5月"),
+        6 => tr!("Jun", "6月"),
+        7 => tr!("Jul", "7月"),
+        8 => tr!("Aug", "8月"),
+        9 => tr!("Sep", "9月"),
+        10 => tr!("Oct", "10月"),
+        11 => tr!("Nov", "11月"),
+        _ => tr!("Dec", "12月"),
+    }
+}
+
+fn weekday_label(day: u32) -> String {
+    match Weekday::try_from(day.saturating_sub(1) as u8).unwrap_or(Weekday::Mon) {
+        Weekday::Mon => tr!("Mon", "周一"),
+        Weekday::Tue => tr!("Tue", "周二"),
+        Weekday::Wed => tr!("Wed", "周三"),
+        Weekday::Thu => tr!("Thu", "周四"),
+        Weekday::Fri => tr!("Fri", "周五"),
+        Weekday::Sat => tr!("Sat", "周六"),
+        Weekday::Sun => tr!("Sun", "周日"),
+    }
+}
+
+fn print_buckets(buckets: &[Bucket], label: fn(u32) -> String) {
+    for b in buckets {
+        println!(
+            "{}",
+            tr!(
+                "{:<4} {:>+7.2}% | up {:>3.0}% | {} samples",
+                "{:<4} {:>+7.2}% | 上涨 {:>3.0}% | {} 个样本",
+                label(b.period),
+                b.mean,
+                b.up_rate,
+                b.samples
+            )
+        );
+    }
+}
+
+pub async fn run(args: &SeasonalityArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    if args.years == 0 {
+        return Err("--years must be positive".into());
+    }
+    let codes: Vec<String> = if args.codes.is_empty() { cfg.watchlist.clone() } else { args.codes.clone() };
+    let len = args.years as usize * TRADING_DAYS_PER_YEAR;
+    let deadline = http::deadline(&cfg.http);
+
+    let mut results = Vec::new();
+    for code in &codes {
+        match http::until(deadline, sina::fetch_etf_kline(code, len)).await {
+            Some(Ok((candles, None | Some(200)))) => match seasonality(code, &candles) {
+                Some(s) => results.push(s),
+                None => eprintln!("{}", tr!("Not enough data for {}: got {} days", "{} 数据不足: 仅 {} 日", code, candles.len())),
+            },
+            Some(Ok((_, Some(status)))) => eprintln!("{}", tr!("HTTP {} for code: {}", "代码 {1} 返回 HTTP {0}", status, code)),
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch {}: {}", "获取 {} 失败: {}", code, e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", code)),
+        }
+    }
+
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&results)?);
+        return Ok(());
+    }
+    for s in &results {
+        println!("\n {}", tr!("Seasonality of {}, {} to {}:", "{} 季节性, {} 至 {}:", s.code, s.from, s.to));
+        println!("-----------------------------------------");
+        print_buckets(&s.months, month_label);
+        println!();
+        print_buckets(&s.weekdays, weekday_label);
+        // Sina alone reaches about four years back.
+        if s.to - s.from < Duration::days(365 * i64::from(args.years) - 30) {
+            println!(
+                "{}",
+                tr!(
+                    "History starts {}; `backfill {}` can add older years",
+                    "历史数据始于 {}; `backfill {}` 可补充更早的年份",
+                    s.from,
+                    s.code
+                )
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,132 @@
+//! What `watch` needs to run as a systemd service: readiness and status
+//! through `sd_notify`, shutdown on SIGTERM, and a health endpoint.
+//!
+//! A unit running `decline-compare watch` can use `Type=notify`; readiness
+//! is sent once the first screen is set up, and each pass updates the
+//! status line `systemctl status` shows. Outside systemd (`NOTIFY_SOCKET`
+//! unset) the notifications are skipped.
+//!
+//! The health endpoint answers any `GET` with the last pass as JSON: 200
+//! while passes keep coming, 503 once none has finished for three
+//! intervals.
+
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::cooldown;
+use crate::decline::Report;
+use crate::i18n::tr;
+
+/// Send `state` (e.g. `READY=1`) to the service manager, if there is one.
+pub fn notify(state: &str) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::net::UnixDatagram;
+
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return;
+        };
+        let Ok(socket) = UnixDatagram::unbound() else {
+            return;
+        };
+        let path = path.to_string_lossy();
+        let sent = match path.strip_prefix('@') {
+            #[cfg(target_os = "linux")]
+            Some(name) => {
+                use std::os::linux::net::SocketAddrExt;
+                std::os::unix::net::SocketAddr::from_abstract_name(name)
+                    .and_then(|addr| socket.send_to_addr(state.as_bytes(), &addr))
+            }
+            _ => socket.send_to(state.as_bytes(), &*path),
+        };
+        if let Err(e) = sent {
+            eprintln!("{}", tr!("Failed to notify the service manager: {}", "通知服务管理器失败: {}", e));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn terminated() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        if let Ok(mut term) = signal(SignalKind::terminate()) {
+            tokio::select! {
+                _ = term.recv() => {}
+                _ = tokio::signal::ctrl_c() => {}
+            }
+            return;
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Health {
+    /// Unix seconds the service started.
+    pub started_at: u64,
+    /// Unix seconds the last pass finished.
+    pub last_pass: Option<u64>,
+    pub passes: u64,
+    /// Alerts raised and codes timed out on the last pass.
+    pub alerts: usize,
+    pub timed_out: Vec<String>,
+}
+
+pub type SharedHealth = Arc<Mutex<Health>>;
+
+impl Health {
+    pub fn shared() -> SharedHealth {
+        Arc::new(Mutex::new(Health { started_at: cooldown::now_secs(), ..Health::default() }))
+    }
+
+    pub fn record(&mut self, report: &Report) {
+        self.last_pass = Some(cooldown::now_secs());
+        self.passes += 1;
+        self.alerts = report.alerts.len();
+        self.timed_out = report.timed_out.clone();
+    }
+}
+
+/// Serve `health` on `addr` until the process exits. `stale_secs` without
+/// a finished pass turns the answer into a 503.
+pub async fn serve_health(addr: &str, health: SharedHealth, stale_secs: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let listener = TcpListener::bind(addr).await?;
+    tokio::spawn(async move {
+        loop {
+            let Ok((mut stream, _)) = listener.accept().await else {
+                continue;
+            };
+            let health = health.clone();
+            tokio::spawn(async move {
+                // Only the request line matters; the rest is ignored.
+                let mut buf = [0u8; 1024];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]);
+                let response = if request.starts_with("GET ") {
+                    let snapshot = health.lock().map(|h| h.clone()).unwrap_or_default();
+                    let since = snapshot.last_pass.unwrap_or(snapshot.started_at);
+                    let fresh = cooldown::now_secs().saturating_sub(since) < stale_secs;
+                    let body = serde_json::to_string(&snapshot).unwrap_or_default();
+                    let status = if fresh { "200 OK" } else { "503 Service Unavailable" };
+                    format!(
+                        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                } else {
+                    "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Ok(())
+}
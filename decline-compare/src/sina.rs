@@ -0,0 +1,370 @@
+use std::collections::HashMap;
+use std::time::Instant;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::breaker;
+use crate::eastmoney;
+use crate::fixture;
+use crate::history;
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::spot;
+use crate::symbols;
+
+#[allow(dead_code)]
+#[derive(Deserialize, Debug)]
+struct SinaKLine {
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    volume: String,
+    day: String,
+}
+
+/// One bar (daily unless fetched with a minute scale), oldest first in every series returned by this module.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    /// End of the bar in exchange time; daily bars are stamped at the close.
+    pub time: NaiveDateTime,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// Sina stamps daily bars "YYYY-MM-DD" and minute bars "YYYY-MM-DD HH:MM:SS".
+fn parse_bar_time(s: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .or_else(|| Some(NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?.and_time(schedule::SESSION_CLOSE)))
+}
+
+impl Candle {
+    fn from_sina(item: &SinaKLine) -> Option<Candle> {
+        Some(Candle {
+            time: parse_bar_time(&item.day)?,
+            open: item.open.parse().ok()?,
+            high: item.high.parse().ok()?,
+            low: item.low.parse().ok()?,
+            close: item.close.parse().ok()?,
+            volume: item.volume.parse().ok()?,
+        })
+    }
+
+    /// Trading day the bar belongs to.
+    pub fn date(&self) -> NaiveDate {
+        self.time.date()
+    }
+}
+
+/// Bar size in minutes for a daily kline request.
+pub const DAILY_SCALE: u32 = 240;
+
+/// Most daily bars one kline request returns.
+pub const MAX_DAILY_BARS: usize = 1023;
+
+/// Daily bars for `code`; spot entries like `XAUUSD` come from `spot`.
+/// Beyond `MAX_DAILY_BARS`, older bars come from the backfilled history.
+/// While Sina's breaker is open the bars come from Eastmoney instead.
+pub async fn fetch_etf_kline(code: &str, day: usize) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    if fixture::active() {
+        return fixture::kline(code, DAILY_SCALE, day);
+    }
+    if spot::is_spot(code) {
+        return spot::fetch_kline(code, day).await;
+    }
+    let len = day.min(MAX_DAILY_BARS);
+    let (mut candles, status) =
+        if !breaker::available(breaker::SINA_KLINES) && breaker::available(breaker::EASTMONEY_KLINES) {
+            (eastmoney::fetch_latest(code, len).await?, Some(200))
+        } else {
+            fetch_kline(code, DAILY_SCALE, len).await?
+        };
+    if day > len {
+        history::extend(code, &mut candles, day);
+    }
+    Ok((candles, status))
+}
+
+/// Fetch `datalen` bars of `scale` minutes each (5/15/30/60, or 240 for daily).
+pub async fn fetch_kline(code: &str, scale: u32, datalen: usize) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    if fixture::active() {
+        return fixture::kline(code, scale, datalen);
+    }
+    let sina_code = symbols::sina_code(code).await;
+    let url = format!(
+        "https://money.finance.sina.com.cn/quotes_service/api/json_v2.php/CN_MarketData.getKLineData?symbol={}&scale={}&ma=no&datalen={}",
+        sina_code, scale, datalen
+    );
+
+    let started = Instant::now();
+    let response = http::get(&url, &[]).await;
+    let error = match &response {
+        Ok(resp) if !resp.is_success() => Some(format!("HTTP {}", resp.status)),
+        Ok(_) => None,
+        Err(e) => Some(e.to_string()),
+    };
+    breaker::record(breaker::SINA_KLINES, started, error);
+    let resp = response?;
+    Ok((parse_klines(&resp.text).unwrap_or_default(), Some(resp.status)))
+}
+
+/// Bars from a kline response; `None` unless it is a list of bars that all
+/// parse.
+pub fn parse_klines(text: &str) -> Option<Vec<Candle>> {
+    let data: Vec<SinaKLine> = serde_json::from_str(text).ok()?;
+    data.iter().map(Candle::from_sina).collect()
+}
+
+/// Fetch realtime quote strings from hq.sinajs.cn for several symbols in one
+/// request. Each entry maps the requested symbol to its comma-separated
+/// fields; symbols Sina doesn't know come back with no fields.
+/// Fails fast while the quote service's breaker is open; empty from
+/// fixtures.
+pub async fn fetch_hq(symbols: &[&str]) -> Result<HashMap<String, Vec<String>>, Box<dyn std::error::Error>> {
+    if fixture::active() {
+        return Ok(HashMap::new());
+    }
+    if !breaker::available(breaker::SINA_QUOTES) {
+        return Err(tr!("{} is failing; skipped until it recovers", "{} 故障, 恢复前跳过", breaker::SINA_QUOTES).into());
+    }
+    let url = format!("https://hq.sinajs.cn/list={}", symbols.join(","));
+    let started = Instant::now();
+    // hq.sinajs.cn rejects requests without a Sina referer.
+    let text = http::get(&url, &[("Referer", "https://finance.sina.com.cn")]).await.and_then(|resp| {
+        if resp.is_success() { Ok(resp.text) } else { Err(format!("HTTP {}", resp.status).into()) }
+    });
+    breaker::record(breaker::SINA_QUOTES, started, text.as_ref().err().map(|e| e.to_string()));
+    Ok(parse_hq(&text?))
+}
+
+/// Symbols per hq.sinajs.cn request, keeping the URL to a length Sina accepts.
+const HQ_BATCH: usize = 200;
+
+/// A realtime quote for an A-share listed fund or stock.
+#[derive(Debug, Clone, Serialize)]
+pub struct Quote {
+    pub name: String,
+    pub open: f64,
+    pub prev_close: f64,
+    /// 0 until the first trade of the session.
+    pub last: f64,
+    pub high: f64,
+    pub low: f64,
+    /// Best bid and ask; 0 when that side of the book is empty.
+    pub bid: f64,
+    pub ask: f64,
+    pub volume: f64,
+    /// Traded value today, CNY.
+    pub amount: f64,
+    pub time: Option<NaiveDateTime>,
+}
+
+impl Quote {
+    /// Fields are name, open, prev close, last, high, low, bid, ask, volume,
+    /// amount, ..., with the date and time at 30 and 31.
+    fn from_hq(fields: &[String]) -> Option<Quote> {
+        let num = |i: usize| fields.get(i)?.parse::<f64>().ok();
+        let time = match (fields.get(30), fields.get(31)) {
+            (Some(d), Some(t)) => NaiveDateTime::parse_from_str(&format!("{} {}", d, t), "%Y-%m-%d %H:%M:%S").ok(),
+            _ => None,
+        };
+        Some(Quote {
+            name: fields.first()?.clone(),
+            open: num(1)?,
+            prev_close: num(2)?,
+            last: num(3)?,
+            high: num(4)?,
+            low: num(5)?,
+            bid: num(6).unwrap_or(0.0),
+            ask: num(7).unwrap_or(0.0),
+            volume: num(8)?,
+            amount: num(9).unwrap_or(0.0),
+            time,
+        })
+    }
+
+    /// Quoted bid-ask spread in basis points of the midpoint; `None` unless
+    /// both sides of the book are quoted.
+    pub fn spread_bps(&self) -> Option<f64> {
+        (self.bid > 0.0 && self.ask >= self.bid).then(|| (self.ask - self.bid) / ((self.ask + self.bid) / 2.0) * 10_000.0)
+    }
+
+    /// Change from the previous close, in percent; `None` before the first trade.
+    pub fn change_pct(&self) -> Option<f64> {
+        (self.last > 0.0 && self.prev_close > 0.0).then(|| (self.last - self.prev_close) / self.prev_close * 100.0)
+    }
+}
+
+/// Realtime quotes for many codes in as few hq.sinajs.cn requests as
+/// possible. Codes Sina has no quote for, and spot entries, are left out of
+/// the map.
+pub async fn fetch_quotes(codes: &[String]) -> Result<HashMap<String, Quote>, Box<dyn std::error::Error>> {
+    let mut quotes = HashMap::new();
+    if fixture::active() {
+        return Ok(quotes);
+    }
+    let codes: Vec<String> = codes.iter().filter(|c| !spot::is_spot(c)).cloned().collect();
+    for chunk in codes.chunks(HQ_BATCH) {
+        let symbols = symbols::sina_codes(chunk).await;
+        let refs: Vec<&str> = symbols.iter().map(String::as_str).collect();
+        let hq = fetch_hq(&refs).await?;
+        for (code, symbol) in chunk.iter().zip(&symbols) {
+            if let Some(quote) = hq.get(symbol).and_then(|f| Quote::from_hq(f)) {
+                quotes.insert(code.clone(), quote);
+            }
+        }
+    }
+    Ok(quotes)
+}
+
+/// Latest published unit NAV per code, from hq.sinajs.cn's `f_` fund feed
+/// (name, unit NAV, accumulated NAV, previous NAV, date, ...). Codes
+/// without a NAV are left out of the map.
+pub async fn fetch_navs(codes: &[String]) -> Result<HashMap<String, f64>, Box<dyn std::error::Error>> {
+    let mut navs = HashMap::new();
+    let codes: Vec<String> = codes.iter().filter(|c| !spot::is_spot(c)).cloned().collect();
+    for chunk in codes.chunks(HQ_BATCH) {
+        let symbols: Vec<String> = chunk.iter().map(|c| format!("f_{}", c)).collect();
+        let refs: Vec<&str> = symbols.iter().map(String::as_str).collect();
+        let hq = fetch_hq(&refs).await?;
+        for (code, symbol) in chunk.iter().zip(&symbols) {
+            if let Some(nav) = hq.get(symbol).and_then(|f| f.get(1)?.parse::<f64>().ok()).filter(|&nav| nav > 0.0) {
+                navs.insert(code.clone(), nav);
+            }
+        }
+    }
+    Ok(navs)
+}
+
+/// Bring a daily series up to date with a realtime quote: the quote replaces
+/// the bar for its own day or is appended as a new one, keeping the series
+/// length. Quotes without a trade yet are ignored.
+pub fn merge_quote(candles: &mut Vec<Candle>, quote: &Quote) {
+    let Some(time) = quote.time.filter(|_| quote.last > 0.0) else {
+        return;
+    };
+    let bar = Candle {
+        time: time.date().and_time(schedule::SESSION_CLOSE),
+        open: quote.open,
+        high: quote.high,
+        low: quote.low,
+        close: quote.last,
+        volume: quote.volume,
+    };
+    match candles.last() {
+        Some(last) if last.date() == bar.date() => *candles.last_mut().unwrap() = bar,
+        Some(last) if last.date() > bar.date() => {}
+        _ => {
+            candles.push(bar);
+            if candles.len() > 1 {
+                candles.remove(0);
+            }
+        }
+    }
+}
+
+/// Requests `fetch_quotes` makes for `count` codes.
+pub fn quote_requests(count: usize) -> usize {
+    count.div_ceil(HQ_BATCH)
+}
+
+/// Parse lines of the form `var hq_str_<symbol>="f0,f1,...";`.
+fn parse_hq(text: &str) -> HashMap<String, Vec<String>> {
+    text.lines()
+        .filter_map(|line| {
+            let rest = line.trim().strip_prefix("var hq_str_")?;
+            let (symbol, value) = rest.split_once('=')?;
+            let value = value.trim_end_matches(';').trim_matches('"');
+            let fields = if value.is_empty() { Vec::new() } else { value.split(',').map(str::to_string).collect() };
+            Some((symbol.to_string(), fields))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An hq.sinajs.cn line: name, open, prev close, last, high, low, bid,
+    /// ask, volume, amount, twenty book fields, date and time.
+    fn hq_line(symbol: &str, last: &str, date: &str) -> String {
+        let book = vec!["100"; 20].join(",");
+        let fields = format!("标普500ETF,1.555,1.550,{},1.575,1.552,1.570,1.571,1234500,1934567.000,{}", last, book);
+        format!("var hq_str_{}=\"{},{},15:00:00,00\";", symbol, fields, date)
+    }
+
+    fn candle(day: u32, close: f64) -> Candle {
+        Candle {
+            time: NaiveDate::from_ymd_opt(2026, 10, day).unwrap().and_time(schedule::SESSION_CLOSE),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100.0,
+        }
+    }
+
+    #[test]
+    fn batched_response_maps_each_symbol() {
+        let text = [
+            hq_line("sh513500", "1.571", "2026-10-14"),
+            "var hq_str_sz999999=\"\";".to_string(),
+            "garbage".to_string(),
+            hq_line("sz159941", "0.000", "2026-10-14"),
+        ]
+        .join("\n");
+        let hq = parse_hq(&text);
+        assert_eq!(hq.len(), 3);
+        assert_eq!(hq["sz999999"], Vec::<String>::new());
+        assert!(Quote::from_hq(&hq["sz999999"]).is_none());
+
+        let quote = Quote::from_hq(&hq["sh513500"]).unwrap();
+        assert_eq!(quote.name, "标普500ETF");
+        assert_eq!((quote.open, quote.prev_close, quote.last), (1.555, 1.550, 1.571));
+        assert_eq!((quote.bid, quote.ask, quote.volume, quote.amount), (1.570, 1.571, 1234500.0, 1934567.0));
+        assert_eq!(quote.time.unwrap().to_string(), "2026-10-14 15:00:00");
+        assert!((quote.change_pct().unwrap() - 1.3548).abs() < 1e-3);
+        assert!((quote.spread_bps().unwrap() - 6.3674).abs() < 1e-3);
+
+        // No trade yet: no change to report.
+        let untraded = Quote::from_hq(&hq["sz159941"]).unwrap();
+        assert_eq!(untraded.change_pct(), None);
+        assert_eq!(quote_requests(0), 0);
+        assert_eq!(quote_requests(HQ_BATCH), 1);
+        assert_eq!(quote_requests(HQ_BATCH + 1), 2);
+    }
+
+    #[test]
+    fn quotes_update_the_latest_bar() {
+        let hq = parse_hq(&[hq_line("a", "1.571", "2026-10-14"), hq_line("b", "0.000", "2026-10-14")].join("\n"));
+        let (traded, untraded) = (Quote::from_hq(&hq["a"]).unwrap(), Quote::from_hq(&hq["b"]).unwrap());
+
+        // Today's bar is replaced in place.
+        let mut candles = vec![candle(13, 1.5), candle(14, 1.55)];
+        merge_quote(&mut candles, &traded);
+        assert_eq!(candles.len(), 2);
+        assert_eq!(candles[1].date(), NaiveDate::from_ymd_opt(2026, 10, 14).unwrap());
+        assert_eq!((candles[1].close, candles[1].high), (1.571, 1.575));
+        assert_eq!(candles[0].close, 1.5);
+
+        // A new day is appended and the oldest dropped.
+        let mut candles = vec![candle(12, 1.4), candle(13, 1.5)];
+        merge_quote(&mut candles, &traded);
+        assert_eq!(candles.iter().map(|c| c.close).collect::<Vec<_>>(), [1.5, 1.571]);
+
+        // Quotes older than the series, and ones without a trade, are ignored.
+        let mut candles = vec![candle(14, 1.5), candle(15, 1.6)];
+        merge_quote(&mut candles, &traded);
+        assert_eq!(candles.iter().map(|c| c.close).collect::<Vec<_>>(), [1.5, 1.6]);
+        let mut candles = vec![candle(13, 1.5)];
+        merge_quote(&mut candles, &untraded);
+        assert_eq!(candles.iter().map(|c| c.close).collect::<Vec<_>>(), [1.5]);
+    }
+}
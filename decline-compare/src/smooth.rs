@@ -0,0 +1,121 @@
+//! Optional noise reduction ahead of the indicators, for minute bars and
+//! thinly traded ETFs whose closes jump around a level. Off unless
+//! `[smoothing] method` or a command's `--smooth` picks one:
+//!
+//! - `kalman`: a local-level Kalman filter, following the close with a
+//!   gain set by `kalman_ratio`, the process-to-measurement noise ratio;
+//!   smaller is smoother and slower;
+//! - `hull`: the Hull moving average over `hull_period` bars, which lags
+//!   less than a plain average of the same length.
+//!
+//! Only the close is smoothed; each bar's open, high and low move with it,
+//! so bars keep their shape. Both filters only look back, so a smoothed
+//! bar never depends on later ones. Declines, closes, ATR-sized ladders
+//! and return statistics stay on the raw bars.
+
+use std::borrow::Cow;
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::config::SmoothingConfig;
+use crate::sina::Candle;
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Smoothing {
+    #[default]
+    Off,
+    Kalman,
+    Hull,
+}
+
+fn kalman(values: &[f64], ratio: f64) -> Vec<f64> {
+    let Some(&first) = values.first() else {
+        return Vec::new();
+    };
+    // Measurement noise is 1, so `ratio` is the process noise.
+    let (mut level, mut variance) = (first, 1.0);
+    values
+        .iter()
+        .map(|&z| {
+            variance += ratio;
+            let gain = variance / (variance + 1.0);
+            level += gain * (z - level);
+            variance *= 1.0 - gain;
+            level
+        })
+        .collect()
+}
+
+/// Weighted moving average of `period` values ending at each index; the
+/// values themselves until there are enough.
+fn wma(values: &[f64], period: usize) -> Vec<f64> {
+    let weights = (period * (period + 1) / 2) as f64;
+    (0..values.len())
+        .map(|i| match i.checked_sub(period - 1) {
+            Some(start) => values[start..=i].iter().zip(1..).map(|(v, w)| v * w as f64).sum::<f64>() / weights,
+            None => values[i],
+        })
+        .collect()
+}
+
+fn hull(values: &[f64], period: usize) -> Vec<f64> {
+    let period = period.max(2);
+    let (half, full) = (wma(values, period / 2), wma(values, period));
+    let raw: Vec<f64> = half.iter().zip(&full).map(|(h, f)| 2.0 * h - f).collect();
+    let mut smoothed = wma(&raw, (period as f64).sqrt().round() as usize);
+    // Until the full average has its window, the combination is the raw value.
+    for (s, v) in smoothed.iter_mut().zip(values).take(period - 1) {
+        *s = *v;
+    }
+    smoothed
+}
+
+impl fmt::Display for Smoothing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Smoothing::Off => "off",
+            Smoothing::Kalman => "Kalman",
+            Smoothing::Hull => "Hull MA",
+        })
+    }
+}
+
+/// `values` smoothed by `method`.
+pub fn series(values: &[f64], method: Smoothing, cfg: &SmoothingConfig) -> Vec<f64> {
+    match method {
+        Smoothing::Off => values.to_vec(),
+        Smoothing::Kalman => kalman(values, cfg.kalman_ratio),
+        Smoothing::Hull => hull(values, cfg.hull_period),
+    }
+}
+
+/// `candles` with smoothed closes, the rest of each bar shifted to match;
+/// borrowed as they are when smoothing is off.
+pub fn candles<'a>(candles: &'a [Candle], method: Smoothing, cfg: &SmoothingConfig) -> Cow<'a, [Candle]> {
+    if method == Smoothing::Off {
+        return Cow::Borrowed(candles);
+    }
+    let closes: Vec<f64> = candles.iter().map(|c| c.close).collect();
+    let smoothed = series(&closes, method, cfg);
+    Cow::Owned(
+        candles
+            .iter()
+            .zip(smoothed)
+            .map(|(c, close)| {
+                let shift = close - c.close;
+                Candle {
+                    time: c.time,
+                    open: c.open + shift,
+                    high: c.high + shift,
+                    low: c.low + shift,
+                    close,
+                    volume: c.volume,
+                }
+            })
+            .collect(),
+    )
+}
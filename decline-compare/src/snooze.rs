@@ -0,0 +1,134 @@
+//! Codes left out of decline reports and alerts for a while, e.g. after
+//! acting on a dip, without taking them off the watchlist.
+//!
+//! Snoozes are kept in `$STOCK_DATA_DIR/snoozed.json` (one file per
+//! profile) and re-read on every screen, so a running watch picks up new
+//! ones on its next pass.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{self, Config, OutputFormat};
+use crate::cooldown;
+use crate::http;
+use crate::i18n::tr;
+use crate::schedule;
+use crate::symbols;
+
+/// How long a snooze lasts without `--for`.
+const DEFAULT_SECS: u64 = 7 * 86_400;
+
+#[derive(Args, Debug)]
+pub struct SnoozeArgs {
+    /// Codes to snooze; with none, lists the active snoozes
+    #[arg(value_parser = symbols::parse_code)]
+    codes: Vec<String>,
+
+    /// How long to snooze for, e.g. 3d or 2w [default: 7d]
+    #[arg(long = "for", value_name = "DURATION", value_parser = http::parse_secs)]
+    duration: Option<u64>,
+
+    /// Lift the snooze on the codes instead, or on every code if none are given
+    #[arg(long, conflicts_with = "duration")]
+    clear: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Snoozes {
+    /// Unix seconds each snoozed code wakes up at.
+    until: BTreeMap<String, u64>,
+}
+
+pub fn default_path(profile: Option<&str>) -> PathBuf {
+    config::data_dir().join(config::profiled("snoozed", "json", profile))
+}
+
+impl Snoozes {
+    /// The snoozes at `path`; none if there is no file yet.
+    pub fn load(path: &Path) -> Result<Snoozes, Box<dyn std::error::Error>> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Snoozes::default()),
+            Err(e) => return Err(tr!("Failed to read {}: {}", "读取 {} 失败: {}", path.display(), e).into()),
+        };
+        serde_json::from_str(&text)
+            .map_err(|e| tr!("Failed to parse {}: {}", "解析 {} 失败: {}", path.display(), e).into())
+    }
+
+    /// Save, dropping snoozes that have run out.
+    pub fn save(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let now = cooldown::now_secs();
+        self.until.retain(|_, &mut until| until > now);
+        config::write_atomic(path, &serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn contains(&self, code: &str) -> bool {
+        self.until.contains_key(code)
+    }
+
+    /// The snoozes still running for `cfg`'s profile.
+    pub fn active(cfg: &Config) -> Result<Snoozes, Box<dyn std::error::Error>> {
+        let now = cooldown::now_secs();
+        let mut snoozes = Snoozes::load(&default_path(cfg.profile.as_deref()))?;
+        snoozes.until.retain(|_, &mut until| until > now);
+        Ok(snoozes)
+    }
+}
+
+#[derive(Serialize)]
+struct SnoozeRow {
+    code: String,
+    until: String,
+}
+
+pub fn run(args: &SnoozeArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let path = default_path(cfg.profile.as_deref());
+    let mut snoozes = Snoozes::load(&path)?;
+    if args.clear {
+        if args.codes.is_empty() {
+            snoozes.until.clear();
+        } else {
+            for code in &args.codes {
+                snoozes.until.remove(code);
+            }
+        }
+        snoozes.save(&path)?;
+    } else if !args.codes.is_empty() {
+        let until = cooldown::now_secs() + args.duration.unwrap_or(DEFAULT_SECS);
+        for code in &args.codes {
+            snoozes.until.insert(code.clone(), until);
+        }
+        snoozes.save(&path)?;
+    }
+
+    let now = cooldown::now_secs();
+    let rows: Vec<SnoozeRow> = snoozes
+        .until
+        .iter()
+        .filter(|&(_, &until)| until > now)
+        .map(|(code, &until)| SnoozeRow {
+            code: code.clone(),
+            until: schedule::at(until).format("%Y-%m-%d %H:%M").to_string(),
+        })
+        .collect();
+    if cfg.output == OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&rows)?);
+        return Ok(());
+    }
+
+    println!("\n {}", tr!("Snoozed codes:", "已暂停的代码:"));
+    println!("-----------------------------------------");
+    if rows.is_empty() {
+        println!("{}", tr!("None", "无"));
+    }
+    for row in &rows {
+        println!("{}", tr!("Code: {} | Until: {}", "代码: {} | 截止: {}", row.code, row.until));
+    }
+    Ok(())
+}
@@ -0,0 +1,111 @@
+//! Daily bars for spot prices that have no A-share listing, so watchlist
+//! entries like `XAUUSD` or `BTC-USD` can sit beside the ETFs tracking them.
+//!
+//! Precious metals come from Sina's global futures service (London spot
+//! gold and silver), crypto pairs from Coinbase's public candles. Prices are
+//! in USD; comparing against a CNY-priced ETF (e.g. `spread 518880/XAUUSD`)
+//! folds the exchange rate into the ratio.
+
+use chrono::{DateTime, NaiveDate};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::http;
+use crate::schedule;
+use crate::sina::Candle;
+
+/// Watchlist entries quoted on Sina's global futures service, and the
+/// symbol each is requested as.
+const METALS: &[(&str, &str)] = &[("XAUUSD", "XAU"), ("XAGUSD", "XAG")];
+
+/// Most daily candles Coinbase returns for one request.
+const COINBASE_MAX_BARS: usize = 300;
+
+/// Whether `code` is a spot entry rather than an exchange-listed code.
+pub fn is_spot(code: &str) -> bool {
+    !code.chars().all(|c| c.is_ascii_digit())
+}
+
+/// The last `days` daily bars of `code`, oldest first, with the HTTP
+/// status like `sina::fetch_etf_kline`. Crypto pairs return at most
+/// `COINBASE_MAX_BARS`.
+pub async fn fetch_kline(code: &str, days: usize) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    let code = code.to_ascii_uppercase();
+    if let Some(&(_, symbol)) = METALS.iter().find(|(c, _)| *c == code) {
+        return fetch_metal(symbol, days).await;
+    }
+    if code.contains('-') {
+        return fetch_coinbase(&code, days).await;
+    }
+    Err(format!("unknown spot symbol '{}' (use XAUUSD, XAGUSD or a Coinbase pair like BTC-USD)", code).into())
+}
+
+#[derive(Deserialize)]
+struct SinaFuturesBar {
+    date: String,
+    open: String,
+    high: String,
+    low: String,
+    close: String,
+    volume: String,
+}
+
+fn bar(date: NaiveDate, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Candle {
+    Candle { time: date.and_time(schedule::SESSION_CLOSE), open, high, low, close, volume }
+}
+
+/// The service answers JSONP, `var _XAU=([{...}, ...]);`, with its whole
+/// history oldest first.
+async fn fetch_metal(symbol: &str, days: usize) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    let url = format!(
+        "https://stock2.finance.sina.com.cn/futures/api/jsonp.php/var%20_{0}=/GlobalFuturesService.getGlobalFuturesDailyKLine?symbol={0}",
+        symbol
+    );
+    let resp = http::get(&url, &[("Referer", "https://finance.sina.com.cn")]).await?;
+    let (status, text) = (resp.status, resp.text);
+    let (Some(start), Some(end)) = (text.find('['), text.rfind(']')) else {
+        return Ok((Vec::new(), Some(status)));
+    };
+    let Ok(rows) = serde_json::from_str::<Vec<SinaFuturesBar>>(&text[start..=end]) else {
+        return Ok((Vec::new(), Some(status)));
+    };
+    let candles: Option<Vec<Candle>> = rows
+        .iter()
+        .map(|r| {
+            Some(bar(
+                NaiveDate::parse_from_str(&r.date, "%Y-%m-%d").ok()?,
+                r.open.parse().ok()?,
+                r.high.parse().ok()?,
+                r.low.parse().ok()?,
+                r.close.parse().ok()?,
+                r.volume.parse().unwrap_or(0.0),
+            ))
+        })
+        .collect();
+    let mut candles = candles.unwrap_or_default();
+    candles.drain(..candles.len().saturating_sub(days));
+    Ok((candles, Some(status)))
+}
+
+/// Coinbase candles are `[time, low, high, open, close, volume]`, newest
+/// first, one per UTC day.
+async fn fetch_coinbase(pair: &str, days: usize) -> Result<(Vec<Candle>, Option<u16>), Box<dyn std::error::Error>> {
+    let url = format!("https://api.exchange.coinbase.com/products/{}/candles?granularity=86400", pair);
+    // Coinbase rejects requests without a user agent.
+    let resp = http::get(&url, &[("User-Agent", "decline-compare")]).await?;
+    let status = resp.status;
+    let Ok(rows) = resp.json::<Vec<Vec<Value>>>() else {
+        return Ok((Vec::new(), Some(status)));
+    };
+    let mut candles: Vec<Candle> = rows
+        .iter()
+        .filter_map(|r| {
+            let num = |i: usize| r.get(i)?.as_f64();
+            let date = DateTime::from_timestamp(r.first()?.as_i64()?, 0)?.date_naive();
+            Some(bar(date, num(3)?, num(2)?, num(1)?, num(4)?, num(5)?))
+        })
+        .collect();
+    candles.reverse();
+    candles.drain(..candles.len().saturating_sub(days.min(COINBASE_MAX_BARS)));
+    Ok((candles, Some(status)))
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+
+use chrono::NaiveDate;
+use clap::Args;
+
+use crate::alerts::{Alert, AlertKind};
+use crate::config::Config;
+use crate::events;
+use crate::http;
+use crate::i18n::tr;
+use crate::indicators;
+use crate::schedule;
+use crate::sina::{self, Candle};
+use crate::symbols;
+
+#[derive(Args, Debug)]
+pub struct SpreadArgs {
+    /// Pair as NUMERATOR/DENOMINATOR, e.g. 518880/513500
+    #[arg(value_parser = parse_pair)]
+    pair: String,
+
+    /// Rolling window for the ratio's mean and standard deviation
+    #[arg(long, default_value_t = 60)]
+    window: usize,
+
+    /// Alert when the ratio's z-score reaches this many standard deviations
+    #[arg(long, default_value_t = 2.0)]
+    threshold: f64,
+}
+
+/// `(day, a.close / b.close)` for every day both series have a bar.
+fn ratio_series(a: &[Candle], b: &[Candle]) -> Vec<(NaiveDate, f64)> {
+    let b_by_day: HashMap<NaiveDate, f64> = b.iter().map(|c| (c.date(), c.close)).collect();
+    a.iter()
+        .filter_map(|c| {
+            let denom = *b_by_day.get(&c.date())?;
+            (denom > 0.0).then(|| (c.date(), c.close / denom))
+        })
+        .collect()
+}
+
+pub async fn fetch(code: &str, len: usize) -> Result<Vec<Candle>, Box<dyn std::error::Error>> {
+    let (mut candles, status) = sina::fetch_etf_kline(code, len).await?;
+    if let Some(status) = status
+        && status != 200
+    {
+        return Err(format!("HTTP {} for code: {}", status, code).into());
+    }
+    schedule::drop_unopened(&mut candles, schedule::now());
+    Ok(candles)
+}
+
+/// `NUM/DEN` with both codes normalized.
+pub fn parse_pair(s: &str) -> Result<String, String> {
+    let Some((num, den)) = s.split_once('/') else {
+        return Err(format!("expected a pair like 518880/513500, got '{}'", s));
+    };
+    Ok(format!("{}/{}", symbols::parse_code(num)?, symbols::parse_code(den)?))
+}
+
+pub async fn run(args: &SpreadArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    // `parse_pair` made sure of the slash.
+    let (num, den) = args.pair.split_once('/').unwrap_or_default();
+    // A few extra bars so holidays that only one side observes don't leave
+    // the window short.
+    let len = args.window + 10;
+    let deadline = http::deadline(&cfg.http);
+    let Some((a, b)) = http::until(deadline, async { (fetch(num, len).await, fetch(den, len).await) }).await else {
+        return Err(tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", args.pair).into());
+    };
+    let (a, b) = (a?, b?);
+
+    let series = ratio_series(&a, &b);
+    let ratios: Vec<f64> = series.iter().map(|(_, r)| *r).collect();
+    let Some(z) = indicators::zscore(&ratios, args.window) else {
+        return Err(
+            tr!("Not enough overlapping data for {}: got {} days", "{} 重叠数据不足: 仅 {} 日", args.pair, ratios.len())
+                .into(),
+        );
+    };
+    let window = &ratios[ratios.len() - args.window..];
+    let mean = window.iter().sum::<f64>() / window.len() as f64;
+    let (day, ratio) = series.last().unwrap();
+
+    println!("\n {}", tr!("Spread {} over {} days:", "{} {} 日价差:", args.pair, args.window));
+    println!("-----------------------------------------");
+    println!(
+        "{}",
+        tr!(
+            "Date: {} ({}) | Ratio: {:.4} | Mean: {:.4} | vs Mean: {:+.2}% | Z-score: {:+.2}",
+            "日期: {} ({}) | 比值: {:.4} | 均值: {:.4} | 偏离均值: {:+.2}% | Z 值: {:+.2}",
+            day,
+            schedule::bar_session(*day, schedule::now()),
+            ratio,
+            mean,
+            (ratio - mean) / mean * 100.0,
+            z
+        )
+    );
+
+    if z.abs() >= args.threshold {
+        let alert = Alert {
+            code: args.pair.clone(),
+            kind: AlertKind::SpreadStretch { zscore: z, threshold: args.threshold },
+        };
+        println!("\n {}", tr!("Alerts:", "预警:"));
+        println!("-----------------------------------------");
+        println!("{}", alert);
+        events::log(&[alert], *day, cfg.profile.as_deref());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,274 @@
+//! Exchange prefixes for Sina symbols (`sh510050`, `sz159915`,
+//! `bj430047`), looked up once per code from Sina's suggestion service and
+//! kept in `$STOCK_DATA_DIR/symbols.json`.
+//!
+//! The first digit is only a fair guess: Shanghai and Shenzhen both list
+//! codes starting with 1 and 5 among their funds, and stocks follow other
+//! rules again. The guess is still used, uncached, when the lookup fails.
+//!
+//! Codes are written many ways, `513500`, `sh513500`, `513500.SH` or
+//! `SSE:513500`; `Symbol` reads them all, and everything downstream keys on
+//! the bare code it yields. A market given with a code is taken over the
+//! lookup for the rest of the run.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::str::FromStr;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::http;
+use crate::i18n::tr;
+use crate::spot;
+
+const TABLE_FILE: &str = "symbols.json";
+
+/// Lookups in flight at once when resolving a batch.
+const LOOKUP_CONCURRENCY: usize = 8;
+
+/// Prefixes Sina uses for mainland exchanges.
+const MARKETS: &[&str] = &["sh", "sz", "bj"];
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SymbolTable {
+    /// Sina symbol by bare code.
+    symbols: BTreeMap<String, String>,
+}
+
+impl SymbolTable {
+    fn save(&self) -> Result<(), Box<dyn std::error::Error>> {
+        fs::create_dir_all(config::data_dir())?;
+        fs::write(default_path(), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+static TABLE: OnceLock<Mutex<SymbolTable>> = OnceLock::new();
+
+fn default_path() -> PathBuf {
+    config::data_dir().join(TABLE_FILE)
+}
+
+fn table() -> &'static Mutex<SymbolTable> {
+    TABLE.get_or_init(|| {
+        let table = fs::read_to_string(default_path())
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        Mutex::new(table)
+    })
+}
+
+/// The prefix the first digit usually means: Shanghai for 5, 6 and 9,
+/// Beijing for 4 and 8, Shenzhen otherwise.
+fn guess(code: &str) -> String {
+    let prefix = match code.chars().next() {
+        Some('5' | '6' | '9') => "sh",
+        Some('4' | '8') => "bj",
+        _ => "sz",
+    };
+    format!("{}{}", prefix, code)
+}
+
+/// Ask Sina's suggestion service for `code`.
+async fn lookup(code: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let url = format!("https://suggest3.sinajs.cn/suggest/key={}", code);
+    let text = http::get(&url, &[]).await?.text;
+    Ok(listed_symbol(&text, code))
+}
+
+/// The mainland listing of `code` in a suggestion response,
+/// `var suggestvalue="name,type,code,symbol,...;...";` with one entry per
+/// match. Matches on other markets or other codes are skipped.
+fn listed_symbol(text: &str, code: &str) -> Option<String> {
+    let value = text.split_once('"').map_or("", |(_, rest)| rest.split('"').next().unwrap_or(""));
+    value.split(';').find_map(|entry| {
+        let fields: Vec<&str> = entry.split(',').collect();
+        let symbol = *fields.get(3)?;
+        let listed = fields.get(2) == Some(&code)
+            && symbol.strip_suffix(code).is_some_and(|market| MARKETS.contains(&market));
+        listed.then(|| symbol.to_string())
+    })
+}
+
+/// The Sina symbol for an exchange-listed `code`.
+pub async fn sina_code(code: &str) -> String {
+    if let Some(symbol) = table().lock().ok().and_then(|t| t.symbols.get(code).cloned()) {
+        return symbol;
+    }
+    match lookup(code).await {
+        Ok(Some(symbol)) => {
+            if let Ok(mut table) = table().lock() {
+                table.symbols.insert(code.to_string(), symbol.clone());
+                if let Err(e) = table.save() {
+                    let path = default_path();
+                    eprintln!(
+                        "{}",
+                        tr!("Failed to save symbols to {}: {}", "保存代码表到 {} 失败: {}", path.display(), e)
+                    );
+                }
+            }
+            symbol
+        }
+        Ok(None) => guess(code),
+        Err(e) => {
+            eprintln!("{}", tr!("Failed to look up the market of {}: {}", "查询 {} 所属市场失败: {}", code, e));
+            guess(code)
+        }
+    }
+}
+
+/// Sina symbols for `codes`, in order.
+pub async fn sina_codes(codes: &[String]) -> Vec<String> {
+    stream::iter(codes).map(|code| sina_code(code)).buffered(LOOKUP_CONCURRENCY).collect().await
+}
+
+/// A code as given, split into the bare code and the market if it names one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    code: String,
+    market: Option<&'static str>,
+}
+
+/// The Sina prefix an exchange name or suffix stands for.
+fn market(name: &str) -> Option<&'static str> {
+    match name.to_ascii_uppercase().as_str() {
+        "SH" | "SS" | "SSE" | "SHSE" => Some("sh"),
+        "SZ" | "SZSE" => Some("sz"),
+        "BJ" | "BSE" => Some("bj"),
+        _ => None,
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let prefixed = s.get(..2).and_then(market).map(|m| (m, &s[2..]));
+        let (market, code) = if let Some((exchange, code)) = s.split_once(':') {
+            (Some(market(exchange).ok_or_else(|| format!("unknown exchange '{}' in '{}'", exchange, s))?), code)
+        } else if let Some((code, suffix)) = s.rsplit_once('.') {
+            (Some(market(suffix).ok_or_else(|| format!("unknown exchange suffix '{}' in '{}'", suffix, s))?), code)
+        } else if let Some((market, code)) = prefixed.filter(|(_, code)| code.chars().all(|c| c.is_ascii_digit())) {
+            (Some(market), code)
+        } else {
+            (None, s)
+        };
+        if market.is_none() && spot::is_spot(code) {
+            return Ok(Symbol { code: code.to_ascii_uppercase(), market: None });
+        }
+        if code.len() != 6 || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Err(format!("expected a 6-digit code like 513500, sh513500, 513500.SH or SSE:513500, got '{}'", s));
+        }
+        Ok(Symbol { code: code.to_string(), market })
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.code)
+    }
+}
+
+impl Symbol {
+    /// Use the market this symbol names, if any, for the rest of the run.
+    fn remember(&self) {
+        if let Some(market) = self.market
+            && let Ok(mut table) = table().lock()
+        {
+            table.symbols.insert(self.code.clone(), format!("{}{}", market, self.code));
+        }
+    }
+}
+
+/// The bare code `s` names, in any of the accepted forms; as a clap
+/// `value_parser` it normalizes code arguments.
+pub fn parse_code(s: &str) -> Result<String, String> {
+    let symbol: Symbol = s.parse()?;
+    symbol.remember();
+    Ok(symbol.code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggestions_resolve_the_mainland_listing() {
+        let text = concat!(
+            "var suggestvalue=\"",
+            "纳指ETF,201,513100,sh513100,纳指ETF,,纳指ETF,99,1,ESG,,;",
+            "标普ETF,201,513500,of513500,标普500ETF,,标普500ETF,99,1,,,;",
+            "标普ETF,201,513500,sh513500,标普500ETF,,标普500ETF,99,1,,,;",
+            "\";"
+        );
+        assert_eq!(listed_symbol(text, "513500").as_deref(), Some("sh513500"));
+        assert_eq!(listed_symbol(text, "513100").as_deref(), Some("sh513100"));
+        // Only the fund-share class, or another code's entry, is no listing.
+        assert_eq!(listed_symbol("var suggestvalue=\"x,201,159941,of159941,x\";", "159941"), None);
+        assert_eq!(listed_symbol(text, "159941"), None);
+        assert_eq!(listed_symbol("var suggestvalue=\"\";", "513500"), None);
+        assert_eq!(listed_symbol("", "513500"), None);
+    }
+
+    #[test]
+    fn guesses_follow_the_first_digit() {
+        for (code, symbol) in [
+            ("513500", "sh513500"),
+            ("600519", "sh600519"),
+            ("159941", "sz159941"),
+            ("000001", "sz000001"),
+            ("300750", "sz300750"),
+            ("430047", "bj430047"),
+            ("830799", "bj830799"),
+        ] {
+            assert_eq!(guess(code), symbol);
+        }
+    }
+
+    #[test]
+    fn codes_parse_in_every_form() {
+        for (input, code, market) in [
+            ("513500", "513500", None),
+            (" 513500 ", "513500", None),
+            ("sh513500", "513500", Some("sh")),
+            ("SZ159941", "159941", Some("sz")),
+            ("bj430047", "430047", Some("bj")),
+            ("513500.SH", "513500", Some("sh")),
+            ("513500.ss", "513500", Some("sh")),
+            ("159941.SZ", "159941", Some("sz")),
+            ("SSE:513500", "513500", Some("sh")),
+            ("szse:159941", "159941", Some("sz")),
+            ("BSE:430047", "430047", Some("bj")),
+            ("XAUUSD", "XAUUSD", None),
+            ("xagusd", "XAGUSD", None),
+            ("BTC-USD", "BTC-USD", None),
+            ("eth-usd", "ETH-USD", None),
+        ] {
+            let symbol: Symbol = input.parse().unwrap_or_else(|e| panic!("{}: {}", input, e));
+            assert_eq!((symbol.code.as_str(), symbol.market), (code, market), "{}", input);
+            assert_eq!(symbol.to_string(), code);
+        }
+    }
+
+    #[test]
+    fn malformed_codes_are_rejected() {
+        for input in ["", "51350", "5135001", "sh51350", "00700.HK", "HK:00700", "SSE:BTC-USD", "513500.", ":513500"] {
+            assert!(input.parse::<Symbol>().is_err(), "{}", input);
+            assert!(parse_code(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_code_yields_the_bare_code() {
+        assert_eq!(parse_code("513500.SH").unwrap(), "513500");
+        assert_eq!(parse_code("SSE:513500").unwrap(), "513500");
+        assert_eq!(parse_code("btc-usd").unwrap(), "BTC-USD");
+    }
+}
@@ -0,0 +1,116 @@
+//! `--format` report templates: literal text with `{field}` placeholders,
+//! e.g. `"{code} {decline:.2}% rsi {rsi:.0}"`.
+//!
+//! A placeholder names a report field or metric, optionally with a number
+//! of decimals after `:.`; `{{` and `}}` are literal braces. Fields a row
+//! has no value for render as "n/a".
+
+use std::str::FromStr;
+
+/// One field's value on a row.
+pub enum Value {
+    Text(String),
+    Number(f64),
+}
+
+impl Value {
+    /// `precision` decimals for numbers, `default` when not given.
+    pub fn render(&self, precision: Option<usize>, default: usize) -> String {
+        match self {
+            Value::Text(text) => text.clone(),
+            Value::Number(n) => format!("{:.*}", precision.unwrap_or(default), n),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Field { name: String, precision: Option<usize> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = s.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let mut spec = String::new();
+                    let mut closed = false;
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            closed = true;
+                            break;
+                        }
+                        spec.push(c);
+                    }
+                    if !closed {
+                        return Err(format!("unclosed '{{' in '{}'", s));
+                    }
+                    let (name, precision) = match spec.split_once(':') {
+                        Some((name, fmt)) => {
+                            let digits = fmt.strip_prefix('.').ok_or_else(|| {
+                                format!("expected a precision like {{{}:.2}}, got {{{}}}", name, spec)
+                            })?;
+                            let precision =
+                                digits.parse().map_err(|_| format!("bad precision '{}' in {{{}}}", digits, spec))?;
+                            (name.trim(), Some(precision))
+                        }
+                        None => (spec.trim(), None),
+                    };
+                    if name.is_empty() {
+                        return Err(format!("empty placeholder in '{}'", s));
+                    }
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                    parts.push(Part::Field { name: name.to_string(), precision });
+                }
+                '}' => return Err(format!("unmatched '}}' in '{}' (use '}}}}' for a literal brace)", s)),
+                _ => text.push(c),
+            }
+        }
+        parts.push(Part::Text(text));
+        parts.retain(|p| !matches!(p, Part::Text(t) if t.is_empty()));
+        Ok(Template { parts })
+    }
+}
+
+impl Template {
+    /// The field names the template refers to.
+    pub fn fields(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|p| match p {
+            Part::Field { name, .. } => Some(name.as_str()),
+            Part::Text(_) => None,
+        })
+    }
+
+    /// Fill the placeholders from `lookup`; numbers without a precision
+    /// get `default` decimals.
+    pub fn render(&self, lookup: impl Fn(&str) -> Option<Value>, default: usize) -> String {
+        self.parts
+            .iter()
+            .map(|p| match p {
+                Part::Text(text) => text.clone(),
+                Part::Field { name, precision } => {
+                    lookup(name).map_or_else(|| "n/a".to_string(), |v| v.render(*precision, default))
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,77 @@
+//! Each ETF's trend regime, from the slopes of its 20- and 60-day moving
+//! averages and ADX: a direction when both averages slope the same way and
+//! ADX shows a trend, strong when ADX is high as well.
+//!
+//! The regime is also the `trend` metric, -2 (strong down) to 2 (strong
+//! up), so filters can pair it with decline depth: `--filter trend<=down`
+//! keeps pullbacks within downtrends.
+
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::i18n::tr;
+use crate::indicators;
+
+/// Moving averages compared, with the bars each one's slope is taken over.
+const FAST: (usize, usize) = (20, 5);
+const SLOW: (usize, usize) = (60, 20);
+
+/// Below this ADX a market is ranging whatever its averages do.
+const TREND_ADX: f64 = 20.0;
+
+/// At or above this ADX a trend is strong.
+const STRONG_ADX: f64 = 30.0;
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Trend {
+    StrongDown,
+    Down,
+    Range,
+    Up,
+    StrongUp,
+}
+
+impl fmt::Display for Trend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Trend::StrongDown => tr!("strong down", "强势下跌"),
+            Trend::Down => tr!("down", "下跌"),
+            Trend::Range => tr!("range", "震荡"),
+            Trend::Up => tr!("up", "上涨"),
+            Trend::StrongUp => tr!("strong up", "强势上涨"),
+        };
+        f.write_str(&text)
+    }
+}
+
+impl Trend {
+    /// The regime as the `trend` metric.
+    pub fn value(self) -> f64 {
+        match self {
+            Trend::StrongDown => -2.0,
+            Trend::Down => -1.0,
+            Trend::Range => 0.0,
+            Trend::Up => 1.0,
+            Trend::StrongUp => 2.0,
+        }
+    }
+}
+
+/// The regime of `closes` given their ADX; `None` without enough history.
+pub fn classify(closes: &[f64], adx: Option<f64>) -> Option<Trend> {
+    let fast = indicators::ma_slope(closes, FAST.0, FAST.1)?;
+    let slow = indicators::ma_slope(closes, SLOW.0, SLOW.1)?;
+    let adx = adx?;
+    if adx < TREND_ADX || fast * slow <= 0.0 {
+        return Some(Trend::Range);
+    }
+    Some(match (fast > 0.0, adx >= STRONG_ADX) {
+        (true, true) => Trend::StrongUp,
+        (true, false) => Trend::Up,
+        (false, true) => Trend::StrongDown,
+        (false, false) => Trend::Down,
+    })
+}
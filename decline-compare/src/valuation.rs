@@ -0,0 +1,76 @@
+//! Valuation of the index an ETF tracks: PE and PB with their historical
+//! percentiles, from Danjuan's index valuation table.
+//!
+//! ETFs are matched to the table by the tracking index in their fund
+//! metadata. `index_code` under `[instruments.funds.<code>]` (e.g.
+//! "SH000300") pins the match when the names differ.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::fundinfo::FundInfo;
+use crate::http;
+
+const VALUATION_URL: &str = "https://danjuanfunds.com/djapi/index_eva/dj";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IndexValuation {
+    pub index_code: String,
+    pub name: String,
+    pub pe: Option<f64>,
+    pub pb: Option<f64>,
+    /// Where the current PE and PB sit in the index's own history, percent.
+    pub pe_pctile: Option<f64>,
+    pub pb_pctile: Option<f64>,
+}
+
+/// Parse `data.items`; Danjuan gives percentiles as 0-1 fractions.
+fn parse(body: &Value) -> Vec<IndexValuation> {
+    let num = |v: &Value| v.as_f64().or_else(|| v.as_str()?.parse().ok());
+    body["data"]["items"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            Some(IndexValuation {
+                index_code: item["index_code"].as_str()?.to_string(),
+                name: item["name"].as_str()?.to_string(),
+                pe: num(&item["pe"]),
+                pb: num(&item["pb"]),
+                pe_pctile: num(&item["pe_percentile"]).map(|p| p * 100.0),
+                pb_pctile: num(&item["pb_percentile"]).map(|p| p * 100.0),
+            })
+        })
+        .collect()
+}
+
+/// Every index in Danjuan's valuation table, in one request.
+pub async fn fetch_all() -> Result<Vec<IndexValuation>, Box<dyn std::error::Error>> {
+    let body: Value = http::get(VALUATION_URL, &[]).await?.json()?;
+    let table = parse(&body);
+    if table.is_empty() {
+        return Err("no index valuations in the response".into());
+    }
+    Ok(table)
+}
+
+/// Drop the suffixes fund documents add to index names, so "中证红利指数
+/// (全收益)" and "中证红利" match.
+fn bare_name(name: &str) -> String {
+    let name = name.split(['(', '（', '*', '×']).next().unwrap_or(name);
+    let name = name.split("指数").next().unwrap_or(name);
+    name.trim().trim_end_matches("价格").to_string()
+}
+
+/// The valuation of the index `fund` tracks: by its configured index code,
+/// else the table entry whose name matches its tracking index.
+pub fn find<'a>(table: &'a [IndexValuation], fund: &FundInfo) -> Option<&'a IndexValuation> {
+    if let Some(code) = &fund.index_code {
+        return table.iter().find(|v| v.index_code.eq_ignore_ascii_case(code));
+    }
+    let tracked = bare_name(fund.tracking_index.as_deref()?);
+    if tracked.is_empty() {
+        return None;
+    }
+    table.iter().find(|v| bare_name(&v.name) == tracked)
+}
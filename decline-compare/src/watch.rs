@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use clap::Args;
+use tokio::time::Instant;
+
+use crate::alerts::Alert;
+use crate::archive;
+use crate::breaker;
+use crate::cache::{self, KlineCache};
+use crate::config::{Config, NotifyConfig};
+use crate::cooldown::{self, AlertState};
+use crate::decline::{self, DeclineArgs, Fetch, Origin};
+use crate::events;
+use crate::export;
+use crate::http;
+use crate::i18n::tr;
+use crate::levels::{self, PriceAlert};
+use crate::notify::{self, Notifier};
+use crate::schedule::{self, BarSession, DeliveryWindow};
+use crate::service::{self, Health};
+use crate::sina::{self, Candle};
+use crate::snooze::Snoozes;
+
+#[derive(Args, Debug)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub decline: DeclineArgs,
+
+    /// Minutes between screen runs [default: 5]
+    #[arg(long)]
+    interval: Option<u64>,
+
+    /// Show triggered alerts as desktop notifications
+    #[arg(long)]
+    desktop: bool,
+
+    /// Post triggered alerts to this Slack incoming-webhook URL
+    #[arg(long)]
+    slack_webhook: Option<String>,
+
+    /// Post triggered alerts to this Discord webhook URL
+    #[arg(long)]
+    discord_webhook: Option<String>,
+
+    /// Minutes before a rule that stays triggered notifies again, never twice
+    /// off the same latest bar [default: 60]
+    #[arg(long)]
+    cooldown: Option<u64>,
+
+    /// Where notification state is kept between runs
+    /// [default: $STOCK_DATA_DIR/alert_state.json, or alert_state.PROFILE.json]
+    #[arg(long)]
+    state_file: Option<PathBuf>,
+
+    /// Only deliver alerts on weekdays inside these Shanghai-time windows,
+    /// e.g. 09:30-11:30,13:00-15:00; anything triggered outside is batched
+    /// into one message when the next window opens
+    #[arg(long, value_delimiter = ',')]
+    deliver: Vec<DeliveryWindow>,
+
+    /// Shorthand for --deliver 09:30-15:00
+    #[arg(long)]
+    market_hours: bool,
+
+    /// Run a single pass and exit, for scheduling from cron
+    #[arg(long)]
+    once: bool,
+
+    /// Serve the health endpoint on this address, e.g. 127.0.0.1:9184
+    #[arg(long)]
+    health_addr: Option<String>,
+
+    /// Shanghai-time windows in which every code's klines are refetched,
+    /// with a pass as each opens [default: 09:30-09:35,15:00-15:10]
+    #[arg(long, value_delimiter = ',')]
+    warmup: Vec<DeliveryWindow>,
+
+    /// Alert when the last trade reaches a price, e.g. "513100 <= 1.25",
+    /// checked every minute; end it in "repeat" to notify on every crossing
+    /// rather than once; repeatable, added to [watch] price_alerts
+    #[arg(long = "price-alert", value_name = "ALERT")]
+    price_alerts: Vec<PriceAlert>,
+
+    /// Fetch and evaluate as usual, but only print the notifications that
+    /// would go out and when; nothing is sent, logged or saved
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+impl WatchArgs {
+    /// Layer the flags given on the command line over `cfg`.
+    pub fn apply(&self, cfg: &mut Config) {
+        self.decline.apply(cfg);
+        if let Some(minutes) = self.interval {
+            cfg.watch.interval_minutes = minutes;
+        }
+        if let Some(addr) = &self.health_addr {
+            cfg.watch.health_addr = Some(addr.clone());
+        }
+        if !self.warmup.is_empty() {
+            cfg.watch.warmup = self.warmup.clone();
+        }
+        cfg.watch.price_alerts.extend(self.price_alerts.iter().cloned());
+        cfg.dry_run = self.dry_run;
+        let notify = &mut cfg.notify;
+        if self.desktop {
+            notify.desktop = true;
+        }
+        if let Some(url) = &self.slack_webhook {
+            notify.slack_webhook = Some(url.clone());
+        }
+        if let Some(url) = &self.discord_webhook {
+            notify.discord_webhook = Some(url.clone());
+        }
+        if let Some(minutes) = self.cooldown {
+            notify.cooldown_minutes = minutes;
+        }
+        if !self.deliver.is_empty() {
+            notify.deliver = self.deliver.clone();
+        }
+        if self.market_hours && !notify.deliver.contains(&schedule::MARKET_HOURS) {
+            notify.deliver.push(schedule::MARKET_HOURS);
+        }
+    }
+}
+
+/// The channels `cfg` turns on.
+pub fn notifiers(cfg: &NotifyConfig) -> Vec<Notifier> {
+    let mut notifiers = Vec::new();
+    if cfg.desktop {
+        notifiers.push(Notifier::Desktop);
+    }
+    if let Some(url) = &cfg.slack_webhook {
+        notifiers.push(Notifier::Slack(url.clone()));
+    }
+    if let Some(url) = &cfg.discord_webhook {
+        notifiers.push(Notifier::Discord(url.clone()));
+    }
+    notifiers
+}
+
+/// Daily bars for the watchlist, and the codes updated from quotes. Codes
+/// seen on an earlier pass are brought up to date from one batched
+/// realtime-quote request; the rest are fetched as klines and kept for next
+/// time.
+pub async fn refresh(
+    cache: &mut HashMap<String, Vec<Candle>>,
+    args: &DeclineArgs,
+    cfg: &Config,
+    deadline: Option<Instant>,
+) -> (Vec<(String, Fetch)>, Vec<String>) {
+    let (cached, missing): (Vec<String>, Vec<String>) =
+        cfg.watchlist.iter().cloned().partition(|code| cache.contains_key(code));
+    let mut quoted = Vec::new();
+    if !cached.is_empty() {
+        match http::until(deadline, sina::fetch_quotes(&cached)).await {
+            Some(Ok(quotes)) => {
+                for (code, quote) in &quotes {
+                    if let Some(candles) = cache.get_mut(code) {
+                        sina::merge_quote(candles, quote);
+                        quoted.push(code.clone());
+                    }
+                }
+            }
+            Some(Err(e)) => eprintln!("{}", tr!("Failed to fetch quotes: {}", "获取实时行情失败: {}", e)),
+            None => eprintln!("{}", tr!("Timed out before the deadline: {}", "截止时间前未获取: {}", "hq.sinajs.cn")),
+        }
+    }
+
+    let mut fetched: HashMap<String, Fetch> =
+        decline::fetch_klines(&missing, args, cfg, deadline).await.into_iter().collect();
+    for (code, fetch) in &fetched {
+        if let Some(Ok((candles, status))) = fetch
+            && status.is_none_or(|s| s == 200)
+            && !candles.is_empty()
+        {
+            cache.insert(code.clone(), candles.clone());
+        }
+    }
+    let bars = cfg
+        .watchlist
+        .iter()
+        .map(|code| {
+            let fetch = match fetched.remove(code) {
+                Some(fetch) => fetch,
+                None => Some(Ok((cache[code].clone(), None))),
+            };
+            (code.clone(), fetch)
+        })
+        .collect();
+    (bars, quoted)
+}
+
+/// What a pass would have delivered under `--dry-run`: the batch per
+/// notifier if a window is open, or how much is held and until when.
+fn preview(notifiers: &[Notifier], windows: &[DeliveryWindow], now: u64, batch: &[Alert], held: usize) {
+    println!("\n {}", tr!("Dry run, nothing sent:", "试运行, 未发送:"));
+    println!("-----------------------------------------");
+    if !schedule::is_open(windows, now) {
+        let opens = schedule::next_window(windows, now)
+            .map_or_else(|| "n/a".to_string(), |at| schedule::at(at).format("%Y-%m-%d %H:%M").to_string());
+        println!(
+            "{}",
+            tr!(
+                "Outside the delivery windows; {} alerts held until {}",
+                "不在推送时段, {} 条预警暂存至 {}",
+                held,
+                opens
+            )
+        );
+        return;
+    }
+    if batch.is_empty() {
+        println!("{}", tr!("No notifications", "无通知"));
+        return;
+    }
+    if notifiers.is_empty() {
+        println!("{}", tr!("No notifiers configured", "未配置通知渠道"));
+    }
+    for notifier in notifiers {
+        println!("{}", tr!("Would notify via {}:", "将通过 {} 通知:", notifier.name()));
+        for alert in batch {
+            println!("  {}", alert);
+        }
+    }
+}
+
+/// The alerts the `[watch]` price levels on codes that aren't snoozed raise
+/// now, printed and, unless this is a dry run, logged as events.
+async fn check_levels(cfg: &Config, state: &mut AlertState) -> Vec<Alert> {
+    let snoozes = match Snoozes::active(cfg) {
+        Ok(snoozes) => snoozes,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Vec::new();
+        }
+    };
+    let awake: Vec<PriceAlert> =
+        cfg.watch.price_alerts.iter().filter(|level| !snoozes.contains(&level.code)).cloned().collect();
+    let raised = levels::check(&awake, state, http::deadline(&cfg.http)).await;
+    for alert in &raised {
+        println!("{}", tr!("Price alert: {}", "价格预警: {}", alert));
+    }
+    if !cfg.dry_run {
+        events::log(&raised, schedule::now().date_naive(), cfg.profile.as_deref());
+    }
+    raised
+}
+
+/// Send `fresh` and anything held back if a delivery window is open, else
+/// hold it for the next; under `--dry-run` only show what would go out.
+async fn deliver(
+    fresh: Vec<Alert>,
+    cfg: &Config,
+    state: &mut AlertState,
+    state_path: &Path,
+    notifiers: &[Notifier],
+    windows: &[DeliveryWindow],
+) {
+    let now = cooldown::now_secs();
+    let batch = if schedule::is_open(windows, now) {
+        let mut batch = state.take_pending();
+        batch.extend(fresh);
+        batch
+    } else {
+        state.defer(fresh);
+        Vec::new()
+    };
+    if cfg.dry_run {
+        preview(notifiers, windows, now, &batch, state.pending().len());
+        return;
+    }
+    // Saved before sending: a run killed mid-send may drop a notification,
+    // but a rerun never sends one twice.
+    if let Err(e) = state.save(state_path) {
+        eprintln!("{}", tr!("Failed to save alert state to {}: {}", "保存预警状态到 {} 失败: {}", state_path.display(), e));
+    }
+    notify::send_all(notifiers, &batch).await;
+}
+
+/// Re-run the decline screen on a fixed interval, forwarding alerts to the
+/// configured notifiers, and check the price levels every minute between.
+/// Runs until SIGTERM or Ctrl-C, or for one pass with `--once`, then saves
+/// the bars and anything still held for delivery.
+pub async fn run(args: &WatchArgs, cfg: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let interval = cfg.watch.interval_minutes;
+    if interval == 0 {
+        return Err("--interval must be at least 1 minute".into());
+    }
+    let notifiers = notifiers(&cfg.notify);
+    let state_path = args.state_file.clone().unwrap_or_else(|| cooldown::default_state_path(cfg.profile.as_deref()));
+    let mut state = AlertState::load(&state_path)?;
+    let windows = &cfg.notify.deliver;
+    if !windows.is_empty() {
+        let list: Vec<String> = windows.iter().map(|w| w.to_string()).collect();
+        eprintln!(
+            "{}",
+            tr!(
+                "Delivering alerts on weekdays during {} (Asia/Shanghai)",
+                "预警仅在工作日 {} (北京时间) 推送",
+                list.join(", ")
+            )
+        );
+    }
+    decline::validate(&args.decline, cfg)?;
+    let health = Health::shared();
+    if let Some(addr) = &cfg.watch.health_addr {
+        service::serve_health(addr, health.clone(), 3 * interval * 60).await?;
+    }
+
+    // Bars saved after the last close save refetching them on a restart.
+    let fetch_len = args.decline.fetch_len(cfg);
+    let cache_path = cache::default_path();
+    let saved =
+        if cache::bypassed() { KlineCache::default() } else { KlineCache::load(&cache_path, fetch_len, cooldown::now_secs()) };
+    let mut bars: HashMap<String, Vec<Candle>> =
+        cfg.watchlist.iter().filter_map(|code| Some((code.clone(), saved.bars(code, fetch_len)?))).collect();
+    let mut session = None;
+
+    let mut ticker = tokio::time::interval(Duration::from_secs(interval * 60));
+    // Each pass checks the levels too, so the first check waits a minute.
+    let poll = Duration::from_secs(levels::POLL_SECS);
+    let mut level_ticker = tokio::time::interval_at(Instant::now() + poll, poll);
+    let has_levels = !cfg.watch.price_alerts.is_empty();
+    let mut shutdown = std::pin::pin!(service::terminated());
+    service::notify("READY=1");
+    let warmup = &cfg.watch.warmup;
+    loop {
+        let next_warmup = schedule::next_window(warmup, cooldown::now_secs())
+            .map(|at| Duration::from_secs(at.saturating_sub(cooldown::now_secs())));
+        tokio::select! {
+            _ = ticker.tick() => {}
+            _ = async {
+                match next_warmup {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => std::future::pending().await,
+                }
+            } => {}
+            _ = level_ticker.tick(), if has_levels => {
+                let raised = check_levels(cfg, &mut state).await;
+                if !raised.is_empty() || !cfg.dry_run {
+                    deliver(raised, cfg, &mut state, &state_path, &notifiers, windows).await;
+                }
+                continue;
+            }
+            _ = &mut shutdown => break,
+        }
+        // Quotes only patch the last bar; warmup passes start over from
+        // klines, so the open and the close get the feed's own bars.
+        // No windows would read as always open.
+        let warming = !warmup.is_empty() && schedule::is_open(warmup, cooldown::now_secs());
+        if warming {
+            bars.clear();
+        }
+        let deadline = http::deadline(&cfg.http);
+        let (fetched, quoted) = refresh(&mut bars, &args.decline, cfg, deadline).await;
+        let mut report = match decline::build(&args.decline, cfg, fetched, deadline).await {
+            Ok(report) => report,
+            Err(e) if args.once => return Err(e),
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        report.stamp(&quoted, Origin::Quote, Some(schedule::now().fixed_offset()));
+        decline::print(&args.decline, cfg, &report);
+        if !cfg.dry_run {
+            archive::save(&args.decline, cfg, &report);
+            export::daily(cfg, &report).await;
+        }
+        session = report.session;
+        breaker::save();
+        if warming && !cfg.dry_run {
+            save_cache(&cache_path, fetch_len, session, &bars);
+        }
+        if let Ok(mut health) = health.lock() {
+            health.record(&report);
+        }
+        service::notify(&format!(
+            "STATUS=Last pass {}: {} alerts, {} timed out",
+            report.generated_at.format("%H:%M"),
+            report.alerts.len(),
+            report.timed_out.len()
+        ));
+
+        let mut fresh = state.filter(&report.alerts, &bars, cooldown::now_secs(), cfg.notify.cooldown_minutes * 60);
+        fresh.extend(check_levels(cfg, &mut state).await);
+        deliver(fresh, cfg, &mut state, &state_path, &notifiers, windows).await;
+        if args.once {
+            break;
+        }
+    }
+
+    service::notify("STOPPING=1");
+    if !args.once {
+        eprintln!("{}", tr!("Shutting down", "正在退出"));
+    }
+    if cfg.dry_run {
+        return Ok(());
+    }
+    // Deliver what was held back if a window is open; otherwise it stays in
+    // the state file for the next run.
+    if schedule::is_open(windows, cooldown::now_secs()) {
+        let pending = state.take_pending();
+        if !pending.is_empty() {
+            notify::send_all(&notifiers, &pending).await;
+        }
+    }
+    if let Err(e) = state.save(&state_path) {
+        eprintln!("{}", tr!("Failed to save alert state to {}: {}", "保存预警状态到 {} 失败: {}", state_path.display(), e));
+    }
+    save_cache(&cache_path, fetch_len, session, &bars);
+    Ok(())
+}
+
+/// Save `bars` for later runs; same rule as the one-off screen, only a
+/// closed session's bars are kept.
+fn save_cache(path: &Path, fetch_len: usize, session: Option<BarSession>, bars: &HashMap<String, Vec<Candle>>) {
+    if session.is_some_and(|s| s != BarSession::Live)
+        && !bars.is_empty()
+        && !cache::bypassed()
+        && let Err(e) = KlineCache::save(
+            path,
+            fetch_len,
+            bars.iter().map(|(code, candles)| (code.clone(), candles.clone())).collect(),
+            cooldown::now_secs(),
+        )
+    {
+        eprintln!("{}", tr!("Failed to save cache to {}: {}", "保存缓存到 {} 失败: {}", path.display(), e));
+    }
+}
@@ -0,0 +1,106 @@
+//! Invariants the indicators hold for any input, checked over generated
+//! price series.
+
+use chrono::NaiveDate;
+use decline_compare::config::SmoothingConfig;
+use decline_compare::indicators;
+use decline_compare::schedule;
+use decline_compare::sina::Candle;
+use decline_compare::smooth::{self, Smoothing};
+use proptest::prelude::*;
+
+/// Closes of a plausible fund price, between a cent and a thousand.
+fn closes(len: impl Into<proptest::sample::SizeRange>) -> impl Strategy<Value = Vec<f64>> {
+    prop::collection::vec(0.01..1000.0f64, len)
+}
+
+/// Daily bars around `closes`, with the high and low `spread` either side.
+fn bars(closes: &[f64], spread: f64) -> Vec<Candle> {
+    let start = NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+    closes
+        .iter()
+        .zip(start.iter_days())
+        .map(|(&close, date)| Candle {
+            time: date.and_time(schedule::SESSION_CLOSE),
+            open: close,
+            high: close * (1.0 + spread),
+            low: close * (1.0 - spread),
+            close,
+            volume: 1.0,
+        })
+        .collect()
+}
+
+fn close_to(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-9 * b.abs().max(1.0)
+}
+
+proptest! {
+    #[test]
+    fn rsi_is_between_0_and_100(closes in closes(2..300), period in 1..50usize) {
+        if let Some(rsi) = indicators::rsi(&closes, period) {
+            prop_assert!((0.0..=100.0).contains(&rsi), "rsi {}", rsi);
+        }
+    }
+
+    #[test]
+    fn drawdown_is_never_positive(closes in closes(1..300)) {
+        let drawdown = indicators::drawdown(&closes).unwrap();
+        prop_assert!(drawdown <= 0.0, "drawdown {}", drawdown);
+    }
+
+    #[test]
+    fn averages_of_a_constant_are_the_constant(value in 0.01..1000.0f64, len in 1..300usize, period in 1..50usize) {
+        let closes = vec![value; len];
+        if let Some(ema) = indicators::ema(&closes, period) {
+            prop_assert!(close_to(ema, value), "ema {} of {}", ema, value);
+        }
+        if let Some(slope) = indicators::ma_slope(&closes, period, len.saturating_sub(period)) {
+            prop_assert!(close_to(slope, 0.0), "ma slope {}", slope);
+        }
+        let cfg = SmoothingConfig { hull_period: period, ..SmoothingConfig::default() };
+        for method in [Smoothing::Kalman, Smoothing::Hull] {
+            for smoothed in smooth::series(&closes, method, &cfg) {
+                prop_assert!(close_to(smoothed, value), "{} gave {} for {}", method, smoothed, value);
+            }
+        }
+    }
+
+    #[test]
+    fn zscore_of_a_constant_is_zero(value in 0.01..1000.0f64, len in 2..100usize) {
+        prop_assert_eq!(indicators::zscore(&vec![value; len], len), Some(0.0));
+    }
+
+    #[test]
+    fn atr_is_never_negative(closes in closes(2..300), spread in 0.0..0.1f64, period in 1..50usize) {
+        if let Some(atr) = indicators::atr(&bars(&closes, spread), period) {
+            prop_assert!(atr >= 0.0, "atr {}", atr);
+        }
+    }
+
+    #[test]
+    fn percentiles_are_between_0_and_100(closes in closes(1..300), spread in 0.0..0.1f64, period in 1..50usize) {
+        if let Some(pct) = indicators::range_percentile(&bars(&closes, spread), period) {
+            prop_assert!((0.0..=100.0).contains(&pct), "range percentile {}", pct);
+        }
+        let last = *closes.last().unwrap();
+        let pct = indicators::percentile_of(last, &closes).unwrap();
+        prop_assert!((0.0..=100.0).contains(&pct), "percentile {}", pct);
+    }
+
+    #[test]
+    fn correlation_is_between_minus_1_and_1(a in closes(2..200), b in closes(2..200)) {
+        if let Some(r) = indicators::correlation(&a, &b) {
+            prop_assert!((-1.0 - 1e-9..=1.0 + 1e-9).contains(&r), "correlation {}", r);
+        }
+    }
+
+    #[test]
+    fn regression_stays_in_range(closes in closes(3..300), period in 3..100usize) {
+        if let Some(fit) = indicators::regression(&closes, period) {
+            prop_assert!(fit.r_squared <= 1.0 + 1e-9, "r² {}", fit.r_squared);
+            prop_assert!((0.0..=100.0).contains(&fit.position), "position {}", fit.position);
+            prop_assert!(fit.annual_slope >= -100.0, "slope {}", fit.annual_slope);
+        }
+    }
+}
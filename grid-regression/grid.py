@@ -5,9 +5,32 @@ import pandas as pd
 import json
 import pickle
 import os
+import sys
+import itertools
+import tomllib
+from multiprocessing import Pool
 from datetime import datetime
 
 
+class EtfCommission(bt.CommInfoBase):
+    """
+    Broker commission for exchange-traded funds: a percentage of the trade
+    value with an optional per-order minimum. ETFs are exempt from stamp
+    duty, so sells cost the same as buys.
+    """
+    params = (
+        ("commission_bps", 0),
+        ("min_commission", 0),
+        ("stocklike", True),
+        ("commtype", bt.CommInfoBase.COMM_PERC),
+    )
+
+    def _getcommission(self, size, price, pseudoexec):
+        if not self.p.commission_bps:
+            return 0.0
+        return max(abs(size) * price * self.p.commission_bps / 10000, self.p.min_commission)
+
+
 class GridStrategy(bt.Strategy):
     params = (
         ("grid_up_pct", 0.02),
@@ -17,6 +40,9 @@ class GridStrategy(bt.Strategy):
         ("buy_strategy", "by_latest_buy"),  # by_latest_buy / by_latest_sell
         ("json_only", False),               # only generate json for regression result, don't print it on console
         ("json_output", None),              # the json file to store regression result
+        ("quiet", False),                   # print nothing, only keep the result on self.result
+        ("stop_loss_pct", 0),               # sell everything once price is this far below the average cost, 0 = off
+        ("max_hold_days", 0),               # sell everything this many bars after the initial buy, 0 = off
     )
 
     def __init__(self):
@@ -28,6 +54,13 @@ class GridStrategy(bt.Strategy):
         self.sell_count = 0
         self.initial_cash = None
         self.max_capital_used = 0
+        self.result = None
+        self.init_bar = None
+        self.commission_paid = 0.0
+
+    def notify_order(self, order):
+        if order.status == order.Completed:
+            self.commission_paid += order.executed.comm
 
     def log_trade(self, action, price, size):
         # the transaction detail
@@ -48,9 +81,29 @@ class GridStrategy(bt.Strategy):
     def start(self):
         self.initial_cash = self.broker.get_value()
 
+    def exit_all(self, action, price):
+        # close the whole position; the grid restarts with BUY INIT on the next bar
+        size = self.position.size
+        if size > 0:
+            self.sell(size=size)
+            self.sell_count += 1
+            self.log_trade(action, price, size)
+        self.units_bought = 0
+        self.last_sell_price = price
+        self.init_bar = None
+
     def next(self):
         price = self.data.close[0]
 
+        if self.units_bought > 0 and self.position.size > 0:
+            if self.params.stop_loss_pct and price <= self.position.price * (1 - self.params.stop_loss_pct):
+                self.exit_all("SELL STOP", price)
+                return
+            if self.params.max_hold_days and self.init_bar is not None \
+                    and len(self) - self.init_bar >= self.params.max_hold_days:
+                self.exit_all("SELL HOLD", price)
+                return
+
         if self.units_bought == 0 and self.broker.get_cash() >= self.params.unit_cash:
             size = int(self.params.unit_cash / price)
             self.buy(size=size)
@@ -58,6 +111,7 @@ class GridStrategy(bt.Strategy):
             self.last_buy_price = price
             self.buy_count += 1
             self.log_trade("BUY INIT", price, size)
+            self.init_bar = len(self)
             current_capital_used = self.units_bought * self.params.unit_cash
             self.max_capital_used = max(self.max_capital_used, current_capital_used)
             return
@@ -112,15 +166,15 @@ class GridStrategy(bt.Strategy):
         trimmed_sell_count = self.sell_count
 
         # Remove the trades until the last one is sell
-        while trimmed_trades and trimmed_trades[-1]["action"] != "SELL":
+        while trimmed_trades and not trimmed_trades[-1]["action"].startswith("SELL"):
             last_trade = trimmed_trades.pop()
             if last_trade["action"].startswith("BUY"):
                 trimmed_buy_count -= 1
-            elif last_trade["action"] == "SELL":
+            elif last_trade["action"].startswith("SELL"):
                 trimmed_sell_count -= 1
 
         # recalcuate final_value 和 profit
-        if trimmed_trades and trimmed_trades[-1]["action"] == "SELL":
+        if trimmed_trades and trimmed_trades[-1]["action"].startswith("SELL"):
             final_value = trimmed_trades[-1]["value"]
             profit = final_value - self.initial_cash
             profit_pct = (profit / self.initial_cash) * 100
@@ -143,8 +197,12 @@ class GridStrategy(bt.Strategy):
             "buy_count": trimmed_buy_count,
             "sell_count": trimmed_sell_count,
             "capital_usage_pct": round(capital_usage_pct, 2), 
+            "commission": round(self.commission_paid, 2),
             "trades": trimmed_trades
         }
+        self.result = result
+        if self.params.quiet:
+            return
         json_str = json.dumps(result, ensure_ascii=False, indent=2)
 
         # print transaction details if json_only is not set on command line
@@ -157,6 +215,7 @@ class GridStrategy(bt.Strategy):
             print("\n===== 统计结果 =====")
             print(f"总买入次数: {trimmed_buy_count}")
             print(f"总卖出次数: {trimmed_sell_count}")
+            print(f"交易佣金: {self.commission_paid:.2f} 元")
             print(f"最终收益: {profit:.2f} 元")
             print(f"收益率: {profit_pct:.2f}%")
 
@@ -170,6 +229,152 @@ class GridStrategy(bt.Strategy):
             print(f"JSON 结果已导出到: {self.params.json_output}")
 
 
+class PortfolioGridStrategy(bt.Strategy):
+    """
+    The grid rules of GridStrategy run on every data feed at once, all
+    drawing on one cash balance. Each symbol may hold at most
+    max_units_per_symbol grid units, and at most max_holdings symbols may be
+    held at the same time (0 = no limit).
+    """
+    params = (
+        ("grid_up_pct", 0.02),
+        ("grid_down_pct", 0.02),
+        ("unit_cash", 10000),
+        ("max_units_per_symbol", 10),
+        ("max_holdings", 0),
+        ("buy_strategy", "by_latest_buy"),
+        ("stop_loss_pct", 0),
+        ("max_hold_days", 0),
+    )
+
+    def __init__(self):
+        self.books = {d: {"units": 0, "last_buy": None, "last_sell": None, "last_trade": None,
+                          "init_bar": None, "buys": 0, "sells": 0} for d in self.datas}
+        self.trades = []
+        self.equity_curve = []
+        self.initial_cash = None
+        self.commission_paid = 0.0
+        self.max_concurrent = 0
+        self.result = None
+
+    def start(self):
+        self.initial_cash = self.broker.get_value()
+
+    def notify_order(self, order):
+        if order.status == order.Completed:
+            self.commission_paid += order.executed.comm
+
+    def place(self, data, action, size):
+        price = data.close[0]
+        if action.startswith("BUY"):
+            self.buy(data=data, size=size)
+            self.books[data]["buys"] += 1
+        else:
+            self.sell(data=data, size=size)
+            self.books[data]["sells"] += 1
+        self.books[data]["last_trade"] = price
+        self.trades.append({
+            "date": str(self.datetime.date(0)),
+            "symbol": data._name,
+            "action": action,
+            "price": round(price, 3),
+            "size": int(size),
+        })
+
+    def next(self):
+        today = self.datetime.date(0)
+        # cash already promised to buys placed on this bar
+        available = self.broker.get_cash()
+        held = sum(1 for book in self.books.values() if book["units"] > 0)
+
+        for data, book in self.books.items():
+            # feeds that have not started yet or have no bar today are skipped
+            if not len(data) or data.datetime.date(0) != today:
+                continue
+            price = data.close[0]
+            position = self.getposition(data)
+            size = int(self.params.unit_cash / price)
+
+            if book["units"] > 0 and position.size > 0:
+                stopped = self.params.stop_loss_pct and price <= position.price * (1 - self.params.stop_loss_pct)
+                expired = self.params.max_hold_days and book["init_bar"] is not None \
+                    and len(self) - book["init_bar"] >= self.params.max_hold_days
+                if stopped or expired:
+                    self.place(data, "SELL STOP" if stopped else "SELL HOLD", position.size)
+                    book.update(units=0, last_sell=price, init_bar=None)
+                    held -= 1
+                    continue
+
+            if book["units"] == 0:
+                if available >= self.params.unit_cash and (not self.params.max_holdings
+                                                           or held < self.params.max_holdings):
+                    self.place(data, "BUY INIT", size)
+                    book.update(units=1, last_buy=price, init_bar=len(self))
+                    available -= self.params.unit_cash
+                    held += 1
+                continue
+
+            if price >= book["last_buy"] * (1 + self.params.grid_up_pct):
+                self.place(data, "SELL", size)
+                book.update(units=book["units"] - 1, last_sell=price, last_buy=price)
+                if book["units"] == 0:
+                    held -= 1
+
+            if self.params.buy_strategy == "by_latest_sell":
+                reference_price = book["last_trade"] or book["last_sell"] or book["last_buy"]
+            else:
+                reference_price = book["last_buy"]
+            if (
+                reference_price
+                and 0 < book["units"] < self.params.max_units_per_symbol
+                and available >= self.params.unit_cash
+                and price <= reference_price * (1 - self.params.grid_down_pct)
+            ):
+                self.place(data, "BUY", size)
+                book.update(units=book["units"] + 1, last_buy=price)
+                available -= self.params.unit_cash
+
+        self.max_concurrent = max(self.max_concurrent, held)
+        self.equity_curve.append({
+            "date": str(today),
+            "value": round(self.broker.get_value(), 2),
+            "cash": round(self.broker.get_cash(), 2),
+            "holdings": held,
+        })
+
+    def stop(self):
+        final_value = self.broker.get_value()
+        profit = final_value - self.initial_cash
+        peak = self.initial_cash
+        max_drawdown = 0.0
+        for point in self.equity_curve:
+            peak = max(peak, point["value"])
+            max_drawdown = max(max_drawdown, (peak - point["value"]) / peak * 100)
+
+        self.result = {
+            "initial_cash": round(self.initial_cash, 2),
+            "final_value": round(final_value, 2),
+            "profit": round(profit, 2),
+            "profit_pct": round(profit / self.initial_cash * 100, 2),
+            "max_drawdown_pct": round(max_drawdown, 2),
+            "buy_count": sum(book["buys"] for book in self.books.values()),
+            "sell_count": sum(book["sells"] for book in self.books.values()),
+            "commission": round(self.commission_paid, 2),
+            "max_concurrent_holdings": self.max_concurrent,
+            "symbols": {
+                data._name: {
+                    "buy_count": book["buys"],
+                    "sell_count": book["sells"],
+                    "units_held": book["units"],
+                    "value": round(self.getposition(data).size * data.close[0], 2) if len(data) else 0.0,
+                }
+                for data, book in self.books.items()
+            },
+            "equity_curve": self.equity_curve,
+            "trades": self.trades,
+        }
+
+
 def get_stock_data(symbol, start_date=None, end_date=None, cached=False):
     symbol = "sh" + symbol if symbol[0] == "5" else "sz" + symbol
 
@@ -208,6 +413,399 @@ def get_stock_data(symbol, start_date=None, end_date=None, cached=False):
 
 
 
+def trading_costs(args):
+    return {
+        "commission_bps": args.commission_bps,
+        "min_commission": args.min_commission,
+        "spread_bps": args.spread_bps,
+        "slippage_bps": args.slippage_bps,
+    }
+
+
+def setup_broker(cerebro, cash, costs=None):
+    """
+    Fund the broker and apply trading costs. Every fill is moved against the
+    order by half the bid/ask spread plus the slippage, both in basis points.
+    """
+    costs = costs or {}
+    cerebro.broker.set_cash(cash)
+    cerebro.broker.addcommissioninfo(EtfCommission(commission_bps=costs.get("commission_bps", 0),
+                                                   min_commission=costs.get("min_commission", 0)))
+    slip = (costs.get("spread_bps", 0) / 2 + costs.get("slippage_bps", 0)) / 10000
+    if slip:
+        cerebro.broker.set_slippage_perc(slip, slip_open=True, slip_limit=True, slip_match=True, slip_out=True)
+
+
+def run_backtest(df, unit_cash, total_units, quiet=True, costs=None, **strategy_params):
+    """Run the grid strategy over df and return its result dict."""
+    cerebro = bt.Cerebro()
+    setup_broker(cerebro, unit_cash * total_units, costs)
+    cerebro.adddata(bt.feeds.PandasData(dataname=df))
+    cerebro.addstrategy(GridStrategy, unit_cash=unit_cash, total_units=total_units, quiet=quiet, **strategy_params)
+    return cerebro.run()[0].result
+
+
+# Exchange names decline-compare accepts as a prefix, suffix or "EXCHANGE:" (symbols.rs).
+MARKETS = {"SH": "sh", "SS": "sh", "SSE": "sh", "SHSE": "sh", "SZ": "sz", "SZSE": "sz", "BJ": "bj", "BSE": "bj"}
+
+# decline-compare's watchlist when its config sets none (ETF_CODES in lib.rs).
+DEFAULT_WATCHLIST = [
+    "513520", "513350", "513870", "512800", "515000", "513030", "516810", "518880", "513500",
+    "512660", "510050", "512000", "513730", "512670", "512400", "513080", "517090", "513800",
+    "515750", "520580", "501090", "515710", "516970", "520830", "515220", "513110", "561360",
+]
+
+
+def parse_code(s):
+    """
+    The bare 6-digit code s names, read the way decline-compare's
+    symbols::parse_code does: 513500, sh513500, 513500.SH or SSE:513500.
+    Returns None for spot entries such as XAUUSD or BTC-USD, which have no
+    ETF history to backtest.
+    """
+    s = s.strip()
+    market = None
+    if ":" in s:
+        market, code = s.split(":", 1)
+    elif "." in s:
+        code, market = s.rsplit(".", 1)
+    elif s[:2].upper() in MARKETS and s[2:].isdigit():
+        market, code = s[:2], s[2:]
+    else:
+        code = s
+    if market is not None and market.upper() not in MARKETS:
+        raise ValueError(f"unknown exchange '{market}' in '{s}'")
+    if market is None and not code.isdigit():
+        return None
+    if len(code) != 6 or not code.isdigit():
+        raise ValueError(f"expected a 6-digit code like 513500, sh513500, 513500.SH or SSE:513500, got '{s}'")
+    return code
+
+
+def platform_config_dir():
+    """Where decline-compare looks for biga.toml without --config, per platform."""
+    home = os.path.expanduser("~")
+    if os.name == "nt":
+        return os.path.join(os.environ.get("APPDATA", home), "biga", "config")
+    if sys.platform == "darwin":
+        return os.path.join(home, "Library", "Application Support", "biga")
+    return os.path.join(os.environ.get("XDG_CONFIG_HOME") or os.path.join(home, ".config"), "biga")
+
+
+def config_file(args):
+    """--config, else the first biga.toml found next to decline-compare, here or in the platform config dir."""
+    if args.config:
+        return args.config
+    candidates = ["../decline-compare/biga.toml", "biga.toml", os.path.join(platform_config_dir(), "biga.toml")]
+    return next((path for path in candidates if os.path.exists(path)), None)
+
+
+def watchlist_symbols(args):
+    """
+    --symbols if given, otherwise the watchlist decline-compare would screen:
+    $BIGA_WATCHLIST, else the --profile table's, else the config file's,
+    else its built-in list. Spot entries in the watchlist are skipped.
+    """
+    if args.symbols:
+        codes = [parse_code(s) for s in args.symbols.split(",")]
+        if None in codes:
+            raise ValueError("--symbols: spot entries like XAUUSD have no ETF history to backtest")
+        return codes
+
+    path = config_file(args)
+    config = {}
+    if path:
+        with open(path, "rb") as f:
+            config = tomllib.load(f)
+    elif args.config:
+        raise FileNotFoundError(f"config file not found: {args.config}")
+    watchlist = config.get("watchlist", DEFAULT_WATCHLIST)
+    if args.profile:
+        profile = config.get("profiles", {}).get(args.profile)
+        if profile is None:
+            raise ValueError(f"unknown profile '{args.profile}': no [profiles.{args.profile}] table in {path}")
+        watchlist = profile.get("watchlist", watchlist)
+    env = os.environ.get("BIGA_WATCHLIST")
+    if env is not None:
+        watchlist = [s.strip() for s in env.split(",") if s.strip()]
+
+    codes = []
+    for entry in watchlist:
+        code = parse_code(entry)
+        if code is None:
+            print(f"跳过现货 {entry}: 无 ETF 历史数据可回测", file=sys.stderr)
+        else:
+            codes.append(code)
+    return codes
+
+
+def run_portfolio(args):
+    """Backtest the grid on every watchlist symbol against one shared cash pool."""
+    cerebro = bt.Cerebro()
+    setup_broker(cerebro, args.total_cash or args.unit_cash * args.total_units, trading_costs(args))
+    for symbol in watchlist_symbols(args):
+        df = get_stock_data(symbol, args.start_date, args.end_date, args.data_cached)
+        cerebro.adddata(bt.feeds.PandasData(dataname=df), name=symbol)
+    cerebro.addstrategy(
+        PortfolioGridStrategy,
+        grid_up_pct=args.grid_up_pct,
+        grid_down_pct=args.grid_down_pct,
+        unit_cash=args.unit_cash,
+        max_units_per_symbol=args.max_units_per_symbol or args.total_units,
+        max_holdings=args.max_holdings,
+        buy_strategy=args.buy_strategy,
+        stop_loss_pct=args.stop_loss_pct,
+        max_hold_days=args.max_hold_days,
+    )
+    return cerebro.run()[0].result
+
+
+def export_equity_curve(result, path):
+    """The portfolio value per day as CSV, or as a chart when path ends in .png."""
+    curve = pd.DataFrame(result["equity_curve"])
+    if path.endswith(".png"):
+        import matplotlib
+        matplotlib.use("Agg")
+        import matplotlib.pyplot as plt
+
+        fig, ax = plt.subplots(figsize=(12, 5))
+        ax.plot(pd.to_datetime(curve["date"]), curve["value"], label="value")
+        ax.plot(pd.to_datetime(curve["date"]), curve["cash"], label="cash", alpha=0.5)
+        ax.axhline(result["initial_cash"], color="grey", linestyle="--", linewidth=0.8)
+        ax.legend()
+        fig.savefig(path, bbox_inches="tight")
+    else:
+        curve.to_csv(path, index=False)
+    print(f"资金曲线已导出到: {path}")
+
+
+def round_trips(trades):
+    """
+    Pair every sell in a trade list with the buys it closes. A grid SELL
+    closes the most recent open buy; SELL STOP and SELL HOLD close them all.
+    Trades of different symbols are matched separately.
+    """
+    lots = {}
+    trips = []
+    for t in trades:
+        symbol = t.get("symbol")
+        open_lots = lots.setdefault(symbol, [])
+        if t["action"].startswith("BUY"):
+            open_lots.append(t)
+            continue
+        closed = open_lots[:] if t["action"] in ("SELL STOP", "SELL HOLD") else open_lots[-1:]
+        del open_lots[len(open_lots) - len(closed):]
+        for lot in closed:
+            size = lot["size"] if len(closed) > 1 else min(lot["size"], t["size"])
+            pnl = (t["price"] - lot["price"]) * size
+            trip = {
+                "entry_date": lot["date"],
+                "entry_price": lot["price"],
+                "entry_reason": lot["action"],
+                "exit_date": t["date"],
+                "exit_price": t["price"],
+                "exit_reason": t["action"],
+                "size": size,
+                "pnl": round(pnl, 2),
+                "pnl_pct": round((t["price"] / lot["price"] - 1) * 100, 2),
+            }
+            if symbol is not None:
+                trip = {"symbol": symbol, **trip}
+            trips.append(trip)
+    return trips
+
+
+def export_trade_log(trades, path):
+    """Every closed trade as CSV, or as JSON when path ends in .json."""
+    trips = round_trips(trades)
+    if path.endswith(".json"):
+        with open(path, "w", encoding="utf-8") as f:
+            f.write(json.dumps(trips, ensure_ascii=False, indent=2))
+    else:
+        pd.DataFrame(trips).to_csv(path, index=False)
+    print(f"交易记录已导出到: {path}")
+
+
+def export_replay(df, trades, path, title=None):
+    """Chart the closes with a marker on every simulated trade."""
+    import matplotlib
+    matplotlib.use("Agg")
+    import matplotlib.pyplot as plt
+
+    markers = {
+        "BUY INIT": ("^", "tab:blue"),
+        "BUY": ("^", "tab:green"),
+        "SELL": ("v", "tab:red"),
+        "SELL STOP": ("x", "black"),
+        "SELL HOLD": ("x", "tab:orange"),
+    }
+    fig, ax = plt.subplots(figsize=(14, 6))
+    ax.plot(df.index, df["Close"], color="grey", linewidth=1)
+    for action, (marker, color) in markers.items():
+        points = [t for t in trades if t["action"] == action]
+        if points:
+            ax.scatter(pd.to_datetime([t["date"] for t in points]), [t["price"] for t in points],
+                       marker=marker, color=color, label=action, zorder=3)
+    if title:
+        ax.set_title(title)
+    ax.legend()
+    fig.savefig(path, bbox_inches="tight")
+    print(f"交易回放图已导出到: {path}")
+
+
+def walk_forward(df, args):
+    """
+    Split df into consecutive train/test windows. For each split the grid
+    percentages with the best in-sample profit are picked on the train
+    window and then evaluated on the following, unseen test window.
+    """
+    candidates = [float(p) for p in args.wf_candidates.split(",")]
+    costs = trading_costs(args)
+    splits = []
+    start = 0
+    while start + args.train_days + args.test_days <= len(df):
+        train = df.iloc[start:start + args.train_days]
+        test = df.iloc[start + args.train_days:start + args.train_days + args.test_days]
+
+        best = None
+        for up in candidates:
+            for down in candidates:
+                result = run_backtest(train, args.unit_cash, args.total_units, costs=costs,
+                                      grid_up_pct=up, grid_down_pct=down, buy_strategy=args.buy_strategy,
+                                      stop_loss_pct=args.stop_loss_pct, max_hold_days=args.max_hold_days)
+                if best is None or result["profit_pct"] > best[2]["profit_pct"]:
+                    best = (up, down, result)
+
+        up, down, in_sample = best
+        out_sample = run_backtest(test, args.unit_cash, args.total_units, costs=costs,
+                                  grid_up_pct=up, grid_down_pct=down, buy_strategy=args.buy_strategy,
+                                  stop_loss_pct=args.stop_loss_pct, max_hold_days=args.max_hold_days)
+        splits.append({
+            "train_start": str(train.index[0].date()),
+            "train_end": str(train.index[-1].date()),
+            "test_start": str(test.index[0].date()),
+            "test_end": str(test.index[-1].date()),
+            "grid_up_pct": up,
+            "grid_down_pct": down,
+            "in_sample_profit_pct": in_sample["profit_pct"],
+            "out_of_sample_profit_pct": out_sample["profit_pct"],
+            "out_of_sample_trades": out_sample["buy_count"] + out_sample["sell_count"],
+        })
+        # the next train window ends where this test window ended
+        start += args.test_days
+
+    oos = [s["out_of_sample_profit_pct"] for s in splits]
+    ins = [s["in_sample_profit_pct"] for s in splits]
+    compounded = 1.0
+    for p in oos:
+        compounded *= 1 + p / 100
+    summary = {
+        "splits": len(splits),
+        "mean_in_sample_profit_pct": round(sum(ins) / len(ins), 2) if ins else 0.0,
+        "mean_out_of_sample_profit_pct": round(sum(oos) / len(oos), 2) if oos else 0.0,
+        "compounded_out_of_sample_pct": round((compounded - 1) * 100, 2),
+        "positive_out_of_sample_pct": round(sum(1 for p in oos if p > 0) / len(oos) * 100, 2) if oos else 0.0,
+    }
+    return {"summary": summary, "splits": splits}
+
+
+def _sweep_point(task):
+    df, unit_cash, total_units, buy_strategy, costs, params = task
+    result = run_backtest(df, unit_cash, total_units, costs=costs, buy_strategy=buy_strategy, **params)
+    return {**params, **{k: result[k] for k in ("profit_pct", "buy_count", "sell_count", "capital_usage_pct", "commission")}}
+
+
+def optimize(df, args):
+    """
+    Grid-search the strategy parameters, one backtest per combination spread
+    over worker processes, and return the results ranked by profit.
+    """
+    def values(text, cast=float):
+        return [cast(v) for v in text.split(",")]
+
+    grid = list(itertools.product(values(args.opt_down), values(args.opt_up),
+                                  values(args.opt_hold, int), values(args.opt_stop)))
+    tasks = [
+        (df, args.unit_cash, args.total_units, args.buy_strategy, trading_costs(args),
+         {"grid_down_pct": down, "grid_up_pct": up, "max_hold_days": hold, "stop_loss_pct": stop})
+        for down, up, hold, stop in grid
+    ]
+    with Pool(args.workers or None) as pool:
+        results = pool.map(_sweep_point, tasks)
+    return sorted(results, key=lambda r: r["profit_pct"], reverse=True)
+
+
+def export_heatmap(results, path):
+    """
+    Best profit_pct for every (grid_down_pct, grid_up_pct) pair over the
+    other parameters, as CSV, or as an image when path ends in .png.
+    """
+    table = pd.DataFrame(results).pivot_table(index="grid_down_pct", columns="grid_up_pct",
+                                              values="profit_pct", aggfunc="max")
+    if path.endswith(".png"):
+        import matplotlib
+        matplotlib.use("Agg")
+        import matplotlib.pyplot as plt
+
+        fig, ax = plt.subplots()
+        image = ax.imshow(table.values, cmap="RdYlGn", aspect="auto", origin="lower")
+        ax.set_xticks(range(len(table.columns)), [str(c) for c in table.columns])
+        ax.set_yticks(range(len(table.index)), [str(i) for i in table.index])
+        ax.set_xlabel("grid_up_pct")
+        ax.set_ylabel("grid_down_pct")
+        fig.colorbar(image, label="profit_pct")
+        fig.savefig(path, bbox_inches="tight")
+    else:
+        table.to_csv(path)
+    print(f"热力图已导出到: {path}")
+
+
+def print_optimize(results, top):
+    print("\n===== 参数优化排名 =====")
+    for rank, r in enumerate(results[:top], 1):
+        print(f"{rank:>3}. 跌幅={r['grid_down_pct']} 涨幅={r['grid_up_pct']} 持有={r['max_hold_days']} "
+              f"止损={r['stop_loss_pct']}  收益率={r['profit_pct']:.2f}%  "
+              f"买入={r['buy_count']} 卖出={r['sell_count']} 资金使用率={r['capital_usage_pct']:.2f}%  佣金={r['commission']:.2f}")
+
+
+def print_portfolio(result):
+    print("\n===== 组合持仓 =====")
+    for symbol, s in result["symbols"].items():
+        print(f"{symbol}  买入={s['buy_count']} 卖出={s['sell_count']}  "
+              f"持有份数={s['units_held']}  市值={s['value']:.2f}")
+
+    print("\n===== 组合统计 =====")
+    print(f"初始资金: {result['initial_cash']:.2f}")
+    print(f"最终资产: {result['final_value']:.2f}")
+    print(f"收益率: {result['profit_pct']:.2f}%")
+    print(f"最大回撤: {result['max_drawdown_pct']:.2f}%")
+    print(f"总买入次数: {result['buy_count']}")
+    print(f"总卖出次数: {result['sell_count']}")
+    print(f"交易佣金: {result['commission']:.2f} 元")
+    print(f"最多同时持有: {result['max_concurrent_holdings']} 只")
+
+
+def print_walk_forward(result):
+    print("\n===== 滚动样本外检验 =====")
+    for s in result["splits"]:
+        print(f"训练 {s['train_start']}~{s['train_end']}  测试 {s['test_start']}~{s['test_end']}  "
+              f"网格 +{s['grid_up_pct']}/-{s['grid_down_pct']}  "
+              f"样本内={s['in_sample_profit_pct']:.2f}%  样本外={s['out_of_sample_profit_pct']:.2f}%")
+
+    summary = result["summary"]
+    print("\n===== 样本外统计 =====")
+    print(f"窗口数: {summary['splits']}")
+    print(f"样本内平均收益率: {summary['mean_in_sample_profit_pct']:.2f}%")
+    print(f"样本外平均收益率: {summary['mean_out_of_sample_profit_pct']:.2f}%")
+    print(f"样本外复合收益率: {summary['compounded_out_of_sample_pct']:.2f}%")
+    print(f"样本外盈利窗口占比: {summary['positive_out_of_sample_pct']:.2f}%")
+
+
+# python grid.py --symbol 513520 --start_date 2024-01-01 --trade_log trades.csv --replay replay.png
+# python grid.py --portfolio --symbols 513520,513500,518880 --start_date 2024-01-01 --total_cash 100000 --max_holdings 2 --equity_output equity.png
+# python grid.py --symbol 513520 --start_date 2024-01-01 --commission_bps 1 --min_commission 5 --spread_bps 10 --slippage_bps 5
+# python grid.py --symbol 513520 --start_date 2022-01-01 --optimize --opt_down 0.01,0.02,0.03 --opt_up 0.01,0.02,0.03 --opt_hold 0,60 --opt_stop 0,0.1 --heatmap_output heatmap.png
+# python grid.py --symbol 513520 --start_date 2022-01-01 --walk_forward --train_days 250 --test_days 60 --wf_candidates 0.01,0.02,0.03,0.05
 # python grid.py --symbol 513520 --start_date 2024-01-01 --end_date 2025-09-09 --grid_up_pct 0.02 --grid_down_pct 0.02 --unit_cash 10000 --total_units 10 --buy_strategy by_latest_sell --json_only --json_output backtest_result.json
 # python grid.py --symbol 513520 --start_date 2024-01-01 --end_date 2025-09-09 --grid_up_pct 0.02 --grid_down_pct 0.02 --unit_cash 10000 --total_units 10 --buy_strategy by_latest_sell
 if __name__ == "__main__":
@@ -225,27 +823,104 @@ if __name__ == "__main__":
     parser.add_argument("--json_only", action="store_true", help="只输出 JSON，不打印交易明细")
     parser.add_argument("--json_output", help="导出 JSON 文件名（可选）")
     parser.add_argument("--data_cached", action= "store_true", help="cache stock data")
+    parser.add_argument("--walk_forward", action="store_true", help="滚动训练/测试窗口，报告样本外表现")
+    parser.add_argument("--train_days", type=int, default=250, help="训练窗口交易日数")
+    parser.add_argument("--test_days", type=int, default=60, help="测试窗口交易日数")
+    parser.add_argument("--wf_candidates", default="0.01,0.02,0.03,0.05",
+                        help="训练窗口中尝试的网格涨跌幅，逗号分隔")
+    parser.add_argument("--stop_loss_pct", type=float, default=0, help="跌破持仓均价该比例时清仓 (0 为关闭)")
+    parser.add_argument("--max_hold_days", type=int, default=0, help="首次买入后持有该交易日数即清仓 (0 为关闭)")
+    parser.add_argument("--optimize", action="store_true", help="网格搜索参数并按收益率排名")
+    parser.add_argument("--opt_down", default="0.01,0.02,0.03,0.05", help="搜索的网格买入跌幅，逗号分隔")
+    parser.add_argument("--opt_up", default="0.01,0.02,0.03,0.05", help="搜索的网格卖出涨幅，逗号分隔")
+    parser.add_argument("--opt_hold", default="0", help="搜索的最长持有天数，逗号分隔 (0 为不限)")
+    parser.add_argument("--opt_stop", default="0", help="搜索的止损比例，逗号分隔 (0 为不止损)")
+    parser.add_argument("--workers", type=int, default=0, help="并行进程数 (0 为 CPU 核数)")
+    parser.add_argument("--top", type=int, default=20, help="显示排名前 N 的参数组合")
+    parser.add_argument("--heatmap_output", help="导出跌幅×涨幅热力图 (.csv 或 .png，可选)")
+    parser.add_argument("--commission_bps", type=float, default=0, help="佣金费率，基点 (ETF 免印花税)")
+    parser.add_argument("--min_commission", type=float, default=0, help="每笔最低佣金 (元)")
+    parser.add_argument("--spread_bps", type=float, default=0, help="买卖价差，基点 (每笔成交按半个价差计)")
+    parser.add_argument("--slippage_bps", type=float, default=0, help="每笔成交滑点，基点")
+    parser.add_argument("--portfolio", action="store_true", help="用同一资金池回测整个自选列表，输出组合资金曲线")
+    parser.add_argument("--symbols", help="组合回测的 ETF 代码，逗号分隔 (默认读取 --config 中的 watchlist)")
+    parser.add_argument("--config", help="读取 watchlist 的配置文件 (默认与 decline-compare 相同的查找顺序)")
+    parser.add_argument("--profile", help="使用配置文件中 [profiles.NAME] 的 watchlist")
+    parser.add_argument("--total_cash", type=float, default=0, help="组合总资金 (0 为 unit_cash × total_units)")
+    parser.add_argument("--max_units_per_symbol", type=int, default=0, help="单只 ETF 最多持有份数 (0 为 total_units)")
+    parser.add_argument("--max_holdings", type=int, default=0, help="最多同时持有的 ETF 数 (0 为不限)")
+    parser.add_argument("--equity_output", help="导出组合资金曲线 (.csv 或 .png，可选)")
+    parser.add_argument("--trade_log", help="导出每笔交易的开平仓记录 (.csv 或 .json，可选)")
+    parser.add_argument("--replay", help="导出带交易标记的价格回放图 (.png，可选)")
     args = parser.parse_args()
 
-    df = get_stock_data(args.symbol, args.start_date, args.end_date, args.data_cached)
-
-    cerebro = bt.Cerebro()
-    cerebro.broker.set_cash(args.unit_cash * args.total_units)
-
-    data = bt.feeds.PandasData(dataname=df)
-    cerebro.adddata(data)
-
-    cerebro.addstrategy(
-        GridStrategy,
-        grid_up_pct=args.grid_up_pct,
-        grid_down_pct=args.grid_down_pct,
-        unit_cash=args.unit_cash,
-        total_units=args.total_units,
-        buy_strategy=args.buy_strategy,
-        json_only=args.json_only,
-        json_output=args.json_output,
-    )
-
-    print(f"初始资金: {cerebro.broker.getvalue():.2f}")
-    cerebro.run()
-    print(f"回测结束资金: {cerebro.broker.getvalue():.2f}")
+    # the portfolio backtest loads its own data, one feed per symbol
+    df = None if args.portfolio else get_stock_data(args.symbol, args.start_date, args.end_date, args.data_cached)
+
+    if args.portfolio:
+        result = run_portfolio(args)
+        if not args.json_only:
+            print_portfolio(result)
+        if args.equity_output:
+            export_equity_curve(result, args.equity_output)
+        if args.trade_log:
+            export_trade_log(result["trades"], args.trade_log)
+        json_str = json.dumps(result, ensure_ascii=False, indent=2)
+        print("\n===== JSON 结果 =====")
+        print(json_str)
+        if args.json_output:
+            with open(args.json_output, "w", encoding="utf-8") as f:
+                f.write(json_str)
+            print(f"JSON 结果已导出到: {args.json_output}")
+    elif args.optimize:
+        results = optimize(df, args)
+        if not args.json_only:
+            print_optimize(results, args.top)
+        if args.heatmap_output:
+            export_heatmap(results, args.heatmap_output)
+        json_str = json.dumps(results, ensure_ascii=False, indent=2)
+        print("\n===== JSON 结果 =====")
+        print(json_str)
+        if args.json_output:
+            with open(args.json_output, "w", encoding="utf-8") as f:
+                f.write(json_str)
+            print(f"JSON 结果已导出到: {args.json_output}")
+    elif args.walk_forward:
+        result = walk_forward(df, args)
+        if not args.json_only:
+            print_walk_forward(result)
+        json_str = json.dumps(result, ensure_ascii=False, indent=2)
+        print("\n===== JSON 结果 =====")
+        print(json_str)
+        if args.json_output:
+            with open(args.json_output, "w", encoding="utf-8") as f:
+                f.write(json_str)
+            print(f"JSON 结果已导出到: {args.json_output}")
+    else:
+        cerebro = bt.Cerebro()
+        setup_broker(cerebro, args.unit_cash * args.total_units, trading_costs(args))
+
+        data = bt.feeds.PandasData(dataname=df)
+        cerebro.adddata(data)
+
+        cerebro.addstrategy(
+            GridStrategy,
+            grid_up_pct=args.grid_up_pct,
+            grid_down_pct=args.grid_down_pct,
+            unit_cash=args.unit_cash,
+            total_units=args.total_units,
+            buy_strategy=args.buy_strategy,
+            json_only=args.json_only,
+            json_output=args.json_output,
+            stop_loss_pct=args.stop_loss_pct,
+            max_hold_days=args.max_hold_days,
+        )
+
+        print(f"初始资金: {cerebro.broker.getvalue():.2f}")
+        strategy = cerebro.run()[0]
+        print(f"回测结束资金: {cerebro.broker.getvalue():.2f}")
+
+        if args.trade_log:
+            export_trade_log(strategy.result["trades"], args.trade_log)
+        if args.replay:
+            export_replay(df, strategy.result["trades"], args.replay, title=args.symbol)